@@ -1,6 +1,7 @@
 use crate::{
     account_statistics::AccountStatistics,
     character::{CharacterRecord, CharacterSlot},
+    moderation::Moderation,
 };
 
 use rpg_core::uid::Uid;
@@ -20,6 +21,7 @@ pub struct Account {
     pub info: AccountInfo,
     pub statistics: AccountStatistics,
     pub characters: Vec<CharacterRecord>,
+    pub moderation: Moderation,
 }
 
 impl Account {
@@ -30,6 +32,10 @@ impl Account {
     pub fn get_character_from_uid(&self, uid: Uid) -> Option<&CharacterRecord> {
         self.characters.iter().find(|c| c.info.uid == uid)
     }
+
+    pub fn get_character_from_uid_mut(&mut self, uid: Uid) -> Option<&mut CharacterRecord> {
+        self.characters.iter_mut().find(|c| c.info.uid == uid)
+    }
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Ser, De)]
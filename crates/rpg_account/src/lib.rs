@@ -3,3 +3,7 @@ pub mod character_statistics;
 
 pub mod account;
 pub mod character;
+pub mod moderation;
+pub mod name;
+
+pub mod save;
@@ -0,0 +1,36 @@
+use serde_derive::{Deserialize as De, Serialize as Ser};
+
+/// A ban or mute placed on an account by an operator
+#[derive(Debug, Clone, PartialEq, Ser, De)]
+pub struct Sanction {
+    pub reason: String,
+    /// Seconds since the unix epoch at which the sanction lapses, `None` never lapses
+    pub expires: Option<u64>,
+}
+
+impl Sanction {
+    pub fn new(reason: String, expires: Option<u64>) -> Self {
+        Self { reason, expires }
+    }
+
+    pub fn is_active(&self, now: u64) -> bool {
+        self.expires.map_or(true, |expires| now < expires)
+    }
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Ser, De)]
+pub struct Moderation {
+    pub ban: Option<Sanction>,
+    pub mute: Option<Sanction>,
+}
+
+impl Moderation {
+    /// The ban preventing the account from logging in, if any
+    pub fn active_ban(&self, now: u64) -> Option<&Sanction> {
+        self.ban.as_ref().filter(|b| b.is_active(now))
+    }
+
+    pub fn is_muted(&self, now: u64) -> bool {
+        self.mute.as_ref().is_some_and(|m| m.is_active(now))
+    }
+}
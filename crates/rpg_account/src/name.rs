@@ -0,0 +1,80 @@
+//! Naming rules for accounts and characters, shared by the client and server
+
+use serde_derive::{Deserialize as De, Serialize as Ser};
+
+pub const NAME_MIN_LENGTH: usize = 3;
+pub const NAME_MAX_LENGTH: usize = 16;
+
+/// Names that may not be used by accounts or characters, compared case-insensitively
+pub const RESERVED_NAMES: &[&str] = &[
+    "admin",
+    "administrator",
+    "moderator",
+    "gm",
+    "server",
+    "system",
+    "root",
+    "null",
+    "con",
+    "nul",
+    "prn",
+    "aux",
+];
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Ser, De)]
+pub enum NameError {
+    TooShort,
+    TooLong,
+    /// Names must start with an ASCII letter
+    InvalidStart,
+    /// Names may only contain ASCII letters, digits, `_` and `-`
+    InvalidCharacter,
+    Reserved,
+}
+
+pub fn validate_account_name(name: &str) -> Result<(), NameError> {
+    validate_name(name)
+}
+
+pub fn validate_character_name(name: &str) -> Result<(), NameError> {
+    validate_name(name)
+}
+
+/// Check that a name only contains characters allowed in names, this does not check any of the
+/// other naming rules
+pub fn has_valid_characters(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+/// Names are unique regardless of case
+pub fn names_match(lhs: &str, rhs: &str) -> bool {
+    lhs.eq_ignore_ascii_case(rhs)
+}
+
+fn validate_name(name: &str) -> Result<(), NameError> {
+    // Only ASCII is accepted so the byte length is the character count
+    if name.len() < NAME_MIN_LENGTH {
+        return Err(NameError::TooShort);
+    }
+
+    if name.len() > NAME_MAX_LENGTH {
+        return Err(NameError::TooLong);
+    }
+
+    if !has_valid_characters(name) {
+        return Err(NameError::InvalidCharacter);
+    }
+
+    if !name.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        return Err(NameError::InvalidStart);
+    }
+
+    if RESERVED_NAMES.iter().any(|r| names_match(r, name)) {
+        return Err(NameError::Reserved);
+    }
+
+    Ok(())
+}
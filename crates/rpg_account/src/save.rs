@@ -0,0 +1,145 @@
+//! Versioned save format
+//!
+//! Every save file starts with a small header, a 4 byte magic followed by a little endian `u16`
+//! version, the bincode encoded record follows. Files that do not start with the magic predate
+//! the header and are treated as version 0.
+//!
+//! Older versions are decoded through a frozen copy of the layout they were written with and
+//! converted to the current record. When the layout of a record or of anything it contains
+//! changes, its `VERSION` must be bumped, the previous layout frozen here with a conversion to the
+//! current one, a migration appended and a fixture of the previous version added to
+//! `tests/fixtures`, see `tests/generate_fixtures.rs`.
+
+use crate::{
+    account::{Account, AccountInfo, AdminAccount},
+    account_statistics::AccountStatistics,
+    character::CharacterRecord,
+    moderation::Moderation,
+};
+
+use serde::{de::DeserializeOwned, Serialize};
+use serde_derive::{Deserialize as De, Serialize as Ser};
+
+use std::fmt;
+
+pub const SAVE_MAGIC: [u8; 4] = *b"RPGS";
+pub const HEADER_LEN: usize = SAVE_MAGIC.len() + 2;
+
+/// Decodes the payload of an older version as the current record
+pub type Migration<T> = fn(&[u8]) -> Result<T, SaveError>;
+
+#[derive(Debug)]
+pub enum SaveError {
+    /// The file was written by a newer version of the game
+    UnsupportedVersion(u16),
+    /// There is no migration from this version
+    MissingMigration(u16),
+    Serialization(bincode::Error),
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnsupportedVersion(version) => write!(f, "unsupported save version {version}"),
+            Self::MissingMigration(version) => write!(f, "no migration from version {version}"),
+            Self::Serialization(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for SaveError {}
+
+impl From<bincode::Error> for SaveError {
+    fn from(e: bincode::Error) -> Self {
+        Self::Serialization(e)
+    }
+}
+
+pub trait Versioned: Serialize + DeserializeOwned + 'static {
+    /// The current version of the record layout
+    const VERSION: u16;
+
+    /// `MIGRATIONS[n]` decodes a version `n` payload as the current version
+    const MIGRATIONS: &'static [Migration<Self>];
+}
+
+/// Version 0 files are headerless, their payload has the same layout as version 1
+///
+/// Only for records whose layout has not changed since version 1.
+pub fn migrate_headerless<T: DeserializeOwned>(payload: &[u8]) -> Result<T, SaveError> {
+    Ok(bincode::deserialize(payload)?)
+}
+
+/// Decode a payload with the frozen layout `L` of its version and convert it
+pub fn migrate_from<L: DeserializeOwned + Into<T>, T>(payload: &[u8]) -> Result<T, SaveError> {
+    Ok(bincode::deserialize::<L>(payload)?.into())
+}
+
+/// The account layout of versions 0 and 1, before the account's moderation state was added
+///
+/// Frozen, this must keep decoding the files written with it.
+#[derive(Debug, Clone, PartialEq, Ser, De)]
+pub struct AccountV1 {
+    pub info: AccountInfo,
+    pub statistics: AccountStatistics,
+    pub characters: Vec<CharacterRecord>,
+}
+
+impl From<AccountV1> for Account {
+    fn from(account: AccountV1) -> Self {
+        Self {
+            info: account.info,
+            statistics: account.statistics,
+            characters: account.characters,
+            moderation: Moderation::default(),
+        }
+    }
+}
+
+impl Versioned for Account {
+    const VERSION: u16 = 2;
+    const MIGRATIONS: &'static [Migration<Self>] = &[
+        migrate_from::<AccountV1, Self>,
+        migrate_from::<AccountV1, Self>,
+    ];
+}
+
+impl Versioned for AdminAccount {
+    const VERSION: u16 = 1;
+    const MIGRATIONS: &'static [Migration<Self>] = &[migrate_headerless];
+}
+
+/// Split a save file into its version and payload
+pub fn read_header(bytes: &[u8]) -> (u16, &[u8]) {
+    if bytes.len() >= HEADER_LEN && bytes[..SAVE_MAGIC.len()] == SAVE_MAGIC {
+        let version = u16::from_le_bytes([bytes[4], bytes[5]]);
+        (version, &bytes[HEADER_LEN..])
+    } else {
+        (0, bytes)
+    }
+}
+
+pub fn encode<T: Versioned>(record: &T) -> Result<Vec<u8>, SaveError> {
+    let mut bytes = Vec::from(SAVE_MAGIC);
+    bytes.extend_from_slice(&T::VERSION.to_le_bytes());
+    bincode::serialize_into(&mut bytes, record)?;
+
+    Ok(bytes)
+}
+
+pub fn decode<T: Versioned>(bytes: &[u8]) -> Result<T, SaveError> {
+    let (version, payload) = read_header(bytes);
+    if version > T::VERSION {
+        return Err(SaveError::UnsupportedVersion(version));
+    }
+
+    if version == T::VERSION {
+        return Ok(bincode::deserialize(payload)?);
+    }
+
+    let Some(migration) = T::MIGRATIONS.get(version as usize) else {
+        return Err(SaveError::MissingMigration(version));
+    };
+
+    migration(payload)
+}
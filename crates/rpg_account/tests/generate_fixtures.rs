@@ -0,0 +1,155 @@
+//! Writes the save fixtures in `tests/fixtures`, see `save_migrations.rs` for their contents
+//!
+//! Run with `cargo test -p rpg_account --test generate_fixtures -- --ignored`. Fixtures that
+//! already exist are never overwritten, they are the files older servers wrote and must stay as
+//! they are. When a record's `VERSION` is bumped, write its previous version here with the frozen
+//! layout from `save` and run the generator to add the new fixture. The character is built from
+//! the metadata in the repository's `assets`, its stats are kept in hash maps so its bytes differ
+//! between runs.
+
+use rpg_account::{
+    account::{Account, AccountId, AccountInfo},
+    account_statistics::AccountStatistics,
+    character::{Character, CharacterInfo, CharacterRecord, CharacterSlot},
+    character_statistics::CharacterStatistics,
+    moderation::Moderation,
+    save::{AccountV1, SAVE_MAGIC},
+};
+use rpg_core::{
+    class::Class,
+    game_mode::GameMode,
+    metadata::Metadata,
+    passive_tree::UnitPassiveSkills,
+    skill::{SkillSlot, SkillSlotId},
+    storage::UnitStorage,
+    uid::NextUid,
+    unit::{HeroInfo, Unit, UnitInfo, UnitKind},
+};
+use rpg_world::zone::ZoneId;
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use std::{fs, path::Path};
+
+fn load_metadata<T: DeserializeOwned>(name: &str) -> T {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("../../assets/metadata")
+        .join(format!("{name}.json"));
+
+    serde_json::from_slice(&fs::read(path).unwrap()).unwrap()
+}
+
+fn metadata() -> Metadata {
+    Metadata {
+        item: load_metadata("item"),
+        unit: load_metadata("unit"),
+        skill: load_metadata("skill"),
+        level: load_metadata("level"),
+        stat: load_metadata("stats"),
+        modifier: load_metadata("modifiers"),
+        passive_tree: load_metadata("passive_tree"),
+    }
+}
+
+fn fixture_info(selected_slot: Option<CharacterSlot>) -> AccountInfo {
+    AccountInfo {
+        id: AccountId(7),
+        name: "fixture".into(),
+        character_slots: 12,
+        selected_slot,
+    }
+}
+
+/// A level 5 hardcore character named `Fixture` in slot 1 with the uid 41
+fn fixture_character(metadata: &Metadata) -> CharacterRecord {
+    let mut next_uid = NextUid::default();
+    for _ in 0..41 {
+        next_uid.next();
+    }
+
+    let class = Class::Dex;
+    let mut unit = Unit::new(
+        next_uid.get(),
+        class,
+        UnitKind::Hero,
+        UnitInfo::Hero(HeroInfo::new(metadata, GameMode::Hardcore)),
+        5,
+        "Fixture",
+        metadata,
+    );
+    unit.passive_skill_points = 2;
+
+    let mut skills = vec![];
+    unit.add_default_skills(&mut skills, metadata);
+    let skill_slots = vec![SkillSlot::new(SkillSlotId(0), Some(skills[0].id))];
+
+    CharacterRecord {
+        info: CharacterInfo {
+            name: "Fixture".into(),
+            slot: CharacterSlot(1),
+            uid: unit.uid,
+            game_mode: GameMode::Hardcore,
+        },
+        statistics: CharacterStatistics {
+            kills: 12,
+            distance_travelled: 250.,
+            ..Default::default()
+        },
+        character: Character {
+            unit,
+            skills,
+            skill_slots,
+            passive_tree: UnitPassiveSkills::new(class),
+            storage: UnitStorage::default(),
+            waypoints: vec![ZoneId(0), ZoneId(1)],
+        },
+    }
+}
+
+/// Write a fixture unless it exists, version 0 files have no header
+fn write_fixture<T: Serialize>(name: &str, version: u16, record: &T) {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(format!("{name}_v{version}.bin"));
+    if path.exists() {
+        return;
+    }
+
+    let mut bytes = vec![];
+    if version > 0 {
+        bytes.extend_from_slice(&SAVE_MAGIC);
+        bytes.extend_from_slice(&version.to_le_bytes());
+    }
+    bincode::serialize_into(&mut bytes, record).unwrap();
+
+    fs::write(&path, bytes).unwrap();
+    println!("wrote {}", path.display());
+}
+
+#[test]
+#[ignore = "writes missing fixtures, run on its own when a save version is added"]
+fn generate_fixtures() {
+    let account_v1 = AccountV1 {
+        info: fixture_info(None),
+        statistics: AccountStatistics::default(),
+        characters: vec![],
+    };
+    write_fixture("account", 0, &account_v1);
+    write_fixture("account", 1, &account_v1);
+
+    let account_v2 = Account {
+        info: fixture_info(None),
+        statistics: AccountStatistics::default(),
+        characters: vec![],
+        moderation: Moderation::default(),
+    };
+    write_fixture("account", 2, &account_v2);
+
+    let account_characters_v1 = AccountV1 {
+        info: fixture_info(Some(CharacterSlot(1))),
+        statistics: AccountStatistics::default(),
+        characters: vec![fixture_character(&metadata())],
+    };
+    write_fixture("account_characters", 0, &account_characters_v1);
+    write_fixture("account_characters", 1, &account_characters_v1);
+}
@@ -0,0 +1,124 @@
+//! Checks that every save version in `tests/fixtures` still loads as the current version
+//!
+//! The `account_v*` fixtures contain an account named `fixture` with the id 7, 12 character slots,
+//! no characters and no moderation state. The `account_characters_v*` fixtures contain the same
+//! account with one level 5 hardcore character named `Fixture` in slot 1. They are written by
+//! `generate_fixtures.rs`.
+
+use rpg_account::{
+    account::{Account, AccountId},
+    character::CharacterSlot,
+    moderation::Moderation,
+    save::{self, SaveError, Versioned, SAVE_MAGIC},
+};
+use rpg_core::{class::Class, game_mode::GameMode, skill::SkillId};
+use rpg_world::zone::ZoneId;
+
+const ACCOUNT_V0: &[u8] = include_bytes!("fixtures/account_v0.bin");
+const ACCOUNT_V1: &[u8] = include_bytes!("fixtures/account_v1.bin");
+const ACCOUNT_V2: &[u8] = include_bytes!("fixtures/account_v2.bin");
+const ACCOUNT_CHARACTERS_V0: &[u8] = include_bytes!("fixtures/account_characters_v0.bin");
+const ACCOUNT_CHARACTERS_V1: &[u8] = include_bytes!("fixtures/account_characters_v1.bin");
+
+fn assert_fixture_account(account: &Account) {
+    assert_eq!(account.info.id, AccountId(7));
+    assert_eq!(account.info.name, "fixture");
+    assert_eq!(account.info.character_slots, 12);
+    assert_eq!(account.info.selected_slot, None);
+    assert!(account.characters.is_empty());
+    assert_eq!(account.moderation, Moderation::default());
+}
+
+fn assert_fixture_character(account: &Account) {
+    assert_eq!(account.info.id, AccountId(7));
+    assert_eq!(account.info.name, "fixture");
+    assert_eq!(account.info.selected_slot, Some(CharacterSlot(1)));
+    assert_eq!(account.moderation, Moderation::default());
+    assert_eq!(account.characters.len(), 1);
+
+    let record = &account.characters[0];
+    assert_eq!(record.info.name, "Fixture");
+    assert_eq!(record.info.slot, CharacterSlot(1));
+    assert_eq!(record.info.uid.id(), 41);
+    assert_eq!(record.info.game_mode, GameMode::Hardcore);
+    assert_eq!(record.statistics.kills, 12);
+    assert_eq!(record.statistics.distance_travelled, 250.);
+
+    let character = &record.character;
+    assert_eq!(character.unit.uid, record.info.uid);
+    assert_eq!(character.unit.name, "Fixture");
+    assert_eq!(character.unit.class, Class::Dex);
+    assert_eq!(character.unit.level, 5);
+    assert_eq!(character.unit.passive_skill_points, 2);
+    assert_eq!(character.skills.len(), 1);
+    assert_eq!(character.skills[0].id, SkillId::BasicBolt);
+    assert_eq!(character.skill_slots.len(), 1);
+    assert_eq!(character.skill_slots[0].skill_id, Some(SkillId::BasicBolt));
+    assert_eq!(character.waypoints, [ZoneId(0), ZoneId(1)]);
+}
+
+#[test]
+fn every_account_version_has_a_migration() {
+    assert_eq!(Account::MIGRATIONS.len(), Account::VERSION as usize);
+}
+
+#[test]
+fn account_v0_migrates() {
+    assert_eq!(save::read_header(ACCOUNT_V0).0, 0);
+
+    let account: Account = save::decode(ACCOUNT_V0).unwrap();
+    assert_fixture_account(&account);
+}
+
+#[test]
+fn account_v1_loads() {
+    assert_eq!(save::read_header(ACCOUNT_V1).0, 1);
+
+    let account: Account = save::decode(ACCOUNT_V1).unwrap();
+    assert_fixture_account(&account);
+}
+
+#[test]
+fn account_v2_loads() {
+    assert_eq!(save::read_header(ACCOUNT_V2).0, 2);
+
+    let account: Account = save::decode(ACCOUNT_V2).unwrap();
+    assert_fixture_account(&account);
+}
+
+#[test]
+fn account_characters_v0_migrates() {
+    assert_eq!(save::read_header(ACCOUNT_CHARACTERS_V0).0, 0);
+
+    let account: Account = save::decode(ACCOUNT_CHARACTERS_V0).unwrap();
+    assert_fixture_character(&account);
+}
+
+#[test]
+fn account_characters_v1_migrates() {
+    assert_eq!(save::read_header(ACCOUNT_CHARACTERS_V1).0, 1);
+
+    let account: Account = save::decode(ACCOUNT_CHARACTERS_V1).unwrap();
+    assert_fixture_character(&account);
+}
+
+#[test]
+fn account_round_trips_with_header() {
+    let account: Account = save::decode(ACCOUNT_V0).unwrap();
+
+    let bytes = save::encode(&account).unwrap();
+    assert_eq!(&bytes[..SAVE_MAGIC.len()], &SAVE_MAGIC);
+    assert_eq!(save::read_header(&bytes).0, Account::VERSION);
+    assert_eq!(save::decode::<Account>(&bytes).unwrap(), account);
+}
+
+#[test]
+fn newer_versions_are_rejected() {
+    let mut bytes = ACCOUNT_V1.to_vec();
+    bytes[4..6].copy_from_slice(&(Account::VERSION + 1).to_le_bytes());
+
+    assert!(matches!(
+        save::decode::<Account>(&bytes),
+        Err(SaveError::UnsupportedVersion(_))
+    ));
+}
@@ -0,0 +1,390 @@
+//! Run with
+//! - `cargo run -p rpg_admin_client -- --name admin clients`
+//! - `cargo run -p rpg_admin_client -- --addr 127.0.0.1 --port 4269 --name admin kick someone`
+//! - `cargo run -p rpg_admin_client -- --name admin ban --client 1234 --duration 3600`
+//!
+//! The admin password is read from `RPG_ADMIN_PASSWORD` unless `--password` is given. A connect
+//! token is requested with the admin credentials unless `--unsecure` is given.
+
+use rpg_account::character::CharacterSlot;
+use rpg_network_protocol::{
+    advertised_features,
+    protocol::*,
+    token::{request_connect_token, TokenRequest},
+    BUILD_ID, PROTOCOL_ID, PROTOCOL_VERSION, SERVER_PORT,
+};
+
+use renet::{
+    transport::{ClientAuthentication, NetcodeClientTransport},
+    ConnectionConfig, RenetClient,
+};
+
+use clap::{Args, Parser, Subcommand};
+
+use std::{
+    env,
+    error::Error,
+    net::{Ipv4Addr, SocketAddr, UdpSocket},
+    process::ExitCode,
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+const TICK: Duration = Duration::from_millis(16);
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Parser, Debug)]
+struct Cli {
+    #[arg(short, long, default_value_t = SERVER_PORT)]
+    port: u16,
+    #[arg(short, long, default_value_t = Ipv4Addr::LOCALHOST)]
+    addr: Ipv4Addr,
+    #[arg(short, long)]
+    name: String,
+    #[arg(long)]
+    password: Option<String>,
+    /// Connect without a connect token, for servers running in unsecure mode
+    #[arg(long)]
+    unsecure: bool,
+    #[command(subcommand)]
+    command: Command,
+}
+
+/// The account a moderation command applies to, either by name or by a connected client
+#[derive(Args, Debug)]
+#[group(required = true, multiple = false)]
+struct Target {
+    account: Option<String>,
+    /// The id of a connected client, as listed by `clients`
+    #[arg(long)]
+    client: Option<u64>,
+}
+
+impl From<&Target> for ModerationTarget {
+    fn from(target: &Target) -> Self {
+        match (&target.account, target.client) {
+            (_, Some(client_id)) => Self::Client(client_id),
+            (Some(account), None) => Self::Account(account.clone()),
+            (None, None) => unreachable!("clap requires an account or a client"),
+        }
+    }
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// List the connected clients
+    Clients,
+    /// List the running games
+    Games,
+    /// Disconnect the client logged into an account
+    Kick {
+        #[command(flatten)]
+        target: Target,
+    },
+    /// Disconnect an account and reject logins to it until the ban expires
+    Ban {
+        #[command(flatten)]
+        target: Target,
+        #[arg(long, default_value = "")]
+        reason: String,
+        /// The length of the ban in seconds, permanent when omitted
+        #[arg(long)]
+        duration: Option<u64>,
+    },
+    /// Lift the ban on an account
+    Unban {
+        #[command(flatten)]
+        target: Target,
+    },
+    /// Reject chat and lobby messages from an account until the mute expires
+    Mute {
+        #[command(flatten)]
+        target: Target,
+        #[arg(long, default_value = "")]
+        reason: String,
+        /// The length of the mute in seconds, permanent when omitted
+        #[arg(long)]
+        duration: Option<u64>,
+    },
+    /// Lift the mute on an account
+    Unmute {
+        #[command(flatten)]
+        target: Target,
+    },
+    /// Send a message to every player
+    Broadcast { message: String },
+    /// Show a character
+    Character { account: String, slot: usize },
+    /// Set the level of a character that is not in a game
+    SetLevel {
+        account: String,
+        slot: usize,
+        level: u8,
+    },
+    /// Set the unspent passive skill points of a character that is not in a game
+    SetPassivePoints {
+        account: String,
+        slot: usize,
+        points: u8,
+    },
+}
+
+impl Command {
+    fn to_message(&self) -> ClientMessage {
+        match self {
+            Self::Clients => ClientMessage::CSAdminListClients(CSAdminListClients),
+            Self::Games => ClientMessage::CSAdminListGames(CSAdminListGames),
+            Self::Kick { target } => ClientMessage::CSAdminKick(CSAdminKick(target.into())),
+            Self::Ban {
+                target,
+                reason,
+                duration,
+            } => ClientMessage::CSAdminBan(CSAdminBan {
+                target: target.into(),
+                reason: reason.clone(),
+                duration: *duration,
+            }),
+            Self::Unban { target } => ClientMessage::CSAdminUnban(CSAdminUnban(target.into())),
+            Self::Mute {
+                target,
+                reason,
+                duration,
+            } => ClientMessage::CSAdminMute(CSAdminMute {
+                target: target.into(),
+                reason: reason.clone(),
+                duration: *duration,
+            }),
+            Self::Unmute { target } => ClientMessage::CSAdminUnmute(CSAdminUnmute(target.into())),
+            Self::Broadcast { message } => {
+                ClientMessage::CSAdminBroadcast(CSAdminBroadcast(message.clone()))
+            }
+            Self::Character { account, slot } => {
+                ClientMessage::CSAdminGetCharacter(CSAdminGetCharacter {
+                    account_name: account.clone(),
+                    slot: CharacterSlot(*slot),
+                })
+            }
+            Self::SetLevel {
+                account,
+                slot,
+                level,
+            } => ClientMessage::CSAdminEditCharacter(CSAdminEditCharacter {
+                account_name: account.clone(),
+                slot: CharacterSlot(*slot),
+                edit: CharacterEdit::Level(*level),
+            }),
+            Self::SetPassivePoints {
+                account,
+                slot,
+                points,
+            } => ClientMessage::CSAdminEditCharacter(CSAdminEditCharacter {
+                account_name: account.clone(),
+                slot: CharacterSlot(*slot),
+                edit: CharacterEdit::PassiveSkillPoints(*points),
+            }),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Session {
+    Connecting,
+    LoggingIn,
+    AwaitingReply,
+    Done(Result<(), String>),
+}
+
+fn send(client: &mut RenetClient, message: &ClientMessage) {
+    let message = bincode::serialize(message).unwrap();
+    client.send_message(ClientChannel::Message, message);
+}
+
+/// Print a reply to the pending request, returning the outcome once the request is complete
+fn handle_message(message: ServerMessage) -> Option<Result<(), String>> {
+    match message {
+        ServerMessage::SCHelloError(msg) => Some(Err(format!(
+            "rejected by server build {}: {}",
+            msg.build_id, msg.reason
+        ))),
+        ServerMessage::SCLoginAdminAccountError(msg) => {
+            Some(Err(format!("login failed: {:?}", msg.0)))
+        }
+        ServerMessage::SCAdminError(msg) => Some(Err(format!("request failed: {:?}", msg.0))),
+        ServerMessage::SCAdminSuccess(_) => Some(Ok(())),
+        ServerMessage::SCAdminClients(msg) => {
+            for client in msg.0 {
+                println!(
+                    "client {} account {:?} ({}) admin {} game {:?} build {} features {:?}",
+                    client.client_id,
+                    client.account_id,
+                    client.account_name.as_deref().unwrap_or("-"),
+                    client.is_admin,
+                    client.game_id,
+                    client.build_id.as_deref().unwrap_or("-"),
+                    client.features
+                );
+            }
+            Some(Ok(()))
+        }
+        ServerMessage::SCAdminGames(msg) => {
+            for game in msg.0 {
+                println!(
+                    "game {:?} mode {:?} running {} players {}/{} {:?}",
+                    game.id,
+                    game.game_mode,
+                    game.running,
+                    game.players.len(),
+                    game.max_players,
+                    game.players
+                );
+            }
+            Some(Ok(()))
+        }
+        ServerMessage::SCAdminCharacter(msg) => {
+            let record = msg.0;
+            println!(
+                "{} slot {} mode {:?} class {:?} level {} passive points {}",
+                record.info.name,
+                record.info.slot.0,
+                record.info.game_mode,
+                record.character.unit.class,
+                record.character.unit.level,
+                record.character.unit.passive_skill_points
+            );
+            Some(Ok(()))
+        }
+        _ => None,
+    }
+}
+
+fn run(cli: &Cli, password: String) -> Result<Result<(), String>, Box<dyn Error>> {
+    let server_addr = SocketAddr::new(cli.addr.into(), cli.port);
+
+    let connection_config = ConnectionConfig {
+        available_bytes_per_tick: 1024 * 1024,
+        client_channels_config: ClientChannel::channels_config(),
+        server_channels_config: ServerChannel::channels_config(),
+    };
+    let mut client = RenetClient::new(connection_config);
+    // Queued until the transport connects, the server ignores everything sent before this
+    send(
+        &mut client,
+        &ClientMessage::CSHello(CSHello {
+            protocol_version: PROTOCOL_VERSION,
+            build_id: BUILD_ID.to_string(),
+            features: advertised_features(),
+        }),
+    );
+
+    let socket = UdpSocket::bind(SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), 0))?;
+    let current_time = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH)?;
+    let authentication = if cli.unsecure {
+        ClientAuthentication::Unsecure {
+            client_id: current_time.as_millis() as u64,
+            protocol_id: PROTOCOL_ID,
+            server_addr,
+            user_data: None,
+        }
+    } else {
+        let request = TokenRequest::Admin {
+            name: cli.name.clone(),
+            password: password.clone(),
+        };
+        ClientAuthentication::Secure {
+            connect_token: request_connect_token(server_addr, &request)?,
+        }
+    };
+    let mut transport = NetcodeClientTransport::new(current_time, authentication, socket)?;
+
+    let started = Instant::now();
+    let mut last_update = started;
+    let mut session = Session::Connecting;
+
+    while !matches!(session, Session::Done(_)) {
+        if started.elapsed() > TIMEOUT {
+            session = Session::Done(Err(format!("timed out while {session:?}")));
+            break;
+        }
+
+        let now = Instant::now();
+        let delta = now - last_update;
+        last_update = now;
+
+        client.update(delta);
+        transport.update(delta, &mut client)?;
+
+        if client.is_connected() {
+            'receive: while let Some(batch) = client.receive_message(ServerChannel::Message) {
+                for message in decode_batch(&batch)? {
+                    match message {
+                        ServerMessage::SCHello(_) if session == Session::Connecting => {
+                            send(&mut client, &ClientMessage::CSConnectAdmin(CSConnectAdmin));
+                            send(
+                                &mut client,
+                                &ClientMessage::CSLoadAdminAccount(CSLoadAdminAccount {
+                                    name: cli.name.clone(),
+                                    password: password.clone(),
+                                }),
+                            );
+                            session = Session::LoggingIn;
+                        }
+                        ServerMessage::SCLoginAdminAccountSuccess(_)
+                            if session == Session::LoggingIn =>
+                        {
+                            send(&mut client, &cli.command.to_message());
+                            session = Session::AwaitingReply;
+                        }
+                        message => {
+                            if let Some(result) = handle_message(message) {
+                                session = Session::Done(result);
+                                break 'receive;
+                            }
+                        }
+                    }
+                }
+            }
+        } else if client.is_disconnected() {
+            session = Session::Done(Err(format!(
+                "disconnected: {:?}",
+                client.disconnect_reason()
+            )));
+            break;
+        }
+
+        transport.send_packets(&mut client)?;
+        thread::sleep(TICK);
+    }
+
+    transport.disconnect();
+
+    let Session::Done(result) = session else {
+        unreachable!();
+    };
+
+    Ok(result)
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    let Some(password) = cli
+        .password
+        .clone()
+        .or_else(|| env::var("RPG_ADMIN_PASSWORD").ok())
+    else {
+        eprintln!("no password given, use --password or set RPG_ADMIN_PASSWORD");
+        return ExitCode::FAILURE;
+    };
+
+    match run(&cli, password) {
+        Ok(Ok(())) => ExitCode::SUCCESS,
+        Ok(Err(e)) => {
+            eprintln!("{e}");
+            ExitCode::FAILURE
+        }
+        Err(e) => {
+            eprintln!("network error: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
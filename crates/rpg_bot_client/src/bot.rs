@@ -0,0 +1,660 @@
+//! A single bot, its connection and the state machine it plays the game with
+//!
+//! A bot logs into the account named after it, creating the account and a character in slot 0 on
+//! first use, joins or creates a game and then repeats the steps of its script until it is
+//! stopped. The bot does not predict anything, its hero position is the one in the newest
+//! snapshot.
+
+use crate::{
+    script::{Script, Step},
+    stats::BotStats,
+};
+
+use rpg_account::{account::Account, character::CharacterSlot};
+use rpg_chat::chat::{ChannelId, Message as ChatMessage, MessageId};
+use rpg_core::{game_mode::GameMode, skill::SkillId, uid::Uid};
+use rpg_network_protocol::{
+    advertised_features,
+    protocol::*,
+    snapshot::{Snapshot, SnapshotDelta, Tick, SNAPSHOT_HISTORY},
+    token::{request_connect_token, TokenError, TokenRequest, TokenRequestError},
+    BUILD_ID, PROTOCOL_ID, PROTOCOL_VERSION,
+};
+
+use renet::{
+    transport::{ClientAuthentication, NetcodeClientTransport},
+    ConnectionConfig, RenetClient,
+};
+
+use glam::Vec3;
+
+use std::{
+    collections::{HashMap, VecDeque},
+    error::Error,
+    net::{Ipv4Addr, SocketAddr, UdpSocket},
+    time::{Duration, Instant, SystemTime},
+};
+
+const SLOT: CharacterSlot = CharacterSlot(0);
+
+const PING_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Pings answered later than this are considered lost
+const PING_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Bots that have not spawned by then give up
+const SPAWN_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How long a dead hero waits before reviving
+const REVIVE_DELAY: Duration = Duration::from_secs(3);
+
+/// Items closer than this can be picked up
+const PICKUP_RANGE: f32 = 0.5;
+
+/// How far from the hero targeted skills are aimed
+const ATTACK_RANGE: f32 = 4.;
+
+/// Settings shared by every bot
+pub(crate) struct BotConfig {
+    pub(crate) server_addr: SocketAddr,
+    pub(crate) password: String,
+    pub(crate) unsecure: bool,
+    pub(crate) script: Script,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Phase {
+    Connecting,
+    LoggingIn,
+    CreatingAccount,
+    CreatingCharacter,
+    JoiningGame,
+    Spawning,
+    Playing,
+    Disconnected(String),
+}
+
+/// Progress through the script
+#[derive(Default)]
+struct Behaviour {
+    step: usize,
+    step_started: Option<Instant>,
+    /// The direction the hero walks in, if it is walking
+    direction: Option<Vec3>,
+    next_turn: Option<Instant>,
+    next_attack: Option<Instant>,
+    input_sequence: u32,
+}
+
+pub(crate) struct Bot {
+    pub(crate) name: String,
+    pub(crate) phase: Phase,
+    pub(crate) stats: BotStats,
+    client: RenetClient,
+    transport: NetcodeClientTransport,
+    started: Instant,
+    /// The account is created once connected, see `TokenRequest::CreateAccount`
+    create_account: bool,
+    state_sequence: StateSequence,
+    account: Option<Account>,
+    hero_uid: Option<Uid>,
+    position: Vec3,
+    died: Option<Instant>,
+    skills: Vec<SkillId>,
+    /// Items on the ground and where they are
+    items: HashMap<Uid, Vec3>,
+    snapshots: VecDeque<Snapshot>,
+    applied: Option<Tick>,
+    next_ping: u32,
+    last_ping: Option<Instant>,
+    /// Pings awaiting a pong and when they were sent
+    pending_pings: VecDeque<(u32, Instant)>,
+    behaviour: Behaviour,
+}
+
+impl Bot {
+    pub(crate) fn connect(name: String, config: &BotConfig) -> Result<Self, Box<dyn Error>> {
+        let connection_config = ConnectionConfig {
+            available_bytes_per_tick: 1024 * 1024,
+            client_channels_config: ClientChannel::channels_config(),
+            server_channels_config: ServerChannel::channels_config(),
+        };
+        let client = RenetClient::new(connection_config);
+
+        let socket = UdpSocket::bind(SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), 0))?;
+        let current_time = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH)?;
+        let mut create_account = false;
+        let authentication = if config.unsecure {
+            ClientAuthentication::Unsecure {
+                // Bots are usually started in the same millisecond, the local port tells them apart
+                client_id: current_time.as_millis() as u64 ^ socket.local_addr()?.port() as u64,
+                protocol_id: PROTOCOL_ID,
+                server_addr: config.server_addr,
+                user_data: None,
+            }
+        } else {
+            let request = TokenRequest::Player {
+                name: name.clone(),
+                password: config.password.clone(),
+            };
+            let connect_token = match request_connect_token(config.server_addr, &request) {
+                Err(TokenRequestError::Rejected(TokenError::InvalidCredentials)) => {
+                    create_account = true;
+                    let request = TokenRequest::CreateAccount { name: name.clone() };
+                    request_connect_token(config.server_addr, &request)?
+                }
+                result => result?,
+            };
+            ClientAuthentication::Secure { connect_token }
+        };
+        let transport = NetcodeClientTransport::new(current_time, authentication, socket)?;
+
+        let mut bot = Self {
+            name,
+            phase: Phase::Connecting,
+            stats: BotStats::default(),
+            client,
+            transport,
+            started: Instant::now(),
+            create_account,
+            state_sequence: StateSequence::default(),
+            account: None,
+            hero_uid: None,
+            position: Vec3::ZERO,
+            died: None,
+            skills: vec![],
+            items: HashMap::new(),
+            snapshots: VecDeque::new(),
+            applied: None,
+            next_ping: 0,
+            last_ping: None,
+            pending_pings: VecDeque::new(),
+            behaviour: Behaviour::default(),
+        };
+
+        // Queued until the transport connects, the server ignores everything sent before this
+        bot.send(&ClientMessage::CSHello(CSHello {
+            protocol_version: PROTOCOL_VERSION,
+            build_id: BUILD_ID.to_string(),
+            features: advertised_features(),
+        }));
+
+        Ok(bot)
+    }
+
+    pub(crate) fn is_disconnected(&self) -> bool {
+        matches!(self.phase, Phase::Disconnected(_))
+    }
+
+    fn send(&mut self, message: &ClientMessage) {
+        let message = bincode::serialize(message).unwrap();
+        self.client.send_message(ClientChannel::Message, message);
+        self.stats.sent += 1;
+    }
+
+    fn disconnect(&mut self, reason: String) {
+        self.stats.error(reason.clone());
+        self.phase = Phase::Disconnected(reason);
+        self.transport.disconnect();
+    }
+
+    /// Stop the bot, this is not counted as an error
+    pub(crate) fn stop(&mut self) {
+        if !self.is_disconnected() {
+            self.phase = Phase::Disconnected("stopped".into());
+            self.transport.disconnect();
+        }
+    }
+
+    /// Exchange packets with the server and act on everything received
+    pub(crate) fn update(&mut self, delta: Duration, config: &BotConfig) {
+        if self.is_disconnected() {
+            return;
+        }
+
+        self.client.update(delta);
+        if let Err(e) = self.transport.update(delta, &mut self.client) {
+            self.disconnect(format!("transport error: {e}"));
+            return;
+        }
+
+        if self.client.is_disconnected() {
+            let reason = format!("disconnected: {:?}", self.client.disconnect_reason());
+            self.disconnect(reason);
+            return;
+        }
+
+        if self.phase != Phase::Playing && self.started.elapsed() > SPAWN_TIMEOUT {
+            let reason = format!("timed out while {:?}", self.phase);
+            self.disconnect(reason);
+            return;
+        }
+
+        if self.client.is_connected() {
+            if let Err(e) = self.receive(config) {
+                self.disconnect(format!("invalid message: {e}"));
+                return;
+            }
+
+            if self.phase == Phase::Playing {
+                self.ping();
+                self.play(&config.script);
+            }
+        }
+
+        if let Err(e) = self.transport.send_packets(&mut self.client) {
+            self.disconnect(format!("transport error: {e}"));
+        }
+    }
+
+    fn receive(&mut self, config: &BotConfig) -> bincode::Result<()> {
+        let mut messages = vec![];
+
+        for channel in [ServerChannel::Message, ServerChannel::Event] {
+            while let Some(batch) = self.client.receive_message(channel) {
+                messages.extend(decode_batch(&batch)?);
+            }
+        }
+        while let Some(batch) = self.client.receive_message(ServerChannel::State) {
+            let Some((sequence, payload)) = decode_sequenced(&batch) else {
+                continue;
+            };
+            if self.state_sequence.accept(sequence) {
+                messages.extend(decode_batch(payload)?);
+            }
+        }
+
+        self.stats.received += messages.len() as u64;
+        for message in messages {
+            self.handle_message(message, config);
+            if self.is_disconnected() {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn load_account(&mut self, config: &BotConfig) {
+        if self.create_account {
+            self.create_account(config);
+            return;
+        }
+
+        self.send(&ClientMessage::CSLoadAccount(CSLoadAccount {
+            name: self.name.clone(),
+            password: config.password.clone(),
+        }));
+        self.phase = Phase::LoggingIn;
+    }
+
+    fn create_account(&mut self, config: &BotConfig) {
+        self.send(&ClientMessage::CSCreateAccount(CSCreateAccount {
+            name: self.name.clone(),
+            email: format!("{}@bots.invalid", self.name),
+            password: config.password.clone(),
+        }));
+        self.phase = Phase::CreatingAccount;
+    }
+
+    /// Use the character in `SLOT`, creating it if there is none
+    fn logged_in(&mut self, account: Account, config: &BotConfig) {
+        self.send(&ClientMessage::CSChatJoin(CSChatJoin));
+
+        let has_character = account.get_character_from_slot(SLOT).is_some();
+        self.account = Some(account);
+
+        if has_character {
+            self.join_game(config);
+        } else {
+            self.send(&ClientMessage::CSCreateCharacter(CSCreateCharacter {
+                name: self.name.clone(),
+                slot: SLOT,
+                class: config.script.class,
+                game_mode: config.script.game_mode,
+            }));
+            self.phase = Phase::CreatingCharacter;
+        }
+    }
+
+    fn join_game(&mut self, config: &BotConfig) {
+        let Some(record) = self
+            .account
+            .as_ref()
+            .and_then(|account| account.get_character_from_slot(SLOT))
+        else {
+            return;
+        };
+
+        // A game is only joined with a character of the same mode
+        let game_mode = record.info.game_mode;
+        self.hero_uid = Some(record.character.unit.uid);
+        self.skills = record
+            .character
+            .skill_slots
+            .iter()
+            .filter_map(|slot| slot.skill_id)
+            .collect();
+
+        if config.script.join_open_game {
+            self.send(&ClientMessage::CSJoinGame(CSJoinGame {
+                game_mode,
+                slot: SLOT,
+                game_id: None,
+            }));
+        } else {
+            self.create_game(game_mode);
+        }
+        self.phase = Phase::JoiningGame;
+    }
+
+    fn create_game(&mut self, game_mode: GameMode) {
+        self.send(&ClientMessage::CSCreateGame(CSCreateGame {
+            game_mode,
+            slot: SLOT,
+        }));
+    }
+
+    fn handle_message(&mut self, message: ServerMessage, config: &BotConfig) {
+        match message {
+            ServerMessage::SCHello(_) if self.phase == Phase::Connecting => {
+                self.send(&ClientMessage::CSConnectPlayer(CSConnectPlayer));
+                self.load_account(config);
+            }
+            ServerMessage::SCHelloError(msg) => {
+                self.disconnect(format!("rejected by server build {}", msg.build_id));
+            }
+            ServerMessage::SCLoginAccountSuccess(msg) if self.phase == Phase::LoggingIn => {
+                self.logged_in(msg.0, config);
+            }
+            ServerMessage::SCLoginAccountError(msg) => match msg.0 {
+                LoginError::AccountNotFound if self.phase == Phase::LoggingIn => {
+                    self.create_account(config);
+                }
+                e => self.disconnect(format!("login failed: {e:?}")),
+            },
+            ServerMessage::SCCreateAccountSuccess(msg) if self.phase == Phase::CreatingAccount => {
+                self.logged_in(msg.0, config);
+            }
+            ServerMessage::SCCreateAccountError(msg) => {
+                self.disconnect(format!("account creation failed: {:?}", msg.0));
+            }
+            ServerMessage::SCCreateCharacterSuccess(msg)
+                if self.phase == Phase::CreatingCharacter =>
+            {
+                if let Some(account) = &mut self.account {
+                    account.characters.push(msg.0);
+                }
+                self.join_game(config);
+            }
+            ServerMessage::SCCreateCharacterError(msg) => {
+                self.disconnect(format!("character creation failed: {:?}", msg.0));
+            }
+            ServerMessage::SCGameJoinError(_) if self.phase == Phase::JoiningGame => {
+                // There is no open game to join, start one instead
+                let game_mode = self
+                    .account
+                    .as_ref()
+                    .and_then(|account| account.get_character_from_slot(SLOT))
+                    .map(|record| record.info.game_mode)
+                    .unwrap_or(config.script.game_mode);
+                self.create_game(game_mode);
+            }
+            ServerMessage::SCGameCreateError(_) => {
+                self.disconnect("game creation failed".into());
+            }
+            ServerMessage::SCPlayerJoinSuccess(_) if self.phase == Phase::JoiningGame => {
+                self.send(&ClientMessage::CSClientReady(CSClientReady));
+                self.phase = Phase::Spawning;
+            }
+            ServerMessage::SCPlayerJoinError(_) => {
+                self.disconnect("player join failed".into());
+            }
+            ServerMessage::SCPlayerSpawn(msg) if self.phase == Phase::Spawning => {
+                self.position = msg.position;
+                self.stats.spawn_time = Some(self.started.elapsed());
+                self.phase = Phase::Playing;
+            }
+            ServerMessage::SCSnapshot(msg) => self.receive_snapshot(&msg.0),
+            ServerMessage::SCPong(msg) => {
+                let Some(index) = self
+                    .pending_pings
+                    .iter()
+                    .position(|(sequence, _)| *sequence == msg.sequence)
+                else {
+                    return;
+                };
+                let (_, sent) = self.pending_pings.remove(index).unwrap();
+                self.stats.latency.record(sent.elapsed().as_secs_f64());
+            }
+            ServerMessage::SCSpawnItem(SCSpawnItem { position, items })
+            | ServerMessage::SCSpawnItems(SCSpawnItems { position, items }) => {
+                for item in items.items {
+                    self.items.insert(item.uid, position);
+                }
+            }
+            ServerMessage::SCDespawnItem(msg) => {
+                self.items.remove(&msg.0);
+            }
+            ServerMessage::SCHeroDeath(msg) if Some(msg.0) == self.hero_uid => {
+                self.died = Some(Instant::now());
+            }
+            ServerMessage::SCPlayerRevive(msg) => {
+                self.position = msg.position;
+                self.died = None;
+            }
+            ServerMessage::SCChatChannelMessageError(_) => self.stats.error("chat message"),
+            ServerMessage::SCRateLimited(msg) => {
+                self.stats.error(format!("rate limited {:?}", msg.category));
+            }
+            ServerMessage::SCServerShutdown(_) => {
+                self.disconnect("server shutdown".into());
+            }
+            _ => {}
+        }
+    }
+
+    fn receive_snapshot(&mut self, delta: &SnapshotDelta) {
+        if self
+            .applied
+            .is_some_and(|applied| !delta.tick.is_newer_than(applied))
+        {
+            return;
+        }
+
+        let baseline = match delta.baseline {
+            Some(tick) => match self.snapshots.iter().find(|s| s.tick == tick) {
+                Some(baseline) => Some(baseline),
+                None => {
+                    self.stats.error("missing snapshot baseline");
+                    return;
+                }
+            },
+            None => None,
+        };
+        let Some(snapshot) = Snapshot::from_delta(delta, baseline) else {
+            self.stats.error("invalid snapshot");
+            return;
+        };
+
+        if let Some(state) = self.hero_uid.and_then(|uid| snapshot.units.get(&uid)) {
+            self.position = state.position;
+        }
+
+        self.send(&ClientMessage::CSSnapshotAck(CSSnapshotAck(snapshot.tick)));
+
+        // the server never encodes against a snapshot older than the baseline again
+        if let Some(tick) = delta.baseline {
+            self.snapshots.retain(|s| !tick.is_newer_than(s.tick));
+        }
+        if self.snapshots.len() >= SNAPSHOT_HISTORY {
+            self.snapshots.pop_front();
+        }
+        self.applied = Some(snapshot.tick);
+        self.snapshots.push_back(snapshot);
+    }
+
+    fn ping(&mut self) {
+        let now = Instant::now();
+        if self
+            .last_ping
+            .is_some_and(|last_ping| now - last_ping < PING_INTERVAL)
+        {
+            return;
+        }
+
+        let sequence = self.next_ping;
+        self.next_ping = self.next_ping.wrapping_add(1);
+        self.last_ping = Some(now);
+
+        let lost = self.pending_pings.len();
+        self.pending_pings
+            .retain(|(_, sent)| now - *sent < PING_TIMEOUT);
+        for _ in self.pending_pings.len()..lost {
+            self.stats.error("ping timeout");
+        }
+        self.pending_pings.push_back((sequence, now));
+
+        self.send(&ClientMessage::CSPing(CSPing(sequence)));
+    }
+
+    fn walk(&mut self, direction: Option<Vec3>) {
+        let sequence = self.behaviour.input_sequence;
+        match direction {
+            Some(direction) => {
+                self.send(&ClientMessage::CSMovePlayer(CSMovePlayer {
+                    sequence,
+                    direction,
+                }));
+            }
+            None if self.behaviour.direction.is_some() => {
+                self.send(&ClientMessage::CSMovePlayerEnd(CSMovePlayerEnd {
+                    sequence,
+                }));
+            }
+            None => return,
+        }
+        self.behaviour.input_sequence = sequence.wrapping_add(1);
+        self.behaviour.direction = direction;
+    }
+
+    /// Run the current step of the script
+    fn play(&mut self, script: &Script) {
+        let now = Instant::now();
+
+        if let Some(died) = self.died {
+            self.walk(None);
+            if now - died >= REVIVE_DELAY {
+                self.send(&ClientMessage::CSPlayerRevice(CSPlayerRevive));
+                // resent if the revive is lost
+                self.died = Some(now);
+            }
+            return;
+        }
+
+        if script.steps.is_empty() {
+            return;
+        }
+
+        match self.behaviour.step_started {
+            None => self.start_step(script, 0, now),
+            Some(started) => {
+                let step = &script.steps[self.behaviour.step];
+                if (now - started).as_secs_f32() >= step.secs() {
+                    let next = (self.behaviour.step + 1) % script.steps.len();
+                    self.start_step(script, next, now);
+                }
+            }
+        }
+
+        match &script.steps[self.behaviour.step] {
+            Step::Idle { .. } | Step::Chat { .. } => self.walk(None),
+            Step::Wander { turn_secs, .. } => {
+                let direction = match self.behaviour.next_turn {
+                    Some(next_turn) if now < next_turn => self.behaviour.direction,
+                    _ => {
+                        self.behaviour.next_turn =
+                            Some(now + Duration::from_secs_f32(turn_secs.max(0.1)));
+                        Some(random_direction())
+                    }
+                };
+                self.walk(direction);
+            }
+            Step::Attack { interval, .. } => {
+                self.walk(None);
+                if self.behaviour.next_attack.is_some_and(|next| now < next) {
+                    return;
+                }
+                self.behaviour.next_attack =
+                    Some(now + Duration::from_secs_f32(interval.max(0.05)));
+
+                if self.skills.is_empty() {
+                    return;
+                }
+                let skill_id = self.skills[fastrand::usize(..self.skills.len())];
+                self.send(&ClientMessage::CSSkillUseTargeted(CSSkillUseTargeted {
+                    skill_id,
+                    target: self.position + random_direction() * ATTACK_RANGE,
+                }));
+            }
+            Step::Loot { .. } => self.loot(),
+        }
+    }
+
+    fn start_step(&mut self, script: &Script, step: usize, now: Instant) {
+        self.behaviour.step = step;
+        self.behaviour.step_started = Some(now);
+        self.behaviour.next_turn = None;
+        self.behaviour.next_attack = None;
+
+        if let Step::Chat { message } = &script.steps[step] {
+            self.chat(message.clone());
+        }
+    }
+
+    /// Walk to the nearest known item and pick it up
+    fn loot(&mut self) {
+        let position = self.position;
+        let Some((uid, item_position)) = self
+            .items
+            .iter()
+            .map(|(uid, item_position)| (*uid, *item_position))
+            .min_by(|(_, a), (_, b)| a.distance(position).total_cmp(&b.distance(position)))
+        else {
+            self.walk(None);
+            return;
+        };
+
+        let offset = item_position - position;
+        let offset = Vec3::new(offset.x, 0., offset.z);
+        if offset.length() < PICKUP_RANGE {
+            self.walk(None);
+            self.send(&ClientMessage::CSItemPickup(CSItemPickup(uid)));
+            // forgotten until the server tells about it again
+            self.items.remove(&uid);
+        } else {
+            self.walk(Some(offset.normalize()));
+        }
+    }
+
+    fn chat(&mut self, message: String) {
+        let Some(sender) = self.account.as_ref().map(|account| account.info.id) else {
+            return;
+        };
+
+        self.send(&ClientMessage::CSChatChannelMessage(CSChatChannelMessage(
+            ChatMessage {
+                message,
+                id: MessageId(0),
+                channel_id: ChannelId(0),
+                sender,
+            },
+        )));
+    }
+}
+
+fn random_direction() -> Vec3 {
+    let angle = fastrand::f32() * std::f32::consts::TAU;
+
+    Vec3::new(angle.cos(), 0., angle.sin())
+}
@@ -0,0 +1,201 @@
+//! Run with
+//! - `cargo run -p rpg_bot_client -- --bots 50`
+//! - `cargo run -p rpg_bot_client -- --addr 127.0.0.1 --port 4269 --bots 200 --duration 3600`
+//! - `cargo run -p rpg_bot_client -- --bots 20 --script bots.json --spawn-interval 50`
+//!
+//! Bot `n` logs into the account `{prefix}{first + n}`, the account and a character are created
+//! the first time a bot connects. All bots share a password, it is read from `RPG_BOT_PASSWORD`
+//! unless `--password` is given. See `script` for the format of script files. A secure server only
+//! issues a few account creation tokens per address, raise `network.rate_limits.account_creation`
+//! in its config before creating many bots.
+//!
+//! A summary of every bot is printed every `--report` seconds and once all bots have stopped. The
+//! exit status is a failure if any bot disconnected before the run ended.
+
+mod bot;
+mod script;
+mod stats;
+
+use bot::{Bot, BotConfig, Phase};
+use script::Script;
+use stats::BotStats;
+
+use rpg_account::name::validate_account_name;
+use rpg_network_protocol::SERVER_PORT;
+
+use clap::Parser;
+
+use std::{
+    collections::BTreeMap,
+    env,
+    net::{Ipv4Addr, SocketAddr},
+    path::PathBuf,
+    process::ExitCode,
+    thread,
+    time::{Duration, Instant},
+};
+
+const TICK: Duration = Duration::from_millis(16);
+
+const DEFAULT_PASSWORD: &str = "bot-password";
+
+#[derive(Parser, Debug)]
+struct Cli {
+    #[arg(short, long, default_value_t = SERVER_PORT)]
+    port: u16,
+    #[arg(short, long, default_value_t = Ipv4Addr::LOCALHOST)]
+    addr: Ipv4Addr,
+    /// The number of bots to run
+    #[arg(short, long, default_value_t = 10)]
+    bots: usize,
+    /// Bot account names start with this
+    #[arg(long, default_value = "bot")]
+    prefix: String,
+    /// The number of the first bot account
+    #[arg(long, default_value_t = 0)]
+    first: usize,
+    #[arg(long)]
+    password: Option<String>,
+    /// A JSON script of what bots do once spawned, see `script`
+    #[arg(long)]
+    script: Option<PathBuf>,
+    /// Stop all bots after this many seconds, run until every bot has disconnected when omitted
+    #[arg(long)]
+    duration: Option<u64>,
+    /// Milliseconds between connecting bots, so logins are not all handled in the same frame
+    #[arg(long, default_value_t = 100)]
+    spawn_interval: u64,
+    /// Seconds between reports
+    #[arg(long, default_value_t = 10)]
+    report: u64,
+    /// Connect without a connect token, for servers running in unsecure mode
+    #[arg(long)]
+    unsecure: bool,
+}
+
+fn report(bots: &[Bot], failed: usize, elapsed: Duration) {
+    let mut phases = BTreeMap::new();
+    let mut total = BotStats::default();
+    for bot in bots {
+        let phase = match &bot.phase {
+            Phase::Disconnected(_) => "Disconnected".to_string(),
+            phase => format!("{phase:?}"),
+        };
+        *phases.entry(phase).or_insert(0) += 1;
+        total.merge(&bot.stats);
+    }
+
+    println!(
+        "[{:.0}s] {} bots {phases:?} {failed} failed to connect",
+        elapsed.as_secs_f32(),
+        bots.len(),
+    );
+    println!("  {total}");
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    for index in [cli.first, cli.first + cli.bots.saturating_sub(1)] {
+        let name = format!("{}{index}", cli.prefix);
+        if let Err(e) = validate_account_name(&name) {
+            eprintln!("invalid bot account name {name}: {e:?}");
+            return ExitCode::FAILURE;
+        }
+    }
+
+    let script = match &cli.script {
+        Some(path) => match Script::from_file(path) {
+            Ok(script) => script,
+            Err(e) => {
+                eprintln!("invalid script {}: {e}", path.display());
+                return ExitCode::FAILURE;
+            }
+        },
+        None => Script::default(),
+    };
+
+    let config = BotConfig {
+        server_addr: SocketAddr::new(cli.addr.into(), cli.port),
+        password: cli
+            .password
+            .clone()
+            .or_else(|| env::var("RPG_BOT_PASSWORD").ok())
+            .unwrap_or_else(|| DEFAULT_PASSWORD.into()),
+        unsecure: cli.unsecure,
+        script,
+    };
+
+    let started = Instant::now();
+    let duration = cli.duration.map(Duration::from_secs);
+    let spawn_interval = Duration::from_millis(cli.spawn_interval);
+    let report_interval = Duration::from_secs(cli.report.max(1));
+
+    let mut bots: Vec<Bot> = Vec::with_capacity(cli.bots);
+    let mut failed = 0;
+    let mut last_spawn: Option<Instant> = None;
+    let mut last_report = started;
+    let mut last_update = started;
+
+    loop {
+        let now = Instant::now();
+        let delta = now - last_update;
+        last_update = now;
+
+        let launched = bots.len() + failed;
+        if launched < cli.bots
+            && last_spawn.map_or(true, |last_spawn| now - last_spawn >= spawn_interval)
+        {
+            last_spawn = Some(now);
+
+            let name = format!("{}{}", cli.prefix, cli.first + launched);
+            match Bot::connect(name.clone(), &config) {
+                Ok(bot) => bots.push(bot),
+                Err(e) => {
+                    eprintln!("{name} failed to connect: {e}");
+                    failed += 1;
+                }
+            }
+        }
+
+        for bot in &mut bots {
+            let was_disconnected = bot.is_disconnected();
+            bot.update(delta, &config);
+            if !was_disconnected {
+                if let Phase::Disconnected(reason) = &bot.phase {
+                    eprintln!("{} {reason}", bot.name);
+                }
+            }
+        }
+
+        if now - last_report >= report_interval {
+            last_report = now;
+            report(&bots, failed, started.elapsed());
+        }
+
+        if duration.is_some_and(|duration| started.elapsed() >= duration) {
+            break;
+        }
+        if bots.len() + failed == cli.bots && bots.iter().all(Bot::is_disconnected) {
+            break;
+        }
+
+        thread::sleep(TICK);
+    }
+
+    let disconnected = bots.iter().filter(|bot| bot.is_disconnected()).count();
+    for bot in &mut bots {
+        bot.stop();
+    }
+
+    report(&bots, failed, started.elapsed());
+    for bot in &bots {
+        println!("  {}: {}", bot.name, bot.stats);
+    }
+
+    if failed > 0 || disconnected > 0 {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
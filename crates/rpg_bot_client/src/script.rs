@@ -0,0 +1,100 @@
+//! Bot scripts
+//!
+//! A script is a JSON file naming the character a bot plays and the steps it repeats once it has
+//! spawned, every field is optional:
+//!
+//! ```json
+//! {
+//!     "class": "Str",
+//!     "game_mode": "Normal",
+//!     "join_open_game": true,
+//!     "steps": [
+//!         { "Wander": { "secs": 10.0, "turn_secs": 2.0 } },
+//!         { "Attack": { "secs": 5.0, "interval": 0.5 } },
+//!         { "Loot": { "secs": 10.0 } },
+//!         { "Chat": { "message": "hello" } },
+//!         { "Idle": { "secs": 2.0 } }
+//!     ]
+//! }
+//! ```
+
+use rpg_core::{class::Class, game_mode::GameMode};
+
+use serde_derive::Deserialize;
+
+use std::{error::Error, fs, path::Path};
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub(crate) enum Step {
+    /// Stand still
+    Idle { secs: f32 },
+    /// Walk in a random direction, picking a new one every `turn_secs`
+    Wander { secs: f32, turn_secs: f32 },
+    /// Use a random slotted skill at a point near the hero every `interval` seconds
+    Attack { secs: f32, interval: f32 },
+    /// Walk to the nearest ground item the bot knows about and pick it up
+    Loot { secs: f32 },
+    /// Send a message to the chat channel
+    Chat { message: String },
+}
+
+impl Step {
+    /// How long the step lasts, the next step starts once it has passed
+    pub(crate) fn secs(&self) -> f32 {
+        match self {
+            Self::Idle { secs }
+            | Self::Wander { secs, .. }
+            | Self::Attack { secs, .. }
+            | Self::Loot { secs } => *secs,
+            Self::Chat { .. } => 0.,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub(crate) struct Script {
+    /// The class of characters created for bots that do not have one yet
+    pub(crate) class: Class,
+    pub(crate) game_mode: GameMode,
+    /// Join any open game and only create one when there is none
+    pub(crate) join_open_game: bool,
+    /// Repeated in order for as long as the bot is in a game
+    pub(crate) steps: Vec<Step>,
+}
+
+impl Default for Script {
+    fn default() -> Self {
+        Self {
+            class: Class::default(),
+            game_mode: GameMode::default(),
+            join_open_game: true,
+            steps: vec![
+                Step::Wander {
+                    secs: 10.,
+                    turn_secs: 2.,
+                },
+                Step::Attack {
+                    secs: 5.,
+                    interval: 0.5,
+                },
+                Step::Loot { secs: 10. },
+                Step::Chat {
+                    message: "hello".into(),
+                },
+                Step::Idle { secs: 2. },
+            ],
+        }
+    }
+}
+
+impl Script {
+    pub(crate) fn from_file(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let script: Self = serde_json::from_str(&fs::read_to_string(path)?)?;
+        if script.steps.iter().all(|step| step.secs() <= 0.) {
+            return Err("a script needs at least one step that lasts some time".into());
+        }
+
+        Ok(script)
+    }
+}
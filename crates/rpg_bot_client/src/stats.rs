@@ -0,0 +1,105 @@
+//! Per bot statistics and the reports printed from them
+
+use std::{collections::BTreeMap, fmt, time::Duration};
+
+/// Round trip times measured with pings, in seconds
+#[derive(Default, Debug, Clone, Copy)]
+pub(crate) struct Latency {
+    samples: u32,
+    total: f64,
+    min: f64,
+    max: f64,
+}
+
+impl Latency {
+    pub(crate) fn record(&mut self, rtt: f64) {
+        if self.samples == 0 {
+            self.min = rtt;
+            self.max = rtt;
+        } else {
+            self.min = self.min.min(rtt);
+            self.max = self.max.max(rtt);
+        }
+        self.samples += 1;
+        self.total += rtt;
+    }
+
+    pub(crate) fn merge(&mut self, other: &Self) {
+        if other.samples == 0 {
+            return;
+        }
+        if self.samples == 0 {
+            *self = *other;
+            return;
+        }
+
+        self.samples += other.samples;
+        self.total += other.total;
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+    }
+
+    fn mean(&self) -> Option<f64> {
+        (self.samples > 0).then(|| self.total / self.samples as f64)
+    }
+}
+
+impl fmt::Display for Latency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.mean() {
+            Some(mean) => write!(
+                f,
+                "rtt min {:.1}ms mean {:.1}ms max {:.1}ms ({} samples)",
+                self.min * 1000.,
+                mean * 1000.,
+                self.max * 1000.,
+                self.samples
+            ),
+            None => write!(f, "rtt -"),
+        }
+    }
+}
+
+#[derive(Default, Debug, Clone)]
+pub(crate) struct BotStats {
+    pub(crate) sent: u64,
+    pub(crate) received: u64,
+    pub(crate) latency: Latency,
+    /// The time from starting to connect until the hero spawned
+    pub(crate) spawn_time: Option<Duration>,
+    /// How often each kind of error happened
+    pub(crate) errors: BTreeMap<String, u32>,
+}
+
+impl BotStats {
+    pub(crate) fn error(&mut self, kind: impl Into<String>) {
+        *self.errors.entry(kind.into()).or_default() += 1;
+    }
+
+    pub(crate) fn merge(&mut self, other: &Self) {
+        self.sent += other.sent;
+        self.received += other.received;
+        self.latency.merge(&other.latency);
+        for (kind, count) in &other.errors {
+            *self.errors.entry(kind.clone()).or_default() += count;
+        }
+    }
+}
+
+impl fmt::Display for BotStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "sent {} received {} {}",
+            self.sent, self.received, self.latency
+        )?;
+        if let Some(spawn_time) = self.spawn_time {
+            write!(f, " spawned in {:.2}s", spawn_time.as_secs_f32())?;
+        }
+        for (kind, count) in &self.errors {
+            write!(f, " {kind}: {count}")?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,169 @@
+//! Remote unit interpolation
+//!
+//! Snapshot states of every unit other than the local hero are buffered with their server tick
+//! and rendered `INTERPOLATION_DELAY` ticks behind the newest snapshot, so a late snapshot has
+//! usually arrived by the time it is needed. When the buffer runs dry a moving unit keeps going
+//! along its last velocity for up to `MAX_EXTRAPOLATION` ticks.
+
+use super::{
+    animation::{AnimationState, ANIM_IDLE, ANIM_WALK},
+    player::Player,
+};
+use crate::net::clock::ServerClock;
+
+use rpg_network_protocol::snapshot::{Tick, UnitActivity, UnitState};
+
+use bevy::{
+    ecs::{
+        component::Component,
+        query::Without,
+        system::{Query, Res, ResMut, Resource},
+    },
+    math::Vec3,
+    time::Time,
+    transform::components::Transform,
+};
+
+use std::collections::VecDeque;
+
+/// How many ticks behind the newest snapshot remote units are rendered
+const INTERPOLATION_DELAY: f32 = 6.;
+
+/// How many ticks past the newest state a unit may be extrapolated
+const MAX_EXTRAPOLATION: f32 = 12.;
+
+const MAX_SAMPLES: usize = 32;
+
+/// Tracks the newest snapshot tick and the time since it arrived
+#[derive(Resource, Default, Debug)]
+pub struct InterpolationClock {
+    newest: Option<Tick>,
+    since_newest: f32,
+}
+
+impl InterpolationClock {
+    pub fn receive(&mut self, tick: Tick) {
+        if self
+            .newest
+            .map_or(true, |newest| tick.is_newer_than(newest))
+        {
+            self.newest = Some(tick);
+            self.since_newest = 0.;
+        }
+    }
+
+    /// The tick being rendered relative to the newest snapshot
+    fn render_offset(&self, tick_seconds: f32) -> f32 {
+        self.since_newest / tick_seconds - INTERPOLATION_DELAY
+    }
+}
+
+/// A rendered sample of a unit's state
+struct Sample {
+    position: Vec3,
+    direction: Vec3,
+    activity: UnitActivity,
+}
+
+/// The recent states of a remote unit, oldest first
+#[derive(Component, Default, Debug)]
+pub struct InterpolationBuffer {
+    states: VecDeque<(Tick, UnitState)>,
+}
+
+impl InterpolationBuffer {
+    pub fn push(&mut self, tick: Tick, state: UnitState) {
+        if self
+            .states
+            .back()
+            .is_some_and(|(newest, _)| !tick.is_newer_than(*newest))
+        {
+            return;
+        }
+
+        if self.states.len() >= MAX_SAMPLES {
+            self.states.pop_front();
+        }
+        self.states.push_back((tick, state));
+    }
+
+    fn sample(&self, newest: Tick, offset: f32) -> Option<Sample> {
+        let time = |tick: Tick| tick.0.wrapping_sub(newest.0) as i32 as f32;
+
+        let Some(index) = self
+            .states
+            .iter()
+            .rposition(|(tick, _)| time(*tick) <= offset)
+        else {
+            // Older than anything buffered, hold the oldest state
+            let (_, state) = self.states.front()?;
+            return Some(Sample {
+                position: state.position,
+                direction: state.direction,
+                activity: state.activity,
+            });
+        };
+
+        let (from_tick, from) = &self.states[index];
+        let Some((to_tick, to)) = self.states.get(index + 1) else {
+            // Past the newest state, extrapolate a moving unit along its last velocity
+            let mut position = from.position;
+            if from.activity == UnitActivity::Moving && index > 0 {
+                let (prev_tick, prev) = &self.states[index - 1];
+                let velocity =
+                    (from.position - prev.position) / (time(*from_tick) - time(*prev_tick));
+                let ahead = (offset - time(*from_tick)).min(MAX_EXTRAPOLATION);
+                position += velocity * ahead;
+            }
+
+            return Some(Sample {
+                position,
+                direction: from.direction,
+                activity: from.activity,
+            });
+        };
+
+        let alpha = (offset - time(*from_tick)) / (time(*to_tick) - time(*from_tick));
+        let direction = from.direction.lerp(to.direction, alpha);
+
+        Some(Sample {
+            position: from.position.lerp(to.position, alpha),
+            direction: if direction.length_squared() > 0.0001 {
+                direction
+            } else {
+                to.direction
+            },
+            activity: from.activity,
+        })
+    }
+}
+
+pub fn interpolate_units(
+    time: Res<Time>,
+    server_clock: Res<ServerClock>,
+    mut clock: ResMut<InterpolationClock>,
+    mut unit_q: Query<(&mut Transform, &mut AnimationState, &InterpolationBuffer), Without<Player>>,
+) {
+    clock.since_newest += time.delta_seconds();
+
+    let Some(newest) = clock.newest else {
+        return;
+    };
+    let offset = clock.render_offset(server_clock.tick_seconds() as f32);
+
+    for (mut transform, mut anim, buffer) in &mut unit_q {
+        let Some(sample) = buffer.sample(newest, offset) else {
+            continue;
+        };
+
+        transform.translation = sample.position;
+        transform.look_to(sample.direction, Vec3::Y);
+
+        // attack, knockback and death animations are driven by their own messages
+        match sample.activity {
+            UnitActivity::Moving if *anim != ANIM_WALK => *anim = ANIM_WALK,
+            UnitActivity::Idle if *anim == ANIM_WALK => *anim = ANIM_IDLE,
+            _ => {}
+        }
+    }
+}
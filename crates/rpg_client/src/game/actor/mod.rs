@@ -1,5 +1,6 @@
 mod actor_internal;
 pub mod animation;
+pub mod interpolation;
 pub mod metadata;
 pub mod player;
 pub mod unit;
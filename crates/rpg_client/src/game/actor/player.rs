@@ -1,3 +1,4 @@
+use super::animation::{AnimationState, ANIM_IDLE, ANIM_WALK};
 use crate::game::{
     controls::{Controls, CursorPosition},
     environment::PlayerSpotLight,
@@ -9,9 +10,9 @@ use crate::game::{
 use rpg_core::skill::SkillInfo;
 use rpg_network_protocol::protocol::*;
 use rpg_util::{
-    actions::{Action, ActionData, UnitActions},
+    actions::{movement_step, Action, ActionData, ActionKind, UnitActions},
     skill::*,
-    unit::{Hero, Villain},
+    unit::{Hero, Unit, Villain},
 };
 
 use bevy::{
@@ -20,7 +21,7 @@ use bevy::{
         change_detection::DetectChanges,
         component::Component,
         query::{With, Without},
-        system::{Query, Res, ResMut},
+        system::{Query, Res, ResMut, Resource},
     },
     gizmos::gizmos::Gizmos,
     log::info,
@@ -33,6 +34,11 @@ use bevy::{
 
 use bevy_renet::renet::RenetClient;
 
+use std::collections::VecDeque;
+
+/// Unacknowledged inputs beyond this are forgotten, the server is far behind or gone
+const MAX_PENDING_INPUTS: usize = 256;
+
 /// Marker to denote the local player in the client
 #[derive(Component)]
 pub struct Player;
@@ -164,16 +170,6 @@ pub fn input_actions(
         }*/
     }
 
-    if controls.mouse_secondary.just_pressed || controls.gamepad_a.just_pressed {
-        //actions.request(Action::new(ActionData::Move(Vec3::NEG_Z), None, true));
-        let message = bincode::serialize(&ClientMessage::CSMovePlayer(CSMovePlayer)).unwrap();
-        net_client.send_message(ClientChannel::Message, message);
-    } else if controls.mouse_secondary.just_released || controls.gamepad_a.just_released {
-        //actions.request(Action::new(ActionData::MoveEnd, None, true));
-        let message = bincode::serialize(&ClientMessage::CSMovePlayerEnd(CSMovePlayerEnd)).unwrap();
-        net_client.send_message(ClientChannel::Message, message);
-    }
-
     /*if controls.gamepad_axis_left != Vec2::ZERO {
         let atan = controls
             .gamepad_axis_left
@@ -201,6 +197,116 @@ pub fn input_actions(
     // debug!("actions: {actions:?} controls: {controls:?}");
 }
 
+/// A movement input the server has not acknowledged yet
+#[derive(Debug, Clone, Copy)]
+pub struct PendingInput {
+    pub sequence: u32,
+    /// The direction the hero was moved in, `None` if it did not move
+    pub direction: Option<Vec3>,
+}
+
+/// Movement inputs sent to the server
+///
+/// The local hero is moved as soon as an input is sent. When a snapshot disagrees with the
+/// prediction the hero is moved to the server's position and the unacknowledged inputs are
+/// replayed on top of it.
+#[derive(Resource, Default, Debug)]
+pub struct PredictedInputs {
+    next_sequence: u32,
+    moving: bool,
+    pub pending: VecDeque<PendingInput>,
+    /// The latest direction the hero turned to, sent on the next tick
+    pub rotation: Option<Vec3>,
+}
+
+impl PredictedInputs {
+    /// Forget the inputs the server applied up to and including `sequence`
+    pub fn acknowledge(&mut self, sequence: u32) {
+        self.pending
+            .retain(|input| (input.sequence.wrapping_sub(sequence) as i32) > 0);
+    }
+
+    fn push(&mut self, direction: Option<Vec3>) -> u32 {
+        let sequence = self.next_sequence;
+        self.next_sequence = sequence.wrapping_add(1);
+
+        if self.pending.len() >= MAX_PENDING_INPUTS {
+            self.pending.pop_front();
+        }
+        self.pending.push_back(PendingInput {
+            sequence,
+            direction,
+        });
+
+        sequence
+    }
+}
+
+/// Send a movement input every tick while moving and predict its outcome
+pub fn predict_movement(
+    time: Res<Time>,
+    mut net_client: ResMut<RenetClient>,
+    controls: Res<Controls>,
+    cursor_position: Res<CursorPosition>,
+    mut inputs: ResMut<PredictedInputs>,
+    mut player_q: Query<(&mut Transform, &Unit, &UnitActions, &mut AnimationState), With<Player>>,
+) {
+    let (mut transform, unit, actions, mut anim) = player_q.single_mut();
+
+    let wants_move = !controls.is_inhibited()
+        && (controls.mouse_secondary.pressed || controls.gamepad_a.pressed);
+
+    if wants_move {
+        let target = cursor_position.ground - transform.translation;
+        let direction = Vec3::new(target.x, 0., target.z).normalize_or_zero();
+
+        // The server ignores movement while attacking or knocked back
+        let blocked = direction == Vec3::ZERO
+            || actions.is_set(ActionKind::Attack)
+            || actions.is_set(ActionKind::Knockback);
+        let moved = (!blocked).then_some(direction);
+
+        let sequence = inputs.push(moved);
+        let message = bincode::serialize(&ClientMessage::CSMovePlayer(CSMovePlayer {
+            sequence,
+            direction,
+        }))
+        .unwrap();
+        net_client.send_message(ClientChannel::Message, message);
+
+        if let Some(direction) = moved {
+            transform.look_to(direction, Vec3::Y);
+            transform.translation += movement_step(unit, direction, time.delta_seconds());
+            if *anim != ANIM_WALK {
+                *anim = ANIM_WALK;
+            }
+        }
+        inputs.moving = true;
+    } else if inputs.moving {
+        let sequence = inputs.push(None);
+        let message = bincode::serialize(&ClientMessage::CSMovePlayerEnd(CSMovePlayerEnd {
+            sequence,
+        }))
+        .unwrap();
+        net_client.send_message(ClientChannel::Message, message);
+
+        if *anim == ANIM_WALK {
+            *anim = ANIM_IDLE;
+        }
+        inputs.moving = false;
+    }
+}
+
+/// Send the hero's rotation at most once per tick, the server limits rotations to its tick rate
+pub fn send_rotation(mut net_client: ResMut<RenetClient>, mut inputs: ResMut<PredictedInputs>) {
+    let Some(direction) = inputs.rotation.take() else {
+        return;
+    };
+
+    let message = bincode::serialize(&ClientMessage::CSRotPlayer(CSRotPlayer(direction))).unwrap();
+    net_client.send_message(ClientChannel::Message, message);
+}
+
 pub fn update_spotlight(
     player_q: Query<&Transform, (With<Player>, Without<SpotLight>)>,
     mut spotlight_q: Query<
@@ -5,7 +5,7 @@ use crate::{
     game::{
         actor::{
             animation::{AnimationState, ANIM_ATTACK, ANIM_IDLE},
-            player::Player,
+            player::{Player, PredictedInputs},
         },
         health_bar::{HealthBar, HealthBarFrame, HealthBarRect},
         metadata::MetadataResources,
@@ -211,7 +211,7 @@ pub fn pick_storable_items(
 }
 
 pub fn action(
-    mut net_client: ResMut<RenetClient>,
+    mut inputs: ResMut<PredictedInputs>,
     time: Res<Time>,
     metadata: Res<MetadataResources>,
     mut rng: ResMut<SharedRng>,
@@ -346,10 +346,7 @@ pub fn action(
                 transform.rotation = lerped;
             }
 
-            let message =
-                bincode::serialize(&ClientMessage::CSRotPlayer(CSRotPlayer(*wanted.forward())))
-                    .unwrap();
-            net_client.send_message(ClientChannel::Message, message);
+            inputs.rotation = Some(*wanted.forward());
 
             action.state = State::Completed;
         }
@@ -173,7 +173,7 @@ pub(crate) fn make_item_stat_string(item: &Item, metadata: &Metadata) -> String
     value
 }
 
-fn spawn_item(
+pub(crate) fn spawn_item(
     commands: &mut Commands,
     rng: &mut Rng,
     renderables: &RenderResources,
@@ -3,7 +3,7 @@
 use crate::{assets::AudioAssets, loader::plugin::OutOfGameCamera, state::AppState};
 
 use super::{
-    actor::{self, player, unit},
+    actor::{self, interpolation, player, unit},
     assets::RenderResources,
     controls::{self, Controls, CursorPosition},
     environment,
@@ -28,7 +28,7 @@ use util::{
 };
 
 use bevy::{
-    app::{App, Plugin, PostUpdate, PreUpdate, Update},
+    app::{App, FixedUpdate, Plugin, PostUpdate, PreUpdate, Update},
     audio::{AudioSink, PlaybackSettings},
     core_pipeline::{bloom::BloomSettings, core_3d::Camera3dBundle, tonemapping::Tonemapping},
     ecs::{
@@ -123,6 +123,8 @@ impl Plugin for GamePlugin {
             .init_resource::<Controls>()
             .init_resource::<CursorPosition>()
             .init_resource::<CursorItem>()
+            .init_resource::<player::PredictedInputs>()
+            .init_resource::<interpolation::InterpolationClock>()
             .init_resource::<GroundItemDrops>()
             .init_resource::<GameState>()
             .init_resource::<world::RpgWorld>()
@@ -184,6 +186,7 @@ impl Plugin for GamePlugin {
                                 .chain(),
                             background_audio,
                             unit::unit_audio,
+                            interpolation::interpolate_units.before(actor::animation::animator),
                             actor::animation::animator,
                             ui::menu::toggle_menu,
                             ui::menu::exit_button,
@@ -204,6 +207,11 @@ impl Plugin for GamePlugin {
                 )
                     .run_if(in_state(AppState::Game).and_then(is_game)),
             )
+            .add_systems(
+                FixedUpdate,
+                (player::predict_movement, player::send_rotation)
+                    .run_if(in_state(AppState::Game).and_then(is_game)),
+            )
             .add_systems(
                 PostUpdate,
                 (
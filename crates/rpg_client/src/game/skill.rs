@@ -34,7 +34,7 @@ use bevy::{
     utils::default,
 };
 
-use std::borrow::Cow;
+use std::{borrow::Cow, time::Duration};
 
 pub(crate) fn prepare_skill(
     instance_uid: InstanceUid,
@@ -238,6 +238,38 @@ pub(crate) fn prepare_skill(
     (aabb, transform, instance, mesh, material, timer)
 }
 
+/// Advance a skill that was spawned on the server `elapsed` seconds ago
+///
+/// Returns `false` when the skill has already expired on the server.
+pub(crate) fn catch_up(
+    transform: &mut Transform,
+    skill_use: &SkillUse,
+    timer: &mut Option<SkillTimer>,
+    elapsed: f32,
+) -> bool {
+    let timer = match timer {
+        Some(SkillTimer::Duration(timer)) => Some(timer),
+        Some(SkillTimer::Tickable(tickable)) => Some(&mut tickable.timer),
+        None => None,
+    };
+    if let Some(timer) = timer {
+        if elapsed >= timer.remaining_secs() {
+            return false;
+        }
+        timer.tick(Duration::from_secs_f32(elapsed));
+    }
+
+    // Orbiting and aerial projectiles follow a path that only plays out on the client
+    if let SkillInstance::Projectile(info) = &skill_use.instance {
+        if info.orbit.is_none() && info.info.aerial.is_none() {
+            transform.translation +=
+                transform.forward() * elapsed * (info.info.speed as f32 / 100.);
+        }
+    }
+
+    true
+}
+
 pub(crate) fn spawn_instance(
     commands: &mut Commands,
     aabb: Aabb3d,
@@ -60,6 +60,9 @@ struct Cli {
     pub addr: Ipv4Addr,
     #[arg(short, long, default_value_t = SERVER_PORT)]
     pub port: u16,
+    /// Connect without a connect token, for servers running in unsecure mode
+    #[arg(long)]
+    pub unsecure: bool,
 }
 
 #[derive(Component)]
@@ -180,6 +183,7 @@ fn generate_network_config(cli: &Cli) -> NetworkClientConfig {
         } else {
             Ipv4Addr::new(192, 168, 0, 102)
         },
+        unsecure: cli.unsecure,
     }
 }
 
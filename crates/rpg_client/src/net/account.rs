@@ -49,11 +49,11 @@ pub(crate) fn receive_account_create_success(
 
 pub(crate) fn receive_account_create_error(mut account_events: EventReader<ServerMessage>) {
     for event in account_events.read() {
-        let ServerMessage::SCCreateAccountError(_) = event else {
+        let ServerMessage::SCCreateAccountError(msg) = event else {
             continue;
         };
 
-        info!("account creation error");
+        info!("account creation error: {:?}", msg.0);
     }
 }
 
@@ -81,11 +81,31 @@ pub(crate) fn receive_account_login_success(
 
 pub(crate) fn receive_account_login_error(mut login_reader: EventReader<ServerMessage>) {
     for event in login_reader.read() {
-        let ServerMessage::SCLoginAccountError(_) = event else {
+        let ServerMessage::SCLoginAccountError(msg) = event else {
             continue;
         };
 
-        info!("login error");
+        info!("login error: {:?}", msg.0);
+    }
+}
+
+pub(crate) fn receive_password_change_success(mut change_reader: EventReader<ServerMessage>) {
+    for event in change_reader.read() {
+        let ServerMessage::SCChangePasswordSuccess(_) = event else {
+            continue;
+        };
+
+        info!("password change success");
+    }
+}
+
+pub(crate) fn receive_password_change_error(mut change_reader: EventReader<ServerMessage>) {
+    for event in change_reader.read() {
+        let ServerMessage::SCChangePasswordError(_) = event else {
+            continue;
+        };
+
+        info!("password change error");
     }
 }
 
@@ -124,11 +144,11 @@ pub(crate) fn receive_character_create_success(
 
 pub(crate) fn receive_character_create_error(mut create_reader: EventReader<ServerMessage>) {
     for event in create_reader.read() {
-        let ServerMessage::SCCreateCharacterError(_) = event else {
+        let ServerMessage::SCCreateCharacterError(msg) = event else {
             continue;
         };
 
-        info!("character creation error");
+        info!("character creation error: {:?}", msg.0);
     }
 }
 
@@ -144,7 +164,7 @@ pub(crate) fn receive_game_join_success(
 
         info!("game join success {msg:?}");
 
-        game_state.mode = msg.0;
+        game_state.mode = msg.game_mode;
         state.set(AppState::GameSpawn);
 
         return;
@@ -173,7 +193,7 @@ pub(crate) fn receive_game_create_success(
 
         info!("game create success {msg:?}");
 
-        game_state.mode = msg.0;
+        game_state.mode = msg.game_mode;
         state.set(AppState::GameSpawn);
 
         create_events.clear();
@@ -0,0 +1,188 @@
+//! Server clock synchronization
+//!
+//! The client pings the server every `PING_INTERVAL` seconds, each pong carries the server tick
+//! it was answered on. Assuming the answer took half of the round trip this gives the local time
+//! of that tick. Of the recent samples the one with the shortest round trip is used, it was the
+//! least delayed by queueing and resends.
+//!
+//! All local times are seconds of `Time<Real>`. The fixed timestep follows the server's tick rate
+//! so that movement inputs are sent and predicted once per server tick.
+
+use rpg_network_protocol::{protocol::*, snapshot::Tick};
+
+use bevy::{
+    ecs::{
+        event::EventReader,
+        system::{Res, ResMut, Resource},
+    },
+    log::info,
+    time::{Fixed, Real, Time},
+};
+
+use bevy_renet::renet::RenetClient;
+
+use std::{collections::VecDeque, time::Duration};
+
+const PING_INTERVAL: f64 = 1.;
+
+/// Pings answered later than this are considered lost
+const PING_TIMEOUT: f64 = 5.;
+
+const MAX_SAMPLES: usize = 8;
+
+/// A round trip measurement
+#[derive(Debug, Clone, Copy)]
+struct ClockSample {
+    rtt: f64,
+    tick: Tick,
+    /// The local time the server was at `tick`
+    local: f64,
+}
+
+#[derive(Resource, Debug)]
+pub struct ServerClock {
+    next_sequence: u32,
+    last_ping: Option<f64>,
+    /// Pings awaiting a pong and the local time they were sent
+    pending: VecDeque<(u32, f64)>,
+    samples: VecDeque<ClockSample>,
+    tick_seconds: f64,
+}
+
+impl Default for ServerClock {
+    fn default() -> Self {
+        Self {
+            next_sequence: 0,
+            last_ping: None,
+            pending: VecDeque::new(),
+            samples: VecDeque::new(),
+            // Assume the default server tick rate until the first pong
+            tick_seconds: 1. / 60.,
+        }
+    }
+}
+
+impl ServerClock {
+    /// The length of a server tick in seconds
+    pub fn tick_seconds(&self) -> f64 {
+        self.tick_seconds
+    }
+
+    fn best_sample(&self) -> Option<&ClockSample> {
+        self.samples.iter().min_by(|a, b| a.rtt.total_cmp(&b.rtt))
+    }
+
+    /// The shortest recent round trip in seconds
+    pub fn rtt(&self) -> Option<f64> {
+        self.best_sample().map(|s| s.rtt)
+    }
+
+    /// The local time the server was at `tick`, `None` until the first pong
+    pub fn tick_to_local(&self, tick: Tick) -> Option<f64> {
+        let sample = self.best_sample()?;
+        let ticks = tick.0.wrapping_sub(sample.tick.0) as i32;
+
+        Some(sample.local + ticks as f64 * self.tick_seconds)
+    }
+
+    /// The server tick estimated to be current at the local time `now`, with the fraction of the
+    /// tick elapsed
+    pub fn local_to_tick(&self, now: f64) -> Option<(Tick, f64)> {
+        let sample = self.best_sample()?;
+        let ticks = (now - sample.local) / self.tick_seconds;
+        let whole = ticks.floor();
+
+        Some((
+            Tick(sample.tick.0.wrapping_add(whole as i64 as u32)),
+            ticks - whole,
+        ))
+    }
+
+    /// Seconds since the server was at `tick`, zero for a tick that has not been reached yet
+    pub fn seconds_since(&self, tick: Tick, now: f64) -> Option<f64> {
+        self.tick_to_local(tick).map(|local| (now - local).max(0.))
+    }
+
+    /// The next ping to send, if one is due
+    fn ping(&mut self, now: f64) -> Option<CSPing> {
+        if self
+            .last_ping
+            .is_some_and(|last_ping| now - last_ping < PING_INTERVAL)
+        {
+            return None;
+        }
+
+        let sequence = self.next_sequence;
+        self.next_sequence = self.next_sequence.wrapping_add(1);
+        self.last_ping = Some(now);
+
+        self.pending.retain(|(_, sent)| now - sent < PING_TIMEOUT);
+        self.pending.push_back((sequence, now));
+
+        Some(CSPing(sequence))
+    }
+
+    fn pong(&mut self, pong: &SCPong, now: f64) {
+        let Some(index) = self.pending.iter().position(|(s, _)| *s == pong.sequence) else {
+            return;
+        };
+        let (_, sent) = self.pending.remove(index).unwrap();
+
+        if pong.tick_rate > 0. {
+            self.tick_seconds = 1. / pong.tick_rate;
+        }
+
+        let rtt = now - sent;
+        if self.samples.len() >= MAX_SAMPLES {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(ClockSample {
+            rtt,
+            tick: pong.tick,
+            local: now - rtt / 2.,
+        });
+    }
+}
+
+pub(crate) fn send_ping(
+    time: Res<Time<Real>>,
+    mut clock: ResMut<ServerClock>,
+    mut net_client: ResMut<RenetClient>,
+) {
+    if !net_client.is_connected() {
+        return;
+    }
+
+    let Some(ping) = clock.ping(time.elapsed_seconds_f64()) else {
+        return;
+    };
+
+    let message = bincode::serialize(&ClientMessage::CSPing(ping)).unwrap();
+    net_client.send_message(ClientChannel::Message, message);
+}
+
+pub(crate) fn receive_pong(
+    time: Res<Time<Real>>,
+    mut fixed_time: ResMut<Time<Fixed>>,
+    mut clock: ResMut<ServerClock>,
+    mut pong_events: EventReader<ServerMessage>,
+) {
+    for event in pong_events.read() {
+        let ServerMessage::SCPong(msg) = event else {
+            continue;
+        };
+
+        clock.pong(msg, time.elapsed_seconds_f64());
+
+        if msg.tick_rate.is_finite() && msg.tick_rate > 0. {
+            let timestep = Duration::from_secs_f64(1. / msg.tick_rate);
+            if fixed_time.timestep() != timestep {
+                info!(
+                    "fixed timestep set to the server tick rate of {}",
+                    msg.tick_rate
+                );
+                fixed_time.set_timestep(timestep);
+            }
+        }
+    }
+}
@@ -5,18 +5,20 @@ use crate::{
             animation::{
                 AnimationState, ANIM_ATTACK, ANIM_DEATH, ANIM_DEFEND, ANIM_IDLE, ANIM_WALK,
             },
-            player::Player,
+            interpolation::{InterpolationBuffer, InterpolationClock},
+            player::{Player, PredictedInputs},
             spawn_actor,
         },
         assets::RenderResources,
         controls::Controls,
         health_bar::{HealthBar, HealthBarFrame},
+        item,
         metadata::MetadataResources,
         plugin::GameState,
         skill,
         world::LoadZone,
     },
-    net::account::RpgAccount,
+    net::{account::RpgAccount, clock::ServerClock},
     state::AppState,
 };
 
@@ -25,14 +27,15 @@ use bevy::{
     ecs::{
         entity::Entity,
         event::{EventReader, EventWriter},
-        query::{With, Without},
+        query::{Has, With, Without},
         schedule::NextState,
-        system::{Commands, Query, Res, ResMut},
+        system::{Commands, Query, Res, ResMut, Resource},
     },
     hierarchy::DespawnRecursiveExt,
     log::{debug, info},
     math::Vec3,
     render::{mesh::Mesh, view::Visibility},
+    time::{Fixed, Real, Time},
     transform::components::Transform,
 };
 
@@ -45,8 +48,12 @@ use rpg_core::{
     unit::{HeroInfo, UnitInfo, UnitKind},
     value::Value,
 };
-use rpg_network_protocol::protocol::*;
+use rpg_network_protocol::{
+    protocol::*,
+    snapshot::{Snapshot, Tick, SNAPSHOT_HISTORY},
+};
 use rpg_util::{
+    actions::movement_step,
     item::{GroundItem, GroundItemDrops},
     skill::{SkillSlots, SkillUse, Skills},
     unit::{Corpse, Hero, Unit, Villain},
@@ -55,6 +62,10 @@ use rpg_util::{
 use audio_manager::plugin::AudioActions;
 use util::random::SharedRng;
 
+use bevy_renet::renet::RenetClient;
+
+use std::collections::VecDeque;
+
 pub(crate) fn receive_player_join_success(mut join_events: EventReader<ServerMessage>) {
     for event in join_events.read() {
         let ServerMessage::SCPlayerJoinSuccess(msg) = event else {
@@ -89,6 +100,9 @@ pub(crate) fn receive_player_spawn(
     mut commands: Commands,
     mut state: ResMut<NextState<AppState>>,
     renderables: Res<RenderResources>,
+    mut received_snapshots: ResMut<ReceivedSnapshots>,
+    mut predicted_inputs: ResMut<PredictedInputs>,
+    mut interpolation_clock: ResMut<InterpolationClock>,
     mut spawn_events: EventReader<ServerMessage>,
     account_q: Query<&RpgAccount>,
 ) {
@@ -99,6 +113,11 @@ pub(crate) fn receive_player_spawn(
 
         info!("spawning local player");
 
+        // snapshots from a previous game are never referenced again
+        *received_snapshots = ReceivedSnapshots::default();
+        *predicted_inputs = PredictedInputs::default();
+        *interpolation_clock = InterpolationClock::default();
+
         let account = account_q.single();
 
         let transform = Transform::from_translation(msg.position);
@@ -208,118 +227,121 @@ pub(crate) fn receive_hero_revive(
     }
 }
 
-pub(crate) fn receive_player_move(
-    mut move_events: EventReader<ServerMessage>,
-    mut player_q: Query<(&mut Transform, &mut AnimationState), With<Player>>,
-) {
-    for event in move_events.read() {
-        let ServerMessage::SCMovePlayer(msg) = event else {
-            continue;
-        };
-
-        // info!("move player {msg:?}");
-        let (mut transform, mut anim) = player_q.single_mut();
-        transform.translation = msg.0;
+/// How far the predicted position of the local hero may drift from the server's before it is
+/// corrected
+const PREDICTION_TOLERANCE: f32 = 0.05;
 
-        if *anim != ANIM_WALK {
-            *anim = ANIM_WALK;
-        }
-    }
+/// Snapshots received from the server that later snapshots may be encoded against
+#[derive(Resource, Default)]
+pub(crate) struct ReceivedSnapshots {
+    snapshots: VecDeque<Snapshot>,
+    applied: Option<Tick>,
 }
 
-pub(crate) fn receive_player_move_end(
-    mut move_events: EventReader<ServerMessage>,
-    mut player_q: Query<(&mut Transform, &mut AnimationState), With<Player>>,
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn receive_snapshot(
+    mut commands: Commands,
+    fixed_time: Res<Time<Fixed>>,
+    mut net_client: ResMut<RenetClient>,
+    mut received: ResMut<ReceivedSnapshots>,
+    mut inputs: ResMut<PredictedInputs>,
+    mut clock: ResMut<InterpolationClock>,
+    mut snapshot_events: EventReader<ServerMessage>,
+    mut unit_q: Query<(
+        Entity,
+        &mut Transform,
+        &mut Unit,
+        Option<&mut InterpolationBuffer>,
+        Has<Player>,
+    )>,
 ) {
-    for event in move_events.read() {
-        let ServerMessage::SCMovePlayerEnd(msg) = event else {
+    for event in snapshot_events.read() {
+        let ServerMessage::SCSnapshot(msg) = event else {
             continue;
         };
 
-        //info!("move player end {msg:?}");
-        let (mut transform, mut anim) = player_q.single_mut();
-        transform.translation = msg.0;
-
-        *anim = ANIM_IDLE;
-    }
-}
-
-pub(crate) fn receive_player_rotation(
-    mut rotation_events: EventReader<ServerMessage>,
-    mut player_q: Query<&mut Transform, With<Player>>,
-) {
-    for event in rotation_events.read() {
-        let ServerMessage::SCRotPlayer(msg) = event else {
+        let delta = &msg.0;
+        if received
+            .applied
+            .is_some_and(|applied| !delta.tick.is_newer_than(applied))
+        {
             continue;
-        };
-
-        // info!("rot: {msg:?}");
-        let mut transform = player_q.single_mut();
-        transform.look_to(msg.0, Vec3::Y);
-    }
-}
+        }
 
-pub(crate) fn receive_unit_move(
-    mut move_events: EventReader<ServerMessage>,
-    mut unit_q: Query<(&mut Transform, &Unit, &mut AnimationState)>,
-) {
-    for event in move_events.read() {
-        let ServerMessage::SCMoveUnit(msg) = event else {
+        let baseline = match delta.baseline {
+            Some(tick) => match received.snapshots.iter().find(|s| s.tick == tick) {
+                Some(baseline) => Some(baseline),
+                None => {
+                    debug!("missing snapshot baseline {tick:?}");
+                    continue;
+                }
+            },
+            None => None,
+        };
+        let Some(snapshot) = Snapshot::from_delta(delta, baseline) else {
+            debug!("invalid snapshot delta {:?}", delta.tick);
             continue;
         };
 
-        for (mut transform, unit, mut anim) in &mut unit_q {
-            if unit.uid != msg.uid {
-                continue;
-            }
-
-            //info!("move: {msg:?}");
-            *anim = ANIM_WALK;
-
-            transform.translation = msg.position;
+        if let Some(sequence) = delta.last_input {
+            inputs.acknowledge(sequence);
         }
-    }
-}
 
-pub(crate) fn receive_unit_move_end(
-    mut move_events: EventReader<ServerMessage>,
-    mut unit_q: Query<(&mut Transform, &Unit, &mut AnimationState)>,
-) {
-    for event in move_events.read() {
-        let ServerMessage::SCMoveUnitEnd(msg) = event else {
-            continue;
-        };
+        clock.receive(snapshot.tick);
 
-        for (mut transform, unit, mut anim) in &mut unit_q {
-            if unit.uid != msg.uid {
+        for (entity, mut transform, mut unit, buffer, is_player) in &mut unit_q {
+            let Some(state) = snapshot.units.get(&unit.uid) else {
                 continue;
-            }
+            };
 
-            //info!("move unit end: {msg:?}");
-            *anim = ANIM_IDLE;
+            if is_player {
+                // Replay the inputs the server has not applied yet on top of its position, each
+                // input moved the hero for one tick
+                let dt = fixed_time.timestep().as_secs_f32();
+                let position = inputs
+                    .pending
+                    .iter()
+                    .filter_map(|input| input.direction)
+                    .fold(state.position, |position, direction| {
+                        position + movement_step(&unit, direction, dt)
+                    });
+
+                if position.distance(transform.translation) > PREDICTION_TOLERANCE {
+                    debug!("correcting predicted position to {position:?}");
+                    transform.translation = position;
+                }
+            } else if let Some(mut buffer) = buffer {
+                buffer.push(snapshot.tick, *state);
+            } else {
+                // remote units are rendered from their buffered states, see `interpolation`
+                let mut buffer = InterpolationBuffer::default();
+                buffer.push(snapshot.tick, *state);
+                commands.entity(entity).insert(buffer);
+            }
 
-            transform.translation = msg.position;
+            let vitals = &mut unit.stats.vitals.stats;
+            if let Some(hp) = vitals.get_mut("Hp") {
+                hp.value = Value::U32(state.hp);
+            }
+            if let Some(hp_max) = vitals.get_mut("HpMax") {
+                hp_max.value = Value::U32(state.hp_max);
+            }
         }
-    }
-}
 
-pub(crate) fn receive_unit_rotation(
-    mut rotation_events: EventReader<ServerMessage>,
-    mut unit_q: Query<(&mut Transform, &Unit)>,
-) {
-    for event in rotation_events.read() {
-        let ServerMessage::SCRotUnit(msg) = event else {
-            continue;
-        };
-
-        for (mut transform, unit) in &mut unit_q {
-            if unit.uid != msg.uid {
-                continue;
-            }
+        let message =
+            bincode::serialize(&ClientMessage::CSSnapshotAck(CSSnapshotAck(snapshot.tick)))
+                .unwrap();
+        net_client.send_message(ClientChannel::Message, message);
 
-            // info!("rot unit: {msg:?}");
-            transform.look_to(msg.direction, Vec3::Y);
+        // the server never encodes against a snapshot older than the baseline again
+        if let Some(tick) = delta.baseline {
+            received.snapshots.retain(|s| !tick.is_newer_than(s.tick));
         }
+        if received.snapshots.len() >= SNAPSHOT_HISTORY {
+            received.snapshots.pop_front();
+        }
+        received.applied = Some(snapshot.tick);
+        received.snapshots.push_back(snapshot);
     }
 }
 
@@ -357,7 +379,10 @@ pub(crate) fn receive_stat_updates(
 }
 
 pub(crate) fn receive_spawn_item(
-    mut ground_items: ResMut<GroundItemDrops>,
+    mut commands: Commands,
+    metadata: Res<MetadataResources>,
+    renderables: Res<RenderResources>,
+    mut rng: ResMut<SharedRng>,
     mut spawn_reader: EventReader<ServerMessage>,
 ) {
     for event in spawn_reader.read() {
@@ -367,7 +392,17 @@ pub(crate) fn receive_spawn_item(
 
         info!("spawning item: {msg:?}");
 
-        ground_items.0.push(msg.items.clone());
+        // the unit that dropped the item may not be visible, spawn it where the server says
+        for item in &msg.items.items {
+            item::spawn_item(
+                &mut commands,
+                &mut rng.0,
+                &renderables,
+                &metadata.rpg,
+                msg.position,
+                item.clone(),
+            );
+        }
     }
 }
 
@@ -409,6 +444,8 @@ pub(crate) fn receive_despawn_item(
 
 pub(crate) fn receive_spawn_skill(
     mut commands: Commands,
+    time: Res<Time<Real>>,
+    clock: Res<ServerClock>,
     mut meshes: ResMut<Assets<Mesh>>,
     mut renderables: ResMut<RenderResources>,
     metadata: Res<MetadataResources>,
@@ -422,7 +459,7 @@ pub(crate) fn receive_spawn_skill(
         let skill_id = msg.id;
         let skill_meta = &metadata.rpg.skill.skills[&skill_id];
 
-        let (aabb, transform, instance, mesh, material, timer) = skill::prepare_skill(
+        let (aabb, mut transform, instance, mesh, material, mut timer) = skill::prepare_skill(
             msg.instance_uid,
             msg.owner_uid,
             &msg.target,
@@ -432,6 +469,15 @@ pub(crate) fn receive_spawn_skill(
             skill_id,
         );
 
+        // The skill has been live on the server for the time it took to get here
+        let elapsed = clock
+            .seconds_since(msg.tick, time.elapsed_seconds_f64())
+            .unwrap_or_default();
+        if !skill::catch_up(&mut transform, &instance, &mut timer, elapsed as f32) {
+            debug!("skill {:?} expired before it arrived", msg.instance_uid);
+            continue;
+        }
+
         skill::spawn_instance(
             &mut commands,
             aabb,
@@ -634,6 +680,8 @@ pub(crate) fn receive_damage(
 }
 
 pub(crate) fn receive_unit_attack(
+    time: Res<Time<Real>>,
+    clock: Res<ServerClock>,
     mut rng: ResMut<SharedRng>,
     metadata: Res<MetadataResources>,
     mut attack_reader: EventReader<ServerMessage>,
@@ -650,6 +698,15 @@ pub(crate) fn receive_unit_attack(
             }
 
             let skill_info = &metadata.rpg.skill.skills[&msg.skill_id];
+
+            // An attack that already finished on the server is not animated
+            let elapsed = clock
+                .seconds_since(msg.tick, time.elapsed_seconds_f64())
+                .unwrap_or_default();
+            if elapsed as f32 >= skill_info.use_duration_secs {
+                continue;
+            }
+
             let audio_key = match skill_info.info {
                 SkillInfo::Direct(_) => match rng.usize(0..2) {
                     0 => "attack_proj1",
@@ -747,6 +804,27 @@ pub(crate) fn receive_hero_death(
     }
 }
 
+pub(crate) fn receive_despawn_unit(
+    mut commands: Commands,
+    mut despawn_reader: EventReader<ServerMessage>,
+    unit_q: Query<(Entity, &Unit)>,
+) {
+    for event in despawn_reader.read() {
+        let ServerMessage::SCDespawnUnit(msg) = event else {
+            continue;
+        };
+
+        info!("despawning unit {msg:?}");
+        for (entity, unit) in &unit_q {
+            if unit.uid != msg.0 {
+                continue;
+            }
+
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
 pub(crate) fn receive_despawn_corpse(
     mut commands: Commands,
     mut despawn_reader: EventReader<ServerMessage>,
@@ -6,6 +6,7 @@ use bevy::{
     log::info,
 };
 
+use rpg_account::account::AccountId;
 use rpg_core::game_mode::GameMode;
 use rpg_lobby::lobby::{LobbyId, LobbyMessage, LobbyPlayer};
 use rpg_network_protocol::protocol::*;
@@ -14,6 +15,7 @@ pub(crate) struct LobbyInfo {
     pub(crate) id: LobbyId,
     pub(crate) name: String,
     pub(crate) game_mode: GameMode,
+    pub(crate) owner: AccountId,
     pub(crate) players: Vec<LobbyPlayer>,
     pub(crate) messages: Vec<LobbyMessage>,
 }
@@ -42,6 +44,7 @@ pub(crate) fn receive_join_success(
             id: msg.0.id,
             name: msg.0.name.clone(),
             game_mode: msg.0.game_mode,
+            owner: msg.0.owner,
             players: msg.0.players.clone(),
             messages: msg.0.messages.clone(),
         });
@@ -80,6 +83,7 @@ pub(crate) fn receive_create_success(
             id: msg.0.id,
             name: msg.0.name.clone(),
             game_mode: msg.0.game_mode,
+            owner: msg.0.owner,
             players: msg.0.players.clone(),
             messages: vec![],
         });
@@ -149,3 +153,55 @@ pub(crate) fn receive_lobby_message(
         });
     }
 }
+
+pub(crate) fn receive_lobby_update(
+    mut lobby: ResMut<Lobby>,
+    mut update_events: EventReader<ServerMessage>,
+) {
+    for event in update_events.read() {
+        let ServerMessage::SCLobbyUpdate(msg) = event else {
+            continue;
+        };
+
+        // updates are only sent to a lobby's players, players returning from a game started by a
+        // lobby are put back into it with an update
+        match &mut lobby.0 {
+            Some(lobby) if lobby.id == msg.0.id => {
+                lobby.owner = msg.0.owner;
+                lobby.players = msg.0.players.clone();
+            }
+            _ => {
+                info!("returned to lobby {:?}", msg.0.id);
+
+                lobby.0 = Some(LobbyInfo {
+                    id: msg.0.id,
+                    name: msg.0.name.clone(),
+                    game_mode: msg.0.game_mode,
+                    owner: msg.0.owner,
+                    players: msg.0.players.clone(),
+                    messages: msg.0.messages.clone(),
+                });
+            }
+        }
+    }
+}
+
+pub(crate) fn receive_ready_error(mut ready_events: EventReader<ServerMessage>) {
+    for event in ready_events.read() {
+        let ServerMessage::SCLobbyReadyError(_) = event else {
+            continue;
+        };
+
+        info!("lobby ready error");
+    }
+}
+
+pub(crate) fn receive_start_error(mut start_events: EventReader<ServerMessage>) {
+    for event in start_events.read() {
+        let ServerMessage::SCLobbyStartError(msg) = event else {
+            continue;
+        };
+
+        info!("lobby start error: {:?}", msg.0);
+    }
+}
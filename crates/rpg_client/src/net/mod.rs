@@ -1,5 +1,6 @@
 pub(crate) mod account;
 pub(crate) mod chat;
+pub(crate) mod clock;
 pub(crate) mod game;
 pub(crate) mod lobby;
 pub(crate) mod plugin;
@@ -1,4 +1,4 @@
-use super::{account, chat, game, lobby};
+use super::{account, chat, clock, game, lobby};
 use crate::state::AppState;
 
 use bevy::{
@@ -6,19 +6,25 @@ use bevy::{
     ecs::{
         event::{EventReader, EventWriter},
         schedule::{common_conditions::*, Condition, IntoSystemConfigs},
-        system::{Res, ResMut, Resource},
+        system::{Commands, Local, Res, ResMut, Resource},
         world::{FromWorld, World},
     },
     log::info,
     prelude::{Deref, DerefMut},
+    tasks::{block_on, futures_lite::future, AsyncComputeTaskPool, Task},
     time::{Fixed, Time, Timer, TimerMode},
 };
 
-use rpg_network_protocol::{protocol::*, PROTOCOL_ID};
+use rpg_network_protocol::{
+    advertised_features,
+    protocol::*,
+    token::{request_connect_token, TokenRequest, TokenRequestError},
+    BUILD_ID, PROTOCOL_ID, PROTOCOL_VERSION,
+};
 
 use bevy_renet::{
     renet::{
-        transport::{ClientAuthentication, NetcodeClientTransport},
+        transport::{ClientAuthentication, ConnectToken, NetcodeClientTransport},
         ConnectionConfig, RenetClient,
     },
     transport::NetcodeClientPlugin,
@@ -28,12 +34,33 @@ use bevy_renet::{
 use std::net::{Ipv4Addr, SocketAddr, UdpSocket};
 use std::time::SystemTime;
 
-#[derive(Debug, Clone)]
+#[derive(Resource, Debug, Clone)]
 pub struct NetworkClientConfig {
     pub client_seed: u64,
     pub client_port: u16,
     pub server_addr: Ipv4Addr,
     pub server_port: u16,
+    /// Connect immediately without a connect token, for servers running in unsecure mode
+    pub unsecure: bool,
+}
+
+impl NetworkClientConfig {
+    pub fn server_socket_addr(&self) -> SocketAddr {
+        SocketAddr::new(self.server_addr.into(), self.server_port)
+    }
+
+    fn create_transport(&self, authentication: ClientAuthentication) -> NetcodeClientTransport {
+        let socket = UdpSocket::bind(SocketAddr::new(
+            Ipv4Addr::UNSPECIFIED.into(),
+            self.client_port,
+        ))
+        .unwrap();
+        let current_time = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap();
+
+        NetcodeClientTransport::new(current_time, authentication, socket).unwrap()
+    }
 }
 
 pub struct NetworkClientPlugin {
@@ -42,41 +69,43 @@ pub struct NetworkClientPlugin {
 
 impl Plugin for NetworkClientPlugin {
     fn build(&self, app: &mut App) {
-        let server_addr = SocketAddr::new(self.config.server_addr.into(), self.config.server_port);
-
         let connection_config = ConnectionConfig {
             available_bytes_per_tick: 1024 * 1024,
             client_channels_config: ClientChannel::channels_config(),
             server_channels_config: ServerChannel::channels_config(),
         };
 
-        let client = RenetClient::new(connection_config);
+        let mut client = RenetClient::new(connection_config);
 
-        info!("connecting to {server_addr:?}");
-        let socket = UdpSocket::bind(SocketAddr::new(
-            Ipv4Addr::UNSPECIFIED.into(),
-            self.config.client_port,
-        ))
-        .unwrap();
-        let current_time = SystemTime::now()
-            .duration_since(SystemTime::UNIX_EPOCH)
-            .unwrap();
-        let client_id = current_time.as_millis() as u64;
-        let authentication = ClientAuthentication::Unsecure {
-            client_id,
-            protocol_id: PROTOCOL_ID,
-            server_addr,
-            user_data: None,
-        };
+        // In secure mode the transport is created once a connect token has been issued
+        if self.config.unsecure {
+            let server_addr = self.config.server_socket_addr();
+            info!("connecting to {server_addr:?} without a connect token");
+
+            let current_time = SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap();
+            let authentication = ClientAuthentication::Unsecure {
+                client_id: current_time.as_millis() as u64,
+                protocol_id: PROTOCOL_ID,
+                server_addr,
+                user_data: None,
+            };
 
-        let transport = NetcodeClientTransport::new(current_time, authentication, socket).unwrap();
+            send_hello(&mut client);
+            app.insert_resource(self.config.create_transport(authentication));
+        }
 
         app.add_event::<ServerMessage>()
             .add_plugins(NetcodeClientPlugin)
             .add_plugins(RenetClientPlugin)
             .add_event::<ServerMessage>()
             .insert_resource(client)
-            .insert_resource(transport)
+            .insert_resource(self.config.clone())
+            .init_resource::<Handshake>()
+            .init_resource::<game::ReceivedSnapshots>()
+            .init_resource::<clock::ServerClock>()
+            // The server's default tick rate, replaced by the actual rate once a pong arrives
             .insert_resource(Time::<Fixed>::from_seconds(1.0 / 60.))
             .init_resource::<ConnectionTimer>()
             .add_systems(Startup, connect)
@@ -84,12 +113,18 @@ impl Plugin for NetworkClientPlugin {
                 Update,
                 (
                     connect,
+                    poll_connect_token,
                     receive_server_hello,
+                    receive_server_message,
+                    receive_server_shutdown,
+                    receive_rate_limited,
                     (
                         account::receive_account_create_success,
                         account::receive_account_create_error,
                         account::receive_account_login_success,
                         account::receive_account_login_error,
+                        account::receive_password_change_success,
+                        account::receive_password_change_error,
                         account::receive_character_create_success,
                         account::receive_character_create_error,
                         account::receive_game_create_success,
@@ -107,6 +142,9 @@ impl Plugin for NetworkClientPlugin {
                             lobby::receive_create_success,
                             lobby::receive_create_error,
                             lobby::receive_lobby_message,
+                            lobby::receive_lobby_update,
+                            lobby::receive_ready_error,
+                            lobby::receive_start_error,
                         ),
                         (
                             chat::receive_join_success,
@@ -118,6 +156,8 @@ impl Plugin for NetworkClientPlugin {
                     )
                         .run_if(in_state(AppState::Menu).or_else(in_state(AppState::Game))),
                     (
+                        clock::send_ping,
+                        clock::receive_pong,
                         game::receive_player_spawn,
                         game::receive_player_join_success,
                         game::receive_player_join_error,
@@ -135,6 +175,7 @@ impl Plugin for NetworkClientPlugin {
                     sync_client,
                     (
                         game::receive_despawn_corpse,
+                        game::receive_despawn_unit,
                         game::receive_despawn_item,
                         game::receive_despawn_skill,
                         game::receive_spawn_item,
@@ -157,14 +198,7 @@ impl Plugin for NetworkClientPlugin {
                     game::receive_damage,
                     game::receive_stat_updates,
                     game::receive_stat_update,
-                    (
-                        game::receive_player_rotation,
-                        game::receive_player_move,
-                        game::receive_player_move_end.after(game::receive_player_move),
-                        game::receive_unit_rotation,
-                        game::receive_unit_move,
-                        game::receive_unit_move_end.after(game::receive_unit_move),
-                    ),
+                    game::receive_snapshot,
                     (
                         game::receive_unit_attack,
                         game::receive_unit_anim,
@@ -179,6 +213,92 @@ impl Plugin for NetworkClientPlugin {
     }
 }
 
+/// The state of the handshake with the server
+#[derive(Resource, Default, Debug, Clone, PartialEq)]
+pub enum Handshake {
+    #[default]
+    Pending,
+    Accepted {
+        build_id: String,
+        /// The optional features supported by both sides
+        features: Vec<String>,
+    },
+    /// The server is incompatible with this build
+    Rejected(String),
+}
+
+/// Queue the hello, this must be the first message sent on a new transport
+fn send_hello(net_client: &mut RenetClient) {
+    let message = bincode::serialize(&ClientMessage::CSHello(CSHello {
+        protocol_version: PROTOCOL_VERSION,
+        build_id: BUILD_ID.to_string(),
+        features: advertised_features(),
+    }))
+    .unwrap();
+    net_client.send_message(ClientChannel::Message, message);
+}
+
+/// A connect token request running on the async compute pool, see `request_connect`
+#[derive(Resource)]
+pub struct PendingConnect {
+    task: Task<Result<ConnectToken, TokenRequestError>>,
+    /// Sent once the transport has been created
+    message: ClientMessage,
+}
+
+/// Request a connect token from the server and connect with it once issued, `message` is sent
+/// after the hello
+///
+/// The token issuer may take a few seconds to reply so the request runs as a task, see
+/// `poll_connect_token`.
+pub fn request_connect(
+    commands: &mut Commands,
+    config: &NetworkClientConfig,
+    request: TokenRequest,
+    message: ClientMessage,
+) {
+    let server_addr = config.server_socket_addr();
+    let task = AsyncComputeTaskPool::get()
+        .spawn(async move { request_connect_token(server_addr, &request) });
+
+    commands.insert_resource(PendingConnect { task, message });
+}
+
+fn poll_connect_token(
+    mut commands: Commands,
+    mut net_client: ResMut<RenetClient>,
+    config: Res<NetworkClientConfig>,
+    pending: Option<ResMut<PendingConnect>>,
+) {
+    let Some(mut pending) = pending else {
+        return;
+    };
+    let Some(result) = block_on(future::poll_once(&mut pending.task)) else {
+        return;
+    };
+
+    commands.remove_resource::<PendingConnect>();
+
+    let server_addr = config.server_socket_addr();
+    let connect_token = match result {
+        Ok(connect_token) => connect_token,
+        Err(e) => {
+            info!("unable to connect to {server_addr:?}: {e}");
+            return;
+        }
+    };
+
+    info!("connecting to {server_addr:?}");
+    send_hello(&mut net_client);
+    net_client.send_message(
+        ClientChannel::Message,
+        bincode::serialize(&pending.message).unwrap(),
+    );
+
+    commands
+        .insert_resource(config.create_transport(ClientAuthentication::Secure { connect_token }));
+}
+
 #[derive(Resource, Deref, DerefMut)]
 pub struct ConnectionTimer(pub Timer);
 
@@ -189,12 +309,26 @@ impl FromWorld for ConnectionTimer {
 }
 
 fn sync_client(
+    mut state_sequence: Local<StateSequence>,
     mut net_client: ResMut<RenetClient>,
     mut message_writer: EventWriter<ServerMessage>,
 ) {
-    while let Some(message) = net_client.receive_message(ServerChannel::Message) {
-        let server_message: ServerMessage = bincode::deserialize(&message).unwrap();
-        message_writer.send(server_message);
+    for channel in [ServerChannel::Message, ServerChannel::Event] {
+        while let Some(message) = net_client.receive_message(channel) {
+            message_writer.send_batch(decode_batch(&message).unwrap());
+        }
+    }
+
+    while let Some(message) = net_client.receive_message(ServerChannel::State) {
+        let Some((sequence, payload)) = decode_sequenced(&message) else {
+            continue;
+        };
+        // State superseded by a newer update is never applied
+        if !state_sequence.accept(sequence) {
+            continue;
+        }
+
+        message_writer.send_batch(decode_batch(payload).unwrap());
     }
 }
 
@@ -226,7 +360,69 @@ fn connect(
     }
 }
 
-// FIXME Move or remove this
-fn receive_server_hello(_net_client: Res<RenetClient>, _hello_events: EventReader<ServerMessage>) {
-    // TODO use this to disallow login/creation?
+fn receive_server_hello(
+    mut net_client: ResMut<RenetClient>,
+    mut handshake: ResMut<Handshake>,
+    mut hello_events: EventReader<ServerMessage>,
+) {
+    for event in hello_events.read() {
+        match event {
+            ServerMessage::SCHello(msg) => {
+                info!(
+                    "connected to server build {} with features {:?}",
+                    msg.build_id, msg.features
+                );
+                *handshake = Handshake::Accepted {
+                    build_id: msg.build_id.clone(),
+                    features: msg.features.clone(),
+                };
+            }
+            ServerMessage::SCHelloError(msg) => {
+                info!("rejected by server build {}: {}", msg.build_id, msg.reason);
+                *handshake = Handshake::Rejected(msg.reason.clone());
+                net_client.disconnect();
+            }
+            _ => {}
+        }
+    }
+}
+
+fn receive_server_message(mut server_events: EventReader<ServerMessage>) {
+    for event in server_events.read() {
+        let ServerMessage::SCServerMessage(msg) = event else {
+            continue;
+        };
+
+        // TODO display server messages in the ui
+        info!("server message: {}", msg.0);
+    }
+}
+
+fn receive_server_shutdown(mut server_events: EventReader<ServerMessage>) {
+    for event in server_events.read() {
+        let ServerMessage::SCServerShutdown(msg) = event else {
+            continue;
+        };
+
+        // TODO display the countdown in the ui
+        info!(
+            "server shutting down in {} seconds: {}",
+            msg.countdown, msg.reason
+        );
+    }
+}
+
+fn receive_rate_limited(mut server_events: EventReader<ServerMessage>) {
+    for event in server_events.read() {
+        let ServerMessage::SCRateLimited(msg) = event else {
+            continue;
+        };
+
+        // TODO display rate limit notices in the ui
+        if msg.muted {
+            info!("muted for sending {:?} messages too quickly", msg.category);
+        } else {
+            info!("sending {:?} messages too quickly", msg.category);
+        }
+    }
 }
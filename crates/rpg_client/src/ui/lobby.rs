@@ -55,10 +55,10 @@ pub(crate) struct LobbyMessages;
 pub(crate) struct LobbyMessageButton;
 
 #[derive(Component)]
-pub(crate) struct GameCreateButton;
+pub(crate) struct GameStartButton;
 
 #[derive(Component)]
-pub(crate) struct GameJoinButton;
+pub(crate) struct ReadyButton;
 
 #[derive(Component)]
 pub(crate) struct LeaveButton;
@@ -273,18 +273,17 @@ pub(crate) fn spawn(
                 ..default()
             })
             .with_children(|p| {
-                p.spawn((button.clone(), GameCreateButton))
-                    .with_children(|p| {
-                        p.spawn(TextBundle::from_section(
-                            "Create Game",
-                            ui_theme.text_style_regular.clone(),
-                        ));
-                    });
+                p.spawn((button.clone(), ReadyButton)).with_children(|p| {
+                    p.spawn(TextBundle::from_section(
+                        "Ready",
+                        ui_theme.text_style_regular.clone(),
+                    ));
+                });
 
-                p.spawn((button.clone(), GameJoinButton))
+                p.spawn((button.clone(), GameStartButton))
                     .with_children(|p| {
                         p.spawn(TextBundle::from_section(
-                            "Join Game",
+                            "Start Game",
                             ui_theme.text_style_regular.clone(),
                         ));
                     });
@@ -360,16 +359,10 @@ pub(crate) fn lobby_send_message(
     }
 }
 
-pub(crate) fn game_create_button(
-    selected_character: Res<SelectedCharacter>,
+pub(crate) fn game_start_button(
     lobby: Res<Lobby>,
     mut net_client: ResMut<RenetClient>,
-    mut account_q: Query<&mut RpgAccount>,
-    button_q: Query<&Interaction, (Changed<Interaction>, With<GameCreateButton>)>,
-    mut menu_set: ParamSet<(
-        Query<&mut Style, With<UiRoot>>,
-        Query<&mut Style, With<LobbyRoot>>,
-    )>,
+    button_q: Query<&Interaction, (Changed<Interaction>, With<GameStartButton>)>,
 ) {
     let interaction = button_q.get_single();
     if let Ok(Interaction::Pressed) = interaction {
@@ -378,37 +371,20 @@ pub(crate) fn game_create_button(
             return;
         };
 
-        let Some(slot_character) = &selected_character.0 else {
-            info!("no character selected");
-            return;
-        };
-
-        let message = bincode::serialize(&ClientMessage::CSCreateGame(CSCreateGame {
-            game_mode: lobby.game_mode,
-            slot: slot_character.slot,
-        }))
-        .unwrap();
+        let message =
+            bincode::serialize(&ClientMessage::CSLobbyStartGame(CSLobbyStartGame(lobby.id)))
+                .unwrap();
 
         net_client.send_message(ClientChannel::Message, message);
-
-        // FIXME temp hack
-        account_q.single_mut().0.info.selected_slot = Some(slot_character.slot);
-
-        menu_set.p0().single_mut().display = Display::None;
-        menu_set.p1().single_mut().display = Display::None;
     }
 }
 
-pub(crate) fn game_join_button(
+pub(crate) fn ready_button(
     selected_character: Res<SelectedCharacter>,
     lobby: Res<Lobby>,
     mut net_client: ResMut<RenetClient>,
     mut account_q: Query<&mut RpgAccount>,
-    button_q: Query<&Interaction, (Changed<Interaction>, With<GameJoinButton>)>,
-    mut menu_set: ParamSet<(
-        Query<&mut Style, With<UiRoot>>,
-        Query<&mut Style, With<LobbyRoot>>,
-    )>,
+    button_q: Query<&Interaction, (Changed<Interaction>, With<ReadyButton>)>,
 ) {
     let interaction = button_q.get_single();
     if let Ok(Interaction::Pressed) = interaction {
@@ -417,27 +393,48 @@ pub(crate) fn game_join_button(
             return;
         };
 
-        let Some(slot_character) = &selected_character.0 else {
-            info!("no character selected");
-            return;
+        let mut account = account_q.single_mut();
+        let is_ready = lobby
+            .players
+            .iter()
+            .any(|p| p.account_id == account.0.info.id && p.is_ready());
+
+        let slot = if is_ready {
+            None
+        } else {
+            let Some(slot_character) = &selected_character.0 else {
+                info!("no character selected");
+                return;
+            };
+
+            // FIXME temp hack
+            account.0.info.selected_slot = Some(slot_character.slot);
+
+            Some(slot_character.slot)
         };
 
-        let message = bincode::serialize(&ClientMessage::CSJoinGame(CSJoinGame {
-            game_mode: lobby.game_mode,
-            slot: slot_character.slot,
+        let message = bincode::serialize(&ClientMessage::CSLobbyReady(CSLobbyReady {
+            id: lobby.id,
+            slot,
         }))
         .unwrap();
 
         net_client.send_message(ClientChannel::Message, message);
-
-        // FIXME temp hack
-        account_q.single_mut().0.info.selected_slot = Some(slot_character.slot);
-
-        menu_set.p0().single_mut().display = Display::None;
-        menu_set.p1().single_mut().display = Display::None;
     }
 }
 
+pub(crate) fn hide_lobby(
+    mut menu_set: ParamSet<(
+        Query<&mut Style, With<UiRoot>>,
+        Query<&mut Style, With<LobbyRoot>>,
+    )>,
+) {
+    // members are moved into the game by the lobby owner, so the menus are hidden here rather
+    // than by a button
+    menu_set.p0().single_mut().display = Display::None;
+    menu_set.p1().single_mut().display = Display::None;
+}
+
 pub(crate) fn leave_button(
     mut net_client: ResMut<RenetClient>,
     button_q: Query<&Interaction, (Changed<Interaction>, With<LeaveButton>)>,
@@ -529,8 +526,16 @@ pub(crate) fn update_lobby(
                     ..default()
                 })
                 .with_children(|p| {
+                    let mut name = player.account_name.clone();
+                    if player.account_id == lobby.owner {
+                        name.push_str(" (owner)");
+                    }
+                    if player.is_ready() {
+                        name.push_str(" (ready)");
+                    }
+
                     p.spawn(TextBundle::from_section(
-                        player.account_name.clone(),
+                        name,
                         ui_theme.text_style_regular.clone(),
                     ));
                 })
@@ -1,6 +1,11 @@
+#![allow(clippy::too_many_arguments)]
+
 use crate::{
     assets::TextureAssets,
-    net::account::RpgAccount,
+    net::{
+        account::RpgAccount,
+        plugin::{request_connect, NetworkClientConfig, PendingConnect},
+    },
     ui::{
         lobby::LobbyRoot,
         menu::{create::CreateRoot, main::MainRoot},
@@ -11,14 +16,14 @@ use ui_util::{style::UiTheme, widgets::EditText};
 
 use rpg_account::character::{CharacterInfo, CharacterSlot};
 use rpg_lobby::lobby::LobbyId;
-use rpg_network_protocol::protocol::*;
+use rpg_network_protocol::{protocol::*, token::TokenRequest};
 
 use bevy::{
     ecs::{
         change_detection::{DetectChanges, Ref},
         component::Component,
         query::{Changed, With},
-        system::{ParamSet, Query, Res, ResMut, Resource},
+        system::{Commands, ParamSet, Query, Res, ResMut, Resource},
     },
     hierarchy::{BuildChildren, ChildBuilder},
     log::*,
@@ -31,7 +36,7 @@ use bevy::{
     utils::default,
 };
 
-use bevy_renet::renet::RenetClient;
+use bevy_renet::renet::{transport::NetcodeClientTransport, RenetClient};
 
 #[derive(Component)]
 pub struct AccountCreateRoot;
@@ -594,7 +599,11 @@ pub fn spawn_list(
 }
 
 pub fn create_button(
+    mut commands: Commands,
     mut net_client: ResMut<RenetClient>,
+    net_config: Res<NetworkClientConfig>,
+    transport: Option<Res<NetcodeClientTransport>>,
+    pending: Option<Res<PendingConnect>>,
     interaction_q: Query<&Interaction, (Changed<Interaction>, With<CreateButton>)>,
     mut account_text_set: ParamSet<(
         Query<&Text, With<CreateName>>,
@@ -626,22 +635,36 @@ pub fn create_button(
             continue;
         }
 
+        if pending.is_some() {
+            info!("account create: already connecting, skipping");
+            continue;
+        }
+
         // TODO some basic validation of input
         // TODO hash the users password
-        let message = bincode::serialize(&ClientMessage::CSCreateAccount(CSCreateAccount {
-            name,
+        let message = ClientMessage::CSCreateAccount(CSCreateAccount {
+            name: name.clone(),
             email,
             password,
-        }))
-        .unwrap();
+        });
 
-        net_client.send_message(ClientChannel::Message, message);
+        if transport.is_none() {
+            let request = TokenRequest::CreateAccount { name };
+            request_connect(&mut commands, &net_config, request, message);
+            continue;
+        }
+
+        net_client.send_message(ClientChannel::Message, bincode::serialize(&message).unwrap());
         info!("sending create account message");
     }
 }
 
 pub fn login_button(
+    mut commands: Commands,
     mut net_client: ResMut<RenetClient>,
+    net_config: Res<NetworkClientConfig>,
+    transport: Option<Res<NetcodeClientTransport>>,
+    pending: Option<Res<PendingConnect>>,
     account_q: Query<&RpgAccount>,
     interaction_q: Query<&Interaction, (Changed<Interaction>, With<LoginButton>)>,
     mut account_text_set: ParamSet<(
@@ -666,13 +689,23 @@ pub fn login_button(
             let name = account_text_set.p0().single().sections[0].value.clone();
             let password = account_text_set.p1().single().sections[0].value.clone();
 
-            let message = bincode::serialize(&ClientMessage::CSLoadAccount(CSLoadAccount {
-                name,
-                password,
-            }))
-            .unwrap();
+            if pending.is_some() {
+                info!("login: already connecting, skipping");
+                continue;
+            }
+
+            let message = ClientMessage::CSLoadAccount(CSLoadAccount {
+                name: name.clone(),
+                password: password.clone(),
+            });
+
+            if transport.is_none() {
+                let request = TokenRequest::Player { name, password };
+                request_connect(&mut commands, &net_config, request, message);
+                continue;
+            }
 
-            net_client.send_message(ClientChannel::Message, message);
+            net_client.send_message(ClientChannel::Message, bincode::serialize(&message).unwrap());
         }
     }
 }
@@ -826,6 +859,7 @@ pub fn list_join_game_button(
         let message = bincode::serialize(&ClientMessage::CSJoinGame(CSJoinGame {
             game_mode: character_record.info.game_mode,
             slot: selected_character.slot,
+            game_id: None,
         }))
         .unwrap();
 
@@ -1,5 +1,6 @@
 use crate::{
     assets::TextureAssets,
+    net::plugin::Handshake,
     state::AppState,
     ui::menu::{
         account::{AccountCreateRoot, AccountLoginRoot},
@@ -15,14 +16,16 @@ use ui_util::{
 
 use bevy::{
     ecs::{
+        change_detection::DetectChanges,
         component::Component,
         query::{Changed, With},
         schedule::NextState,
-        system::{ParamSet, Query, ResMut},
+        system::{ParamSet, Query, Res, ResMut},
     },
     hierarchy::{BuildChildren, ChildBuilder},
     render::color::Color,
     sprite::{BorderRect, ImageScaleMode, SliceScaleMode, TextureSlicer},
+    text::Text,
     ui::{
         node_bundles::{AtlasImageBundle, ButtonBundle, ImageBundle, NodeBundle, TextBundle},
         AlignItems, AlignSelf, BackgroundColor, Display, Interaction, JustifyContent, Style,
@@ -34,6 +37,10 @@ use bevy::{
 #[derive(Component)]
 pub struct MainRoot;
 
+/// Explains why the server refused the connection
+#[derive(Component)]
+pub struct ConnectionStatusText;
+
 #[derive(Component)]
 pub struct ExitButton;
 
@@ -128,6 +135,12 @@ pub fn spawn(
                 ..default()
             });
 
+            p.spawn((
+                ConnectionStatusText,
+                TextBundle::from_section("", ui_theme.text_style_regular.clone())
+                    .with_style(ui_theme.row_style.clone()),
+            ));
+
             p.spawn(NodeBundle {
                 style: ui_theme.vertical_spacer.clone(),
                 ..default()
@@ -270,6 +283,21 @@ pub fn spawn(
         });
 }
 
+pub fn display_connection_status(
+    handshake: Res<Handshake>,
+    mut status_q: Query<&mut Text, With<ConnectionStatusText>>,
+) {
+    if !handshake.is_changed() {
+        return;
+    }
+
+    let Handshake::Rejected(reason) = &*handshake else {
+        return;
+    };
+
+    status_q.single_mut().sections[0].value = format!("Unable to connect: {reason}");
+}
+
 pub fn exit_button(
     mut state: ResMut<NextState<AppState>>,
     interaction_q: Query<&Interaction, (Changed<Interaction>, With<ExitButton>)>,
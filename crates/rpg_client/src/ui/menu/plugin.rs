@@ -1,10 +1,11 @@
 use crate::{
     assets::TextureAssets,
     loader::plugin::OutOfGameCamera,
-    net::account::RpgAccount,
+    net::{account::RpgAccount, lobby::Lobby},
     state::AppState,
     ui::{
-        chat, lobby,
+        chat,
+        lobby::{self, LobbyRoot},
         menu::{self, account::AccountListRoot, main::MainRoot},
     },
 };
@@ -26,7 +27,7 @@ use bevy::{
     ecs::{
         entity::Entity,
         query::With,
-        schedule::{common_conditions::in_state, IntoSystemConfigs, NextState, OnEnter},
+        schedule::{common_conditions::in_state, IntoSystemConfigs, NextState, OnEnter, OnExit},
         system::{Commands, ParamSet, Query, Res, ResMut},
     },
     hierarchy::BuildChildren,
@@ -53,6 +54,7 @@ impl Plugin for MenuPlugin {
             .init_resource::<menu::create::SelectedClass>()
             .add_systems(OnEnter(AppState::MenuLoad), spawn)
             .add_systems(OnEnter(AppState::Menu), display_menu)
+            .add_systems(OnExit(AppState::Menu), lobby::hide_lobby)
             .add_systems(
                 Update,
                 (
@@ -62,6 +64,7 @@ impl Plugin for MenuPlugin {
                         menu::main::account_login_button,
                         menu::main::settings_button,
                         menu::main::credits_button,
+                        menu::main::display_connection_status,
                     ),
                     (
                         menu::account::cancel_create_button,
@@ -93,8 +96,8 @@ impl Plugin for MenuPlugin {
                     ),
                     menu::credits::cancel_button,
                     (
-                        lobby::game_create_button,
-                        lobby::game_join_button,
+                        lobby::ready_button,
+                        lobby::game_start_button,
                         lobby::lobby_send_message,
                         lobby::leave_button,
                         lobby::update_lobby_messages,
@@ -109,10 +112,12 @@ impl Plugin for MenuPlugin {
 
 fn display_menu(
     mut commands: Commands,
+    lobby: Res<Lobby>,
     mut menu_set: ParamSet<(
         Query<&mut Style, With<UiRoot>>,
         Query<&mut Style, With<MainRoot>>,
         Query<&mut Style, With<AccountListRoot>>,
+        Query<&mut Style, With<LobbyRoot>>,
     )>,
     account_q: Query<&RpgAccount>,
     camera_q: Query<(), With<OutOfGameCamera>>,
@@ -140,7 +145,12 @@ fn display_menu(
     let account = account_q.get_single();
     menu_set.p0().single_mut().display = Display::Flex;
     if let Ok(RpgAccount(_)) = account {
-        menu_set.p2().single_mut().display = Display::Flex;
+        // players leaving a game started by a lobby return to it
+        if lobby.0.is_some() {
+            menu_set.p3().single_mut().display = Display::Flex;
+        } else {
+            menu_set.p2().single_mut().display = Display::Flex;
+        }
     } else {
         menu_set.p1().single_mut().display = Display::Flex;
     }
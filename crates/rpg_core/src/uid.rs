@@ -1,6 +1,6 @@
 use serde_derive::{Deserialize as De, Serialize as Ser};
 
-#[derive(Ser, De, Default, PartialEq, PartialOrd, Debug, Copy, Clone)]
+#[derive(Ser, De, Default, PartialEq, Eq, Hash, PartialOrd, Debug, Copy, Clone)]
 pub struct Uid(u64);
 
 impl Uid {
@@ -18,7 +18,7 @@ impl InstanceUid {
     }
 }
 
-#[derive(Ser, De, Default, PartialEq, PartialOrd, Debug)]
+#[derive(Ser, De, Default, PartialEq, PartialOrd, Debug, Clone)]
 pub struct NextUid(Uid);
 
 impl NextUid {
@@ -29,6 +29,11 @@ impl NextUid {
     pub fn get(&self) -> Uid {
         self.0
     }
+
+    /// Skip ahead so that `uid` is never handed out again
+    pub fn skip_past(&mut self, uid: Uid) {
+        self.0 .0 = self.0 .0.max(uid.0 + 1);
+    }
 }
 
 #[derive(Ser, De, Default, PartialEq, PartialOrd, Debug)]
@@ -1,4 +1,4 @@
-use rpg_account::account::AccountId;
+use rpg_account::{account::AccountId, character::CharacterSlot};
 use rpg_chat::chat::MessageId;
 use rpg_core::game_mode::GameMode;
 
@@ -11,6 +11,22 @@ pub struct LobbyId(pub u64);
 pub struct LobbyPlayer {
     pub account_id: AccountId,
     pub account_name: String,
+    /// The character the player has picked, the player is ready when this is set
+    pub ready_slot: Option<CharacterSlot>,
+}
+
+impl LobbyPlayer {
+    pub fn new(account_id: AccountId, account_name: String) -> Self {
+        Self {
+            account_id,
+            account_name,
+            ready_slot: None,
+        }
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.ready_slot.is_some()
+    }
 }
 
 #[derive(Ser, De, Debug, Clone, PartialEq)]
@@ -26,16 +42,19 @@ pub struct Lobby {
     pub id: LobbyId,
     pub name: String,
     pub game_mode: GameMode,
+    /// The player that is able to start a game for the lobby
+    pub owner: AccountId,
     pub players: Vec<LobbyPlayer>,
     pub messages: Vec<LobbyMessage>,
 }
 
 impl Lobby {
-    pub fn new(id: LobbyId, name: String, game_mode: GameMode) -> Self {
+    pub fn new(id: LobbyId, name: String, game_mode: GameMode, owner: AccountId) -> Self {
         Self {
             id,
             name,
             game_mode,
+            owner,
             players: vec![],
             messages: vec![],
         }
@@ -60,7 +79,37 @@ impl Lobby {
         }
     }
 
+    /// Removes a player, ownership is passed on to the longest present player if the owner leaves
     pub fn remove_player(&mut self, id: AccountId) {
         self.players.retain(|p| p.account_id != id);
+
+        if self.owner == id {
+            if let Some(player) = self.players.first() {
+                self.owner = player.account_id;
+            }
+        }
+    }
+
+    pub fn is_owner(&self, account_id: AccountId) -> bool {
+        self.owner == account_id
+    }
+
+    pub fn get_player(&self, account_id: AccountId) -> Option<&LobbyPlayer> {
+        self.players.iter().find(|p| p.account_id == account_id)
+    }
+
+    /// Set or clear the character slot a player is ready with
+    pub fn set_ready(&mut self, account_id: AccountId, slot: Option<CharacterSlot>) -> bool {
+        if let Some(player) = self.players.iter_mut().find(|p| p.account_id == account_id) {
+            player.ready_slot = slot;
+
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn ready_players(&self) -> impl Iterator<Item = &LobbyPlayer> {
+        self.players.iter().filter(|p| p.is_ready())
     }
 }
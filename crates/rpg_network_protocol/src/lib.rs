@@ -1,9 +1,37 @@
 //! Network protocol
 
 pub mod protocol;
+pub mod snapshot;
+pub mod token;
 
 // Use a port of 0 to automatically select a port
 pub const PROTOCOL_ID: u64 = 0;
 pub const SERVER_PORT: u16 = 4269;
 
-pub const KEY: [u8; 32] = [0; 32];
+/// Bumped whenever a protocol change breaks compatibility with older builds
+pub const PROTOCOL_VERSION: u32 = 14;
+
+/// Identifies a build in the handshake, set `RPG_BUILD_ID` when building to include a commit
+pub const BUILD_ID: &str = match option_env!("RPG_BUILD_ID") {
+    Some(build_id) => build_id,
+    None => env!("CARGO_PKG_VERSION"),
+};
+
+/// The optional protocol features this build supports
+///
+/// Features are advertised by name during the handshake so that peers ignore the ones they do not
+/// know, a feature may only be used once both sides have advertised it.
+pub const FEATURES: &[&str] = &[];
+
+/// The features from `ours` that the peer also advertised
+pub fn negotiate_features(ours: &[&str], theirs: &[String]) -> Vec<String> {
+    ours.iter()
+        .filter(|feature| theirs.iter().any(|f| f == *feature))
+        .map(|feature| feature.to_string())
+        .collect()
+}
+
+/// All the features this build supports, as sent in a hello
+pub fn advertised_features() -> Vec<String> {
+    FEATURES.iter().map(|feature| feature.to_string()).collect()
+}
@@ -4,8 +4,9 @@ use serde_derive::{Deserialize, Serialize};
 
 // TODO split these up into multiple protocols once the basic design is settled
 use rpg_account::{
-    account::{Account, AccountInfo},
+    account::{Account, AccountId, AccountInfo, AdminAccount},
     character::{CharacterInfo, CharacterRecord, CharacterSlot},
+    name::NameError,
 };
 use rpg_chat::chat::{ChannelId, Message as ChatMessage};
 use rpg_core::{
@@ -21,18 +22,41 @@ use rpg_core::{
 use rpg_lobby::lobby::{Lobby, LobbyId, LobbyMessage};
 use rpg_world::zone::ZoneId;
 
+use crate::snapshot::{SnapshotDelta, Tick};
+
 use bevy_ecs::event::Event;
 use renet::{ChannelConfig, SendType};
 
+use bincode::Options;
+
 use std::time::Duration;
 
 // Channels
 
+/// Client input is applied in the order it was issued so it shares a single reliable channel
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum ClientChannel {
     Message,
 }
+
+/// Server messages are delivered on the channel matching their delivery class, see
+/// `ServerMessage::channel`
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum ServerChannel {
+    /// Reliable and ordered, account, lobby, chat and anything else that must apply in order
+    ///
+    /// Units and items enter and leave a client's area of interest repeatedly, their spawns and
+    /// despawns are sent here so that a despawn never overtakes the spawn before it.
     Message,
+    /// Reliable and unordered, one off events such as combat results, skills and stat changes
+    ///
+    /// Messages may overtake each other when packets are lost, receivers must not rely on the
+    /// order within this channel.
+    Event,
+    /// Unreliable and sequenced, transient state superseded by the next update
+    ///
+    /// Every batch starts with a sequence number, see `decode_sequenced` and `StateSequence`.
+    State,
 }
 
 impl From<ClientChannel> for u8 {
@@ -59,26 +83,130 @@ impl From<ServerChannel> for u8 {
     fn from(channel_id: ServerChannel) -> Self {
         match channel_id {
             ServerChannel::Message => 0,
+            ServerChannel::Event => 1,
+            ServerChannel::State => 2,
         }
     }
 }
 
 impl ServerChannel {
     pub fn channels_config() -> Vec<ChannelConfig> {
-        vec![ChannelConfig {
-            channel_id: Self::Message.into(),
-            max_memory_usage_bytes: 10 * 1024 * 1024,
-            send_type: SendType::ReliableOrdered {
-                resend_time: Duration::from_millis(200),
+        vec![
+            ChannelConfig {
+                channel_id: Self::Message.into(),
+                max_memory_usage_bytes: 10 * 1024 * 1024,
+                send_type: SendType::ReliableOrdered {
+                    resend_time: Duration::from_millis(200),
+                },
             },
-        }]
+            ChannelConfig {
+                channel_id: Self::Event.into(),
+                max_memory_usage_bytes: 10 * 1024 * 1024,
+                send_type: SendType::ReliableUnordered {
+                    resend_time: Duration::from_millis(200),
+                },
+            },
+            ChannelConfig {
+                channel_id: Self::State.into(),
+                max_memory_usage_bytes: 5 * 1024 * 1024,
+                send_type: SendType::Unreliable,
+            },
+        ]
+    }
+}
+
+// Batching
+//
+// The server coalesces the messages it sends a client on a channel within a frame into batches,
+// every message in a batch is prefixed with its encoded length.
+
+/// The size of the length prefix of each message in a batch
+pub const BATCH_LENGTH_BYTES: usize = 4;
+
+/// Append an encoded message to a batch
+pub fn push_batch_message(batch: &mut Vec<u8>, message: &[u8]) {
+    batch.extend_from_slice(&(message.len() as u32).to_le_bytes());
+    batch.extend_from_slice(message);
+}
+
+/// Decode the messages of a batch in the order they were sent
+///
+/// Batches received on `ServerChannel::State` must have their sequence number removed first,
+/// see `decode_sequenced`.
+pub fn decode_batch(mut bytes: &[u8]) -> bincode::Result<Vec<ServerMessage>> {
+    let mut messages = vec![];
+    while !bytes.is_empty() {
+        let truncated = || Box::new(bincode::ErrorKind::Custom("truncated batch".into()));
+
+        if bytes.len() < BATCH_LENGTH_BYTES {
+            return Err(truncated());
+        }
+        let (length, rest) = bytes.split_at(BATCH_LENGTH_BYTES);
+        let length = u32::from_le_bytes(length.try_into().unwrap()) as usize;
+        if rest.len() < length {
+            return Err(truncated());
+        }
+        let (message, rest) = rest.split_at(length);
+
+        messages.push(bincode::deserialize(message)?);
+        bytes = rest;
+    }
+
+    Ok(messages)
+}
+
+/// Split a batch received on `ServerChannel::State` into its sequence number and payload
+pub fn decode_sequenced(bytes: &[u8]) -> Option<(u32, &[u8])> {
+    if bytes.len() < 4 {
+        return None;
     }
+    let (sequence, payload) = bytes.split_at(4);
+
+    Some((u32::from_le_bytes(sequence.try_into().ok()?), payload))
+}
+
+/// Tracks the newest state message received, anything older is stale and dropped
+#[derive(Default, Debug)]
+pub struct StateSequence {
+    newest: Option<u32>,
 }
 
+impl StateSequence {
+    /// Returns false for a message older than the newest one accepted, sequence numbers wrap
+    pub fn accept(&mut self, sequence: u32) -> bool {
+        if let Some(newest) = self.newest {
+            if (sequence.wrapping_sub(newest) as i32) < 0 {
+                return false;
+            }
+        }
+        self.newest = Some(sequence);
+
+        true
+    }
+}
+
+/// Identifies one of the game instances hosted by a server
+#[derive(Serialize, Deserialize, Default, Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct GameId(pub u64);
+
 // Messages
 
 // Client -> Server
 
+// Handshake Messages
+
+/// The first message sent by a client
+///
+/// This must stay the first `ClientMessage` variant and its layout must never change, so that
+/// builds with different protocol versions can still tell that they are incompatible.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct CSHello {
+    pub protocol_version: u32,
+    pub build_id: String,
+    /// The optional features the client supports
+    pub features: Vec<String>,
+}
+
 // Account Messages
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct CSConnectPlayer;
@@ -105,6 +233,12 @@ pub struct CSCreateAccount {
     pub password: String,
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct CSChangePassword {
+    pub password: String,
+    pub new_password: String,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct CSCreateCharacter {
     pub name: String,
@@ -131,6 +265,17 @@ pub struct CSLobbyMessage {
     pub message: String,
 }
 
+/// Mark the player ready with the character in `slot`, or not ready when `slot` is `None`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct CSLobbyReady {
+    pub id: LobbyId,
+    pub slot: Option<CharacterSlot>,
+}
+
+/// Sent by the lobby owner to move all ready players into a new game
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct CSLobbyStartGame(pub LobbyId);
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct CSCreateGame {
     pub game_mode: GameMode,
@@ -141,6 +286,8 @@ pub struct CSCreateGame {
 pub struct CSJoinGame {
     pub game_mode: GameMode,
     pub slot: CharacterSlot,
+    /// The game to join, any open game of `game_mode` is joined when this is `None`
+    pub game_id: Option<GameId>,
 }
 
 // Chat Messages
@@ -175,11 +322,21 @@ pub struct CSPlayerJoin;
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct CSJoinZone(pub ZoneId);
 
+/// Movement input for one client tick, sent every tick while the player is moving
+///
+/// `sequence` increases by one with every movement input, the server acknowledges the newest one
+/// it processed in `SnapshotDelta::last_input`.
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
-pub struct CSMovePlayer;
+pub struct CSMovePlayer {
+    pub sequence: u32,
+    pub direction: Vec3,
+}
 
+/// The player stopped moving
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
-pub struct CSMovePlayerEnd;
+pub struct CSMovePlayerEnd {
+    pub sequence: u32,
+}
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct CSRotPlayer(pub Vec3);
@@ -202,29 +359,167 @@ pub struct CSItemPickup(pub Uid);
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct CSPlayerRevive;
 
+/// The newest snapshot the client has applied, later snapshots are encoded against it
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct CSSnapshotAck(pub Tick);
+
+/// Sent periodically to measure the round trip and the server clock, answered with `SCPong`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct CSPing(pub u32);
+
+// Admin Messages
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct CSAdminListClients;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct CSAdminListGames;
+
+/// The account a moderation request applies to
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum ModerationTarget {
+    Account(String),
+    /// The account logged in on a connected client
+    Client(u64),
+}
+
+/// Disconnect the client logged into the target account
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct CSAdminKick(pub ModerationTarget);
+
+/// Disconnect the target account and reject logins to it until the ban expires
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct CSAdminBan {
+    pub target: ModerationTarget,
+    pub reason: String,
+    /// The length of the ban in seconds, the ban is permanent when this is `None`
+    pub duration: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct CSAdminUnban(pub ModerationTarget);
+
+/// Reject chat and lobby messages from the target account until the mute expires
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct CSAdminMute {
+    pub target: ModerationTarget,
+    pub reason: String,
+    /// The length of the mute in seconds, the mute is permanent when this is `None`
+    pub duration: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct CSAdminUnmute(pub ModerationTarget);
+
+/// Send a server message to every player
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct CSAdminBroadcast(pub String);
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct CSAdminGetCharacter {
+    pub account_name: String,
+    pub slot: CharacterSlot,
+}
+
+/// A change an admin can make to a character that is not in a game
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq)]
+pub enum CharacterEdit {
+    Level(u8),
+    PassiveSkillPoints(u8),
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct CSAdminEditCharacter {
+    pub account_name: String,
+    pub slot: CharacterSlot,
+    pub edit: CharacterEdit,
+}
+
 // Server -> Client
 
-// Account Messages
+// Handshake Messages
+
+/// Accepts a client's `CSHello`, the layout of this message must never change
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
-pub struct SCHello;
+pub struct SCHello {
+    pub protocol_version: u32,
+    pub build_id: String,
+    /// The optional features supported by both the client and the server
+    pub features: Vec<String>,
+}
+
+/// Rejects a client's `CSHello`, the layout of this message must never change
+///
+/// The server disconnects the client after sending this.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SCHelloError {
+    pub protocol_version: u32,
+    pub build_id: String,
+    pub reason: String,
+}
+
+// Account Messages
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct SCCreateAccountSuccess(pub Account);
 
+/// The reason an account could not be created
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq)]
+pub enum CreateAccountError {
+    InvalidName(NameError),
+    NameTaken,
+    InvalidPassword,
+    Unavailable,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
-pub struct SCCreateAccountError;
+pub struct SCCreateAccountError(pub CreateAccountError);
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct SCLoginAccountSuccess(pub Account);
 
+/// The reason a login attempt was rejected
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq)]
+pub enum LoginError {
+    InvalidName(NameError),
+    AccountNotFound,
+    InvalidPassword,
+    AlreadyAuthenticated,
+    /// The account is in use by another client
+    AlreadyLoggedIn,
+    Banned,
+    Unavailable,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SCLoginAccountError(pub LoginError);
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
-pub struct SCLoginAccountError;
+pub struct SCLoginAdminAccountSuccess(pub AdminAccount);
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SCLoginAdminAccountError(pub LoginError);
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SCChangePasswordSuccess;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SCChangePasswordError;
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct SCCreateCharacterSuccess(pub CharacterRecord);
 
+/// The reason a character could not be created
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq)]
+pub enum CreateCharacterError {
+    InvalidName(NameError),
+    NameTaken,
+    SlotInUse,
+    InvalidSlot,
+    Unavailable,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
-pub struct SCCreateCharacterError;
+pub struct SCCreateCharacterError(pub CreateCharacterError);
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct SCAccount(pub Account);
@@ -265,14 +560,42 @@ pub struct SCLobbyMessageSuccess;
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct SCLobbyMessageError;
 
+/// The players of a lobby or their ready state have changed
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
-pub struct SCGameCreateSuccess(pub GameMode);
+pub struct SCLobbyUpdate(pub Lobby);
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SCLobbyReadyError;
+
+/// The reason a lobby could not start a game
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq)]
+pub enum LobbyStartError {
+    NotFound,
+    NotOwner,
+    OwnerNotReady,
+    InGame,
+    /// None of the ready players could be moved into the game, they are already in a game or no
+    /// longer have a matching character in the slot they picked
+    NoPlayersAvailable,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SCLobbyStartError(pub LobbyStartError);
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SCGameCreateSuccess {
+    pub game_id: GameId,
+    pub game_mode: GameMode,
+}
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct SCGameCreateError;
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
-pub struct SCGameJoinSuccess(pub GameMode);
+pub struct SCGameJoinSuccess {
+    pub game_id: GameId,
+    pub game_mode: GameMode,
+}
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct SCGameJoinError;
@@ -331,31 +654,18 @@ pub struct SCPlayerRevive {
     pub deaths: u32,
 }
 
+/// The units relevant to the client this tick, see `snapshot`
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
-pub struct SCMovePlayer(pub Vec3);
-
-#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
-pub struct SCMovePlayerEnd(pub Vec3);
-
-#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
-pub struct SCRotPlayer(pub Vec3);
-
-#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
-pub struct SCMoveUnit {
-    pub uid: Uid,
-    pub position: Vec3,
-}
-
-#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
-pub struct SCMoveUnitEnd {
-    pub uid: Uid,
-    pub position: Vec3,
-}
+pub struct SCSnapshot(pub SnapshotDelta);
 
+/// The answer to a `CSPing`
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
-pub struct SCRotUnit {
-    pub uid: Uid,
-    pub direction: Vec3,
+pub struct SCPong {
+    pub sequence: u32,
+    /// The server tick when the ping was answered
+    pub tick: Tick,
+    /// Server ticks per second
+    pub tick_rate: f64,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
@@ -370,6 +680,8 @@ pub struct SCSpawnSkill {
     pub id: SkillId,
     pub owner_uid: Uid,
     pub target: SkillTarget,
+    /// The server tick the skill was spawned on
+    pub tick: Tick,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
@@ -425,6 +737,10 @@ pub struct SCHeroRevive(pub Vec3);
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct SCDespawnCorpse(pub Uid);
 
+/// The unit is no longer relevant to the client
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SCDespawnUnit(pub Uid);
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct SCCombatResult(pub CombatResult);
 
@@ -438,6 +754,8 @@ pub struct SCUnitAnim {
 pub struct SCUnitAttack {
     pub uid: Uid,
     pub skill_id: SkillId,
+    /// The server tick the attack started on
+    pub tick: Tick,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
@@ -461,15 +779,102 @@ pub struct SCZoneLoad(pub ZoneId);
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct SCZoneUnload(pub ZoneId);
 
+/// A message from the server operators
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SCServerMessage(pub String);
+
+/// The server will disconnect all clients and stop in `countdown` seconds
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SCServerShutdown {
+    pub reason: String,
+    pub countdown: u32,
+}
+
+/// The kinds of messages the server limits the rate of
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RateLimitCategory {
+    Chat,
+    Lobby,
+    Skill,
+    Movement,
+    Rotation,
+    /// Account creation, login and password changes
+    Account,
+}
+
+/// Messages of this category were sent too quickly and are being dropped
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SCRateLimited {
+    pub category: RateLimitCategory,
+    /// Set once the client has been muted for sending too many messages
+    pub muted: bool,
+}
+
+// Admin Messages
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct AdminClientInfo {
+    pub client_id: u64,
+    pub account_id: Option<AccountId>,
+    pub account_name: Option<String>,
+    pub is_admin: bool,
+    pub game_id: Option<GameId>,
+    /// The client's build, `None` until its hello has been accepted
+    pub build_id: Option<String>,
+    /// The optional features agreed on in the handshake
+    pub features: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SCAdminClients(pub Vec<AdminClientInfo>);
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct AdminGameInfo {
+    pub id: GameId,
+    pub game_mode: GameMode,
+    pub running: bool,
+    pub max_players: u8,
+    pub players: Vec<AccountId>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SCAdminGames(pub Vec<AdminGameInfo>);
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SCAdminCharacter(pub CharacterRecord);
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SCAdminSuccess;
+
+/// The reason an admin request was rejected
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq)]
+pub enum AdminError {
+    NotAuthorized,
+    AccountNotFound,
+    AccountOffline,
+    CharacterNotFound,
+    CharacterInGame,
+    InvalidEdit,
+    Unavailable,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SCAdminError(pub AdminError);
+
 /// Server -> Client
 #[derive(Event, Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub enum ServerMessage {
-    // Account Messages
+    // Handshake Messages, these must remain the first variants
     SCHello(SCHello),
+    SCHelloError(SCHelloError),
+    // Account Messages
     SCCreateAccountSuccess(SCCreateAccountSuccess),
     SCCreateAccountError(SCCreateAccountError),
     SCLoginAccountSuccess(SCLoginAccountSuccess),
     SCLoginAccountError(SCLoginAccountError),
+    SCLoginAdminAccountSuccess(SCLoginAdminAccountSuccess),
+    SCLoginAdminAccountError(SCLoginAdminAccountError),
+    SCChangePasswordSuccess(SCChangePasswordSuccess),
+    SCChangePasswordError(SCChangePasswordError),
     SCCreateCharacterSuccess(SCCreateCharacterSuccess),
     SCCreateCharacterError(SCCreateCharacterError),
     SCAccount(SCAccount),
@@ -485,6 +890,9 @@ pub enum ServerMessage {
     SCLobbyMessageSuccess(SCLobbyMessageSuccess),
     SCLobbyMessageError(SCLobbyMessageError),
     SCLobbyMessage(SCLobbyMessage),
+    SCLobbyUpdate(SCLobbyUpdate),
+    SCLobbyReadyError(SCLobbyReadyError),
+    SCLobbyStartError(SCLobbyStartError),
     SCGameCreateSuccess(SCGameCreateSuccess),
     SCGameCreateError(SCGameCreateError),
     SCGameJoinSuccess(SCGameJoinSuccess),
@@ -506,12 +914,8 @@ pub enum ServerMessage {
     SCPlayerJoinError(SCPlayerJoinError),
     SCPlayerLeave(SCPlayerLeave),
     SCPlayerSpawn(SCPlayerSpawn),
-    SCMovePlayer(SCMovePlayer),
-    SCMovePlayerEnd(SCMovePlayerEnd),
-    SCRotPlayer(SCRotPlayer),
-    SCMoveUnit(SCMoveUnit),
-    SCMoveUnitEnd(SCMoveUnitEnd),
-    SCRotUnit(SCRotUnit),
+    SCSnapshot(SCSnapshot),
+    SCPong(SCPong),
     SCStatUpdate(SCStatUpdate),
     SCStatUpdates(SCStatUpdates),
     SCSpawnSkill(SCSpawnSkill),
@@ -526,6 +930,7 @@ pub enum ServerMessage {
     SCPlayerRevive(SCPlayerRevive),
     SCHeroRevive(SCHeroRevive),
     SCDespawnCorpse(SCDespawnCorpse),
+    SCDespawnUnit(SCDespawnUnit),
     SCCombatResult(SCCombatResult),
     SCDamage(SCDamage),
     SCUnitAnim(SCUnitAnim),
@@ -535,22 +940,59 @@ pub enum ServerMessage {
     SCItemStore(SCItemStore),
     SCZoneLoad(SCZoneLoad),
     SCZoneUnload(SCZoneUnload),
+    SCServerMessage(SCServerMessage),
+    SCServerShutdown(SCServerShutdown),
+    SCRateLimited(SCRateLimited),
+
+    // Admin Messages
+    SCAdminClients(SCAdminClients),
+    SCAdminGames(SCAdminGames),
+    SCAdminCharacter(SCAdminCharacter),
+    SCAdminSuccess(SCAdminSuccess),
+    SCAdminError(SCAdminError),
+}
+
+impl ServerMessage {
+    /// The channel this message is delivered on
+    pub fn channel(&self) -> ServerChannel {
+        match self {
+            Self::SCSnapshot(_) | Self::SCPong(_) => ServerChannel::State,
+            // Sent once per change rather than repeated in every snapshot, so they must arrive
+            Self::SCStatUpdate(_)
+            | Self::SCStatUpdates(_)
+            | Self::SCUnitAnim(_)
+            | Self::SCPlayerSpawn(_)
+            | Self::SCSpawnSkill(_)
+            | Self::SCDespawnSkill(_)
+            | Self::SCDespawnCorpse(_)
+            | Self::SCCombatResult(_)
+            | Self::SCDamage(_)
+            | Self::SCUnitAttack(_) => ServerChannel::Event,
+            // Including unit and item spawns and despawns, see `ServerChannel::Message`
+            _ => ServerChannel::Message,
+        }
+    }
 }
 
 /// Client -> Server
 #[derive(Event, Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub enum ClientMessage {
+    // Handshake Messages, this must remain the first variant
+    CSHello(CSHello),
     // Account Messages
     CSConnectPlayer(CSConnectPlayer),
     CSConnectAdmin(CSConnectAdmin),
     CSCreateAccount(CSCreateAccount),
     CSLoadAccount(CSLoadAccount),
     CSLoadAdminAccount(CSLoadAdminAccount),
+    CSChangePassword(CSChangePassword),
     CSCreateCharacter(CSCreateCharacter),
     CSLobbyCreate(CSLobbyCreate),
     CSLobbyJoin(CSLobbyJoin),
     CSLobbyLeave(CSLobbyLeave),
     CSLobbyMessage(CSLobbyMessage),
+    CSLobbyReady(CSLobbyReady),
+    CSLobbyStartGame(CSLobbyStartGame),
     CSCreateGame(CSCreateGame),
     CSJoinGame(CSJoinGame),
 
@@ -575,4 +1017,35 @@ pub enum ClientMessage {
     CSMovePlayerEnd(CSMovePlayerEnd),
     CSSkillUseDirect(CSSkillUseDirect),
     CSSkillUseTargeted(CSSkillUseTargeted),
+    CSSnapshotAck(CSSnapshotAck),
+    CSPing(CSPing),
+
+    // Admin Messages
+    CSAdminListClients(CSAdminListClients),
+    CSAdminListGames(CSAdminListGames),
+    CSAdminKick(CSAdminKick),
+    CSAdminBan(CSAdminBan),
+    CSAdminUnban(CSAdminUnban),
+    CSAdminMute(CSAdminMute),
+    CSAdminUnmute(CSAdminUnmute),
+    CSAdminBroadcast(CSAdminBroadcast),
+    CSAdminGetCharacter(CSAdminGetCharacter),
+    CSAdminEditCharacter(CSAdminEditCharacter),
+}
+
+/// The largest client message the server will decode, no legitimate message comes close
+pub const MAX_CLIENT_MESSAGE_BYTES: u64 = 64 * 1024;
+
+impl ClientMessage {
+    /// Decode a message from an untrusted client
+    ///
+    /// This matches the encoding of `bincode::serialize` but bounds the bytes read, so a
+    /// forged length can never cause a large allocation.
+    pub fn decode(bytes: &[u8]) -> bincode::Result<Self> {
+        bincode::DefaultOptions::new()
+            .with_fixint_encoding()
+            .allow_trailing_bytes()
+            .with_limit(MAX_CLIENT_MESSAGE_BYTES)
+            .deserialize(bytes)
+    }
 }
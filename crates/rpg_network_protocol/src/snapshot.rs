@@ -0,0 +1,179 @@
+//! Tick based snapshots of the units relevant to a client
+//!
+//! The server takes a snapshot for every client each tick and sends it delta encoded against the
+//! last snapshot the client acknowledged, or in full when there is none. Deltas are sent
+//! unreliably, a lost delta is simply superseded by the next one since it still refers to a
+//! baseline the client has.
+
+use glam::Vec3;
+
+use serde_derive::{Deserialize, Serialize};
+
+use rpg_core::uid::Uid;
+
+use std::collections::HashMap;
+
+/// The number of unacknowledged snapshots kept, a client further behind gets a full snapshot
+pub const SNAPSHOT_HISTORY: usize = 64;
+
+/// A server simulation tick
+#[derive(Serialize, Deserialize, Default, Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Tick(pub u32);
+
+impl Tick {
+    pub fn next(self) -> Self {
+        Self(self.0.wrapping_add(1))
+    }
+
+    /// Tick numbers wrap, a tick is newer if it is less than half the range ahead
+    pub fn is_newer_than(self, other: Self) -> bool {
+        (self.0.wrapping_sub(other.0) as i32) > 0
+    }
+}
+
+/// What a unit is doing, drives its animation on clients
+#[derive(Serialize, Deserialize, Default, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum UnitActivity {
+    #[default]
+    Idle,
+    Moving,
+    Attacking,
+    Knockback,
+}
+
+/// The replicated state of a single unit
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq)]
+pub struct UnitState {
+    pub position: Vec3,
+    pub direction: Vec3,
+    pub hp: u32,
+    pub hp_max: u32,
+    pub activity: UnitActivity,
+}
+
+/// The fields of a unit that changed since the baseline, all are set for a unit new to it
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct UnitDelta {
+    pub uid: Uid,
+    pub position: Option<Vec3>,
+    pub direction: Option<Vec3>,
+    pub hp: Option<u32>,
+    pub hp_max: Option<u32>,
+    pub activity: Option<UnitActivity>,
+}
+
+impl UnitDelta {
+    fn new(uid: Uid, state: &UnitState, baseline: Option<&UnitState>) -> Option<Self> {
+        fn changed<T: Copy + PartialEq>(value: T, baseline: Option<T>) -> Option<T> {
+            (baseline != Some(value)).then_some(value)
+        }
+
+        let delta = Self {
+            uid,
+            position: changed(state.position, baseline.map(|b| b.position)),
+            direction: changed(state.direction, baseline.map(|b| b.direction)),
+            hp: changed(state.hp, baseline.map(|b| b.hp)),
+            hp_max: changed(state.hp_max, baseline.map(|b| b.hp_max)),
+            activity: changed(state.activity, baseline.map(|b| b.activity)),
+        };
+
+        let is_empty = delta.position.is_none()
+            && delta.direction.is_none()
+            && delta.hp.is_none()
+            && delta.hp_max.is_none()
+            && delta.activity.is_none();
+
+        (!is_empty).then_some(delta)
+    }
+
+    fn apply(&self, baseline: Option<&UnitState>) -> Option<UnitState> {
+        Some(UnitState {
+            position: self.position.or(baseline.map(|b| b.position))?,
+            direction: self.direction.or(baseline.map(|b| b.direction))?,
+            hp: self.hp.or(baseline.map(|b| b.hp))?,
+            hp_max: self.hp_max.or(baseline.map(|b| b.hp_max))?,
+            activity: self.activity.or(baseline.map(|b| b.activity))?,
+        })
+    }
+}
+
+/// A snapshot encoded against `baseline`, a full snapshot when there is no baseline
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SnapshotDelta {
+    pub tick: Tick,
+    pub baseline: Option<Tick>,
+    pub changed: Vec<UnitDelta>,
+    /// Units in the baseline that are not in this snapshot
+    pub removed: Vec<Uid>,
+    /// The newest movement input of the receiving client applied in this snapshot
+    pub last_input: Option<u32>,
+}
+
+/// The state of every unit relevant to a client at a tick
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Snapshot {
+    pub tick: Tick,
+    pub units: HashMap<Uid, UnitState>,
+}
+
+impl Snapshot {
+    pub fn new(tick: Tick) -> Self {
+        Self {
+            tick,
+            units: HashMap::new(),
+        }
+    }
+
+    /// Encode this snapshot as the changes since `baseline`
+    pub fn delta_from(&self, baseline: Option<&Snapshot>) -> SnapshotDelta {
+        let changed = self
+            .units
+            .iter()
+            .filter_map(|(uid, state)| {
+                UnitDelta::new(*uid, state, baseline.and_then(|b| b.units.get(uid)))
+            })
+            .collect();
+
+        let removed = baseline
+            .map(|b| {
+                b.units
+                    .keys()
+                    .filter(|uid| !self.units.contains_key(uid))
+                    .copied()
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        SnapshotDelta {
+            tick: self.tick,
+            baseline: baseline.map(|b| b.tick),
+            changed,
+            removed,
+            last_input: None,
+        }
+    }
+
+    /// Rebuild a snapshot from a delta and the baseline it was encoded against
+    ///
+    /// Returns `None` when the baseline does not match or the delta is missing fields of a unit
+    /// that is new to the baseline.
+    pub fn from_delta(delta: &SnapshotDelta, baseline: Option<&Snapshot>) -> Option<Self> {
+        if delta.baseline != baseline.map(|b| b.tick) {
+            return None;
+        }
+
+        let mut units = baseline.map(|b| b.units.clone()).unwrap_or_default();
+        for uid in &delta.removed {
+            units.remove(uid);
+        }
+        for unit in &delta.changed {
+            let state = unit.apply(units.get(&unit.uid))?;
+            units.insert(unit.uid, state);
+        }
+
+        Some(Self {
+            tick: delta.tick,
+            units,
+        })
+    }
+}
@@ -0,0 +1,153 @@
+//! Connect tokens
+//!
+//! Before connecting in secure mode a client presents its credentials to the token issuer, a TCP
+//! service listening on the same address and port as the game server. The issuer replies with a
+//! netcode connect token that is only valid for a short time and carries a client id chosen by
+//! the server. Every message is a little endian `u32` length followed by a bincode payload.
+//!
+//! The token's user data carries a `TokenIdentity` so the server only lets the connection log
+//! into the account whose credentials were presented.
+
+use rpg_account::account::AccountId;
+
+use renet::transport::{ConnectToken, NETCODE_USER_DATA_BYTES};
+
+use serde::{de::DeserializeOwned, Serialize};
+use serde_derive::{Deserialize, Serialize};
+
+use std::{
+    fmt,
+    io::{self, Read, Write},
+    net::{SocketAddr, TcpStream},
+    time::Duration,
+};
+
+/// The largest message either side will read
+pub const MAX_TOKEN_MESSAGE_BYTES: u32 = 4096;
+
+/// How long the issuer and clients wait on a token exchange before giving up
+pub const TOKEN_IO_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum TokenRequest {
+    /// Log into an existing player account
+    Player { name: String, password: String },
+    /// Log into an admin account
+    Admin { name: String, password: String },
+    /// Create a new player account once connected
+    CreateAccount { name: String },
+}
+
+/// The reason a connect token was not issued
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq)]
+pub enum TokenError {
+    InvalidCredentials,
+    AccountExists,
+    Banned,
+    /// Too many accounts were requested from the same address
+    RateLimited,
+    Unavailable,
+}
+
+/// Who a connect token was issued to
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum TokenIdentity {
+    Player(AccountId),
+    Admin(AccountId),
+    /// The account does not exist yet, only this name may be created
+    CreateAccount(String),
+}
+
+impl TokenIdentity {
+    /// Encode into a connect token's user data, `None` if it does not fit
+    pub fn to_user_data(&self) -> Option<[u8; NETCODE_USER_DATA_BYTES]> {
+        let bytes = bincode::serialize(self).ok()?;
+
+        let mut user_data = [0; NETCODE_USER_DATA_BYTES];
+        user_data.get_mut(..bytes.len())?.copy_from_slice(&bytes);
+
+        Some(user_data)
+    }
+
+    pub fn from_user_data(user_data: &[u8; NETCODE_USER_DATA_BYTES]) -> Option<Self> {
+        bincode::deserialize(user_data).ok()
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum TokenResponse {
+    /// A connect token as written by `ConnectToken::write`
+    Token(Vec<u8>),
+    Error(TokenError),
+}
+
+#[derive(Debug)]
+pub enum TokenRequestError {
+    Io(io::Error),
+    Rejected(TokenError),
+    InvalidToken,
+}
+
+impl fmt::Display for TokenRequestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "token request failed: {e}"),
+            Self::Rejected(e) => write!(f, "token request rejected: {e:?}"),
+            Self::InvalidToken => write!(f, "the issuer sent an invalid connect token"),
+        }
+    }
+}
+
+impl std::error::Error for TokenRequestError {}
+
+impl From<io::Error> for TokenRequestError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+pub fn write_message(writer: &mut impl Write, message: &impl Serialize) -> io::Result<()> {
+    let payload =
+        bincode::serialize(message).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+    writer.write_all(&(payload.len() as u32).to_le_bytes())?;
+    writer.write_all(&payload)?;
+    writer.flush()
+}
+
+pub fn read_message<T: DeserializeOwned>(reader: &mut impl Read) -> io::Result<T> {
+    let mut len = [0; 4];
+    reader.read_exact(&mut len)?;
+
+    let len = u32::from_le_bytes(len);
+    if len > MAX_TOKEN_MESSAGE_BYTES {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "token message too large",
+        ));
+    }
+
+    let mut payload = vec![0; len as usize];
+    reader.read_exact(&mut payload)?;
+
+    bincode::deserialize(&payload).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Ask the token issuer at `addr` for a connect token, this blocks for at most a few seconds
+pub fn request_connect_token(
+    addr: SocketAddr,
+    request: &TokenRequest,
+) -> Result<ConnectToken, TokenRequestError> {
+    let mut stream = TcpStream::connect_timeout(&addr, TOKEN_IO_TIMEOUT)?;
+    stream.set_read_timeout(Some(TOKEN_IO_TIMEOUT))?;
+    stream.set_write_timeout(Some(TOKEN_IO_TIMEOUT))?;
+
+    write_message(&mut stream, request)?;
+
+    match read_message(&mut stream)? {
+        TokenResponse::Token(token) => {
+            ConnectToken::read(&mut token.as_slice()).map_err(|_| TokenRequestError::InvalidToken)
+        }
+        TokenResponse::Error(e) => Err(TokenRequestError::Rejected(e)),
+    }
+}
@@ -1,13 +1,51 @@
+use crate::store::AccountStorage;
+
 use rpg_account::account::{Account, AdminAccount};
+use rpg_util::{
+    item::UnitStorage,
+    skill::{SkillSlots, Skills},
+    unit::{Unit, Waypoints},
+};
 
 use bevy::{
-    ecs::{bundle::Bundle, component::Component},
+    app::AppExit,
+    ecs::{
+        bundle::Bundle,
+        component::Component,
+        event::EventReader,
+        system::{Query, Res, ResMut, Resource},
+    },
+    log::{error, info},
     prelude::{Deref, DerefMut},
+    time::{Time, Timer, TimerMode},
 };
 
 #[derive(Debug, Deref, DerefMut, Component)]
 pub(crate) struct AccountInstance(pub(crate) Account);
 
+impl AccountInstance {
+    /// Write the live state of a spawned hero back into the matching `CharacterRecord`
+    pub(crate) fn update_character(
+        &mut self,
+        unit: &Unit,
+        skills: &Skills,
+        skill_slots: &SkillSlots,
+        storage: &UnitStorage,
+        waypoints: &Waypoints,
+    ) {
+        let Some(record) = self.0.get_character_from_uid_mut(unit.uid) else {
+            info!("no character record found for {:?}", unit.uid);
+            return;
+        };
+
+        record.character.unit = unit.0.clone();
+        record.character.skills = skills.0.clone();
+        record.character.skill_slots = skill_slots.slots.clone();
+        record.character.storage = storage.0.clone();
+        record.character.waypoints = waypoints.0.clone();
+    }
+}
+
 #[derive(Debug, Deref, DerefMut, Component)]
 pub(crate) struct AdminAccountInstance(pub(crate) AdminAccount);
 
@@ -20,3 +58,76 @@ pub(crate) struct AccountInstanceBundle {
 pub(crate) struct AdminAccountInstanceBundle {
     pub account: AdminAccountInstance,
 }
+
+#[derive(Resource, Deref, DerefMut)]
+pub(crate) struct AutosaveTimer(pub(crate) Timer);
+
+impl Default for AutosaveTimer {
+    fn default() -> Self {
+        Self(Timer::from_seconds(60.0, TimerMode::Repeating))
+    }
+}
+
+/// Save an account, failures are logged and `false` is returned
+pub(crate) fn save_account(store: &mut AccountStorage, account: &Account) -> bool {
+    if let Err(e) = store.save_account(account) {
+        error!("unable to save account {}: {e}", account.info.name);
+        return false;
+    }
+
+    true
+}
+
+/// Sync the hero state of an account, if it has a spawned hero, and save the account
+pub(crate) fn save_account_instance(
+    store: &mut AccountStorage,
+    account: &mut AccountInstance,
+    hero: Option<(&Unit, &Skills, &SkillSlots, &UnitStorage, &Waypoints)>,
+) -> bool {
+    if let Some((unit, skills, skill_slots, storage, waypoints)) = hero {
+        account.update_character(unit, skills, skill_slots, storage, waypoints);
+    }
+
+    save_account(store, &account.0)
+}
+
+pub(crate) fn autosave_accounts(
+    time: Res<Time>,
+    mut store: ResMut<AccountStorage>,
+    mut autosave_timer: ResMut<AutosaveTimer>,
+    mut account_q: Query<(
+        &mut AccountInstance,
+        Option<(&Unit, &Skills, &SkillSlots, &UnitStorage, &Waypoints)>,
+    )>,
+) {
+    autosave_timer.tick(time.delta());
+    if !autosave_timer.just_finished() {
+        return;
+    }
+
+    info!("autosaving accounts");
+    for (mut account, hero) in &mut account_q {
+        save_account_instance(&mut store, &mut account, hero);
+    }
+}
+
+/// Save every account when the app exits without going through the graceful shutdown, which
+/// saves them itself
+pub(crate) fn save_accounts_on_exit(
+    mut exit_reader: EventReader<AppExit>,
+    mut store: ResMut<AccountStorage>,
+    mut account_q: Query<(
+        &mut AccountInstance,
+        Option<(&Unit, &Skills, &SkillSlots, &UnitStorage, &Waypoints)>,
+    )>,
+) {
+    if exit_reader.is_empty() {
+        return;
+    }
+    exit_reader.clear();
+
+    info!("saving accounts before exit");
+    for (mut account, hero) in &mut account_q {
+        save_account_instance(&mut store, &mut account, hero);
+    }
+}
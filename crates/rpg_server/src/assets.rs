@@ -116,6 +116,6 @@ pub(crate) fn load_metadata(
 
         info!("metadata initialized");
 
-        next_state.set(AppState::Lobby);
+        next_state.set(AppState::Running);
     }
 }
@@ -50,6 +50,14 @@ impl ChatManager {
             channel.remove_subscriber(account_id);
         }
     }
+
+    /// Unsubscribe an account from every channel, e.g. once its client disconnects
+    pub(crate) fn remove_account(&mut self, account_id: AccountId) {
+        let channel_ids: Vec<_> = self.channels.keys().copied().collect();
+        for channel_id in channel_ids {
+            self.remove_subscriber(channel_id, account_id);
+        }
+    }
 }
 
 pub(crate) fn setup(mut chat: ResMut<ChatManager>) {
@@ -0,0 +1,490 @@
+//! Server settings
+//!
+//! Settings are read from an optional JSON file, any field missing from the file keeps its
+//! default. Command line arguments override the file, `RPG_SAVE_ROOT` is used when neither sets a
+//! save root and `RPG_SERVER_KEY` when the file has no private key.
+
+use rpg_network_protocol::SERVER_PORT;
+
+use bevy::ecs::system::Resource;
+
+use serde_derive::{Deserialize as De, Serialize as Ser};
+use thiserror::Error;
+
+use std::{
+    env, fs, io,
+    net::{Ipv4Addr, SocketAddr},
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("unable to read {path}: {source}")]
+    Io { path: PathBuf, source: io::Error },
+    #[error("unable to parse {path}: {source}")]
+    Parse {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+    #[error("invalid {field}: {reason}")]
+    Invalid {
+        field: &'static str,
+        reason: &'static str,
+    },
+}
+
+/// The length of the key used to encrypt connect tokens
+pub const PRIVATE_KEY_BYTES: usize = 32;
+
+#[derive(Debug, Clone, Ser, De)]
+#[serde(default, deny_unknown_fields)]
+pub struct NetworkSettings {
+    pub addr: Ipv4Addr,
+    pub port: u16,
+    pub max_clients: usize,
+    /// The address clients connect to, defaults to `addr` or to localhost when `addr` is
+    /// unspecified
+    pub public_addr: Option<Ipv4Addr>,
+    /// Hex encoded, shared by the token issuer and the transport
+    pub private_key: Option<String>,
+    /// Accept connections without a connect token, this is only meant for development
+    pub unsecure: bool,
+    pub rate_limits: RateLimitSettings,
+}
+
+impl Default for NetworkSettings {
+    fn default() -> Self {
+        Self {
+            addr: Ipv4Addr::UNSPECIFIED,
+            port: SERVER_PORT,
+            max_clients: 16,
+            public_addr: None,
+            private_key: None,
+            unsecure: false,
+            rate_limits: RateLimitSettings::default(),
+        }
+    }
+}
+
+impl NetworkSettings {
+    pub fn listen_addr(&self) -> SocketAddr {
+        SocketAddr::new(self.addr.into(), self.port)
+    }
+
+    pub fn public_addr(&self) -> SocketAddr {
+        let addr = match self.public_addr {
+            Some(addr) => addr,
+            None if self.addr.is_unspecified() => Ipv4Addr::LOCALHOST,
+            None => self.addr,
+        };
+
+        SocketAddr::new(addr.into(), self.port)
+    }
+
+    /// The decoded private key, `None` when it is missing or malformed
+    pub fn private_key(&self) -> Option<[u8; PRIVATE_KEY_BYTES]> {
+        let hex = self.private_key.as_deref()?;
+        if hex.len() != PRIVATE_KEY_BYTES * 2 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return None;
+        }
+
+        let mut key = [0; PRIVATE_KEY_BYTES];
+        for (byte, digits) in key.iter_mut().zip(hex.as_bytes().chunks(2)) {
+            // Only ascii hex digits remain so every pair is valid utf8
+            *byte = u8::from_str_radix(std::str::from_utf8(digits).ok()?, 16).ok()?;
+        }
+
+        Some(key)
+    }
+}
+
+/// A token bucket, a client may send `burst` messages at once and `per_second` on average
+#[derive(Debug, Clone, Copy, Ser, De)]
+#[serde(deny_unknown_fields)]
+pub struct BucketSettings {
+    pub burst: f32,
+    pub per_second: f32,
+}
+
+impl BucketSettings {
+    fn is_valid(&self) -> bool {
+        self.burst.is_finite()
+            && self.burst >= 1.
+            && self.per_second.is_finite()
+            && self.per_second > 0.
+    }
+}
+
+#[derive(Debug, Clone, Ser, De)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimitSettings {
+    pub chat: BucketSettings,
+    pub lobby: BucketSettings,
+    pub skill: BucketSettings,
+    /// Account creation, login and password change messages, each costs a password hash
+    pub account: BucketSettings,
+    /// Movement and rotation messages allowed per simulation tick, clients send at most one of
+    /// each per tick
+    pub movement_headroom: f32,
+    /// Connect tokens for new accounts issued to one address, checked by the token issuer
+    pub account_creation: BucketSettings,
+    /// Strikes are forgiven after this many seconds without exceeding a limit
+    pub strike_window: f64,
+    /// Each second a client keeps exceeding a chat, lobby, skill or account limit is a strike, at
+    /// this many strikes from chat or lobby messages a client's account is muted, 0 never mutes
+    pub mute_strikes: u32,
+    /// Seconds an automatic mute lasts
+    pub mute_duration: u64,
+    /// At this many strikes a client is disconnected
+    pub kick_strikes: u32,
+}
+
+impl RateLimitSettings {
+    /// The bucket for movement and for rotation messages at a simulation tick rate
+    pub fn movement(&self, tick_rate: f64) -> BucketSettings {
+        let per_second = tick_rate as f32 * self.movement_headroom;
+
+        // A second of messages, enough to absorb a stall on the client or the network
+        BucketSettings {
+            burst: per_second,
+            per_second,
+        }
+    }
+}
+
+impl Default for RateLimitSettings {
+    fn default() -> Self {
+        Self {
+            chat: BucketSettings {
+                burst: 5.,
+                per_second: 1.,
+            },
+            lobby: BucketSettings {
+                burst: 5.,
+                per_second: 1.,
+            },
+            skill: BucketSettings {
+                burst: 20.,
+                per_second: 10.,
+            },
+            account: BucketSettings {
+                burst: 5.,
+                per_second: 0.2,
+            },
+            movement_headroom: 2.,
+            account_creation: BucketSettings {
+                burst: 3.,
+                per_second: 1. / 600.,
+            },
+            strike_window: 30.,
+            mute_strikes: 3,
+            mute_duration: 300,
+            kick_strikes: 6,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Ser, De)]
+#[serde(default, deny_unknown_fields)]
+pub struct SimulationSettings {
+    /// Updates per second of both the run loop and the fixed timestep
+    pub tick_rate: f64,
+    pub max_players: u8,
+    pub rng_seed: u64,
+    /// Villains spawned when a game starts
+    pub villain_count: u32,
+    /// Seconds before a corpse is despawned
+    pub corpse_timer: f32,
+    /// Players are only told about units and items within this distance of their hero
+    pub interest_radius: f32,
+}
+
+impl Default for SimulationSettings {
+    fn default() -> Self {
+        Self {
+            tick_rate: 60.,
+            max_players: 8,
+            rng_seed: 1234,
+            villain_count: 32,
+            corpse_timer: 300.,
+            interest_radius: 16.,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Ser, De)]
+#[serde(default, deny_unknown_fields)]
+pub struct PersistenceSettings {
+    pub save_root: Option<PathBuf>,
+}
+
+#[derive(Resource, Debug, Clone, Default, Ser, De)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerSettings {
+    pub network: NetworkSettings,
+    pub simulation: SimulationSettings,
+    pub persistence: PersistenceSettings,
+}
+
+impl ServerSettings {
+    /// Read settings from a JSON file
+    pub fn from_file(path: &Path) -> Result<Self, ConfigError> {
+        let data = fs::read_to_string(path).map_err(|source| ConfigError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+
+        serde_json::from_str(&data).map_err(|source| ConfigError::Parse {
+            path: path.to_path_buf(),
+            source,
+        })
+    }
+
+    /// Fall back to `RPG_SAVE_ROOT` and `RPG_SERVER_KEY` for settings that have not been
+    /// configured
+    pub fn apply_env(&mut self) {
+        if self.persistence.save_root.is_none() {
+            self.persistence.save_root = env::var_os("RPG_SAVE_ROOT").map(PathBuf::from);
+        }
+        if self.network.private_key.is_none() {
+            self.network.private_key = env::var("RPG_SERVER_KEY").ok();
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let invalid = |field, reason| Err(ConfigError::Invalid { field, reason });
+
+        if self.network.max_clients == 0 {
+            return invalid("network.max_clients", "must be at least 1");
+        }
+        if !self.network.unsecure && self.network.private_key().is_none() {
+            return invalid(
+                "network.private_key",
+                "must be 64 hex digits, set in the config file or with RPG_SERVER_KEY",
+            );
+        }
+        let limits = &self.network.rate_limits;
+        if ![
+            limits.chat,
+            limits.lobby,
+            limits.skill,
+            limits.account,
+            limits.account_creation,
+        ]
+        .iter()
+        .all(BucketSettings::is_valid)
+        {
+            return invalid(
+                "network.rate_limits",
+                "bursts must be at least 1 and rates must be positive",
+            );
+        }
+        if !limits.movement_headroom.is_finite() || limits.movement_headroom < 1. {
+            return invalid(
+                "network.rate_limits.movement_headroom",
+                "must be at least 1",
+            );
+        }
+        if !limits.strike_window.is_finite() || limits.strike_window <= 0. {
+            return invalid(
+                "network.rate_limits.strike_window",
+                "must be a positive number of seconds",
+            );
+        }
+        if limits.kick_strikes == 0 {
+            return invalid("network.rate_limits.kick_strikes", "must be at least 1");
+        }
+        if !(1. ..=1000.).contains(&self.simulation.tick_rate) {
+            return invalid("simulation.tick_rate", "must be between 1 and 1000");
+        }
+        if self.simulation.max_players == 0 {
+            return invalid("simulation.max_players", "must be at least 1");
+        }
+        if !self.simulation.corpse_timer.is_finite() || self.simulation.corpse_timer <= 0. {
+            return invalid(
+                "simulation.corpse_timer",
+                "must be a positive number of seconds",
+            );
+        }
+        if !self.simulation.interest_radius.is_finite() || self.simulation.interest_radius <= 0. {
+            return invalid("simulation.interest_radius", "must be a positive distance");
+        }
+        match &self.persistence.save_root {
+            None => {
+                return invalid(
+                    "persistence.save_root",
+                    "must be set in the config file, with --save-root or with RPG_SAVE_ROOT",
+                )
+            }
+            Some(root) if !root.is_dir() => {
+                return invalid("persistence.save_root", "must be an existing directory");
+            }
+            Some(_) => {}
+        }
+
+        Ok(())
+    }
+
+    /// The save root, only valid once the settings have been validated
+    pub fn save_root(&self) -> &Path {
+        self.persistence.save_root.as_deref().unwrap()
+    }
+
+    /// The length of a simulation tick in seconds
+    pub fn timestep(&self) -> f64 {
+        1. / self.simulation.tick_rate
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::process;
+
+    /// Write `json` to a file only used by the calling test
+    fn settings_file(name: &str, json: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("rpg_server_config_{}_{name}.json", process::id()));
+        fs::write(&path, json).unwrap();
+
+        path
+    }
+
+    /// Settings that pass validation
+    fn valid_settings() -> ServerSettings {
+        let mut settings = ServerSettings::default();
+        settings.network.unsecure = true;
+        settings.persistence.save_root = Some(env::temp_dir());
+
+        settings
+    }
+
+    fn invalid_field(settings: &ServerSettings) -> Option<&'static str> {
+        match settings.validate() {
+            Err(ConfigError::Invalid { field, .. }) => Some(field),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn missing_fields_keep_their_defaults() {
+        let path = settings_file(
+            "partial",
+            r#"{ "network": { "port": 4000 }, "simulation": { "tick_rate": 30 } }"#,
+        );
+        let settings = ServerSettings::from_file(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(settings.network.port, 4000);
+        assert_eq!(settings.network.max_clients, 16);
+        assert_eq!(settings.simulation.tick_rate, 30.);
+        assert_eq!(settings.simulation.villain_count, 32);
+        assert_eq!(settings.persistence.save_root, None);
+    }
+
+    #[test]
+    fn unknown_fields_are_rejected() {
+        let path = settings_file("unknown", r#"{ "network": { "prot": 4000 } }"#);
+        let result = ServerSettings::from_file(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert!(matches!(result, Err(ConfigError::Parse { .. })));
+    }
+
+    #[test]
+    fn missing_files_are_reported() {
+        let path = env::temp_dir().join("rpg_server_config_does_not_exist.json");
+
+        assert!(matches!(
+            ServerSettings::from_file(&path),
+            Err(ConfigError::Io { .. })
+        ));
+    }
+
+    #[test]
+    fn private_keys_are_decoded() {
+        let mut network = NetworkSettings {
+            private_key: Some("00ff".repeat(PRIVATE_KEY_BYTES / 2)),
+            ..Default::default()
+        };
+        let key = network.private_key().unwrap();
+        assert_eq!(key[0], 0x00);
+        assert_eq!(key[1], 0xff);
+
+        network.private_key = Some("0".repeat(PRIVATE_KEY_BYTES * 2 - 1));
+        assert_eq!(network.private_key(), None);
+
+        network.private_key = Some("zz".repeat(PRIVATE_KEY_BYTES));
+        assert_eq!(network.private_key(), None);
+    }
+
+    #[test]
+    fn valid_settings_pass() {
+        assert!(valid_settings().validate().is_ok());
+    }
+
+    #[test]
+    fn secure_settings_need_a_private_key() {
+        let mut settings = valid_settings();
+        settings.network.unsecure = false;
+        assert_eq!(invalid_field(&settings), Some("network.private_key"));
+
+        settings.network.private_key = Some("ab".repeat(PRIVATE_KEY_BYTES));
+        assert!(settings.validate().is_ok());
+    }
+
+    #[test]
+    fn out_of_range_values_are_rejected() {
+        let cases: [(fn(&mut ServerSettings), &str); 10] = [
+            (|s| s.network.max_clients = 0, "network.max_clients"),
+            (
+                |s| s.network.rate_limits.chat.burst = 0.,
+                "network.rate_limits",
+            ),
+            (
+                |s| s.network.rate_limits.account.burst = 0.5,
+                "network.rate_limits",
+            ),
+            (
+                |s| s.network.rate_limits.account_creation.per_second = 0.,
+                "network.rate_limits",
+            ),
+            (
+                |s| s.network.rate_limits.movement_headroom = 0.5,
+                "network.rate_limits.movement_headroom",
+            ),
+            (
+                |s| s.network.rate_limits.strike_window = 0.,
+                "network.rate_limits.strike_window",
+            ),
+            (|s| s.simulation.tick_rate = 0., "simulation.tick_rate"),
+            (
+                |s| s.simulation.corpse_timer = 0.,
+                "simulation.corpse_timer",
+            ),
+            (
+                |s| s.simulation.interest_radius = f32::NAN,
+                "simulation.interest_radius",
+            ),
+            (|s| s.persistence.save_root = None, "persistence.save_root"),
+        ];
+
+        for (invalidate, field) in cases {
+            let mut settings = valid_settings();
+            invalidate(&mut settings);
+            assert_eq!(invalid_field(&settings), Some(field));
+        }
+    }
+
+    #[test]
+    fn public_addr_defaults_to_a_connectable_addr() {
+        let mut network = NetworkSettings::default();
+        assert_eq!(network.public_addr().ip(), Ipv4Addr::LOCALHOST);
+
+        network.addr = Ipv4Addr::new(10, 0, 0, 1);
+        assert_eq!(network.public_addr().ip(), network.addr);
+
+        network.public_addr = Some(Ipv4Addr::new(192, 0, 2, 1));
+        assert_eq!(network.public_addr().ip(), Ipv4Addr::new(192, 0, 2, 1));
+    }
+}
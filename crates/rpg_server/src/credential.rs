@@ -0,0 +1,67 @@
+use rpg_account::{
+    account::AccountId,
+    save::{migrate_headerless, Migration, Versioned},
+};
+
+use bevy::log::info;
+
+use argon2::{
+    password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Argon2,
+};
+use serde_derive::{Deserialize as De, Serialize as Ser};
+
+/// The credential of an account, this is stored alongside the account and is never sent to clients
+#[derive(Debug, Clone, Ser, De)]
+pub(crate) struct Credential {
+    pub(crate) account_id: AccountId,
+    /// A salted argon2 hash in PHC string format
+    pub(crate) password_hash: String,
+}
+
+impl Versioned for Credential {
+    const VERSION: u16 = 1;
+    const MIGRATIONS: &'static [Migration<Self>] = &[migrate_headerless];
+}
+
+impl Credential {
+    pub(crate) fn new(account_id: AccountId, password: &str) -> Option<Self> {
+        Some(Self {
+            account_id,
+            password_hash: hash_password(password)?,
+        })
+    }
+
+    pub(crate) fn verify(&self, password: &str) -> bool {
+        let Ok(hash) = PasswordHash::new(&self.password_hash) else {
+            info!("invalid password hash for {:?}", self.account_id);
+            return false;
+        };
+
+        Argon2::default()
+            .verify_password(password.as_bytes(), &hash)
+            .is_ok()
+    }
+
+    pub(crate) fn set_password(&mut self, password: &str) -> bool {
+        let Some(password_hash) = hash_password(password) else {
+            return false;
+        };
+
+        self.password_hash = password_hash;
+
+        true
+    }
+}
+
+fn hash_password(password: &str) -> Option<String> {
+    let salt = SaltString::generate(&mut OsRng);
+
+    match Argon2::default().hash_password(password.as_bytes(), &salt) {
+        Ok(hash) => Some(hash.to_string()),
+        Err(e) => {
+            info!("unable to hash password: {e:?}");
+            None
+        }
+    }
+}
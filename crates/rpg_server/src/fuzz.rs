@@ -0,0 +1,384 @@
+//! Entry points for the fuzz targets in `fuzz/`
+//!
+//! Inputs are routed through the real server app, headless and with an in-memory store, from a
+//! client in each state a connection can be in. Decoded messages reach the same handlers as
+//! messages from the transport. The app is built once per thread and reused between inputs.
+
+use crate::{
+    account::AccountInstance,
+    build_app_with_store,
+    config::ServerSettings,
+    credential::Credential,
+    game::instance::GameManager,
+    net::{
+        context::NetworkContext,
+        rate_limit::RateLimits,
+        server::{receive_message, ClientMessageEvent, NetworkParamsRW},
+    },
+    shutdown::ShutdownHandle,
+    state::AppState,
+    store::{AccountStorage, MemoryAccountStore},
+};
+
+use rpg_account::{
+    account::{AccountId, AdminAccount, AdminAccountInfo},
+    character::CharacterSlot,
+};
+use rpg_core::{class::Class, game_mode::GameMode};
+use rpg_network_protocol::{protocol::*, BUILD_ID, PROTOCOL_VERSION};
+
+use bevy::{
+    app::{App, PluginsState, PreUpdate},
+    ecs::{
+        event::EventWriter,
+        schedule::State,
+        system::{Query, Res, ResMut, Resource},
+        world::World,
+    },
+    tasks::tick_global_task_pools_on_main_thread,
+    time::{Time, TimeUpdateStrategy},
+};
+
+use bevy_renet::renet::ClientId;
+
+use std::{
+    cell::RefCell,
+    env,
+    net::Ipv4Addr,
+    path::Path,
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Enough repeats to push every client past the violation limit
+const ROUTE_REPEATS: usize = 12;
+
+/// How long the server may take to load its metadata
+const START_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How long hashing or verifying a password may take
+const LOGIN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How many updates joining a game may take before the player is left outside of one
+const JOIN_UPDATES: usize = 100;
+
+const ADMIN_NAME: &str = "fuzzadmin";
+const PLAYER_NAME: &str = "fuzzer";
+const PASSWORD: &str = "fuzz-password";
+const SLOT: CharacterSlot = CharacterSlot(0);
+
+thread_local! {
+    static SERVER: RefCell<Option<FuzzServer>> = const { RefCell::new(None) };
+}
+
+/// Route arbitrary bytes as if they were sent by a client in each state a connection can be in,
+/// this must never panic
+pub fn route_client_message(data: &[u8]) {
+    SERVER.with(|server| {
+        let mut server = server.borrow_mut();
+        let server = server.get_or_insert_with(FuzzServer::start);
+
+        server.reset_clients();
+        server.join_game();
+
+        // Every message arrives at the same time so the rate limits are exercised too
+        for client_id in clients() {
+            for _ in 0..ROUTE_REPEATS {
+                server.push(client_id, data.to_vec());
+            }
+        }
+
+        // The fixed update handlers run once per update, the second lets the replies go out
+        server.app.update();
+        server.app.update();
+    });
+}
+
+/// A client that never completed the handshake
+fn unknown() -> ClientId {
+    ClientId::from_raw(1)
+}
+
+/// A client that completed the handshake but never logged in
+fn anonymous() -> ClientId {
+    ClientId::from_raw(2)
+}
+
+/// A client logged into a player account, usually with a hero in a game
+fn player() -> ClientId {
+    ClientId::from_raw(3)
+}
+
+/// A client logged into an admin account
+fn admin() -> ClientId {
+    ClientId::from_raw(4)
+}
+
+fn clients() -> [ClientId; 4] {
+    [unknown(), anonymous(), player(), admin()]
+}
+
+/// Messages routed on the next update as if they were received from the transport
+#[derive(Resource, Default)]
+struct FuzzInput(Vec<(ClientId, Vec<u8>)>);
+
+fn receive_fuzz_input(
+    time: Res<Time>,
+    mut input: ResMut<FuzzInput>,
+    mut store: ResMut<AccountStorage>,
+    mut net_params: NetworkParamsRW,
+    mut message_writer: EventWriter<ClientMessageEvent>,
+    mut account_q: Query<&mut AccountInstance>,
+) {
+    let now = time.elapsed_seconds_f64();
+    for (client_id, bytes) in std::mem::take(&mut input.0) {
+        receive_message(
+            &mut store,
+            &mut net_params,
+            &mut message_writer,
+            &mut account_q,
+            client_id,
+            &bytes,
+            now,
+        );
+    }
+}
+
+struct FuzzServer {
+    app: App,
+}
+
+impl FuzzServer {
+    /// Build the app and bring every client into its state, through the handlers where possible
+    fn start() -> Self {
+        // The server loads its metadata from the repository's `assets`
+        if env::var_os("BEVY_ASSET_ROOT").is_none() {
+            env::set_var(
+                "BEVY_ASSET_ROOT",
+                Path::new(env!("CARGO_MANIFEST_DIR")).join("../.."),
+            );
+        }
+
+        let mut settings = ServerSettings::default();
+        settings.network.addr = Ipv4Addr::LOCALHOST;
+        settings.network.port = 0;
+        settings.network.unsecure = true;
+        // Required by the settings, the in-memory store never writes to it
+        settings.persistence.save_root = Some(env::temp_dir().join("rpg_server_fuzz"));
+        settings.validate().unwrap();
+        let timestep = Duration::from_secs_f64(settings.timestep());
+
+        let admin_id = AccountId(u64::MAX);
+        let mut store = MemoryAccountStore::default();
+        store.admin_accounts.insert(
+            ADMIN_NAME.into(),
+            AdminAccount {
+                info: AdminAccountInfo {
+                    id: admin_id,
+                    name: ADMIN_NAME.into(),
+                },
+            },
+        );
+        store.admin_credentials.insert(
+            ADMIN_NAME.into(),
+            Credential::new(admin_id, PASSWORD).unwrap(),
+        );
+
+        let mut app = build_app_with_store(
+            settings,
+            ShutdownHandle::default(),
+            AccountStorage::new(store),
+        );
+        app.insert_resource(TimeUpdateStrategy::ManualDuration(timestep))
+            .init_resource::<FuzzInput>()
+            .add_systems(PreUpdate, receive_fuzz_input);
+
+        while app.plugins_state() == PluginsState::Adding {
+            tick_global_task_pools_on_main_thread();
+        }
+        app.finish();
+        app.cleanup();
+
+        let mut server = Self { app };
+
+        let started = Instant::now();
+        while *server.app.world.resource::<State<AppState>>().get() != AppState::Running {
+            assert!(
+                started.elapsed() < START_TIMEOUT,
+                "the server metadata did not load"
+            );
+            server.app.update();
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        let mut context = server.app.world.resource_mut::<NetworkContext>();
+        for client_id in clients() {
+            context.add_client(client_id, None);
+        }
+
+        for client_id in [anonymous(), player(), admin()] {
+            server.send(
+                client_id,
+                ClientMessage::CSHello(CSHello {
+                    protocol_version: PROTOCOL_VERSION,
+                    build_id: BUILD_ID.to_string(),
+                    features: vec![],
+                }),
+            );
+        }
+
+        // One message per update, the handlers of a single update run in no particular order
+        server.send(player(), ClientMessage::CSConnectPlayer(CSConnectPlayer));
+        server.send(
+            player(),
+            ClientMessage::CSCreateAccount(CSCreateAccount {
+                name: PLAYER_NAME.into(),
+                email: format!("{PLAYER_NAME}@fuzz.invalid"),
+                password: PASSWORD.into(),
+            }),
+        );
+        server.wait_for_login(player());
+        server.send(
+            player(),
+            ClientMessage::CSCreateCharacter(CSCreateCharacter {
+                name: PLAYER_NAME.into(),
+                slot: SLOT,
+                class: Class::default(),
+                game_mode: GameMode::Normal,
+            }),
+        );
+
+        server.send(admin(), ClientMessage::CSConnectAdmin(CSConnectAdmin));
+        server.send(
+            admin(),
+            ClientMessage::CSLoadAdminAccount(CSLoadAdminAccount {
+                name: ADMIN_NAME.into(),
+                password: PASSWORD.into(),
+            }),
+        );
+        server.wait_for_login(admin());
+
+        server
+    }
+
+    /// Update until a client's password has been checked and it is logged in
+    fn wait_for_login(&mut self, client_id: ClientId) {
+        let started = Instant::now();
+        while self.app.world.resource::<NetworkContext>().clients[&client_id]
+            .account_id
+            .is_none()
+        {
+            assert!(
+                started.elapsed() < LOGIN_TIMEOUT,
+                "client {client_id} did not log in"
+            );
+            self.app.update();
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    fn push(&mut self, client_id: ClientId, bytes: Vec<u8>) {
+        self.app
+            .world
+            .resource_mut::<FuzzInput>()
+            .0
+            .push((client_id, bytes));
+    }
+
+    /// Route a message and update once
+    fn send(&mut self, client_id: ClientId, message: ClientMessage) {
+        self.push(client_id, bincode::serialize(&message).unwrap());
+        self.app.update();
+    }
+
+    /// Clear what earlier inputs did to the clients so that every input starts alike
+    fn reset_clients(&mut self) {
+        let mut context = self.app.world.resource_mut::<NetworkContext>();
+        context.pending_disconnects.clear();
+        for client in context.clients.values_mut() {
+            client.violations = 0;
+            client.rate_limits = RateLimits::default();
+        }
+    }
+
+    /// Put the player back into a game after an input took it out of one
+    fn join_game(&mut self) {
+        if in_game(&self.app.world) {
+            return;
+        }
+
+        self.send(
+            player(),
+            ClientMessage::CSCreateGame(CSCreateGame {
+                game_mode: GameMode::Normal,
+                slot: SLOT,
+            }),
+        );
+
+        for _ in 0..JOIN_UPDATES {
+            if in_game(&self.app.world) {
+                self.send(player(), ClientMessage::CSClientReady(CSClientReady));
+                return;
+            }
+            self.app.update();
+        }
+    }
+}
+
+fn in_game(world: &World) -> bool {
+    world
+        .resource::<GameManager>()
+        .get_game_from_client_id(player())
+        .is_some()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chat::ChatManager;
+
+    use rpg_chat::chat::ChannelId;
+
+    fn encode(message: &ClientMessage) -> Vec<u8> {
+        bincode::serialize(message).unwrap()
+    }
+
+    #[test]
+    fn messages_reach_the_handlers() {
+        route_client_message(&encode(&ClientMessage::CSChatJoin(CSChatJoin)));
+
+        SERVER.with(|server| {
+            let server = server.borrow();
+            let world = &server.as_ref().unwrap().app.world;
+
+            let player = world.resource::<NetworkContext>().clients[&player()]
+                .account_id
+                .expect("the player is logged in");
+            assert!(in_game(world));
+
+            let chat = world.resource::<ChatManager>();
+            let channel = chat.get_channel(ChannelId(0)).unwrap();
+            assert_eq!(channel.subscribers, vec![player]);
+        });
+    }
+
+    #[test]
+    fn malformed_and_hostile_messages_do_not_panic() {
+        let inputs = [
+            vec![],
+            vec![0xff; 64],
+            encode(&ClientMessage::CSHello(CSHello {
+                protocol_version: PROTOCOL_VERSION,
+                build_id: BUILD_ID.to_string(),
+                features: vec![],
+            })),
+            encode(&ClientMessage::CSClientReady(CSClientReady)),
+            encode(&ClientMessage::CSLobbyLeave(CSLobbyLeave)),
+            encode(&ClientMessage::CSPlayerLeave(CSPlayerLeave)),
+        ];
+
+        for input in inputs {
+            route_client_message(&input);
+        }
+    }
+}
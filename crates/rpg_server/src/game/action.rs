@@ -1,14 +1,17 @@
 use super::{
-    plugin::{AabbResources, GameState},
+    instance::{GameInstance, GameManager},
+    interest::InterestMap,
+    plugin::AabbResources,
     skill,
+    snapshot::ServerTick,
     unit::can_move,
 };
-use crate::{account::AccountInstance, assets::MetadataResources, net::server::NetworkParamsRW};
+use crate::{assets::MetadataResources, net::server::NetworkParamsRW};
 
-use rpg_core::{skill::SkillUseResult, unit::UnitKind};
+use rpg_core::skill::SkillUseResult;
 use rpg_network_protocol::protocol::*;
 use rpg_util::{
-    actions::{ActionData, ActionKind, State, UnitActions},
+    actions::{movement_step, ActionData, ActionKind, State, UnitActions},
     skill::{SkillSlots, Skills},
     unit::{Corpse, Unit},
 };
@@ -33,10 +36,13 @@ pub(crate) struct MovingUnits(pub(crate) Vec<Entity>);
 // TODO split this up further, rpg_util actions needs to be reworked, each action should implement
 // it's handlers
 // actions should accumulate responses and handle dispatching all network message at once
+#[allow(clippy::too_many_arguments)]
 pub(crate) fn action(
     mut commands: Commands,
     mut net_params: NetworkParamsRW,
-    mut game_state: ResMut<GameState>,
+    interest: Res<InterestMap>,
+    tick: Res<ServerTick>,
+    mut game_manager: ResMut<GameManager>,
     mut moving_units: ResMut<MovingUnits>,
     time: Res<Time>,
     metadata: Res<MetadataResources>,
@@ -50,7 +56,7 @@ pub(crate) fn action(
             &mut Transform,
             &AabbComponent,
             &mut UnitActions,
-            Option<&AccountInstance>,
+            &GameInstance,
         ),
         Without<Corpse>,
     >,
@@ -61,13 +67,17 @@ pub(crate) fn action(
 
     let mut want_move_units = Vec::new();
 
-    for (entity, mut unit, mut skills, skill_slots, mut transform, _, mut actions, account) in
+    for (entity, mut unit, mut skills, skill_slots, mut transform, _, mut actions, game_instance) in
         &mut unit_q
     {
         if actions.is_inactive() {
             continue;
         }
 
+        let Some(game) = game_manager.get_game_mut(game_instance.0) else {
+            continue;
+        };
+
         // All of the following action handlers are in a strict order
 
         // First react to any knockback events, this blocks all other actions
@@ -106,8 +116,14 @@ pub(crate) fn action(
                     }
                     let distance =
                         (attack.user.distance(attack.skill_target.target) * 100.).round() as u32;
-                    let skill_id = skill_slots.slots[0].skill_id.unwrap();
-                    assert_eq!(skill_id, attack.skill_id);
+                    // Only the first slot can be used for now, any other skill is dropped
+                    let Some(skill_id) = skill_slots.slots[0]
+                        .skill_id
+                        .filter(|skill_id| *skill_id == attack.skill_id)
+                    else {
+                        action.state = State::Completed;
+                        continue;
+                    };
 
                     let Some(skill_info) = metadata.rpg.skill.skills.get(&skill_id) else {
                         panic!("skill metadata not found");
@@ -132,14 +148,17 @@ pub(crate) fn action(
                         }
                     }
 
-                    let message = bincode::serialize(&ServerMessage::SCUnitAttack(SCUnitAttack {
+                    let message = ServerMessage::SCUnitAttack(SCUnitAttack {
                         uid: unit.uid,
                         skill_id,
-                    }))
-                    .unwrap();
-                    net_params
-                        .server
-                        .broadcast_message(ServerChannel::Message, message);
+                        tick: tick.0,
+                    });
+                    game.broadcast_relevant(
+                        &mut net_params.outbound,
+                        &interest,
+                        unit.uid,
+                        &message,
+                    );
 
                     let duration = skill_info.use_duration_secs
                         * unit.stats.vitals.stats["Cooldown"].value.f32();
@@ -169,7 +188,7 @@ pub(crate) fn action(
                         state.session_stats.villain_attacks += 1;
                     }*/
 
-                    let instance_uid = game_state.next_instance_uid.get();
+                    let instance_uid = game.next_instance_uid.get();
 
                     let (skill_aabb, skill_transform, skill_use, timer) = skill::prepare_skill(
                         &attack,
@@ -180,11 +199,12 @@ pub(crate) fn action(
                         instance_uid,
                     );
 
-                    game_state.next_instance_uid.next();
+                    game.next_instance_uid.next();
 
                     // debug!("spawning skill");
                     skill::spawn_instance(
                         &mut commands,
+                        *game_instance,
                         skill_aabb,
                         skill_transform,
                         skill_use,
@@ -193,17 +213,20 @@ pub(crate) fn action(
                         timer,
                     );
 
-                    let message = bincode::serialize(&ServerMessage::SCSpawnSkill(SCSpawnSkill {
+                    let message = ServerMessage::SCSpawnSkill(SCSpawnSkill {
                         instance_uid,
                         id: skill.id,
                         owner_uid: unit.uid,
                         target: attack.skill_target.clone(),
-                    }))
-                    .unwrap();
-
-                    net_params
-                        .server
-                        .broadcast_message(ClientChannel::Message, message);
+                        tick: tick.0,
+                    });
+
+                    game.broadcast_relevant(
+                        &mut net_params.outbound,
+                        &interest,
+                        unit.uid,
+                        &message,
+                    );
 
                     // The action is completed at this point
                     action.state = State::Completed;
@@ -233,42 +256,6 @@ pub(crate) fn action(
                 transform.rotation = lerped;
             }
 
-            let direction = transform.forward();
-
-            if unit.kind == UnitKind::Hero {
-                let client = net_params
-                    .context
-                    .get_client_from_account_id(account.as_ref().unwrap().0.info.id)
-                    .unwrap();
-
-                let message =
-                    bincode::serialize(&ServerMessage::SCRotPlayer(SCRotPlayer(*direction)))
-                        .unwrap();
-                net_params
-                    .server
-                    .send_message(client.client_id, ServerChannel::Message, message);
-
-                let message = bincode::serialize(&ServerMessage::SCRotUnit(SCRotUnit {
-                    uid: unit.uid,
-                    direction: *direction,
-                }))
-                .unwrap();
-                net_params.server.broadcast_message_except(
-                    client.client_id,
-                    ServerChannel::Message,
-                    message,
-                );
-            } else {
-                let message = bincode::serialize(&ServerMessage::SCRotUnit(SCRotUnit {
-                    uid: unit.uid,
-                    direction: *direction,
-                }))
-                .unwrap();
-                net_params
-                    .server
-                    .broadcast_message(ServerChannel::Message, message);
-            }
-
             action.state = State::Completed;
         }
 
@@ -288,7 +275,14 @@ pub(crate) fn try_move_units(
     mut moving_units: ResMut<MovingUnits>,
     time: Res<Time>,
     mut move_q: Query<
-        (Entity, &Unit, &Transform, &AabbComponent, &mut UnitActions),
+        (
+            Entity,
+            &Unit,
+            &Transform,
+            &AabbComponent,
+            &GameInstance,
+            &mut UnitActions,
+        ),
         Without<Corpse>,
     >,
 ) {
@@ -298,7 +292,7 @@ pub(crate) fn try_move_units(
         let mut combinations = move_q.iter_combinations_mut();
         'combos: loop {
             let Some(
-                [(l_entity, l_u, l_t, l_aabb, mut l_a), (r_entity, r_u, r_t, r_aabb, mut r_a)],
+                [(l_entity, l_u, l_t, l_aabb, l_g, mut l_a), (r_entity, r_u, r_t, r_aabb, r_g, mut r_a)],
             ) = combinations.fetch_next()
             else {
                 break 'combos;
@@ -308,12 +302,16 @@ pub(crate) fn try_move_units(
                 continue;
             }
 
+            // units in other game instances can never collide
+            if l_g != r_g {
+                continue;
+            }
+
             let is_l = *entity == l_entity;
 
             if is_l {
                 let action = l_a.get_mut(ActionKind::Move).unwrap();
-                let movespeed = l_u.get_effective_movement_speed() as f32 / 100. * dt;
-                let wanted_translation = l_t.translation + *l_t.forward() * movespeed;
+                let wanted_translation = l_t.translation + movement_step(l_u, *l_t.forward(), dt);
 
                 if !can_move(
                     (&wanted_translation, &l_t.rotation, &l_aabb),
@@ -325,8 +323,7 @@ pub(crate) fn try_move_units(
                 }
             } else {
                 let action = r_a.get_mut(ActionKind::Move).unwrap();
-                let movespeed = r_u.get_effective_movement_speed() as f32 / 100. * dt;
-                let wanted_translation = r_t.translation + *r_t.forward() * movespeed;
+                let wanted_translation = r_t.translation + movement_step(r_u, *r_t.forward(), dt);
 
                 if !can_move(
                     (&wanted_translation, &r_t.rotation, &r_aabb),
@@ -347,98 +344,24 @@ pub(crate) fn try_move_units(
 }
 
 pub(crate) fn move_units(
-    mut net_params: NetworkParamsRW,
     mut moving_units: ResMut<MovingUnits>,
     time: Res<Time>,
-    mut move_q: Query<
-        (
-            &mut Unit,
-            &mut Transform,
-            &mut UnitActions,
-            Option<&mut AccountInstance>,
-        ),
-        Without<Corpse>,
-    >,
+    mut move_q: Query<(&mut Unit, &mut Transform, &mut UnitActions), Without<Corpse>>,
 ) {
     let dt = time.delta_seconds();
 
     for entity in moving_units.0.iter() {
-        let (mut m_unit, mut m_t, mut m_action, m_acc) = move_q.get_mut(*entity).unwrap();
+        let (mut m_unit, mut m_t, mut m_action) = move_q.get_mut(*entity).unwrap();
+
         let action = m_action.get_mut(ActionKind::Move).unwrap();
 
-        let movespeed = m_unit.get_effective_movement_speed() as f32 / 100. * dt;
+        let wanted_translation = m_t.translation + movement_step(&m_unit, *m_t.forward(), dt);
         if m_unit.can_run() {
             m_unit.stats.consume_stamina(dt);
         }
-        let wanted_translation = m_t.translation + *m_t.forward() * movespeed;
         m_t.translation = wanted_translation;
 
-        if m_unit.kind == UnitKind::Hero {
-            let client = net_params
-                .context
-                .get_client_from_account_id(m_acc.as_ref().unwrap().0.info.id)
-                .unwrap();
-
-            if action.state == State::Completed {
-                let message = bincode::serialize(&ServerMessage::SCMovePlayerEnd(SCMovePlayerEnd(
-                    m_t.translation,
-                )))
-                .unwrap();
-                net_params
-                    .server
-                    .send_message(client.client_id, ServerChannel::Message, message);
-
-                let message = bincode::serialize(&ServerMessage::SCMoveUnitEnd(SCMoveUnitEnd {
-                    uid: m_unit.uid,
-                    position: m_t.translation,
-                }))
-                .unwrap();
-
-                net_params.server.broadcast_message_except(
-                    client.client_id,
-                    ServerChannel::Message,
-                    message,
-                );
-            } else {
-                let message =
-                    bincode::serialize(&ServerMessage::SCMovePlayer(SCMovePlayer(m_t.translation)))
-                        .unwrap();
-                net_params
-                    .server
-                    .send_message(client.client_id, ServerChannel::Message, message);
-
-                let message = bincode::serialize(&ServerMessage::SCMoveUnit(SCMoveUnit {
-                    uid: m_unit.uid,
-                    position: m_t.translation,
-                }))
-                .unwrap();
-                net_params.server.broadcast_message_except(
-                    client.client_id,
-                    ServerChannel::Message,
-                    message,
-                );
-            }
-        } else {
-            if action.state == State::Completed {
-                let message = bincode::serialize(&ServerMessage::SCMoveUnitEnd(SCMoveUnitEnd {
-                    uid: m_unit.uid,
-                    position: m_t.translation,
-                }))
-                .unwrap();
-                net_params
-                    .server
-                    .broadcast_message(ServerChannel::Message, message);
-            } else {
-                let message = bincode::serialize(&ServerMessage::SCMoveUnit(SCMoveUnit {
-                    uid: m_unit.uid,
-                    position: m_t.translation,
-                }))
-                .unwrap();
-                net_params
-                    .server
-                    .broadcast_message(ServerChannel::Message, message);
-            }
-        }
+        // clients see the unit move in the next snapshot, see `snapshot`
 
         if action.state == State::Pending {
             action.state = State::Active;
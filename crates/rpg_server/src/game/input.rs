@@ -0,0 +1,90 @@
+//! Player movement input
+//!
+//! Clients predict their own hero and send one movement input per tick while moving. Inputs are
+//! queued on the hero and applied one per tick, a hero only moves while it has input so the server
+//! and the client step it the same number of times.
+
+use rpg_util::{
+    actions::{Action, ActionData, ActionKind, State, UnitActions},
+    unit::{Corpse, Hero},
+};
+
+use bevy::{
+    ecs::{
+        component::Component,
+        query::{With, Without},
+        system::Query,
+    },
+    math::Vec3,
+    transform::components::Transform,
+};
+
+use std::collections::VecDeque;
+
+/// Inputs beyond this are dropped oldest first, the client is corrected by the next snapshot
+const MAX_QUEUED_INPUTS: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum MoveInput {
+    Move(Vec3),
+    End,
+}
+
+#[derive(Component, Default, Debug)]
+pub(crate) struct PlayerInputs {
+    queue: VecDeque<(u32, MoveInput)>,
+    /// The sequence number of the newest input applied
+    pub(crate) last_applied: Option<u32>,
+}
+
+impl PlayerInputs {
+    pub(crate) fn push(&mut self, sequence: u32, input: MoveInput) {
+        // inputs arrive in order on a reliable channel, anything else is a misbehaving client
+        let newest = self.queue.back().map(|(s, _)| *s).or(self.last_applied);
+        if newest.is_some_and(|newest| (sequence.wrapping_sub(newest) as i32) <= 0) {
+            return;
+        }
+
+        if self.queue.len() >= MAX_QUEUED_INPUTS {
+            self.queue.pop_front();
+        }
+        self.queue.push_back((sequence, input));
+    }
+}
+
+pub(crate) fn apply_player_inputs(
+    mut hero_q: Query<
+        (&mut Transform, &mut UnitActions, &mut PlayerInputs),
+        (With<Hero>, Without<Corpse>),
+    >,
+) {
+    for (mut transform, mut actions, mut inputs) in &mut hero_q {
+        let input = inputs.queue.pop_front();
+        if let Some((sequence, _)) = input {
+            inputs.last_applied = Some(sequence);
+        }
+
+        match input {
+            Some((_, MoveInput::Move(direction))) => {
+                let direction = direction.normalize_or_zero();
+                if direction == Vec3::ZERO
+                    || actions.is_set(ActionKind::Knockback)
+                    || actions.is_set(ActionKind::Attack)
+                {
+                    continue;
+                }
+
+                // Movement follows the input rather than the hero's current rotation
+                transform.look_to(direction, Vec3::Y);
+                actions.set(Action::new(ActionData::LookDir(direction), None, true));
+                actions.request(Action::new(ActionData::Move(direction), None, true));
+            }
+            // Without input for this tick the hero stops, as it does on the client
+            Some((_, MoveInput::End)) | None => {
+                if let Some(action) = actions.get_mut(ActionKind::Move) {
+                    action.state = State::Completed;
+                }
+            }
+        }
+    }
+}
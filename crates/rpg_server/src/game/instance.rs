@@ -0,0 +1,205 @@
+use super::interest::InterestMap;
+use crate::{
+    net::send::{EncodedMessage, Outbound},
+    world::RpgWorld,
+};
+
+use bevy::{
+    ecs::{component::Component, entity::Entity, system::Resource},
+    log::info,
+};
+
+use rpg_account::{account::AccountId, character::CharacterSlot};
+use rpg_core::{
+    game_mode::GameMode,
+    uid::{NextInstanceUid, Uid},
+};
+use rpg_lobby::lobby::LobbyId;
+use rpg_network_protocol::protocol::*;
+
+use bevy_renet::renet::ClientId;
+
+/// Tags every entity that belongs to a game instance, this includes the heroes of its players
+#[derive(Component, Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) struct GameInstance(pub(crate) GameId);
+
+#[derive(Default, Debug)]
+pub(crate) struct GameOptions {
+    pub(crate) mode: GameMode,
+    pub(crate) max_players: u8,
+}
+
+#[derive(Debug)]
+pub(crate) struct PlayerIdInfo {
+    pub(crate) slot: CharacterSlot,
+    pub(crate) client_id: ClientId,
+    pub(crate) account_id: AccountId,
+    pub(crate) character_id: Uid,
+    pub(crate) entity: Entity,
+}
+
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum GameStatus {
+    /// The game has been created and is waiting for it's world to be spawned
+    #[default]
+    Spawning,
+    Running,
+    /// All players have left, the game will be despawned
+    Ending,
+}
+
+pub(crate) struct Game {
+    pub(crate) id: GameId,
+    pub(crate) status: GameStatus,
+    pub(crate) players: Vec<PlayerIdInfo>,
+    pub(crate) options: GameOptions,
+    pub(crate) next_instance_uid: NextInstanceUid,
+    pub(crate) world: RpgWorld,
+    /// The lobby that started the game, players return to it when they leave
+    pub(crate) lobby_id: Option<LobbyId>,
+}
+
+impl Game {
+    pub(crate) fn new(id: GameId, options: GameOptions) -> Self {
+        Self {
+            id,
+            status: GameStatus::default(),
+            players: vec![],
+            options,
+            next_instance_uid: NextInstanceUid::default(),
+            world: RpgWorld::default(),
+            lobby_id: None,
+        }
+    }
+
+    pub(crate) fn get_id_info_from_uid(&self, uid: Uid) -> Option<&PlayerIdInfo> {
+        self.players.iter().find(|p| p.character_id == uid)
+    }
+
+    pub(crate) fn get_id_info_from_account_id(
+        &self,
+        account_id: AccountId,
+    ) -> Option<&PlayerIdInfo> {
+        self.players.iter().find(|p| p.account_id == account_id)
+    }
+
+    pub(crate) fn is_full(&self) -> bool {
+        self.players.len() >= self.options.max_players as usize
+    }
+
+    /// Check if new players may join the game
+    pub(crate) fn is_joinable(&self) -> bool {
+        self.status != GameStatus::Ending && !self.is_full()
+    }
+
+    /// Send a message to every player in the game
+    pub(crate) fn broadcast_message(&self, outbound: &mut Outbound, message: &ServerMessage) {
+        let message = EncodedMessage::new(message);
+        for player in &self.players {
+            outbound.push(player.client_id, &message);
+        }
+    }
+
+    /// Send a message about the unit or ground item `uid` to every player that can see it, a
+    /// player can always see their own hero
+    pub(crate) fn broadcast_relevant(
+        &self,
+        outbound: &mut Outbound,
+        interest: &InterestMap,
+        uid: Uid,
+        message: &ServerMessage,
+    ) {
+        let message = EncodedMessage::new(message);
+        for player in self
+            .players
+            .iter()
+            .filter(|p| p.character_id == uid || interest.is_relevant(p.client_id, uid))
+        {
+            outbound.push(player.client_id, &message);
+        }
+    }
+
+    /// Send a message about `uid` to every player that can see it except `except_id`
+    pub(crate) fn broadcast_relevant_except(
+        &self,
+        outbound: &mut Outbound,
+        interest: &InterestMap,
+        uid: Uid,
+        except_id: ClientId,
+        message: &ServerMessage,
+    ) {
+        let message = EncodedMessage::new(message);
+        for player in self.players.iter().filter(|p| {
+            p.client_id != except_id
+                && (p.character_id == uid || interest.is_relevant(p.client_id, uid))
+        }) {
+            outbound.push(player.client_id, &message);
+        }
+    }
+}
+
+#[derive(Default, Resource)]
+pub(crate) struct GameManager {
+    pub(crate) games: Vec<Game>,
+    pub(crate) next_game_id: GameId,
+}
+
+impl GameManager {
+    pub(crate) fn create_game(&mut self, options: GameOptions) -> GameId {
+        let id = self.next_game_id;
+        self.next_game_id.0 += 1;
+
+        self.games.push(Game::new(id, options));
+
+        id
+    }
+
+    pub(crate) fn remove_game(&mut self, id: GameId) {
+        self.games.retain(|g| g.id != id);
+    }
+
+    pub(crate) fn get_game(&self, id: GameId) -> Option<&Game> {
+        self.games.iter().find(|g| g.id == id)
+    }
+
+    pub(crate) fn get_game_mut(&mut self, id: GameId) -> Option<&mut Game> {
+        self.games.iter_mut().find(|g| g.id == id)
+    }
+
+    pub(crate) fn get_game_from_client_id(&self, client_id: ClientId) -> Option<&Game> {
+        self.games
+            .iter()
+            .find(|g| g.players.iter().any(|p| p.client_id == client_id))
+    }
+
+    pub(crate) fn get_game_from_account_id(&self, account_id: AccountId) -> Option<&Game> {
+        self.games
+            .iter()
+            .find(|g| g.get_id_info_from_account_id(account_id).is_some())
+    }
+
+    /// Find a game of the given mode that is accepting players
+    pub(crate) fn find_open_game(&self, mode: GameMode) -> Option<GameId> {
+        self.games
+            .iter()
+            .find(|g| g.options.mode == mode && g.is_joinable())
+            .map(|g| g.id)
+    }
+
+    /// Remove a client from the game it is playing, a game that has no remaining players is
+    /// marked as ending
+    pub(crate) fn remove_player(&mut self, client_id: ClientId) -> Option<GameId> {
+        let game = self
+            .games
+            .iter_mut()
+            .find(|g| g.players.iter().any(|p| p.client_id == client_id))?;
+
+        game.players.retain(|p| p.client_id != client_id);
+        if game.players.is_empty() {
+            info!("no players remain, ending game {:?}", game.id);
+            game.status = GameStatus::Ending;
+        }
+
+        Some(game.id)
+    }
+}
@@ -0,0 +1,203 @@
+//! Area of interest
+//!
+//! Each player is only told about the units and ground items in the same zone within
+//! `simulation.interest_radius` of their hero. Entities are spawned on a client when they become
+//! relevant and despawned once they leave the radius, updates about anything else are dropped.
+//! Spawns and despawns are delivered in order so a client never sees them the wrong way around.
+
+use super::{
+    instance::{GameInstance, GameManager, GameStatus},
+    item::GroundItem,
+};
+use crate::{config::ServerSettings, net::server::NetworkParamsRW, world::InZone};
+
+use rpg_core::{item::ItemDrops, uid::Uid, unit::UnitKind};
+use rpg_network_protocol::protocol::*;
+use rpg_util::{
+    skill::{SkillSlots, Skills},
+    unit::{Corpse, Unit},
+};
+
+use bevy::{
+    ecs::{
+        query::Has,
+        system::{Query, Res, ResMut, Resource},
+    },
+    transform::components::Transform,
+};
+
+use bevy_renet::renet::ClientId;
+
+use std::collections::{HashMap, HashSet};
+
+/// Entities are kept a little past the radius so one moving along the edge is not spawned and
+/// despawned repeatedly
+const INTEREST_HYSTERESIS: f32 = 4.0;
+
+/// The entities a client has been told about
+#[derive(Default, Debug)]
+pub(crate) struct ClientInterest {
+    units: HashSet<Uid>,
+    items: HashSet<Uid>,
+}
+
+#[derive(Resource, Default)]
+pub(crate) struct InterestMap(HashMap<ClientId, ClientInterest>);
+
+impl InterestMap {
+    /// Check if a client knows about a unit or ground item
+    pub(crate) fn is_relevant(&self, client_id: ClientId, uid: Uid) -> bool {
+        self.0
+            .get(&client_id)
+            .is_some_and(|i| i.units.contains(&uid) || i.items.contains(&uid))
+    }
+
+    /// Forget an entity that has been despawned on every client that knew about it
+    pub(crate) fn forget(&mut self, uid: Uid) {
+        for interest in self.0.values_mut() {
+            interest.units.remove(&uid);
+            interest.items.remove(&uid);
+        }
+    }
+
+    /// Forget everything a client was told, called when it leaves a game
+    pub(crate) fn remove_client(&mut self, client_id: ClientId) {
+        self.0.remove(&client_id);
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn update_interest(
+    settings: Res<ServerSettings>,
+    game_manager: Res<GameManager>,
+    mut interest: ResMut<InterestMap>,
+    mut net_params: NetworkParamsRW,
+    unit_q: Query<(
+        &Transform,
+        &Unit,
+        &Skills,
+        &SkillSlots,
+        &GameInstance,
+        &InZone,
+        Has<Corpse>,
+    )>,
+    item_q: Query<(&Transform, &GroundItem, &GameInstance, &InZone)>,
+) {
+    let radius = settings.simulation.interest_radius;
+
+    for game in &game_manager.games {
+        if game.status != GameStatus::Running {
+            continue;
+        }
+
+        for player in &game.players {
+            // the hero is only spawned once the client has finished loading
+            let Ok((viewer, _, _, _, _, viewer_zone, _)) = unit_q.get(player.entity) else {
+                continue;
+            };
+
+            let client_interest = interest.0.entry(player.client_id).or_default();
+            let is_relevant = |known: bool, transform: &Transform, zone: &InZone| {
+                let radius = if known {
+                    radius + INTEREST_HYSTERESIS
+                } else {
+                    radius
+                };
+
+                zone == viewer_zone && transform.translation.distance(viewer.translation) <= radius
+            };
+
+            let mut seen_units = HashSet::new();
+            for (transform, unit, skills, skill_slots, unit_game, zone, is_corpse) in &unit_q {
+                if unit_game.0 != game.id || unit.uid == player.character_id {
+                    continue;
+                }
+                seen_units.insert(unit.uid);
+
+                let known = client_interest.units.contains(&unit.uid);
+                match (known, is_relevant(known, transform, zone)) {
+                    // corpses are only ever despawned, there is nothing to see
+                    (false, true) if !is_corpse => {
+                        let message = match unit.kind {
+                            UnitKind::Hero => ServerMessage::SCSpawnHero(SCSpawnHero {
+                                position: transform.translation,
+                                uid: unit.uid,
+                                name: unit.name.clone(),
+                                class: unit.class,
+                                level: unit.level,
+                                deaths: None,
+                                skills: skills.0.clone(),
+                                skill_slots: skill_slots.slots.clone(),
+                            }),
+                            UnitKind::Villain => ServerMessage::SCSpawnVillain(SCSpawnVillain {
+                                position: transform.translation,
+                                direction: *transform.forward(),
+                                uid: unit.uid,
+                                level: unit.level,
+                                info: unit.info.villain().clone(),
+                                skills: skills.0.clone(),
+                                skill_slots: skill_slots.slots.clone(),
+                            }),
+                        };
+
+                        net_params.outbound.send(player.client_id, &message);
+                        client_interest.units.insert(unit.uid);
+                    }
+                    (true, false) => {
+                        let message = ServerMessage::SCDespawnUnit(SCDespawnUnit(unit.uid));
+                        net_params.outbound.send(player.client_id, &message);
+                        client_interest.units.remove(&unit.uid);
+                    }
+                    _ => {}
+                }
+            }
+
+            let mut seen_items = HashSet::new();
+            for (transform, item, item_game, zone) in &item_q {
+                if item_game.0 != game.id {
+                    continue;
+                }
+                seen_items.insert(item.0.uid);
+
+                let known = client_interest.items.contains(&item.0.uid);
+                match (known, is_relevant(known, transform, zone)) {
+                    (false, true) => {
+                        let message = ServerMessage::SCSpawnItem(SCSpawnItem {
+                            position: transform.translation,
+                            items: ItemDrops {
+                                source: item.0.uid,
+                                items: vec![item.0.clone()],
+                            },
+                        });
+                        net_params.outbound.send(player.client_id, &message);
+                        client_interest.items.insert(item.0.uid);
+                    }
+                    (true, false) => {
+                        let message = ServerMessage::SCDespawnItem(SCDespawnItem(item.0.uid));
+                        net_params.outbound.send(player.client_id, &message);
+                        client_interest.items.remove(&item.0.uid);
+                    }
+                    _ => {}
+                }
+            }
+
+            // anything the client knows about that no longer exists has been despawned
+            client_interest.units.retain(|uid| {
+                let exists = seen_units.contains(uid);
+                if !exists {
+                    let message = ServerMessage::SCDespawnUnit(SCDespawnUnit(*uid));
+                    net_params.outbound.send(player.client_id, &message);
+                }
+                exists
+            });
+            client_interest.items.retain(|uid| {
+                let exists = seen_items.contains(uid);
+                if !exists {
+                    let message = ServerMessage::SCDespawnItem(SCDespawnItem(*uid));
+                    net_params.outbound.send(player.client_id, &message);
+                }
+                exists
+            });
+        }
+    }
+}
@@ -1,6 +1,11 @@
-use super::plugin::{AabbResources, GameSessionCleanup};
+use super::{
+    instance::GameInstance,
+    plugin::{AabbResources, GameSessionCleanup},
+};
+
+use crate::world::InZone;
 
-use rpg_core::{item::Item, uid::Uid};
+use rpg_core::item::Item;
 use rpg_util::{item::GroundItemDrops, unit::Unit};
 
 use util::{cleanup::CleanupStrategy, math::AabbComponent};
@@ -14,17 +19,20 @@ use bevy::{
     transform::components::Transform,
 };
 
+/// Ground items float this far above the ground the units stand on
+pub(crate) const GROUND_ITEM_HEIGHT: f32 = 0.8;
+
 #[derive(Component)]
-pub(crate) struct GroundItem(pub(crate) Uid);
+pub(crate) struct GroundItem(pub(crate) Item);
 
 pub(crate) fn spawn_ground_items(
     mut commands: Commands,
     aabbs: Res<AabbResources>,
     mut ground_drop_items: ResMut<GroundItemDrops>,
-    mut unit_q: Query<(&Transform, &Unit)>,
+    mut unit_q: Query<(&Transform, &Unit, &GameInstance, &InZone)>,
 ) {
     while let Some(items) = ground_drop_items.0.pop() {
-        for (source_transform, source_unit) in &mut unit_q {
+        for (source_transform, source_unit, game, zone) in &mut unit_q {
             if source_unit.uid != items.source {
                 continue;
             }
@@ -33,6 +41,8 @@ pub(crate) fn spawn_ground_items(
                 spawn_item(
                     &mut commands,
                     &aabbs,
+                    *game,
+                    *zone,
                     source_transform.translation,
                     item.clone(),
                 );
@@ -41,17 +51,26 @@ pub(crate) fn spawn_ground_items(
     }
 }
 
-fn spawn_item(commands: &mut Commands, aabbs: &AabbResources, position: Vec3, item: Item) {
+fn spawn_item(
+    commands: &mut Commands,
+    aabbs: &AabbResources,
+    game: GameInstance,
+    zone: InZone,
+    position: Vec3,
+    item: Item,
+) {
     // info!("spawning ground item at {position:?}");
     let aabb = AabbComponent(aabbs.aabbs["item_normal"]);
 
-    let transform = Transform::from_xyz(position.x, 0.8, position.z);
+    let transform = Transform::from_xyz(position.x, GROUND_ITEM_HEIGHT, position.z);
 
     commands.spawn((
         GameSessionCleanup,
         CleanupStrategy::DespawnRecursive,
+        game,
+        zone,
         transform,
-        GroundItem(item.uid),
+        GroundItem(item),
         aabb,
     ));
 }
@@ -1,7 +1,11 @@
 pub(crate) mod action;
 
+pub(crate) mod input;
+pub(crate) mod instance;
+pub(crate) mod interest;
 pub(crate) mod item;
 pub(crate) mod skill;
+pub(crate) mod snapshot;
 pub(crate) mod unit;
 pub(crate) mod villain;
 
@@ -1,88 +1,101 @@
-use super::{action, item, skill, unit, villain};
+use super::{
+    action, input,
+    instance::{GameInstance, GameManager, GameStatus},
+    interest::{self, InterestMap},
+    item, skill,
+    snapshot::{self, ServerTick, SnapshotHistory},
+    unit, villain,
+};
 
 use crate::{
+    account::{self, AutosaveTimer},
     assets::MetadataResources,
+    config::ServerSettings,
     net::server::NetworkParamsRW,
     server_state::ServerMetadataResource,
+    shutdown::ShutdownCountdown,
     state::AppState,
+    store::AccountStorage,
     world::{self, WorldPlugin},
 };
 
 use bevy::{
-    app::{App, FixedPreUpdate, FixedUpdate, Plugin, Update},
+    app::{App, FixedFirst, FixedPostUpdate, FixedPreUpdate, FixedUpdate, Last, Plugin, Update},
     ecs::{
         component::Component,
         entity::Entity,
-        schedule::{common_conditions::in_state, IntoSystemConfigs, NextState, OnEnter},
-        system::{Commands, Res, ResMut, Resource},
+        query::With,
+        schedule::{
+            common_conditions::{in_state, not, resource_exists},
+            IntoSystemConfigs,
+        },
+        system::{Commands, Query, Res, ResMut, Resource},
     },
+    hierarchy::DespawnRecursiveExt,
     log::info,
     math::{bounding::Aabb3d, Vec3},
 };
 
-use rpg_account::{account::AccountId, character::CharacterSlot};
-use rpg_core::{
-    game_mode::GameMode,
-    uid::{NextInstanceUid, Uid},
-    villain::VillainId,
-};
+use rpg_core::villain::VillainId;
 use rpg_network_protocol::protocol::*;
 use rpg_util::{
     item::GroundItemDrops,
     skill::{clean_skills, update_skill, SkillContactEvent},
 };
+use rpg_world::zone::ZoneId;
 
-use util::random::{Rng, SharedRng};
-
-use bevy_renet::renet::ClientId;
+use util::{cleanup::CleanupStrategy, random::SharedRng};
 
 use std::borrow::Cow;
 use std::collections::HashMap;
 
-#[derive(Default, Debug)]
-pub(crate) struct GameOptions {
-    pub(crate) mode: GameMode,
-    pub(crate) max_players: u8,
-}
-
-#[derive(Debug)]
-pub(crate) struct PlayerIdInfo {
-    pub(crate) slot: CharacterSlot,
-    pub(crate) client_id: ClientId,
-    pub(crate) account_id: AccountId,
-    pub(crate) character_id: Uid,
-    pub(crate) entity: Entity,
-}
-
-#[derive(Default, Debug, Resource)]
-pub(crate) struct GameState {
-    pub(crate) players: Vec<PlayerIdInfo>,
-    pub(crate) options: GameOptions,
-    pub(crate) next_instance_uid: NextInstanceUid,
-}
-
-#[derive(Default, Resource)]
+#[derive(Resource)]
 pub(crate) struct AabbResources {
     pub(crate) aabbs: HashMap<Cow<'static, str>, Aabb3d>,
 }
 
-impl GameState {
-    pub(crate) fn client_ids(&self) -> Vec<ClientId> {
-        self.players.iter().map(|p| p.client_id).collect()
-    }
+impl Default for AabbResources {
+    fn default() -> Self {
+        // FIXME more aabbs need to be inserted
+        let mut aabbs = HashMap::new();
+
+        aabbs.insert(
+            Cow::Borrowed("hero"),
+            Aabb3d {
+                min: Vec3::new(-0.3, 0.0, -0.25),
+                max: Vec3::new(0.3, 1.8, 0.25),
+            },
+        );
 
-    pub(crate) fn get_id_info_from_uid(&self, uid: Uid) -> Option<&PlayerIdInfo> {
-        self.players.iter().find(|p| p.character_id == uid)
-    }
+        aabbs.insert(
+            Cow::Borrowed("direct_attack"),
+            Aabb3d {
+                min: Vec3::new(-0.1, -0.1, -0.5),
+                max: Vec3::new(0.1, 0.1, 0.5),
+            },
+        );
+
+        aabbs.insert(
+            Cow::Borrowed("item_normal"),
+            Aabb3d {
+                min: Vec3::new(-0.2, -0.2, -0.2),
+                max: Vec3::new(0.2, 0.2, 0.2),
+            },
+        );
+
+        aabbs.insert(
+            Cow::Borrowed("bolt_01"),
+            Aabb3d {
+                min: Vec3::new(-0.1, -0.1, -0.25),
+                max: Vec3::new(0.1, 0.1, 0.25),
+            },
+        );
 
-    pub(crate) fn get_id_info_from_account_id(
-        &self,
-        account_id: AccountId,
-    ) -> Option<&PlayerIdInfo> {
-        self.players.iter().find(|p| p.account_id == account_id)
+        Self { aabbs }
     }
 }
 
+/// Marks entities that are despawned when their game ends
 #[derive(Default, Component)]
 pub(crate) struct GameSessionCleanup;
 
@@ -92,38 +105,38 @@ impl Plugin for GamePlugin {
     fn build(&self, app: &mut App) {
         app.add_plugins(WorldPlugin)
             .add_event::<SkillContactEvent>()
-            .init_resource::<GameState>()
+            .init_resource::<GameManager>()
+            .init_resource::<InterestMap>()
+            .init_resource::<ServerTick>()
+            .init_resource::<SnapshotHistory>()
             .init_resource::<AabbResources>()
             .init_resource::<action::MovingUnits>()
             .init_resource::<GroundItemDrops>()
-            .insert_resource(SharedRng(Rng::with_seed(1234)))
-            .add_systems(OnEnter(AppState::SpawnSimulation), setup_simulation)
+            .init_resource::<AutosaveTimer>()
             .add_systems(
                 Update,
-                transition_to_game
-                    .run_if(in_state(AppState::SpawnSimulation))
-                    .after(world::spawn_world),
+                (setup_games.after(world::spawn_world), cleanup_games)
+                    .run_if(in_state(AppState::Running)),
             )
-            .add_systems(OnEnter(AppState::Simulation), join_clients)
             .add_systems(
-                OnEnter(AppState::CleanupSimulation),
-                (util::cleanup::cleanup::<GameSessionCleanup>, world::cleanup),
+                Update,
+                account::autosave_accounts.run_if(in_state(AppState::Running)),
             )
             .add_systems(
-                Update,
-                transition_to_lobby.run_if(in_state(AppState::CleanupSimulation)),
+                Last,
+                account::save_accounts_on_exit.run_if(not(resource_exists::<ShutdownCountdown>)),
             )
             .add_systems(
                 FixedPreUpdate,
                 (
-                    villain::remote_spawn,
+                    interest::update_interest,
                     skill::update_invulnerability,
                     unit::remove_corpses,
                     clean_skills,
                     unit::upkeep,
                     item::spawn_ground_items,
                 )
-                    .run_if(in_state(AppState::Simulation)),
+                    .run_if(in_state(AppState::Running)),
             )
             .add_systems(
                 FixedUpdate,
@@ -133,6 +146,7 @@ impl Plugin for GamePlugin {
                     skill::handle_contacts,
                     villain::find_target,
                     villain::villain_think,
+                    input::apply_player_inputs,
                     action::action,
                     action::try_move_units,
                     action::move_units,
@@ -140,88 +154,97 @@ impl Plugin for GamePlugin {
                     //unit::collide_units,
                 )
                     .chain()
-                    .run_if(in_state(AppState::Simulation)),
+                    .run_if(in_state(AppState::Running)),
+            )
+            .add_systems(
+                FixedFirst,
+                snapshot::advance_tick.run_if(in_state(AppState::Running)),
+            )
+            .add_systems(
+                FixedPostUpdate,
+                snapshot::send_snapshots.run_if(in_state(AppState::Running)),
             );
     }
 }
 
-pub(crate) fn setup_simulation(
+/// Spawn the villains of newly created games and join their players once the world is loaded
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn setup_games(
     mut commands: Commands,
     mut rng: ResMut<SharedRng>,
+    settings: Res<ServerSettings>,
     metadata: Res<MetadataResources>,
-    mut aabbs: ResMut<AabbResources>,
+    aabbs: Res<AabbResources>,
+    mut store: ResMut<AccountStorage>,
     mut server_metadata: ResMut<ServerMetadataResource>,
+    mut game_manager: ResMut<GameManager>,
+    mut net_params: NetworkParamsRW,
 ) {
-    info!("spawning game");
-
-    // FIXME more aabbs need to be inserted, impl FromWorld and move
-    aabbs.aabbs.insert(
-        Cow::Owned("hero".into()),
-        Aabb3d {
-            min: Vec3::new(-0.3, 0.0, -0.25),
-            max: Vec3::new(0.3, 1.8, 0.25),
-        },
-    );
+    for game in &mut game_manager.games {
+        if game.status != GameStatus::Spawning || game.world.zones.is_empty() {
+            continue;
+        }
+
+        info!("spawning game {:?}", game.id);
+
+        for _ in 0..settings.simulation.villain_count {
+            let position = Vec3::new(rng.f32() * 128.0 - 64.0, 0., rng.f32() * 128.0 - 64.0);
+
+            let villain_id = VillainId::sample(&mut rng);
+            villain::spawn(
+                &mut commands,
+                game.id,
+                // only the starting town is spawned for now
+                ZoneId(0),
+                &mut server_metadata.0.next_uid,
+                &position,
+                &metadata.rpg,
+                aabbs.aabbs["hero"],
+                villain_id,
+            );
+        }
 
-    aabbs.aabbs.insert(
-        Cow::Owned("direct_attack".into()),
-        Aabb3d {
-            min: Vec3::new(-0.1, -0.1, -0.5),
-            max: Vec3::new(0.1, 0.1, 0.5),
-        },
-    );
+        // Villain uids come from the same counter as heroes and items, it must never run backwards
+        if let Err(e) = store.save_metadata(&server_metadata.0) {
+            info!("unable to save server metadata: {e}");
+        }
 
-    aabbs.aabbs.insert(
-        Cow::Owned("item_normal".into()),
-        Aabb3d {
-            min: Vec3::new(-0.2, -0.2, -0.2),
-            max: Vec3::new(0.2, 0.2, 0.2),
-        },
-    );
+        game.status = GameStatus::Running;
 
-    aabbs.aabbs.insert(
-        Cow::Owned("bolt_01".into()),
-        Aabb3d {
-            min: Vec3::new(-0.1, -0.1, -0.25),
-            max: Vec3::new(0.1, 0.1, 0.25),
-        },
-    );
-
-    for _ in 0..32 {
-        let position = Vec3::new(rng.f32() * 128.0 - 64.0, 0., rng.f32() * 128.0 - 64.0);
-
-        let villain_id = VillainId::sample(&mut rng);
-        villain::spawn(
-            &mut commands,
-            &mut server_metadata.0.next_uid,
-            &position,
-            &metadata.rpg,
-            aabbs.aabbs["hero"],
-            villain_id,
-        );
-    }
-
-    // TODO write server metadata
-}
-
-pub(crate) fn transition_to_game(mut state: ResMut<NextState<AppState>>) {
-    info!("transitioning to game simulation");
-    state.set(AppState::Simulation);
-}
+        info!("joining clients to game {:?}", game.id);
 
-pub(crate) fn transition_to_lobby(mut state: ResMut<NextState<AppState>>) {
-    info!("transitioning to lobby");
-    state.set(AppState::Lobby);
+        let message = ServerMessage::SCPlayerJoinSuccess(SCPlayerJoinSuccess);
+        game.broadcast_message(&mut net_params.outbound, &message);
+    }
 }
 
-pub(crate) fn join_clients(game_state: ResMut<GameState>, mut net_params: NetworkParamsRW) {
-    info!("joining clients to game");
-
-    let message =
-        bincode::serialize(&ServerMessage::SCPlayerJoinSuccess(SCPlayerJoinSuccess)).unwrap();
-    for client_id in game_state.client_ids() {
-        net_params
-            .server
-            .send_message(client_id, ServerChannel::Message, message.clone());
+/// Despawn games that no longer have any players
+pub(crate) fn cleanup_games(
+    mut commands: Commands,
+    mut game_manager: ResMut<GameManager>,
+    cleanup_q: Query<(Entity, &CleanupStrategy, &GameInstance), With<GameSessionCleanup>>,
+) {
+    let ending: Vec<_> = game_manager
+        .games
+        .iter()
+        .filter(|g| g.status == GameStatus::Ending)
+        .map(|g| g.id)
+        .collect();
+
+    for game_id in ending {
+        info!("cleaning up game {game_id:?}");
+
+        for (entity, strategy, instance) in &cleanup_q {
+            if instance.0 != game_id {
+                continue;
+            }
+
+            match strategy {
+                CleanupStrategy::Despawn => commands.entity(entity).despawn(),
+                CleanupStrategy::DespawnRecursive => commands.entity(entity).despawn_recursive(),
+            }
+        }
+
+        game_manager.remove_game(game_id);
     }
 }
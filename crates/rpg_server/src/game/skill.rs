@@ -1,9 +1,12 @@
 use super::{
-    plugin::{AabbResources, GameSessionCleanup, GameState},
+    instance::{GameInstance, GameManager},
+    interest::InterestMap,
+    plugin::{AabbResources, GameSessionCleanup},
     unit::CorpseTimer,
 };
 use crate::{
-    assets::MetadataResources, net::server::NetworkParamsRW, server_state::ServerMetadataResource,
+    assets::MetadataResources, config::ServerSettings, net::server::NetworkParamsRW,
+    server_state::ServerMetadataResource,
 };
 
 use rpg_core::{
@@ -214,6 +217,7 @@ pub(crate) fn prepare_skill(
 
 pub(crate) fn spawn_instance(
     commands: &mut Commands,
+    game: GameInstance,
     aabb: Aabb3d,
     transform: Transform,
     skill_use_instance: SkillUse,
@@ -227,6 +231,7 @@ pub(crate) fn spawn_instance(
         .spawn((
             GameSessionCleanup,
             CleanupStrategy::DespawnRecursive,
+            game,
             AabbComponent(aabb),
             Invulnerability::default(),
             skill_use,
@@ -252,11 +257,14 @@ pub(crate) fn collide_skills(
         &Invulnerability,
         &SkillUse,
         &SkillOwner,
+        &GameInstance,
         Option<&mut SkillTimer>,
     )>,
-    unit_q: Query<(Entity, &Transform, &AabbComponent, &Unit), Without<Corpse>>,
+    unit_q: Query<(Entity, &Transform, &AabbComponent, &Unit, &GameInstance), Without<Corpse>>,
 ) {
-    for (s_entity, s_transform, s_aabb, invulnerability, instance, owner, mut timer) in &skill_q {
+    for (s_entity, s_transform, s_aabb, invulnerability, instance, owner, s_game, mut timer) in
+        &skill_q
+    {
         if let Some(SkillTimer::Tickable(tickable)) = &mut timer {
             if !tickable.can_damage {
                 continue;
@@ -264,8 +272,9 @@ pub(crate) fn collide_skills(
         }
 
         let owner_kind = owner.owner_kind;
-        for (u_entity, u_transform, u_aabb, unit) in &unit_q {
-            if !unit.is_alive()
+        for (u_entity, u_transform, u_aabb, unit, u_game) in &unit_q {
+            if u_game != s_game
+                || !unit.is_alive()
                 || unit.uid == instance.owner
                 || unit.kind == owner_kind
                 || invulnerability.iter().any(|i| i.entity == u_entity)
@@ -311,10 +320,12 @@ pub(crate) fn collide_skills(
 pub fn handle_contacts(
     mut commands: Commands,
     time: Res<Time>,
+    settings: Res<ServerSettings>,
     metadata: Res<MetadataResources>,
-    game_state: Res<GameState>,
+    game_manager: Res<GameManager>,
     mut server_metadata: ResMut<ServerMetadataResource>,
     mut net_params: NetworkParamsRW,
+    interest: Res<InterestMap>,
     mut ground_drops: ResMut<GroundItemDrops>,
     mut rng: ResMut<SharedRng>,
     mut skill_events: EventReader<SkillContactEvent>,
@@ -323,6 +334,7 @@ pub fn handle_contacts(
         &mut Transform,
         &mut Invulnerability,
         &mut SkillUse,
+        &GameInstance,
         Option<&mut SkillTimer>,
     )>,
     mut unit_q: Query<
@@ -337,9 +349,8 @@ pub fn handle_contacts(
     >,
 ) {
     for event in skill_events.read() {
-        let Ok(
-            [(_, mut attacker, _, _, _), (d_entity, mut defender, mut d_actions, d_transform, d_corpse)],
-        ) = unit_q.get_many_mut([event.owner, event.defender])
+        let Ok([(_, mut attacker, _, _, _), (d_entity, mut defender, mut d_actions, _, d_corpse)]) =
+            unit_q.get_many_mut([event.owner, event.defender])
         else {
             panic!("Unable to query attacker and/or defender unit(s)");
         };
@@ -348,8 +359,11 @@ pub fn handle_contacts(
             continue;
         }
 
-        let (s_entity, mut s_transform, mut invulnerability, mut skill_use, timer) =
+        let (s_entity, mut s_transform, mut invulnerability, mut skill_use, s_game, timer) =
             skill_q.get_mut(event.entity).unwrap();
+        let Some(game) = game_manager.get_game(s_game.0) else {
+            continue;
+        };
         let combat_result =
             defender.handle_attack(&mut attacker, &metadata.rpg, &mut rng.0, &skill_use.damage);
 
@@ -364,14 +378,16 @@ pub fn handle_contacts(
                     game_state.session_stats.times_blocked += 1;
                 }*/
 
-                let message = bincode::serialize(&ServerMessage::SCUnitAnim(SCUnitAnim {
+                let message = ServerMessage::SCUnitAnim(SCUnitAnim {
                     uid: defender.uid,
                     anim: 1,
-                }))
-                .unwrap();
-                net_params
-                    .server
-                    .broadcast_message(ServerChannel::Message, message);
+                });
+                game.broadcast_relevant(
+                    &mut net_params.outbound,
+                    &interest,
+                    defender.uid,
+                    &message,
+                );
 
                 match &skill_use.instance {
                     SkillInstance::Direct(_) | SkillInstance::Projectile(_) => {
@@ -388,14 +404,16 @@ pub fn handle_contacts(
                 } else {
                     game_state.session_stats.times_dodged += 1;
                 }*/
-                let message = bincode::serialize(&ServerMessage::SCUnitAnim(SCUnitAnim {
+                let message = ServerMessage::SCUnitAnim(SCUnitAnim {
                     uid: defender.uid,
                     anim: 0,
-                }))
-                .unwrap();
-                net_params
-                    .server
-                    .broadcast_message(ServerChannel::Message, message);
+                });
+                game.broadcast_relevant(
+                    &mut net_params.outbound,
+                    &interest,
+                    defender.uid,
+                    &message,
+                );
             }
             CombatResult::Damage(damage) => {
                 /*if defender.kind == UnitKind::Villain {
@@ -405,26 +423,22 @@ pub fn handle_contacts(
                 }*/
 
                 if defender.kind == UnitKind::Hero {
-                    let id_info = game_state.get_id_info_from_uid(defender.uid).unwrap();
-
-                    let message = bincode::serialize(&ServerMessage::SCCombatResult(
-                        SCCombatResult(combat_result.clone()),
-                    ))
-                    .unwrap();
-                    net_params.server.send_message(
-                        id_info.client_id,
-                        ServerChannel::Message,
-                        message,
-                    );
+                    let id_info = game.get_id_info_from_uid(defender.uid).unwrap();
+
+                    let message =
+                        ServerMessage::SCCombatResult(SCCombatResult(combat_result.clone()));
+                    net_params.outbound.send(id_info.client_id, &message);
                 } else if defender.kind == UnitKind::Villain {
-                    let message = bincode::serialize(&ServerMessage::SCDamage(SCDamage {
+                    let message = ServerMessage::SCDamage(SCDamage {
                         uid: defender.uid,
                         damage: damage.clone(),
-                    }))
-                    .unwrap();
-                    net_params
-                        .server
-                        .broadcast_message(ServerChannel::Message, message);
+                    });
+                    game.broadcast_relevant(
+                        &mut net_params.outbound,
+                        &interest,
+                        defender.uid,
+                        &message,
+                    );
                 }
 
                 if let SkillInstance::Projectile(_) = &skill_use.instance {
@@ -447,12 +461,13 @@ pub fn handle_contacts(
 
                 // game_state.session_stats.villain_hits += 1;
 
-                let message =
-                    bincode::serialize(&ServerMessage::SCHeroDeath(SCHeroDeath(defender.uid)))
-                        .unwrap();
-                net_params
-                    .server
-                    .broadcast_message(ServerChannel::Message, message);
+                let message = ServerMessage::SCHeroDeath(SCHeroDeath(defender.uid));
+                game.broadcast_relevant(
+                    &mut net_params.outbound,
+                    &interest,
+                    defender.uid,
+                    &message,
+                );
 
                 commands.entity(event.defender).insert((Corpse,));
             }
@@ -472,44 +487,32 @@ pub fn handle_contacts(
                 ) {
                     //game_state.session_stats.items_spawned += death.items.len() as u32;
 
-                    let drops = ItemDrops {
+                    // clients are told about the items once they are spawned, see `interest`
+                    ground_drops.0.push(ItemDrops {
                         source: defender.uid,
                         items: items.clone(),
-                    };
-
-                    ground_drops.0.push(drops.clone());
-
-                    let message = bincode::serialize(&ServerMessage::SCSpawnItems(SCSpawnItems {
-                        position: d_transform.translation,
-                        items: drops,
-                    }))
-                    .unwrap();
-                    net_params
-                        .server
-                        .broadcast_message(ServerChannel::Message, message);
+                    });
                 }
 
-                let id_info = game_state.get_id_info_from_uid(attacker.uid).unwrap();
+                let id_info = game.get_id_info_from_uid(attacker.uid).unwrap();
 
-                let message = bincode::serialize(&ServerMessage::SCCombatResult(SCCombatResult(
-                    combat_result.clone(),
-                )))
-                .unwrap();
-                net_params
-                    .server
-                    .send_message(id_info.client_id, ServerChannel::Message, message);
+                let message = ServerMessage::SCCombatResult(SCCombatResult(combat_result.clone()));
+                net_params.outbound.send(id_info.client_id, &message);
 
-                let message = bincode::serialize(&ServerMessage::SCVillainDeath(SCVillainDeath(
+                let message = ServerMessage::SCVillainDeath(SCVillainDeath(defender.uid));
+                game.broadcast_relevant(
+                    &mut net_params.outbound,
+                    &interest,
                     defender.uid,
-                )))
-                .unwrap();
-                net_params
-                    .server
-                    .broadcast_message(ServerChannel::Message, message);
+                    &message,
+                );
 
                 commands.entity(event.defender).insert((
                     Corpse,
-                    CorpseTimer(Timer::from_seconds(300., TimerMode::Once)),
+                    CorpseTimer(Timer::from_seconds(
+                        settings.simulation.corpse_timer,
+                        TimerMode::Once,
+                    )),
                 ));
             }
             _ => debug!("combat error"),
@@ -0,0 +1,154 @@
+//! Snapshot replication
+//!
+//! Once per tick every player is sent the state of the units relevant to them, delta encoded
+//! against the newest snapshot they acknowledged. Movement, rotation and vitals of units are only
+//! replicated this way.
+
+use super::{
+    input::PlayerInputs,
+    instance::{GameManager, GameStatus},
+    interest::InterestMap,
+};
+use crate::net::server::NetworkParamsRW;
+
+use rpg_network_protocol::{
+    protocol::*,
+    snapshot::{Snapshot, Tick, UnitActivity, UnitState, SNAPSHOT_HISTORY},
+};
+use rpg_util::{
+    actions::{ActionKind, State, UnitActions},
+    unit::Unit,
+};
+
+use bevy::{
+    ecs::system::{Query, Res, ResMut, Resource},
+    transform::components::Transform,
+};
+
+use bevy_renet::renet::ClientId;
+
+use std::collections::{HashMap, VecDeque};
+
+/// The current simulation tick, advanced once per fixed update
+#[derive(Resource, Default, Debug)]
+pub(crate) struct ServerTick(pub(crate) Tick);
+
+/// The snapshots sent to a client that it may still acknowledge
+#[derive(Default, Debug)]
+pub(crate) struct ClientSnapshots {
+    acked: Option<Tick>,
+    sent: VecDeque<Snapshot>,
+}
+
+impl ClientSnapshots {
+    fn baseline(&self) -> Option<&Snapshot> {
+        let acked = self.acked?;
+        self.sent.iter().find(|s| s.tick == acked)
+    }
+}
+
+#[derive(Resource, Default)]
+pub(crate) struct SnapshotHistory(HashMap<ClientId, ClientSnapshots>);
+
+impl SnapshotHistory {
+    /// Record a client's acknowledgement, snapshots older than it are no longer needed
+    pub(crate) fn acknowledge(&mut self, client_id: ClientId, tick: Tick) {
+        let Some(client) = self.0.get_mut(&client_id) else {
+            return;
+        };
+        if client.acked.is_some_and(|acked| !tick.is_newer_than(acked)) {
+            return;
+        }
+        // A client can only acknowledge a snapshot it was sent
+        if !client.sent.iter().any(|s| s.tick == tick) {
+            return;
+        }
+
+        client.acked = Some(tick);
+        client.sent.retain(|s| !tick.is_newer_than(s.tick));
+    }
+
+    /// Forget a client's snapshots, the next one it is sent is a full snapshot
+    pub(crate) fn remove_client(&mut self, client_id: ClientId) {
+        self.0.remove(&client_id);
+    }
+}
+
+pub(crate) fn advance_tick(mut tick: ResMut<ServerTick>) {
+    tick.0 = tick.0.next();
+}
+
+fn unit_activity(actions: &UnitActions) -> UnitActivity {
+    if actions.get(ActionKind::Knockback).is_some() {
+        UnitActivity::Knockback
+    } else if actions.get(ActionKind::Attack).is_some() {
+        UnitActivity::Attacking
+    } else if actions
+        .get(ActionKind::Move)
+        .is_some_and(|a| a.state == State::Pending || a.state == State::Active)
+    {
+        UnitActivity::Moving
+    } else {
+        UnitActivity::Idle
+    }
+}
+
+pub(crate) fn send_snapshots(
+    tick: Res<ServerTick>,
+    game_manager: Res<GameManager>,
+    interest: Res<InterestMap>,
+    mut history: ResMut<SnapshotHistory>,
+    mut net_params: NetworkParamsRW,
+    unit_q: Query<(&Transform, &Unit, &UnitActions)>,
+    input_q: Query<&PlayerInputs>,
+) {
+    let states: Vec<_> = unit_q
+        .iter()
+        .map(|(transform, unit, actions)| {
+            let state = UnitState {
+                position: transform.translation,
+                direction: *transform.forward(),
+                hp: *unit.stats.vitals.stats["Hp"].value.u32(),
+                hp_max: *unit.stats.vitals.stats["HpMax"].value.u32(),
+                activity: unit_activity(actions),
+            };
+
+            (unit.uid, state)
+        })
+        .collect();
+
+    for game in &game_manager.games {
+        if game.status != GameStatus::Running {
+            continue;
+        }
+
+        for player in &game.players {
+            // nothing is replicated until the hero has spawned
+            if unit_q.get(player.entity).is_err() {
+                continue;
+            }
+
+            let mut snapshot = Snapshot::new(tick.0);
+            snapshot
+                .units
+                .extend(states.iter().copied().filter(|(uid, _)| {
+                    *uid == player.character_id || interest.is_relevant(player.client_id, *uid)
+                }));
+
+            let client = history.0.entry(player.client_id).or_default();
+            let mut delta = snapshot.delta_from(client.baseline());
+            delta.last_input = input_q
+                .get(player.entity)
+                .ok()
+                .and_then(|inputs| inputs.last_applied);
+
+            let message = ServerMessage::SCSnapshot(SCSnapshot(delta));
+            net_params.outbound.send(player.client_id, &message);
+
+            if client.sent.len() >= SNAPSHOT_HISTORY {
+                client.sent.pop_front();
+            }
+            client.sent.push_back(snapshot);
+        }
+    }
+}
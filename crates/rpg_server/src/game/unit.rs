@@ -1,3 +1,7 @@
+use super::{
+    instance::{GameInstance, GameManager},
+    interest::InterestMap,
+};
 use crate::{account::AccountInstance, assets::MetadataResources, net::server::NetworkParamsRW};
 
 use rpg_network_protocol::protocol::*;
@@ -9,7 +13,7 @@ use bevy::{
         component::Component,
         entity::Entity,
         query::{With, Without},
-        system::{Commands, Query, Res},
+        system::{Commands, Query, Res, ResMut},
     },
     hierarchy::DespawnRecursiveExt,
     log::info,
@@ -42,11 +46,8 @@ pub(crate) fn upkeep(
         if !updates.is_empty() {
             // debug!("{updates:?}");
 
-            let message =
-                bincode::serialize(&ServerMessage::SCStatUpdates(SCStatUpdates(updates))).unwrap();
-            net_params
-                .server
-                .send_message(client.client_id, ServerChannel::Message, message);
+            let message = ServerMessage::SCStatUpdates(SCStatUpdates(updates));
+            net_params.outbound.send(client.client_id, &message);
         }
     }
 }
@@ -54,19 +55,19 @@ pub(crate) fn upkeep(
 pub(crate) fn remove_corpses(
     mut commands: Commands,
     mut net_params: NetworkParamsRW,
+    mut interest: ResMut<InterestMap>,
     time: Res<Time>,
-    mut unit_q: Query<(Entity, &Unit, &mut CorpseTimer), With<Corpse>>,
+    game_manager: Res<GameManager>,
+    mut unit_q: Query<(Entity, &Unit, &GameInstance, &mut CorpseTimer), With<Corpse>>,
 ) {
-    for (entity, unit, mut timer) in &mut unit_q {
+    for (entity, unit, game, mut timer) in &mut unit_q {
         timer.tick(time.delta());
         if timer.just_finished() {
-            let message =
-                bincode::serialize(&ServerMessage::SCDespawnCorpse(SCDespawnCorpse(unit.uid)))
-                    .unwrap();
-            // TODO tell the client to despawn the entity
-            net_params
-                .server
-                .broadcast_message(ServerChannel::Message, message);
+            let message = ServerMessage::SCDespawnCorpse(SCDespawnCorpse(unit.uid));
+            if let Some(game) = game_manager.get_game(game.0) {
+                game.broadcast_relevant(&mut net_params.outbound, &interest, unit.uid, &message);
+            }
+            interest.forget(unit.uid);
 
             commands.entity(entity).despawn_recursive();
         }
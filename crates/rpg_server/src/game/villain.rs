@@ -1,6 +1,6 @@
-use super::plugin::GameSessionCleanup;
+use super::{instance::GameInstance, plugin::GameSessionCleanup};
 
-use crate::{account::AccountInstance, assets::MetadataResources, net::server::NetworkParamsRW};
+use crate::{assets::MetadataResources, world::InZone};
 
 use rpg_core::{
     metadata::Metadata,
@@ -15,6 +15,7 @@ use rpg_util::{
     skill::{get_skill_origin, SkillSlots, Skills},
     unit::{Corpse, Hero, Unit, UnitBundle, Villain, VillainBundle},
 };
+use rpg_world::zone::ZoneId;
 
 use util::{cleanup::CleanupStrategy, math::AabbComponent, random::SharedRng};
 
@@ -75,7 +76,6 @@ impl Goal {
 #[derive(Default, Debug, Component)]
 pub(crate) struct VillainController {
     pub(crate) goal: Goal,
-    pub(crate) spawned_on: Vec<Entity>,
     pub(crate) origin: Vec3,
 }
 
@@ -83,7 +83,6 @@ impl VillainController {
     fn new(origin: Vec3) -> Self {
         Self {
             origin,
-            spawned_on: vec![],
             goal: Goal::default(),
         }
     }
@@ -91,6 +90,8 @@ impl VillainController {
 
 pub(crate) fn spawn(
     commands: &mut Commands,
+    game_id: GameId,
+    zone_id: ZoneId,
     next_uid: &mut NextUid,
     origin: &Vec3,
     metadata: &Metadata,
@@ -122,6 +123,8 @@ pub(crate) fn spawn(
     commands.spawn((
         CleanupStrategy::DespawnRecursive,
         GameSessionCleanup,
+        GameInstance(game_id),
+        InZone(zone_id),
         AabbComponent(aabb),
         VillainBundle {
             villain: Villain,
@@ -133,71 +136,24 @@ pub(crate) fn spawn(
     ));
 }
 
-// TODO optimize
-pub(crate) fn remote_spawn(
-    mut net_params: NetworkParamsRW,
-    hero_q: Query<(Entity, &Transform, &AccountInstance), (With<Hero>, Without<Corpse>)>,
+pub(crate) fn find_target(
+    metadata: Res<MetadataResources>,
+    hero_q: Query<
+        (Entity, &Transform, &GameInstance),
+        (With<Hero>, Without<Villain>, Without<Corpse>),
+    >,
     mut villain_q: Query<
         (
             &Transform,
             &Unit,
-            &Skills,
-            &SkillSlots,
+            &GameInstance,
             &mut VillainController,
+            &mut UnitActions,
         ),
         (With<Villain>, Without<Corpse>),
     >,
 ) {
-    for (transform, unit, skills, skill_slots, mut controller) in &mut villain_q {
-        for (hero_entity, hero_transform, account) in &hero_q {
-            let distance = transform.translation.distance(hero_transform.translation);
-            if distance > 16.0 {
-                continue;
-            }
-
-            // Check that the hero is not already spawned on this client
-            if controller.spawned_on.contains(&hero_entity) {
-                continue;
-            }
-
-            let villain_info = unit.info.villain().clone();
-
-            // info!("spawning nearby monster on client {villain_info:?}");
-            controller.spawned_on.push(hero_entity);
-
-            let client_id = net_params
-                .context
-                .get_client_from_account_id(account.0.info.id)
-                .unwrap()
-                .client_id;
-
-            let message = bincode::serialize(&ServerMessage::SCSpawnVillain(SCSpawnVillain {
-                position: transform.translation,
-                direction: *transform.forward(),
-                info: villain_info,
-                level: unit.level,
-                uid: unit.uid,
-                skills: skills.0.clone(),
-                skill_slots: skill_slots.slots.clone(),
-            }))
-            .unwrap();
-
-            net_params
-                .server
-                .send_message(client_id, ServerChannel::Message, message);
-        }
-    }
-}
-
-pub(crate) fn find_target(
-    metadata: Res<MetadataResources>,
-    hero_q: Query<(Entity, &Transform), (With<Hero>, Without<Villain>, Without<Corpse>)>,
-    mut villain_q: Query<
-        (&Transform, &Unit, &mut VillainController, &mut UnitActions),
-        (With<Villain>, Without<Corpse>),
-    >,
-) {
-    for (transform, unit, mut villain, mut actions) in &mut villain_q {
+    for (transform, unit, game, mut villain, mut actions) in &mut villain_q {
         if villain.goal.info.is_roaming() {
             continue;
         }
@@ -208,7 +164,7 @@ pub(crate) fn find_target(
         if let GoalInfo::Target(info) = &mut villain.goal.info {
             // Check if the current target is out of range and if so invalidate it
             if let Some(target) = &info.0 {
-                if let Ok((_, hero_transform)) = hero_q.get(*target) {
+                if let Ok((_, hero_transform, _)) = hero_q.get(*target) {
                     let distance = (transform.translation.distance(hero_transform.translation)
                         * 100.)
                         .round() as u32;
@@ -237,7 +193,11 @@ pub(crate) fn find_target(
         let mut nearest = None::<Entity>;
         let mut nearest_distance = max_distance;
 
-        for (hero_entity, hero_transform) in &hero_q {
+        for (hero_entity, hero_transform, hero_game) in &hero_q {
+            if hero_game != game {
+                continue;
+            }
+
             // TODO check villain and hero `ZoneId` to avoid needless computations
             let distance =
                 (transform.translation.distance(hero_transform.translation) * 100.).round() as u32;
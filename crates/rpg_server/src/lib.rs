@@ -0,0 +1,118 @@
+//! The game server
+//!
+//! The binary only handles the command line, signals and exit codes. The app itself is built here
+//! so it can also be driven in-process.
+
+mod assets;
+pub mod config;
+mod server_state;
+pub mod state;
+mod store;
+
+mod net;
+
+mod account;
+mod chat;
+mod credential;
+mod game;
+mod lobby;
+mod moderation;
+pub mod shutdown;
+
+mod world;
+
+#[cfg(any(test, feature = "fuzz"))]
+pub mod fuzz;
+
+use crate::{
+    assets::{load_metadata, JsonAssets},
+    chat::ChatManager,
+    config::ServerSettings,
+    game::plugin::GamePlugin,
+    lobby::LobbyManager,
+    net::{server::NetworkServerPlugin, token::TokenIssuer},
+    server_state::ServerMetadataResource,
+    shutdown::ShutdownHandle,
+    state::AppState,
+    store::{AccountStorage, DiskAccountStore},
+};
+
+use util::{
+    plugin::UtilityPlugin,
+    random::{Rng, SharedRng},
+};
+
+use bevy::{
+    app::{App, PluginGroup, ScheduleRunnerPlugin, Startup, Update},
+    asset::AssetPlugin,
+    core::TaskPoolPlugin,
+    ecs::schedule::{common_conditions::in_state, IntoSystemConfigs},
+    log::LogPlugin,
+    time::{Fixed, Time},
+    MinimalPlugins,
+};
+
+use std::{io, time::Duration};
+
+/// Build the server app from validated settings
+pub fn build_app(settings: ServerSettings, shutdown: ShutdownHandle) -> App {
+    let store = AccountStorage::new(DiskAccountStore::new(settings.save_root()));
+
+    build_app_with_store(settings, shutdown, store)
+}
+
+/// Like `build_app` but the server persists to `store` instead of the save root
+pub(crate) fn build_app_with_store(
+    settings: ServerSettings,
+    shutdown: ShutdownHandle,
+    store: AccountStorage,
+) -> App {
+    let network = settings.network.clone();
+    let tick_rate = settings.simulation.tick_rate;
+
+    let mut app = App::new();
+    app.init_state::<AppState>()
+        .add_plugins(
+            MinimalPlugins
+                .set(ScheduleRunnerPlugin::run_loop(Duration::from_secs_f64(
+                    settings.timestep(),
+                )))
+                .set(TaskPoolPlugin::default()),
+        )
+        .insert_resource(Time::<Fixed>::from_seconds(settings.timestep()))
+        .add_plugins(LogPlugin::default())
+        .add_plugins(AssetPlugin::default())
+        .add_plugins(UtilityPlugin)
+        .init_resource::<JsonAssets>()
+        .insert_resource(store)
+        .insert_resource(SharedRng(Rng::with_seed(settings.simulation.rng_seed)))
+        .insert_resource(shutdown)
+        .insert_resource(settings)
+        .init_resource::<ServerMetadataResource>()
+        .init_resource::<ChatManager>()
+        .init_resource::<LobbyManager>()
+        .add_systems(Startup, chat::setup)
+        .add_systems(Update, load_metadata.run_if(in_state(AppState::Loading)))
+        .add_systems(
+            Update,
+            (shutdown::begin_shutdown, shutdown::finish_shutdown).chain(),
+        )
+        .add_plugins(NetworkServerPlugin {
+            settings: network,
+            tick_rate,
+        })
+        .add_plugins(GamePlugin);
+
+    app
+}
+
+/// Start issuing connect tokens on a background thread, the settings must be validated and
+/// must not be unsecure
+pub fn spawn_token_issuer(settings: &ServerSettings) -> io::Result<()> {
+    // The key has already been validated
+    let private_key = settings.network.private_key().unwrap();
+    let store = DiskAccountStore::new(settings.save_root());
+    TokenIssuer::bind(&settings.network, private_key, store)?.spawn();
+
+    Ok(())
+}
@@ -11,10 +11,15 @@ pub(crate) struct LobbyManager {
 }
 
 impl LobbyManager {
-    pub(crate) fn add_lobby(&mut self, name: String, game_mode: GameMode) -> Option<LobbyId> {
+    pub(crate) fn add_lobby(
+        &mut self,
+        name: String,
+        game_mode: GameMode,
+        owner: AccountId,
+    ) -> Option<LobbyId> {
         let id = self.next_lobby_id;
         if !self.lobbies.iter().any(|l| l.id == id) {
-            let lobby = Lobby::new(self.next_lobby_id, name, game_mode);
+            let lobby = Lobby::new(self.next_lobby_id, name, game_mode, owner);
             self.lobbies.push(lobby);
 
             self.next_lobby_id.0 += 1;
@@ -51,10 +56,7 @@ impl LobbyManager {
 
     pub(crate) fn add_player(&mut self, id: LobbyId, account_id: AccountId, name: String) -> bool {
         if let Some(lobby) = self.lobbies.iter_mut().find(|l| l.id == id) {
-            lobby.add_player(LobbyPlayer {
-                account_id,
-                account_name: name,
-            })
+            lobby.add_player(LobbyPlayer::new(account_id, name))
         } else {
             false
         }
@@ -65,4 +67,17 @@ impl LobbyManager {
             lobby.remove_player(account_id);
         }
     }
+
+    /// Remove an account from every lobby it is in, lobbies without players are removed
+    pub(crate) fn remove_account(&mut self, account_id: AccountId) -> Vec<LobbyId> {
+        let mut changed = vec![];
+        for lobby in self.lobbies.iter_mut().filter(|l| l.has_player(account_id)) {
+            lobby.remove_player(account_id);
+            changed.push(lobby.id);
+        }
+
+        self.lobbies.retain(|l| !l.players.is_empty());
+
+        changed
+    }
 }
@@ -1,57 +1,94 @@
 //! Run with
 //! - `cargo run -p rpg_server`
 //! - `cargo run -p rpg_server -- --port 4269 --addr 127.0.0.1`
-
-mod assets;
-mod server_state;
-mod state;
-
-mod net;
-
-mod account;
-mod chat;
-mod game;
-mod lobby;
-
-mod world;
-
-use crate::{
-    assets::{load_metadata, JsonAssets},
-    chat::ChatManager,
-    game::plugin::GamePlugin,
-    lobby::LobbyManager,
-    net::server::NetworkServerPlugin,
-    server_state::ServerMetadataResource,
-    state::AppState,
-};
-
-use rpg_network_protocol::*;
-use util::plugin::UtilityPlugin;
-
-use bevy::{
-    app::{App, PluginGroup, ScheduleRunnerPlugin, Startup, Update},
-    asset::AssetPlugin,
-    core::TaskPoolPlugin,
-    ecs::schedule::{common_conditions::in_state, IntoSystemConfigs},
-    log::LogPlugin,
-    time::{Fixed, Time},
-    MinimalPlugins,
+//! - `cargo run -p rpg_server -- --config server.json --max-clients 32`
+//! - `cargo run -p rpg_server -- --unsecure`, accepts clients without connect tokens
+//! - `cargo run -p rpg_server -- --generate-key`, prints a new private key and exits
+
+use rpg_server::{
+    build_app,
+    config::{ConfigError, ServerSettings, PRIVATE_KEY_BYTES},
+    shutdown::{ShutdownHandle, EXIT_FORCED, EXIT_INVALID_CONFIG, EXIT_PANIC},
+    spawn_token_issuer,
 };
 
+use argon2::password_hash::rand_core::{OsRng, RngCore};
 use clap::Parser;
 
-use std::{net::Ipv4Addr, time::Duration};
+use std::{net::Ipv4Addr, path::PathBuf};
 
+/// Arguments given here override the config file
 #[derive(Parser, PartialEq, Debug)]
 struct Cli {
-    #[arg(short, long, default_value_t = SERVER_PORT)]
-    port: u16,
-    #[arg(short, long, default_value_t = Ipv4Addr::UNSPECIFIED)]
-    addr: Ipv4Addr,
+    /// A JSON file with the server settings
+    #[arg(short, long)]
+    config: Option<PathBuf>,
+    #[arg(short, long)]
+    port: Option<u16>,
+    #[arg(short, long)]
+    addr: Option<Ipv4Addr>,
+    #[arg(long)]
+    max_clients: Option<usize>,
+    /// The address written into connect tokens
+    #[arg(long)]
+    public_addr: Option<Ipv4Addr>,
+    /// Accept clients without a connect token, only meant for development
+    #[arg(long)]
+    unsecure: bool,
+    /// Print a new private key for the config file and exit
+    #[arg(long)]
+    generate_key: bool,
+    #[arg(long)]
+    tick_rate: Option<f64>,
+    #[arg(long)]
+    max_players: Option<u8>,
+    #[arg(long)]
+    rng_seed: Option<u64>,
+    #[arg(long)]
+    villain_count: Option<u32>,
+    #[arg(long)]
+    corpse_timer: Option<f32>,
+    #[arg(long)]
+    save_root: Option<PathBuf>,
+}
+
+impl Cli {
+    fn apply(self, settings: &mut ServerSettings) {
+        let network = &mut settings.network;
+        network.addr = self.addr.unwrap_or(network.addr);
+        network.port = self.port.unwrap_or(network.port);
+        network.max_clients = self.max_clients.unwrap_or(network.max_clients);
+        network.public_addr = self.public_addr.or(network.public_addr);
+        network.unsecure |= self.unsecure;
+
+        let simulation = &mut settings.simulation;
+        simulation.tick_rate = self.tick_rate.unwrap_or(simulation.tick_rate);
+        simulation.max_players = self.max_players.unwrap_or(simulation.max_players);
+        simulation.rng_seed = self.rng_seed.unwrap_or(simulation.rng_seed);
+        simulation.villain_count = self.villain_count.unwrap_or(simulation.villain_count);
+        simulation.corpse_timer = self.corpse_timer.unwrap_or(simulation.corpse_timer);
+
+        if self.save_root.is_some() {
+            settings.persistence.save_root = self.save_root;
+        }
+    }
+}
+
+fn load_settings(cli: Cli) -> Result<ServerSettings, ConfigError> {
+    let mut settings = match &cli.config {
+        Some(path) => ServerSettings::from_file(path)?,
+        None => ServerSettings::default(),
+    };
+
+    cli.apply(&mut settings);
+    settings.apply_env();
+    settings.validate()?;
+
+    Ok(settings)
 }
 
 use std::io::Error;
-use std::sync::atomic::AtomicBool;
+use std::process::ExitCode;
 use std::sync::Arc;
 use std::thread;
 use std::time;
@@ -61,71 +98,115 @@ use signal_hook::consts::TERM_SIGNALS;
 use signal_hook::flag;
 use signal_hook::iterator::Signals;
 
-fn main() -> Result<(), Error> {
+/// Exits with one of the `shutdown::EXIT_*` codes
+fn main() -> Result<ExitCode, Error> {
     let cli = Cli::parse();
+    if cli.generate_key {
+        let mut key = [0; PRIVATE_KEY_BYTES];
+        OsRng.fill_bytes(&mut key);
+        println!(
+            "{}",
+            key.iter().map(|b| format!("{b:02x}")).collect::<String>()
+        );
+
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let settings = match load_settings(cli) {
+        Ok(settings) => settings,
+        Err(e) => {
+            eprintln!("invalid server configuration: {e}");
+            return Ok(ExitCode::from(EXIT_INVALID_CONFIG));
+        }
+    };
 
-    let term_now = Arc::new(AtomicBool::new(false));
+    let shutdown = ShutdownHandle::default();
     for sig in TERM_SIGNALS {
-        // When terminated by a second term signal, exit with exit code 1.
-        // This will do nothing the first time (because term_now is false).
-        flag::register_conditional_shutdown(*sig, 1, Arc::clone(&term_now))?;
-        flag::register(*sig, Arc::clone(&term_now))?;
+        // When terminated by a second term signal, exit with `EXIT_FORCED`.
+        // This will do nothing the first time (because the shutdown has not been requested).
+        flag::register_conditional_shutdown(
+            *sig,
+            EXIT_FORCED as i32,
+            Arc::clone(&shutdown.requested),
+        )?;
+        // The app polls this flag and begins shutting down once it is set
+        flag::register(*sig, Arc::clone(&shutdown.requested))?;
     }
 
     let mut signals = Signals::new(TERM_SIGNALS)?;
 
+    if !settings.network.unsecure {
+        spawn_token_issuer(&settings)?;
+    }
+
+    let app_shutdown = shutdown.clone();
     let t = thread::spawn(move || {
-        App::new()
-            .init_state::<AppState>()
-            .add_plugins(
-                MinimalPlugins
-                    .set(ScheduleRunnerPlugin::run_loop(Duration::from_secs_f64(
-                        1.0 / 60.0,
-                    )))
-                    .set(TaskPoolPlugin::default()),
-            )
-            .insert_resource(Time::<Fixed>::from_seconds(1.0 / 60.))
-            .add_plugins(LogPlugin::default())
-            .add_plugins(AssetPlugin::default())
-            .add_plugins(UtilityPlugin)
-            .init_resource::<JsonAssets>()
-            .init_resource::<ServerMetadataResource>()
-            .init_resource::<ChatManager>()
-            .init_resource::<LobbyManager>()
-            .add_systems(Startup, chat::setup)
-            .add_systems(Update, load_metadata.run_if(in_state(AppState::Loading)))
-            .add_plugins(NetworkServerPlugin {
-                addr: cli.addr,
-                port: cli.port,
-            })
-            .add_plugins(GamePlugin)
-            .run();
+        build_app(settings, app_shutdown).run();
     });
 
-    'outer: loop {
+    // The app thread exits on it's own once a shutdown has been requested and completed
+    while !t.is_finished() {
         for signal in signals.pending() {
             match signal {
-                SIGINT => {
-                    eprintln!("\nCaught: SIGINT; joining app thread");
-                    break 'outer;
-                }
-                SIGTERM => {
-                    eprintln!("\nCaught: SIGTERM; joining app thread");
-                    break 'outer;
-                }
-                term_sig => {
-                    eprintln!("\nCaught: {:?}; joining app thread", term_sig);
-                    break 'outer;
-                }
+                SIGINT => eprintln!("\nCaught: SIGINT; shutting down"),
+                SIGTERM => eprintln!("\nCaught: SIGTERM; shutting down"),
+                term_sig => eprintln!("\nCaught: {:?}; shutting down", term_sig),
             }
+            eprintln!("\nEnter Ctrl+C again to exit immediately.");
         }
 
         thread::sleep(time::Duration::from_millis(1));
     }
 
-    eprintln!("\nEnter Ctrl+C again to exit immediately.");
+    if t.join().is_err() {
+        return Ok(ExitCode::from(EXIT_PANIC));
+    }
 
-    t.join().unwrap();
+    Ok(ExitCode::from(shutdown.status()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn arguments_override_settings() {
+        let mut settings = ServerSettings::default();
+        settings.network.port = 4000;
+        settings.simulation.villain_count = 8;
+
+        Cli::parse_from([
+            "rpg_server",
+            "--port",
+            "4001",
+            "--unsecure",
+            "--tick-rate",
+            "30",
+            "--save-root",
+            "/tmp/rpg",
+        ])
+        .apply(&mut settings);
+
+        assert_eq!(settings.network.port, 4001);
+        assert!(settings.network.unsecure);
+        assert_eq!(settings.simulation.tick_rate, 30.);
+        assert_eq!(
+            settings.persistence.save_root,
+            Some(PathBuf::from("/tmp/rpg"))
+        );
+
+        // settings without an argument are left alone
+        assert_eq!(settings.simulation.villain_count, 8);
+        assert_eq!(settings.network.max_clients, 16);
+    }
 
-    Ok(())
+    #[test]
+    fn unsecure_is_not_cleared_by_a_missing_flag() {
+        let mut settings = ServerSettings::default();
+        settings.network.unsecure = true;
+
+        Cli::parse_from(["rpg_server"]).apply(&mut settings);
+
+        assert!(settings.network.unsecure);
+    }
 }
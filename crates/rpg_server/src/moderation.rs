@@ -0,0 +1,9 @@
+use std::time::SystemTime;
+
+/// The current time in seconds since the unix epoch, sanctions expire relative to this
+pub(crate) fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
@@ -7,27 +7,34 @@ use crate::{
         AccountInstance, AccountInstanceBundle, AdminAccountInstance, AdminAccountInstanceBundle,
     },
     assets::MetadataResources,
-    game::plugin::{GameState, PlayerIdInfo},
+    config::ServerSettings,
+    credential::Credential,
+    game::instance::{GameManager, GameOptions, GameStatus, PlayerIdInfo},
+    moderation::unix_time,
     server_state::ServerMetadataResource,
-    state::AppState,
+    store::{AccountStorage, StoreError},
     world::LoadZone,
 };
 
 use bevy::{
     ecs::{
         event::{EventReader, EventWriter},
-        schedule::NextState,
-        system::{Commands, Query, Res, ResMut},
+        system::{Commands, Query, Res, ResMut, Resource},
     },
     log::info,
-    math::Vec3,
+    tasks::{block_on, futures_lite::future, AsyncComputeTaskPool, Task},
 };
 
 use rpg_account::{
-    account::{Account, AccountInfo, AdminAccount, AdminAccountInfo},
+    account::{Account, AccountId, AccountInfo, AdminAccount},
     account_statistics::AccountStatistics,
     character::{Character, CharacterInfo, CharacterRecord},
     character_statistics::CharacterStatistics,
+    moderation::Moderation,
+    name::{
+        has_valid_characters, names_match, validate_account_name, validate_character_name,
+        NameError,
+    },
 };
 use rpg_core::{
     passive_tree::UnitPassiveSkills,
@@ -35,16 +42,108 @@ use rpg_core::{
     storage::UnitStorage,
     unit::{HeroInfo, Unit as RpgUnit, UnitInfo, UnitKind},
 };
-use rpg_network_protocol::protocol::*;
+use rpg_network_protocol::{protocol::*, token::TokenIdentity};
 use rpg_world::zone::ZoneId;
 
-use util::fs::{open_read, open_write};
+use bevy_renet::renet::ClientId;
+
+/// Password hashing and verification in flight
+///
+/// Argon2 is deliberately slow, it runs on the compute task pool so that a client sending
+/// passwords never stalls the hosted games. Outcomes are applied by `finish_credential_checks`.
+#[derive(Resource, Default)]
+pub(crate) struct CredentialTasks(Vec<(ClientId, Task<CredentialCheck>)>);
+
+impl CredentialTasks {
+    fn spawn(
+        &mut self,
+        client_id: ClientId,
+        check: impl FnOnce() -> CredentialCheck + Send + 'static,
+    ) {
+        let task = AsyncComputeTaskPool::get().spawn(async move { check() });
+        self.0.push((client_id, task));
+    }
+}
 
-use std::{env, path::Path};
+pub(crate) enum CredentialCheck {
+    /// The password of a new account was hashed, `None` if it could not be
+    Create {
+        account: Account,
+        credential: Option<Credential>,
+    },
+    Login {
+        account: Account,
+        verified: bool,
+    },
+    AdminLogin {
+        account: AdminAccount,
+        verified: bool,
+    },
+    /// The new password was hashed, `None` if the old password did not match
+    ChangePassword {
+        name: String,
+        account_id: AccountId,
+        credential: Option<Credential>,
+    },
+}
 
-pub(crate) fn receive_account_create(
+/// Apply the outcome of finished password checks, the client may have changed or left meanwhile
+pub(crate) fn finish_credential_checks(
     mut commands: Commands,
+    mut store: ResMut<AccountStorage>,
+    mut tasks: ResMut<CredentialTasks>,
+    mut net_params: NetworkParamsRW,
+) {
+    let mut finished = vec![];
+    tasks.0.retain_mut(
+        |(client_id, task)| match block_on(future::poll_once(task)) {
+            Some(check) => {
+                finished.push((*client_id, check));
+                false
+            }
+            None => true,
+        },
+    );
+
+    for (client_id, check) in finished {
+        match check {
+            CredentialCheck::Create {
+                account,
+                credential,
+            } => finish_account_create(
+                &mut commands,
+                &mut store,
+                &mut net_params,
+                client_id,
+                account,
+                credential,
+            ),
+            CredentialCheck::Login { account, verified } => {
+                finish_account_login(&mut commands, &mut net_params, client_id, account, verified)
+            }
+            CredentialCheck::AdminLogin { account, verified } => {
+                finish_admin_login(&mut commands, &mut net_params, client_id, account, verified)
+            }
+            CredentialCheck::ChangePassword {
+                name,
+                account_id,
+                credential,
+            } => finish_password_change(
+                &mut store,
+                &mut net_params,
+                client_id,
+                &name,
+                account_id,
+                credential,
+            ),
+        }
+    }
+}
+
+pub(crate) fn receive_account_create(
+    mut store: ResMut<AccountStorage>,
     mut server_metadata: ResMut<ServerMetadataResource>,
+    mut credential_tasks: ResMut<CredentialTasks>,
     mut account_create_reader: EventReader<ClientMessageEvent>,
     mut net_params: NetworkParamsRW,
 ) {
@@ -52,11 +151,9 @@ pub(crate) fn receive_account_create(
         let ClientMessage::CSCreateAccount(msg) = &event.message else {
             continue;
         };
-        let client = net_params
-            .context
-            .clients
-            .get_mut(&event.client_id)
-            .unwrap();
+        let Some(client) = net_params.context.get_client_from_id(event.client_id) else {
+            continue;
+        };
         if client.is_authenticated_player() {
             info!("already authenticated client attempted to create account {client:?}");
             continue;
@@ -64,83 +161,197 @@ pub(crate) fn receive_account_create(
 
         // Allow authenticated admins to create accounts
         if client.is_admin() && !client.is_authenticated() {
-            panic!("unauthenticated admin: {client:?}");
+            info!("unauthenticated admin attempted to create account {client:?}");
+            continue;
         }
 
-        let account_file_path = format!(
-            "{}/server/accounts/{}.bin",
-            std::env::var("RPG_SAVE_ROOT").unwrap(),
-            msg.name
-        );
-        let account_path = Path::new(account_file_path.as_str());
-        let account_file = open_read(account_path);
+        if let Err(e) = validate_account_name(&msg.name) {
+            info!("invalid account name {:?}: {e:?}", msg.name);
+            send_create_account_error(
+                &mut net_params,
+                event.client_id,
+                CreateAccountError::InvalidName(e),
+            );
+            continue;
+        }
 
-        let meta_file_path = format!(
-            "{}/server/meta.bin",
-            std::env::var("RPG_SAVE_ROOT").unwrap(),
-        );
-        let meta_path = Path::new(meta_file_path.as_str());
-        let meta_file = open_write(meta_path);
+        // Admins create accounts for others, players only the one their token was issued for
+        let identity = TokenIdentity::CreateAccount(msg.name.clone());
+        if !client.is_admin() && !client.token_allows(&identity) {
+            info!(
+                "client token was not issued for account {}: {client:?}",
+                msg.name
+            );
+            send_create_account_error(
+                &mut net_params,
+                event.client_id,
+                CreateAccountError::Unavailable,
+            );
+            continue;
+        }
 
-        let Ok(meta_file) = meta_file else {
-            info!("cannot open {meta_file_path} for writing");
-            return;
+        match store.account_name_exists(&msg.name) {
+            Ok(false) => {}
+            Ok(true) => {
+                info!("account already exists");
+                send_create_account_error(
+                    &mut net_params,
+                    event.client_id,
+                    CreateAccountError::NameTaken,
+                );
+                continue;
+            }
+            Err(e) => {
+                info!("unable to check for existing account {}: {e}", msg.name);
+                send_create_account_error(
+                    &mut net_params,
+                    event.client_id,
+                    CreateAccountError::Unavailable,
+                );
+                continue;
+            }
+        }
+
+        let account_id = match store.next_account_id(&mut server_metadata.0) {
+            Ok(account_id) => account_id,
+            Err(e) => {
+                info!("unable to allocate an account id: {e}");
+                send_create_account_error(
+                    &mut net_params,
+                    event.client_id,
+                    CreateAccountError::Unavailable,
+                );
+                continue;
+            }
+        };
+
+        let account = Account {
+            info: AccountInfo {
+                character_slots: 12,
+                name: msg.name.clone(),
+                id: account_id,
+                selected_slot: None,
+            },
+            statistics: AccountStatistics::default(),
+            characters: vec![],
+            moderation: Moderation::default(),
         };
 
-        if let Ok(_) = account_file {
-            info!("account already exists");
+        let password = msg.password.clone();
+        credential_tasks.spawn(event.client_id, move || {
+            let credential = Credential::new(account_id, &password);
+            CredentialCheck::Create {
+                account,
+                credential,
+            }
+        });
+    }
+}
+
+fn finish_account_create(
+    commands: &mut Commands,
+    store: &mut AccountStorage,
+    net_params: &mut NetworkParamsRW,
+    client_id: ClientId,
+    account: Account,
+    credential: Option<Credential>,
+) {
+    let Some(client) = net_params.context.get_client_from_id(client_id) else {
+        return;
+    };
+    if client.is_authenticated_player() {
+        info!("client was authenticated while creating account {client:?}");
+        return;
+    }
+
+    let Some(credential) = credential else {
+        send_create_account_error(net_params, client_id, CreateAccountError::InvalidPassword);
+        return;
+    };
+
+    let name = &account.info.name;
+    info!("creating account {name}");
+
+    if let Err(e) = store.create_account(&account) {
+        info!("unable to create account {name}: {e}");
+        let error = if let StoreError::AlreadyExists = e {
+            CreateAccountError::NameTaken
         } else {
-            let Ok(account_file) = open_write(account_path) else {
-                info!("unable to open account file for writing");
-                continue;
-            };
-
-            let account = Account {
-                info: AccountInfo {
-                    character_slots: 12,
-                    name: msg.name.clone(),
-                    id: server_metadata.0.next_account_id,
-                    selected_slot: None,
-                },
-                statistics: AccountStatistics::default(),
-                characters: vec![],
-            };
-
-            // finally update the server metadata
-            server_metadata.0.next_account_id.0 += 1;
-
-            info!("writing account file to {account_file_path}");
-
-            bincode::serialize_into(meta_file, &server_metadata.0).unwrap();
-            bincode::serialize_into(account_file, &account).unwrap();
-
-            // Set the newly created account to be autenticated
-            client.client_type = ClientType::Player;
-            client.account_id = Some(account.info.id);
-            info!("spawning account for {client:?}");
-
-            let account_entity = commands
-                .spawn(AccountInstanceBundle {
-                    account: AccountInstance(account.clone()),
-                })
-                .id();
-
-            client.entity = account_entity;
-
-            let message = bincode::serialize(&ServerMessage::SCCreateAccountSuccess(
-                SCCreateAccountSuccess(account.clone()),
-            ))
-            .unwrap();
-
-            net_params
-                .server
-                .send_message(event.client_id, ServerChannel::Message, message);
+            CreateAccountError::Unavailable
+        };
+        send_create_account_error(net_params, client_id, error);
+        return;
+    }
+
+    // Only written once the account exists, a credential must never outlive a failed create
+    if let Err(e) = store.save_credential(name, &credential) {
+        info!("unable to save credential for {name}: {e}");
+        if let Err(e) = store.delete_account(name) {
+            info!("unable to remove account {name} without a credential: {e}");
         }
+        send_create_account_error(net_params, client_id, CreateAccountError::Unavailable);
+        return;
+    }
+
+    let Some(client) = net_params.context.clients.get_mut(&client_id) else {
+        return;
+    };
+
+    // Admins create accounts for others and stay logged into their own
+    if !client.is_admin() {
+        // Set the newly created account to be autenticated
+        client.client_type = ClientType::Player;
+        client.account_id = Some(account.info.id);
+        info!("spawning account for {client:?}");
+
+        let account_entity = commands
+            .spawn(AccountInstanceBundle {
+                account: AccountInstance(account.clone()),
+            })
+            .id();
+
+        client.entity = account_entity;
+    }
+
+    let message = ServerMessage::SCCreateAccountSuccess(SCCreateAccountSuccess(account));
+    net_params.outbound.send(client_id, &message);
+}
+
+fn send_create_account_error(
+    net_params: &mut NetworkParamsRW,
+    client_id: ClientId,
+    error: CreateAccountError,
+) {
+    let message = ServerMessage::SCCreateAccountError(SCCreateAccountError(error));
+    net_params.outbound.send(client_id, &message);
+}
+
+pub(crate) fn receive_admin_connect(
+    mut connect_reader: EventReader<ClientMessageEvent>,
+    mut net_params: NetworkParamsRW,
+) {
+    for event in connect_reader.read() {
+        let ClientMessage::CSConnectAdmin(_) = &event.message else {
+            continue;
+        };
+
+        let Some(client) = net_params.context.clients.get_mut(&event.client_id) else {
+            continue;
+        };
+        if client.client_type != ClientType::Unknown {
+            info!("client attempted to reconnect as admin {client:?}");
+            continue;
+        }
+
+        info!("admin client connected {client:?}");
+        client.client_type = ClientType::Admin;
     }
 }
 
 pub(crate) fn receive_admin_login(
     mut commands: Commands,
+    store: Res<AccountStorage>,
+    mut credential_tasks: ResMut<CredentialTasks>,
     mut login_reader: EventReader<ClientMessageEvent>,
     mut net_params: NetworkParamsRW,
 ) {
@@ -150,59 +361,119 @@ pub(crate) fn receive_admin_login(
         };
 
         let client_id = event.client_id;
-        let client = net_params.context.clients.get_mut(&client_id).unwrap();
-        if client.is_authenticated_player() {
-            info!("authenticated player attempted to login to account {client:?}");
+        let Some(client) = net_params.context.get_client_from_id(client_id) else {
+            continue;
+        };
+        if !client.is_admin() {
+            info!("non-admin client attempted to login to an admin account {client:?}");
+            send_admin_login_error(&mut net_params, client_id, LoginError::Unavailable);
             continue;
         } else if client.is_authenticated_admin() {
             info!("authenticated admin attempted to login to account {client:?}");
+            send_admin_login_error(&mut net_params, client_id, LoginError::AlreadyAuthenticated);
             continue;
         }
 
-        let file_path = format!(
-            "{}/server/admin_accounts/{}.bin",
-            env::var("RPG_SAVE_ROOT").unwrap(),
-            msg.name
-        );
-        let path = Path::new(file_path.as_str());
-        let file = open_read(path);
+        // The naming rules only apply to new accounts, existing names may predate them
+        if !has_valid_characters(&msg.name) {
+            info!("invalid admin account name {:?}", msg.name);
+            send_admin_login_error(
+                &mut net_params,
+                client_id,
+                LoginError::InvalidName(NameError::InvalidCharacter),
+            );
+            continue;
+        }
 
-        let Ok(file) = file else {
-            info!("account does not exist {client:?}");
+        let Ok(credential) = store.load_admin_credential(&msg.name) else {
+            info!("admin credential does not exist {client:?}");
+            send_admin_login_error(&mut net_params, client_id, LoginError::AccountNotFound);
             continue;
         };
 
-        let account: Result<AdminAccount, _> = bincode::deserialize_from(file);
-        if let Ok(account) = account {
-            // FIXME assign a client id and send it to the client
-            client.client_type = ClientType::Player;
-            client.account_id = Some(account.info.id);
-            info!("spawning admin account for {client:?}");
+        let account = match store.load_admin_account(&msg.name) {
+            Ok(account) => account,
+            Err(StoreError::NotFound) => {
+                info!("admin account does not exist {client:?}");
+                send_admin_login_error(&mut net_params, client_id, LoginError::AccountNotFound);
+                continue;
+            }
+            Err(e) => {
+                info!("unable to load admin account {}: {e}", msg.name);
+                send_admin_login_error(&mut net_params, client_id, LoginError::Unavailable);
+                continue;
+            }
+        };
 
-            let account_entity = commands
-                .spawn(AdminAccountInstanceBundle {
-                    account: AdminAccountInstance(account.clone()),
-                })
-                .id();
+        if !client.token_allows(&TokenIdentity::Admin(account.info.id)) {
+            info!("client token was issued for another account {client:?}");
+            send_admin_login_error(&mut net_params, client_id, LoginError::Unavailable);
+            continue;
+        }
 
-            client.entity = account_entity;
+        // The token issuer already verified the password of the account the token is for
+        if client.identity.is_some() {
+            finish_admin_login(&mut commands, &mut net_params, client_id, account, true);
+            continue;
+        }
 
-            /* TODO disabled for now
-            net_params.server.send_message_to_target::<Channel1, _>(
-                SCLoginAdminAccountSuccess(account.clone()),
-                NetworkTarget::Only(vec![*client_id]),
-            );
+        let password = msg.password.clone();
+        credential_tasks.spawn(client_id, move || CredentialCheck::AdminLogin {
+            verified: credential.verify(&password),
+            account,
+        });
+    }
+}
 
-            commands.spawn(AdminAccountInstance(account));
-            */
-        } else {
-            info!("unable to deserialize account: {file_path}");
-        }
+fn finish_admin_login(
+    commands: &mut Commands,
+    net_params: &mut NetworkParamsRW,
+    client_id: ClientId,
+    account: AdminAccount,
+    verified: bool,
+) {
+    let Some(client) = net_params.context.clients.get_mut(&client_id) else {
+        return;
+    };
+    if client.is_authenticated_admin() {
+        info!("admin was authenticated while logging in {client:?}");
+        return;
     }
+
+    if !verified {
+        info!("invalid admin password {client:?}");
+        send_admin_login_error(net_params, client_id, LoginError::InvalidPassword);
+        return;
+    }
+
+    client.account_id = Some(account.info.id);
+    info!("spawning admin account for {client:?}");
+
+    let account_entity = commands
+        .spawn(AdminAccountInstanceBundle {
+            account: AdminAccountInstance(account.clone()),
+        })
+        .id();
+
+    client.entity = account_entity;
+
+    let message = ServerMessage::SCLoginAdminAccountSuccess(SCLoginAdminAccountSuccess(account));
+    net_params.outbound.send(client_id, &message);
+}
+
+fn send_admin_login_error(
+    net_params: &mut NetworkParamsRW,
+    client_id: ClientId,
+    error: LoginError,
+) {
+    let message = ServerMessage::SCLoginAdminAccountError(SCLoginAdminAccountError(error));
+    net_params.outbound.send(client_id, &message);
 }
 
 pub(crate) fn receive_account_login(
     mut commands: Commands,
+    store: Res<AccountStorage>,
+    mut credential_tasks: ResMut<CredentialTasks>,
     mut login_reader: EventReader<ClientMessageEvent>,
     mut net_params: NetworkParamsRW,
 ) {
@@ -212,54 +483,206 @@ pub(crate) fn receive_account_login(
         };
 
         let client_id = event.client_id;
-        let client = net_params.context.clients.get_mut(&client_id).unwrap();
+        let Some(client) = net_params.context.get_client_from_id(client_id) else {
+            continue;
+        };
         if client.is_authenticated_player() {
             info!("authenticated player attempted to load account {client:?}");
+            send_login_error(&mut net_params, client_id, LoginError::AlreadyAuthenticated);
+            continue;
+        } else if client.is_admin() {
+            info!("admin client attempted to load a player account {client:?}");
+            send_login_error(&mut net_params, client_id, LoginError::Unavailable);
             continue;
         }
 
-        let file_path = format!(
-            "{}/server/accounts/{}.bin",
-            env::var("RPG_SAVE_ROOT").unwrap(),
-            msg.name
-        );
-        let path = Path::new(file_path.as_str());
-        let file = open_read(path);
-
-        if let Ok(file) = file {
-            let account: Result<Account, _> = bincode::deserialize_from(file);
-            if let Ok(account) = account {
-                // FIXME assign a client id and send it to the client
-                client.client_type = ClientType::Player;
-                client.account_id = Some(account.info.id);
-                info!("spawning account for {client:?}");
-
-                let account_entity = commands
-                    .spawn(AccountInstanceBundle {
-                        account: AccountInstance(account.clone()),
-                    })
-                    .id();
-
-                client.entity = account_entity;
-
-                let message = bincode::serialize(&ServerMessage::SCLoginAccountSuccess(
-                    SCLoginAccountSuccess(account.clone()),
-                ))
-                .unwrap();
-                net_params
-                    .server
-                    .send_message(client_id, ServerChannel::Message, message);
-            } else {
-                info!("unable to deserialize account: {file_path}");
+        // The naming rules only apply to new accounts, existing names may predate them
+        if !has_valid_characters(&msg.name) {
+            info!("invalid account name {:?}", msg.name);
+            send_login_error(
+                &mut net_params,
+                client_id,
+                LoginError::InvalidName(NameError::InvalidCharacter),
+            );
+            continue;
+        }
+
+        let account = match store.load_account(&msg.name) {
+            Ok(account) => account,
+            Err(StoreError::NotFound) => {
+                info!("account does not exist {client:?}");
+                send_login_error(&mut net_params, client_id, LoginError::AccountNotFound);
+                continue;
             }
-        } else {
-            info!("account does not exist {client:?}");
+            Err(e) => {
+                info!("unable to load account {}: {e}", msg.name);
+                send_login_error(&mut net_params, client_id, LoginError::Unavailable);
+                continue;
+            }
+        };
+
+        if !client.token_allows(&TokenIdentity::Player(account.info.id)) {
+            info!("client token was issued for another account {client:?}");
+            send_login_error(&mut net_params, client_id, LoginError::Unavailable);
+            continue;
+        }
+
+        // The token issuer already verified the password of the account the token is for
+        if client.identity.is_some() {
+            finish_account_login(&mut commands, &mut net_params, client_id, account, true);
+            continue;
+        }
+
+        let Ok(credential) = store.load_credential(&msg.name) else {
+            info!("account has no credential {client:?}");
+            send_login_error(&mut net_params, client_id, LoginError::Unavailable);
+            continue;
+        };
+
+        let password = msg.password.clone();
+        credential_tasks.spawn(client_id, move || CredentialCheck::Login {
+            verified: credential.verify(&password),
+            account,
+        });
+    }
+}
+
+fn finish_account_login(
+    commands: &mut Commands,
+    net_params: &mut NetworkParamsRW,
+    client_id: ClientId,
+    account: Account,
+    verified: bool,
+) {
+    let Some(client) = net_params.context.get_client_from_id(client_id) else {
+        return;
+    };
+    if client.is_authenticated_player() {
+        info!("client was authenticated while logging in {client:?}");
+        return;
+    }
+
+    if !verified {
+        info!("invalid password {client:?}");
+        send_login_error(net_params, client_id, LoginError::InvalidPassword);
+        return;
+    }
+
+    if let Some(ban) = account.moderation.active_ban(unix_time()) {
+        info!("banned account attempted to login {client:?}: {ban:?}");
+        send_login_error(net_params, client_id, LoginError::Banned);
+        return;
+    }
+
+    // Two sessions of one account would save over each other
+    if net_params
+        .context
+        .get_client_from_account_id(account.info.id)
+        .is_some()
+    {
+        info!("account {:?} is already logged in", account.info.id);
+        send_login_error(net_params, client_id, LoginError::AlreadyLoggedIn);
+        return;
+    }
+
+    let Some(client) = net_params.context.clients.get_mut(&client_id) else {
+        return;
+    };
+
+    // FIXME assign a client id and send it to the client
+    client.client_type = ClientType::Player;
+    client.account_id = Some(account.info.id);
+    info!("spawning account for {client:?}");
+
+    let account_entity = commands
+        .spawn(AccountInstanceBundle {
+            account: AccountInstance(account.clone()),
+        })
+        .id();
+
+    client.entity = account_entity;
+
+    let message = ServerMessage::SCLoginAccountSuccess(SCLoginAccountSuccess(account));
+    net_params.outbound.send(client_id, &message);
+}
+
+fn send_login_error(net_params: &mut NetworkParamsRW, client_id: ClientId, error: LoginError) {
+    let message = ServerMessage::SCLoginAccountError(SCLoginAccountError(error));
+    net_params.outbound.send(client_id, &message);
+}
+
+pub(crate) fn receive_password_change(
+    store: Res<AccountStorage>,
+    mut credential_tasks: ResMut<CredentialTasks>,
+    mut change_reader: EventReader<ClientMessageEvent>,
+    mut net_params: NetworkParamsRW,
+    account_q: Query<&AccountInstance>,
+) {
+    for event in change_reader.read() {
+        let ClientMessage::CSChangePassword(msg) = &event.message else {
+            continue;
+        };
+
+        let client_id = event.client_id;
+        let Some(client) = net_params.context.get_client_from_id(client_id) else {
+            continue;
+        };
+        if !client.is_authenticated_player() {
+            info!("unauthenticated client attempted to change password {client:?}");
+            continue;
         }
+
+        let Ok(account) = account_q.get(client.entity) else {
+            continue;
+        };
+
+        let name = account.info.name.clone();
+        let account_id = account.info.id;
+        let Ok(mut credential) = store.load_credential(&name) else {
+            info!("password change rejected for {account_id:?}");
+            let message = ServerMessage::SCChangePasswordError(SCChangePasswordError);
+            net_params.outbound.send(client_id, &message);
+            continue;
+        };
+
+        let password = msg.password.clone();
+        let new_password = msg.new_password.clone();
+        credential_tasks.spawn(client_id, move || {
+            let changed = credential.verify(&password) && credential.set_password(&new_password);
+            CredentialCheck::ChangePassword {
+                name,
+                account_id,
+                credential: changed.then_some(credential),
+            }
+        });
     }
 }
 
+fn finish_password_change(
+    store: &mut AccountStorage,
+    net_params: &mut NetworkParamsRW,
+    client_id: ClientId,
+    name: &str,
+    account_id: AccountId,
+    credential: Option<Credential>,
+) {
+    let changed =
+        credential.is_some_and(|credential| store.save_credential(name, &credential).is_ok());
+
+    let message = if changed {
+        info!("password changed for {account_id:?}");
+        ServerMessage::SCChangePasswordSuccess(SCChangePasswordSuccess)
+    } else {
+        info!("password change rejected for {account_id:?}");
+        ServerMessage::SCChangePasswordError(SCChangePasswordError)
+    };
+
+    net_params.outbound.send(client_id, &message);
+}
+
 pub(crate) fn receive_character_create(
     metadata: Res<MetadataResources>,
+    mut store: ResMut<AccountStorage>,
     mut server_metadata: ResMut<ServerMetadataResource>,
     mut character_create_reader: EventReader<ClientMessageEvent>,
     mut net_params: NetworkParamsRW,
@@ -271,103 +694,140 @@ pub(crate) fn receive_character_create(
         };
 
         let client_id = event.client_id;
-        let client = net_params.context.get_client_from_id(client_id).unwrap();
+        let Some(client) = net_params.context.get_client_from_id(client_id) else {
+            continue;
+        };
         if !client.is_authenticated_player() {
             info!("unauthenticated client attempted to create character {client:?}");
             continue;
         }
 
-        for mut account in &mut account_q {
-            if account.0.characters.iter().any(|c| c.info.slot == msg.slot) {
-                info!("character already exists");
-
-                let message =
-                    bincode::serialize(&ServerMessage::SCCreateAccountError(SCCreateAccountError))
-                        .unwrap();
-                net_params
-                    .server
-                    .send_message(client_id, ServerChannel::Message, message);
-            } else {
-                let unit_info = UnitInfo::Hero(HeroInfo::new(&metadata.rpg, msg.game_mode));
-                let mut unit = RpgUnit::new(
-                    server_metadata.0.next_uid.get(),
-                    msg.class,
-                    UnitKind::Hero,
-                    unit_info,
-                    1,
-                    msg.name.clone(),
-                    &metadata.rpg,
-                );
-                let mut skills = Vec::new();
-                unit.add_default_skills(&mut skills, &metadata.rpg);
-                let skill_slots = vec![SkillSlot::new(SkillSlotId(0), Some(skills[0].id))];
-
-                server_metadata.0.next_uid.next();
-
-                let character_info = CharacterInfo {
-                    name: msg.name.clone(),
-                    slot: msg.slot,
-                    uid: unit.uid,
-                    game_mode: msg.game_mode,
-                };
-
-                let character = CharacterRecord {
-                    info: character_info,
-                    statistics: CharacterStatistics::default(),
-                    character: Character {
-                        unit,
-                        skills,
-                        skill_slots,
-                        passive_tree: UnitPassiveSkills::new(msg.class),
-                        storage: UnitStorage::default(),
-                        waypoints: vec![ZoneId(0)],
-                    },
-                };
-
-                let message = bincode::serialize(&ServerMessage::SCCreateCharacterSuccess(
-                    SCCreateCharacterSuccess(character.clone()),
-                ))
-                .unwrap();
-
-                net_params
-                    .server
-                    .send_message(client_id, ServerChannel::Message, message);
-
-                account.0.characters.push(character);
-
-                let file_path = format!(
-                    "{}/server/accounts/{}.bin",
-                    env::var("RPG_SAVE_ROOT").unwrap(),
-                    account.0.info.name
+        let Ok(mut account) = account_q.get_mut(client.entity) else {
+            continue;
+        };
+
+        if let Err(e) = validate_character_name(&msg.name) {
+            info!("invalid character name {:?}: {e:?}", msg.name);
+            send_create_character_error(
+                &mut net_params,
+                client_id,
+                CreateCharacterError::InvalidName(e),
+            );
+            continue;
+        }
+
+        if msg.slot.0 >= account.info.character_slots {
+            info!("invalid character slot {:?}", msg.slot);
+            send_create_character_error(
+                &mut net_params,
+                client_id,
+                CreateCharacterError::InvalidSlot,
+            );
+            continue;
+        }
+
+        if account.0.characters.iter().any(|c| c.info.slot == msg.slot) {
+            info!("character already exists");
+            send_create_character_error(
+                &mut net_params,
+                client_id,
+                CreateCharacterError::SlotInUse,
+            );
+            continue;
+        }
+
+        // Characters of this account that have not been saved yet are not visible to the store
+        let name_in_use = account
+            .0
+            .characters
+            .iter()
+            .any(|c| names_match(&c.info.name, &msg.name));
+        match store.character_name_exists(&msg.name) {
+            Ok(false) if !name_in_use => {}
+            Ok(_) => {
+                info!("character name already exists {:?}", msg.name);
+                send_create_character_error(
+                    &mut net_params,
+                    client_id,
+                    CreateCharacterError::NameTaken,
                 );
-                let path = Path::new(file_path.as_str());
-                let Ok(file) = open_write(path) else {
-                    info!("unable to open account file for writing");
-                    continue;
-                };
-
-                let meta_file_path = format!(
-                    "{}/server/meta.bin",
-                    std::env::var("RPG_SAVE_ROOT").unwrap(),
+                continue;
+            }
+            Err(e) => {
+                info!("unable to check for existing character {}: {e}", msg.name);
+                send_create_character_error(
+                    &mut net_params,
+                    client_id,
+                    CreateCharacterError::Unavailable,
                 );
-                let meta_path = Path::new(meta_file_path.as_str());
-                let meta_file = open_write(meta_path);
+                continue;
+            }
+        }
+
+        let unit_info = UnitInfo::Hero(HeroInfo::new(&metadata.rpg, msg.game_mode));
+        let mut unit = RpgUnit::new(
+            server_metadata.0.next_uid.get(),
+            msg.class,
+            UnitKind::Hero,
+            unit_info,
+            1,
+            msg.name.clone(),
+            &metadata.rpg,
+        );
+        let mut skills = Vec::new();
+        unit.add_default_skills(&mut skills, &metadata.rpg);
+        let skill_slots = vec![SkillSlot::new(SkillSlotId(0), Some(skills[0].id))];
 
-                let Ok(meta_file) = meta_file else {
-                    info!("cannot open {meta_file_path} for writing");
-                    return;
-                };
+        server_metadata.0.next_uid.next();
 
-                bincode::serialize_into(file, &account.0).unwrap();
-                bincode::serialize_into(meta_file, &server_metadata.0).unwrap();
-            }
+        let character_info = CharacterInfo {
+            name: msg.name.clone(),
+            slot: msg.slot,
+            uid: unit.uid,
+            game_mode: msg.game_mode,
+        };
+
+        let character = CharacterRecord {
+            info: character_info,
+            statistics: CharacterStatistics::default(),
+            character: Character {
+                unit,
+                skills,
+                skill_slots,
+                passive_tree: UnitPassiveSkills::new(msg.class),
+                storage: UnitStorage::default(),
+                waypoints: vec![ZoneId(0)],
+            },
+        };
+
+        let message =
+            ServerMessage::SCCreateCharacterSuccess(SCCreateCharacterSuccess(character.clone()));
+
+        net_params.outbound.send(client_id, &message);
+
+        account.0.characters.push(character);
+
+        if let Err(e) = store.save_metadata(&server_metadata.0) {
+            info!("unable to save server metadata: {e}");
+        }
+        if let Err(e) = store.save_account(&account.0) {
+            info!("unable to save account {}: {e}", account.0.info.name);
         }
     }
 }
 
+fn send_create_character_error(
+    net_params: &mut NetworkParamsRW,
+    client_id: ClientId,
+    error: CreateCharacterError,
+) {
+    let message = ServerMessage::SCCreateCharacterError(SCCreateCharacterError(error));
+    net_params.outbound.send(client_id, &message);
+}
+
 pub(crate) fn receive_game_create(
-    mut state: ResMut<NextState<AppState>>,
-    mut game_state: ResMut<GameState>,
+    settings: Res<ServerSettings>,
+    mut game_manager: ResMut<GameManager>,
     mut load_writer: EventWriter<LoadZone>,
     mut net_params: NetworkParamsRW,
     mut create_events: EventReader<ClientMessageEvent>,
@@ -379,20 +839,26 @@ pub(crate) fn receive_game_create(
         };
 
         let client_id = event.client_id;
-        let client = net_params.context.clients.get(&client_id).unwrap();
+        let Some(client) = net_params.context.clients.get(&client_id) else {
+            continue;
+        };
         if !client.is_authenticated_player() {
             continue;
         };
 
-        if !game_state.players.is_empty() {
-            // TODO only a single game instance is currently supported
-            info!("game already active");
+        if game_manager.get_game_from_client_id(client_id).is_some() {
+            info!("client attempted to create a game while in a game");
+            let message = ServerMessage::SCGameCreateError(SCGameCreateError);
+            net_params.outbound.send(client_id, &message);
+
             continue;
         }
 
         info!("create game {msg:?}");
 
-        let mut account = account_q.get_mut(client.entity).unwrap();
+        let Ok(mut account) = account_q.get_mut(client.entity) else {
+            continue;
+        };
 
         let Some(character) = account.get_character_from_slot(msg.slot) else {
             info!("no character in slot");
@@ -408,36 +874,44 @@ pub(crate) fn receive_game_create(
             continue;
         }
 
+        let game_id = game_manager.create_game(GameOptions {
+            mode: msg.game_mode,
+            max_players: settings.simulation.max_players,
+        });
+        let game = game_manager.get_game_mut(game_id).unwrap();
+
         // add the creator to the player list
-        game_state.players.push(PlayerIdInfo {
+        game.players.push(PlayerIdInfo {
             slot: msg.slot,
             account_id: account.0.info.id,
             character_id: character.info.uid,
             client_id,
             entity: client.entity,
         });
-        game_state.options.max_players = 8;
-        game_state.options.mode = msg.game_mode;
 
-        let message = bincode::serialize(&ServerMessage::SCGameCreateSuccess(SCGameCreateSuccess(
-            msg.game_mode,
-        )))
-        .unwrap();
-        net_params
-            .server
-            .send_message(client_id, ServerChannel::Message, message);
+        let message = ServerMessage::SCGameCreateSuccess(SCGameCreateSuccess {
+            game_id,
+            game_mode: msg.game_mode,
+        });
+        net_params.outbound.send(client_id, &message);
 
         account.info.selected_slot = Some(msg.slot);
 
-        load_writer.send(LoadZone(ZoneId(0)));
-
-        state.set(AppState::SpawnSimulation); // TODO NEEDED?
+        load_writer.send(LoadZone {
+            game_id,
+            zone_id: ZoneId(0),
+        });
     }
 }
 
+fn send_game_join_error(net_params: &mut NetworkParamsRW, client_id: ClientId) {
+    let message = ServerMessage::SCGameJoinError(SCGameJoinError);
+    net_params.outbound.send(client_id, &message);
+}
+
 // FIXME move to net/game.rs
 pub(crate) fn receive_game_join(
-    mut game_state: ResMut<GameState>,
+    mut game_manager: ResMut<GameManager>,
     mut net_params: NetworkParamsRW,
     mut join_events: EventReader<ClientMessageEvent>,
     account_q: Query<&AccountInstance>,
@@ -448,33 +922,24 @@ pub(crate) fn receive_game_join(
         };
 
         let client_id = event.client_id;
-        let client = net_params.context.clients.get(&client_id).unwrap();
+        let Some(client) = net_params.context.clients.get(&client_id) else {
+            continue;
+        };
         if !client.is_authenticated_player() {
             continue;
         };
 
-        // If the game is full reject new joins
-        if game_state.players.len() >= game_state.options.max_players as usize {
-            info!("game full");
-            join_events.clear();
-            return;
-        }
-
         info!("join game {msg:?}");
 
-        let account = account_q.get(client.entity).unwrap();
-        if game_state
-            .players
-            .iter()
-            .any(|a| a.account_id == account.info.id)
+        let Ok(account) = account_q.get(client.entity) else {
+            continue;
+        };
+        if game_manager
+            .get_game_from_account_id(account.info.id)
+            .is_some()
         {
             info!("client attempted to join a game while in a game");
-            let message =
-                bincode::serialize(&ServerMessage::SCGameJoinError(SCGameJoinError)).unwrap();
-            net_params
-                .server
-                .send_message(client_id, ServerChannel::Message, message);
-
+            send_game_join_error(&mut net_params, client_id);
             continue;
         }
 
@@ -483,33 +948,40 @@ pub(crate) fn receive_game_join(
             continue;
         };
 
+        let Some(game_id) = msg
+            .game_id
+            .or_else(|| game_manager.find_open_game(character.info.game_mode))
+        else {
+            info!("no open {:?} game found", character.info.game_mode);
+            send_game_join_error(&mut net_params, client_id);
+            continue;
+        };
+
+        let Some(game) = game_manager.get_game_mut(game_id) else {
+            info!("game {game_id:?} does not exist");
+            send_game_join_error(&mut net_params, client_id);
+            continue;
+        };
+
+        // If the game is full or ending reject new joins
+        if !game.is_joinable() {
+            info!("game {game_id:?} is not joinable");
+            send_game_join_error(&mut net_params, client_id);
+            continue;
+        }
+
         // Ensure the player is of the correct type to join the game
-        if character.info.game_mode != game_state.options.mode {
+        if character.info.game_mode != game.options.mode {
             info!(
                 "a {:?} player attemped to join a {:?} game",
-                character.info.game_mode, game_state.options.mode
+                character.info.game_mode, game.options.mode
             );
+            send_game_join_error(&mut net_params, client_id);
+            continue;
         }
 
-        // TODO optimize
-        // spawn the new player on all connected players
-        let message = bincode::serialize(&ServerMessage::SCSpawnHero(SCSpawnHero {
-            position: Vec3::ZERO,
-            uid: character.info.uid,
-            class: character.character.unit.class,
-            level: character.character.unit.level,
-            name: character.character.unit.name.clone(),
-            skills: character.character.skills.clone(),
-            deaths: None,
-            skill_slots: character.character.skill_slots.clone(),
-        }))
-        .unwrap();
-
-        net_params
-            .server
-            .broadcast_message_except(client_id, ServerChannel::Message, message);
-
-        game_state.players.push(PlayerIdInfo {
+        // other players are told about the hero once it has spawned, see `interest`
+        game.players.push(PlayerIdInfo {
             slot: msg.slot,
             account_id: account.0.info.id,
             character_id: character.info.uid,
@@ -517,12 +989,21 @@ pub(crate) fn receive_game_join(
             entity: client.entity,
         });
 
-        let message = bincode::serialize(&ServerMessage::SCGameJoinSuccess(SCGameJoinSuccess(
-            game_state.options.mode,
-        )))
-        .unwrap();
-        net_params
-            .server
-            .send_message(client_id, ServerChannel::Message, message);
+        let message = ServerMessage::SCGameJoinSuccess(SCGameJoinSuccess {
+            game_id,
+            game_mode: game.options.mode,
+        });
+        net_params.outbound.send(client_id, &message);
+
+        // a game that is still spawning will send these once it's world has been loaded
+        if game.status == GameStatus::Running {
+            for zone_id in game.world.zones.keys() {
+                let message = ServerMessage::SCZoneLoad(SCZoneLoad(*zone_id));
+                net_params.outbound.send(client_id, &message);
+            }
+
+            let message = ServerMessage::SCPlayerJoinSuccess(SCPlayerJoinSuccess);
+            net_params.outbound.send(client_id, &message);
+        }
     }
 }
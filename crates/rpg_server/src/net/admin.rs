@@ -0,0 +1,592 @@
+use super::{
+    send::EncodedMessage,
+    server::{ClientMessageEvent, NetworkParamsRW},
+};
+use crate::{
+    account::{save_account, AccountInstance, AdminAccountInstance},
+    game::instance::{GameManager, GameStatus},
+    moderation::unix_time,
+    store::{AccountStorage, StoreError},
+};
+
+use bevy::{
+    ecs::{
+        entity::Entity,
+        event::EventReader,
+        system::{Query, Res, ResMut},
+    },
+    log::info,
+};
+
+use rpg_account::{
+    account::Account,
+    character::CharacterRecord,
+    moderation::{Moderation, Sanction},
+    name::names_match,
+};
+use rpg_network_protocol::protocol::*;
+use rpg_util::{
+    item::UnitStorage,
+    skill::{SkillSlots, Skills},
+    unit::{Unit, Waypoints},
+};
+
+use bevy_renet::renet::ClientId;
+
+fn send_admin_message(
+    net_params: &mut NetworkParamsRW,
+    client_id: ClientId,
+    message: ServerMessage,
+) {
+    net_params.outbound.send(client_id, &message);
+}
+
+fn send_admin_error(net_params: &mut NetworkParamsRW, client_id: ClientId, error: AdminError) {
+    send_admin_message(
+        net_params,
+        client_id,
+        ServerMessage::SCAdminError(SCAdminError(error)),
+    );
+}
+
+/// Check that a request was sent by a logged in admin, rejecting it otherwise
+fn authorize(net_params: &mut NetworkParamsRW, client_id: ClientId) -> bool {
+    let Some(client) = net_params.context.clients.get(&client_id) else {
+        return false;
+    };
+    if client.is_authenticated_admin() {
+        return true;
+    }
+
+    info!("unauthorized admin request from {client:?}");
+    send_admin_error(net_params, client_id, AdminError::NotAuthorized);
+
+    false
+}
+
+/// Find the connected player targeted by a moderation request
+fn find_target_client(
+    net_params: &NetworkParamsRW,
+    account_q: &Query<&AccountInstance>,
+    target: &ModerationTarget,
+) -> Option<(ClientId, Entity)> {
+    net_params
+        .context
+        .clients
+        .values()
+        .filter(|c| c.is_authenticated_player())
+        .find(|c| match target {
+            ModerationTarget::Client(id) => c.client_id.raw() == *id,
+            ModerationTarget::Account(name) => account_q
+                .get(c.entity)
+                .is_ok_and(|a| names_match(&a.info.name, name)),
+        })
+        .map(|c| (c.client_id, c.entity))
+}
+
+/// Apply `update` to the moderation state of the target account and persist it
+///
+/// Returns the client the account is logged in on, offline accounts can only be targeted by name.
+fn moderate_account(
+    store: &mut AccountStorage,
+    net_params: &NetworkParamsRW,
+    account_q: &mut Query<&mut AccountInstance>,
+    target: &ModerationTarget,
+    update: impl FnOnce(&mut Moderation),
+) -> Result<Option<ClientId>, AdminError> {
+    let online = find_target_client(net_params, &account_q.to_readonly(), target);
+    if let Some((client_id, entity)) = online {
+        let mut account = account_q
+            .get_mut(entity)
+            .map_err(|_| AdminError::Unavailable)?;
+        update(&mut account.moderation);
+        save_account(store, &account.0);
+
+        return Ok(Some(client_id));
+    }
+
+    let ModerationTarget::Account(name) = target else {
+        return Err(AdminError::AccountOffline);
+    };
+
+    let mut account = match store.load_account(name) {
+        Ok(account) => account,
+        Err(StoreError::NotFound | StoreError::InvalidName) => {
+            return Err(AdminError::AccountNotFound);
+        }
+        Err(e) => {
+            info!("unable to load account {name}: {e}");
+            return Err(AdminError::Unavailable);
+        }
+    };
+
+    update(&mut account.moderation);
+    if let Err(e) = store.save_account(&account) {
+        info!("unable to save account {name}: {e}");
+        return Err(AdminError::Unavailable);
+    }
+
+    Ok(None)
+}
+
+fn send_admin_result(
+    net_params: &mut NetworkParamsRW,
+    client_id: ClientId,
+    result: Result<(), AdminError>,
+) {
+    match result {
+        Ok(()) => send_admin_message(
+            net_params,
+            client_id,
+            ServerMessage::SCAdminSuccess(SCAdminSuccess),
+        ),
+        Err(e) => send_admin_error(net_params, client_id, e),
+    }
+}
+
+fn apply_character_edit(
+    record: &mut CharacterRecord,
+    edit: CharacterEdit,
+) -> Result<(), AdminError> {
+    match edit {
+        CharacterEdit::Level(level) => {
+            if level == 0 {
+                return Err(AdminError::InvalidEdit);
+            }
+            record.character.unit.level = level;
+        }
+        CharacterEdit::PassiveSkillPoints(points) => {
+            record.character.unit.passive_skill_points = points;
+        }
+    }
+
+    Ok(())
+}
+
+pub(crate) fn receive_admin_list_clients(
+    game_manager: Res<GameManager>,
+    mut list_reader: EventReader<ClientMessageEvent>,
+    mut net_params: NetworkParamsRW,
+    account_q: Query<&AccountInstance>,
+    admin_q: Query<&AdminAccountInstance>,
+) {
+    for event in list_reader.read() {
+        let ClientMessage::CSAdminListClients(_) = &event.message else {
+            continue;
+        };
+
+        let client_id = event.client_id;
+        if !authorize(&mut net_params, client_id) {
+            continue;
+        }
+
+        let clients = net_params
+            .context
+            .clients
+            .values()
+            .map(|c| AdminClientInfo {
+                client_id: c.client_id.raw(),
+                account_id: c.account_id,
+                account_name: account_q
+                    .get(c.entity)
+                    .map(|a| a.info.name.clone())
+                    .or_else(|_| admin_q.get(c.entity).map(|a| a.info.name.clone()))
+                    .ok(),
+                is_admin: c.is_admin(),
+                game_id: game_manager
+                    .get_game_from_client_id(c.client_id)
+                    .map(|g| g.id),
+                build_id: c.handshake.as_ref().map(|h| h.build_id.clone()),
+                features: c
+                    .handshake
+                    .as_ref()
+                    .map(|h| h.features.clone())
+                    .unwrap_or_default(),
+            })
+            .collect();
+
+        send_admin_message(
+            &mut net_params,
+            client_id,
+            ServerMessage::SCAdminClients(SCAdminClients(clients)),
+        );
+    }
+}
+
+pub(crate) fn receive_admin_list_games(
+    game_manager: Res<GameManager>,
+    mut list_reader: EventReader<ClientMessageEvent>,
+    mut net_params: NetworkParamsRW,
+) {
+    for event in list_reader.read() {
+        let ClientMessage::CSAdminListGames(_) = &event.message else {
+            continue;
+        };
+
+        let client_id = event.client_id;
+        if !authorize(&mut net_params, client_id) {
+            continue;
+        }
+
+        let games = game_manager
+            .games
+            .iter()
+            .map(|g| AdminGameInfo {
+                id: g.id,
+                game_mode: g.options.mode,
+                running: g.status == GameStatus::Running,
+                max_players: g.options.max_players,
+                players: g.players.iter().map(|p| p.account_id).collect(),
+            })
+            .collect();
+
+        send_admin_message(
+            &mut net_params,
+            client_id,
+            ServerMessage::SCAdminGames(SCAdminGames(games)),
+        );
+    }
+}
+
+pub(crate) fn receive_admin_kick(
+    mut kick_reader: EventReader<ClientMessageEvent>,
+    mut net_params: NetworkParamsRW,
+    account_q: Query<&AccountInstance>,
+) {
+    for event in kick_reader.read() {
+        let ClientMessage::CSAdminKick(msg) = &event.message else {
+            continue;
+        };
+
+        let client_id = event.client_id;
+        if !authorize(&mut net_params, client_id) {
+            continue;
+        }
+
+        let Some((kick_id, _)) = find_target_client(&net_params, &account_q, &msg.0) else {
+            info!("admin attempted to kick an offline account {:?}", msg.0);
+            send_admin_error(&mut net_params, client_id, AdminError::AccountOffline);
+            continue;
+        };
+
+        info!("admin kicked {:?} on client {kick_id}", msg.0);
+        net_params.server.disconnect(kick_id);
+
+        send_admin_result(&mut net_params, client_id, Ok(()));
+    }
+}
+
+pub(crate) fn receive_admin_ban(
+    mut store: ResMut<AccountStorage>,
+    mut ban_reader: EventReader<ClientMessageEvent>,
+    mut net_params: NetworkParamsRW,
+    mut account_q: Query<&mut AccountInstance>,
+) {
+    for event in ban_reader.read() {
+        let ClientMessage::CSAdminBan(msg) = &event.message else {
+            continue;
+        };
+
+        let client_id = event.client_id;
+        if !authorize(&mut net_params, client_id) {
+            continue;
+        }
+
+        let expires = msg.duration.map(|d| unix_time().saturating_add(d));
+        let ban = Sanction::new(msg.reason.clone(), expires);
+        let result = moderate_account(&mut store, &net_params, &mut account_q, &msg.target, |m| {
+            m.ban = Some(ban);
+        });
+
+        if let Ok(online) = result {
+            info!(
+                "admin banned {:?} until {expires:?}: {}",
+                msg.target, msg.reason
+            );
+            // The account is saved again as the client disconnects, with the ban in place
+            if let Some(ban_id) = online {
+                net_params.server.disconnect(ban_id);
+            }
+        }
+
+        send_admin_result(&mut net_params, client_id, result.map(|_| ()));
+    }
+}
+
+pub(crate) fn receive_admin_unban(
+    mut store: ResMut<AccountStorage>,
+    mut unban_reader: EventReader<ClientMessageEvent>,
+    mut net_params: NetworkParamsRW,
+    mut account_q: Query<&mut AccountInstance>,
+) {
+    for event in unban_reader.read() {
+        let ClientMessage::CSAdminUnban(msg) = &event.message else {
+            continue;
+        };
+
+        let client_id = event.client_id;
+        if !authorize(&mut net_params, client_id) {
+            continue;
+        }
+
+        let result = moderate_account(&mut store, &net_params, &mut account_q, &msg.0, |m| {
+            m.ban = None;
+        });
+        if result.is_ok() {
+            info!("admin unbanned {:?}", msg.0);
+        }
+
+        send_admin_result(&mut net_params, client_id, result.map(|_| ()));
+    }
+}
+
+pub(crate) fn receive_admin_mute(
+    mut store: ResMut<AccountStorage>,
+    mut mute_reader: EventReader<ClientMessageEvent>,
+    mut net_params: NetworkParamsRW,
+    mut account_q: Query<&mut AccountInstance>,
+) {
+    for event in mute_reader.read() {
+        let ClientMessage::CSAdminMute(msg) = &event.message else {
+            continue;
+        };
+
+        let client_id = event.client_id;
+        if !authorize(&mut net_params, client_id) {
+            continue;
+        }
+
+        let expires = msg.duration.map(|d| unix_time().saturating_add(d));
+        let mute = Sanction::new(msg.reason.clone(), expires);
+        let result = moderate_account(&mut store, &net_params, &mut account_q, &msg.target, |m| {
+            m.mute = Some(mute);
+        });
+        if result.is_ok() {
+            info!(
+                "admin muted {:?} until {expires:?}: {}",
+                msg.target, msg.reason
+            );
+        }
+
+        send_admin_result(&mut net_params, client_id, result.map(|_| ()));
+    }
+}
+
+pub(crate) fn receive_admin_unmute(
+    mut store: ResMut<AccountStorage>,
+    mut unmute_reader: EventReader<ClientMessageEvent>,
+    mut net_params: NetworkParamsRW,
+    mut account_q: Query<&mut AccountInstance>,
+) {
+    for event in unmute_reader.read() {
+        let ClientMessage::CSAdminUnmute(msg) = &event.message else {
+            continue;
+        };
+
+        let client_id = event.client_id;
+        if !authorize(&mut net_params, client_id) {
+            continue;
+        }
+
+        let result = moderate_account(&mut store, &net_params, &mut account_q, &msg.0, |m| {
+            m.mute = None;
+        });
+        if result.is_ok() {
+            info!("admin unmuted {:?}", msg.0);
+        }
+
+        send_admin_result(&mut net_params, client_id, result.map(|_| ()));
+    }
+}
+
+pub(crate) fn receive_admin_broadcast(
+    mut broadcast_reader: EventReader<ClientMessageEvent>,
+    mut net_params: NetworkParamsRW,
+) {
+    for event in broadcast_reader.read() {
+        let ClientMessage::CSAdminBroadcast(msg) = &event.message else {
+            continue;
+        };
+
+        let client_id = event.client_id;
+        if !authorize(&mut net_params, client_id) {
+            continue;
+        }
+
+        info!("admin broadcast: {}", msg.0);
+
+        let message = EncodedMessage::new(&ServerMessage::SCServerMessage(SCServerMessage(
+            msg.0.clone(),
+        )));
+        let player_ids: Vec<_> = net_params
+            .context
+            .clients
+            .values()
+            .filter(|c| c.is_authenticated_player())
+            .map(|c| c.client_id)
+            .collect();
+
+        for player_id in player_ids {
+            net_params.outbound.push(player_id, &message);
+        }
+
+        send_admin_message(
+            &mut net_params,
+            client_id,
+            ServerMessage::SCAdminSuccess(SCAdminSuccess),
+        );
+    }
+}
+
+pub(crate) fn receive_admin_get_character(
+    store: Res<AccountStorage>,
+    mut get_reader: EventReader<ClientMessageEvent>,
+    mut net_params: NetworkParamsRW,
+    account_q: Query<(
+        &AccountInstance,
+        Option<(&Unit, &Skills, &SkillSlots, &UnitStorage, &Waypoints)>,
+    )>,
+) {
+    for event in get_reader.read() {
+        let ClientMessage::CSAdminGetCharacter(msg) = &event.message else {
+            continue;
+        };
+
+        let client_id = event.client_id;
+        if !authorize(&mut net_params, client_id) {
+            continue;
+        }
+
+        // Prefer the live state of online accounts, including any spawned hero
+        let online = account_q
+            .iter()
+            .find(|(a, _)| names_match(&a.info.name, &msg.account_name));
+
+        let account: Account = match online {
+            Some((account, hero)) => {
+                let mut account = AccountInstance(account.0.clone());
+                if let Some((unit, skills, skill_slots, storage, waypoints)) = hero {
+                    account.update_character(unit, skills, skill_slots, storage, waypoints);
+                }
+                account.0
+            }
+            None => match store.load_account(&msg.account_name) {
+                Ok(account) => account,
+                Err(StoreError::NotFound | StoreError::InvalidName) => {
+                    send_admin_error(&mut net_params, client_id, AdminError::AccountNotFound);
+                    continue;
+                }
+                Err(e) => {
+                    info!("unable to load account {}: {e}", msg.account_name);
+                    send_admin_error(&mut net_params, client_id, AdminError::Unavailable);
+                    continue;
+                }
+            },
+        };
+
+        let Some(record) = account.get_character_from_slot(msg.slot) else {
+            send_admin_error(&mut net_params, client_id, AdminError::CharacterNotFound);
+            continue;
+        };
+
+        send_admin_message(
+            &mut net_params,
+            client_id,
+            ServerMessage::SCAdminCharacter(SCAdminCharacter(record.clone())),
+        );
+    }
+}
+
+pub(crate) fn receive_admin_edit_character(
+    game_manager: Res<GameManager>,
+    mut store: ResMut<AccountStorage>,
+    mut edit_reader: EventReader<ClientMessageEvent>,
+    mut net_params: NetworkParamsRW,
+    mut account_q: Query<&mut AccountInstance>,
+) {
+    for event in edit_reader.read() {
+        let ClientMessage::CSAdminEditCharacter(msg) = &event.message else {
+            continue;
+        };
+
+        let client_id = event.client_id;
+        if !authorize(&mut net_params, client_id) {
+            continue;
+        }
+
+        // Online accounts are edited in place so the change is not lost on the next save
+        if let Some(mut account) = account_q
+            .iter_mut()
+            .find(|a| names_match(&a.info.name, &msg.account_name))
+        {
+            // The hero of a character in a game owns its state until the player leaves
+            if game_manager
+                .get_game_from_account_id(account.info.id)
+                .is_some()
+            {
+                send_admin_error(&mut net_params, client_id, AdminError::CharacterInGame);
+                continue;
+            }
+
+            let Some(record) = account
+                .characters
+                .iter_mut()
+                .find(|c| c.info.slot == msg.slot)
+            else {
+                send_admin_error(&mut net_params, client_id, AdminError::CharacterNotFound);
+                continue;
+            };
+
+            if let Err(e) = apply_character_edit(record, msg.edit) {
+                send_admin_error(&mut net_params, client_id, e);
+                continue;
+            }
+
+            save_account(&mut store, &account.0);
+        } else {
+            let mut account = match store.load_account(&msg.account_name) {
+                Ok(account) => account,
+                Err(StoreError::NotFound | StoreError::InvalidName) => {
+                    send_admin_error(&mut net_params, client_id, AdminError::AccountNotFound);
+                    continue;
+                }
+                Err(e) => {
+                    info!("unable to load account {}: {e}", msg.account_name);
+                    send_admin_error(&mut net_params, client_id, AdminError::Unavailable);
+                    continue;
+                }
+            };
+
+            let Some(record) = account
+                .characters
+                .iter_mut()
+                .find(|c| c.info.slot == msg.slot)
+            else {
+                send_admin_error(&mut net_params, client_id, AdminError::CharacterNotFound);
+                continue;
+            };
+
+            if let Err(e) = apply_character_edit(record, msg.edit) {
+                send_admin_error(&mut net_params, client_id, e);
+                continue;
+            }
+
+            if let Err(e) = store.save_account(&account) {
+                info!("unable to save account {}: {e}", msg.account_name);
+                send_admin_error(&mut net_params, client_id, AdminError::Unavailable);
+                continue;
+            }
+        }
+
+        info!(
+            "admin edited character {:?} of {}: {:?}",
+            msg.slot, msg.account_name, msg.edit
+        );
+
+        send_admin_message(
+            &mut net_params,
+            client_id,
+            ServerMessage::SCAdminSuccess(SCAdminSuccess),
+        );
+    }
+}
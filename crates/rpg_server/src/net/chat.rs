@@ -1,14 +1,16 @@
-use super::server::{ClientMessageEvent, NetworkParamsRW};
-use crate::{account::AccountInstance, chat::ChatManager};
+use super::{
+    send::EncodedMessage,
+    server::{ClientMessageEvent, NetworkParamsRW},
+};
+use crate::{account::AccountInstance, chat::ChatManager, moderation::unix_time};
 
-use rpg_account::account::AccountId;
-use rpg_chat::chat::{Channel, ChannelId, Message};
+use rpg_chat::chat::{ChannelId, Message};
 use rpg_network_protocol::protocol::*;
 
 use bevy::{
     ecs::{
         event::EventReader,
-        system::{Commands, Query, Res, ResMut},
+        system::{Query, ResMut},
     },
     log::info,
 };
@@ -25,14 +27,14 @@ pub(crate) fn receive_chat_join(
         };
 
         let client_id = event.client_id;
-        let client = net_params.context.clients.get(&client_id).unwrap();
+        let Some(client) = net_params.context.clients.get(&client_id) else {
+            continue;
+        };
         if !client.is_authenticated() {
             info!("unauthenticated client attempted to join chat: {client:?}");
             continue;
         }
 
-        // TODO Handle rejections for banned accounts etc.
-
         // TODO For now add the client to a global channel
 
         for account in &account_q {
@@ -43,18 +45,15 @@ pub(crate) fn receive_chat_join(
             if chat.channel_exists(ChannelId(0)) {
                 chat.add_subscriber(ChannelId(0), client.account_id.unwrap());
 
-                let message =
-                    bincode::serialize(&ServerMessage::SCChatJoinSuccess(SCChatJoinSuccess(0)))
-                        .unwrap();
-                net_params
-                    .server
-                    .send_message(client_id, ServerChannel::Message, message);
+                let message = ServerMessage::SCChatJoinSuccess(SCChatJoinSuccess(0));
+                net_params.outbound.send(client_id, &message);
             }
         }
     }
 }
 
 pub(crate) fn receive_chat_leave(
+    mut chat: ResMut<ChatManager>,
     mut leave_reader: EventReader<ClientMessageEvent>,
     mut net_params: NetworkParamsRW,
 ) {
@@ -64,17 +63,20 @@ pub(crate) fn receive_chat_leave(
         };
 
         let client_id = event.client_id;
-        let client = net_params.context.clients.get(&client_id).unwrap();
+        let Some(client) = net_params.context.clients.get(&client_id) else {
+            continue;
+        };
         if !client.is_authenticated() {
             info!("unauthenticated client attempted to leave chat: {client:?}");
             continue;
         }
 
-        // TODO Handle rejections for banned accounts etc.
-        let message = bincode::serialize(&ServerMessage::SCChatLeave(SCChatLeave)).unwrap();
-        net_params
-            .server
-            .send_message(client_id, ServerChannel::Message, message);
+        if let Some(account_id) = client.account_id {
+            chat.remove_subscriber(ChannelId(0), account_id);
+        }
+
+        let message = ServerMessage::SCChatLeave(SCChatLeave);
+        net_params.outbound.send(client_id, &message);
     }
 }
 
@@ -82,6 +84,7 @@ pub(crate) fn receive_chat_channel_message(
     mut message_reader: EventReader<ClientMessageEvent>,
     mut net_params: NetworkParamsRW,
     mut chat: ResMut<ChatManager>,
+    account_q: Query<&AccountInstance>,
 ) {
     for event in message_reader.read() {
         let ClientMessage::CSChatChannelMessage(msg) = &event.message else {
@@ -89,43 +92,41 @@ pub(crate) fn receive_chat_channel_message(
         };
 
         let client_id = event.client_id;
-        let client = net_params.context.clients.get(&client_id).unwrap();
+        let Some(client) = net_params.context.clients.get(&client_id) else {
+            continue;
+        };
         if !client.is_authenticated() {
             info!("unauthenticated client attempted to message chat: {client:?}");
             continue;
         }
 
-        // TODO Handle rejections for banned accounts etc.
+        if account_q
+            .get(client.entity)
+            .is_ok_and(|a| a.moderation.is_muted(unix_time()))
+        {
+            info!("muted client attempted to message chat: {client:?}");
+            let message = ServerMessage::SCChatChannelMessageError(SCChatChannelMessageError);
+            net_params.outbound.send(client_id, &message);
+            continue;
+        }
+
         let Some(channel) = chat.get_channel(msg.0.channel_id) else {
             continue;
         };
 
-        let subscriber_ids: Vec<_> = channel
-            .subscribers
-            .iter()
-            .map(|s| {
-                let client = net_params
-                    .context
-                    .clients
-                    .iter()
-                    .find(|c| c.1.account_id.unwrap() == *s)
-                    .unwrap();
-
-                *client.0
-            })
-            .collect();
-
-        let message = bincode::serialize(&ServerMessage::SCChatMessage(SCChatMessage(Message {
+        // Subscribers are removed when they disconnect, a missing client is skipped all the same
+        let subscriber_ids = net_params
+            .context
+            .get_client_ids_for_account_ids(&channel.subscribers);
+
+        let message = EncodedMessage::new(&ServerMessage::SCChatMessage(SCChatMessage(Message {
             channel_id: msg.0.channel_id,
             id: msg.0.id,
             sender: msg.0.sender.clone(),
             message: msg.0.message.clone(),
-        })))
-        .unwrap();
+        })));
         for client_id in subscriber_ids {
-            net_params
-                .server
-                .send_message(client_id, ServerChannel::Message, message.clone());
+            net_params.outbound.push(client_id, &message);
         }
     }
 }
@@ -1,8 +1,11 @@
+use super::rate_limit::RateLimits;
+
 use bevy::ecs::entity::Entity;
 
 use bevy_renet::renet::ClientId;
 
 use rpg_account::account::AccountId;
+use rpg_network_protocol::token::TokenIdentity;
 
 #[derive(Debug, Default, PartialEq, Eq)]
 pub(crate) enum ClientType {
@@ -12,24 +15,50 @@ pub(crate) enum ClientType {
     Admin,
 }
 
+/// Agreed on once a client's hello has been accepted
 #[derive(Debug, PartialEq, Eq)]
+pub(crate) struct Handshake {
+    pub(crate) build_id: String,
+    /// The optional features supported by both sides
+    pub(crate) features: Vec<String>,
+}
+
+#[derive(Debug, PartialEq)]
 pub(crate) struct Client {
     pub(crate) client_id: ClientId,
     pub(crate) entity: Entity,
     pub(crate) client_type: ClientType,
     pub(crate) account_id: Option<AccountId>,
+    pub(crate) handshake: Option<Handshake>,
+    /// Who the client's connect token was issued to, `None` in unsecure mode
+    pub(crate) identity: Option<TokenIdentity>,
+    /// The number of protocol violations, see `NetworkContext::record_violation`
+    pub(crate) violations: u32,
+    /// When a violation was last forgiven, see `NetworkContext::forgive_violations`
+    pub(crate) last_forgiven: f64,
+    pub(crate) rate_limits: RateLimits,
 }
 
 impl Client {
-    pub(crate) fn new(client_id: ClientId) -> Self {
+    pub(crate) fn new(client_id: ClientId, identity: Option<TokenIdentity>) -> Self {
         Self {
             client_id,
             entity: Entity::PLACEHOLDER,
             account_id: None,
             client_type: ClientType::Unknown,
+            handshake: None,
+            identity,
+            violations: 0,
+            last_forgiven: 0.,
+            rate_limits: RateLimits::default(),
         }
     }
 
+    /// Whether the client's connect token was issued for `identity`, anything goes without a token
+    pub(crate) fn token_allows(&self, identity: &TokenIdentity) -> bool {
+        self.identity.as_ref().map_or(true, |i| i == identity)
+    }
+
     pub(crate) fn is_authenticated(&self) -> bool {
         self.account_id.is_some() && (self.is_player() || self.is_admin())
     }
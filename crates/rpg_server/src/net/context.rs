@@ -1,4 +1,5 @@
 use super::client::Client;
+use crate::config::RateLimitSettings;
 
 use bevy::{
     ecs::{
@@ -6,21 +7,62 @@ use bevy::{
         system::{Commands, Resource},
     },
     hierarchy::DespawnRecursiveExt,
-    log::info,
+    log::{info, warn},
 };
 
 use rpg_account::account::AccountId;
+use rpg_network_protocol::token::TokenIdentity;
 
 use bevy_renet::renet::ClientId;
 
 use std::collections::HashMap;
 
+/// Clients are disconnected once they commit this many protocol violations
+const MAX_VIOLATIONS: u32 = 10;
+/// One violation is forgiven every this many seconds, only a steady stream leads to a disconnect
+const VIOLATION_DECAY_SECS: f64 = 30.;
+
+/// Something a well behaved client never sends
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Violation {
+    /// The message could not be decoded
+    Malformed,
+    /// A message before the handshake or a second hello
+    Handshake,
+    /// The message requires a login the client does not have
+    Unauthenticated,
+    /// The message refers to something that does not exist or does not belong to the client
+    InvalidTarget,
+    /// The message contains a value no client can produce, like a non-finite position
+    InvalidValue,
+}
+
 #[derive(Resource, Default)]
 pub(crate) struct NetworkContext {
     pub(crate) clients: HashMap<ClientId, Client>,
+    /// Disconnected on the next update so that a final message is sent first
+    pub(crate) pending_disconnects: Vec<ClientId>,
+    pub(crate) rate_limits: RateLimitSettings,
+    /// Simulation ticks per second, movement limits follow it
+    pub(crate) tick_rate: f64,
+    /// Clients must connect with a token from the issuer, false in unsecure mode
+    pub(crate) require_tokens: bool,
 }
 
 impl NetworkContext {
+    pub(crate) fn new(
+        rate_limits: RateLimitSettings,
+        tick_rate: f64,
+        require_tokens: bool,
+    ) -> Self {
+        Self {
+            rate_limits,
+            tick_rate,
+            require_tokens,
+            ..Default::default()
+        }
+    }
+
     pub(crate) fn get_client_from_id(&self, id: ClientId) -> Option<&Client> {
         self.clients.get(&id)
     }
@@ -31,8 +73,9 @@ impl NetworkContext {
             .filter(|c| c.is_authenticated_player())
     }
 
+    /// Find the player logged into an account, admin accounts are not included
     pub(crate) fn get_client_from_account_id(&self, id: AccountId) -> Option<&Client> {
-        self.clients.values().find(|a| {
+        self.clients.values().filter(|a| a.is_player()).find(|a| {
             if let Some(a_id) = a.account_id {
                 a_id == id
             } else {
@@ -41,25 +84,16 @@ impl NetworkContext {
         })
     }
 
-    // TODO rework this to be move flexible, for now this is fine
+    /// The clients of the players logged into `account_ids`, accounts without one are skipped
     pub(crate) fn get_client_ids_for_account_ids(
         &self,
-        account_ids: &Vec<AccountId>,
+        account_ids: &[AccountId],
     ) -> Vec<ClientId> {
-        let client_ids: Vec<_> = account_ids
+        account_ids
             .iter()
-            .map(|a| {
-                self.clients
-                    .values()
-                    .find(|v| v.is_authenticated() && v.account_id.unwrap() == *a)
-                    .unwrap()
-                    .client_id
-            })
-            .collect();
-
-        assert_eq!(client_ids.len(), account_ids.len());
-
-        client_ids
+            .filter_map(|a| self.get_client_from_account_id(*a))
+            .map(|c| c.client_id)
+            .collect()
     }
 
     pub(crate) fn is_client_authenticated(&self, id: ClientId) -> bool {
@@ -70,10 +104,52 @@ impl NetworkContext {
         }
     }
 
-    pub(crate) fn add_client(&mut self, id: ClientId) {
-        assert!(!self.clients.contains_key(&id));
+    /// Count a protocol violation against a client, disconnecting it after `MAX_VIOLATIONS`
+    pub(crate) fn record_violation(&mut self, id: ClientId, violation: Violation) {
+        let Some(client) = self.clients.get_mut(&id) else {
+            return;
+        };
+
+        client.violations += 1;
+        info!(
+            "client {id} protocol violation {violation:?} ({}/{MAX_VIOLATIONS})",
+            client.violations
+        );
+
+        if client.violations >= MAX_VIOLATIONS {
+            info!("disconnecting client {id} after too many protocol violations");
+            self.disconnect_later(id);
+        }
+    }
+
+    /// Forgive violations that are old enough, see `VIOLATION_DECAY_SECS`
+    pub(crate) fn forgive_violations(&mut self, now: f64) {
+        for client in self.clients.values_mut() {
+            // The decay starts with the first violation
+            if client.violations == 0 || now - client.last_forgiven >= VIOLATION_DECAY_SECS {
+                client.violations = client.violations.saturating_sub(1);
+                client.last_forgiven = now;
+            }
+        }
+    }
+
+    pub(crate) fn is_disconnecting(&self, id: ClientId) -> bool {
+        self.pending_disconnects.contains(&id)
+    }
+
+    pub(crate) fn disconnect_later(&mut self, id: ClientId) {
+        if !self.pending_disconnects.contains(&id) {
+            self.pending_disconnects.push(id);
+        }
+    }
+
+    pub(crate) fn add_client(&mut self, id: ClientId, identity: Option<TokenIdentity>) {
+        if self.clients.contains_key(&id) {
+            warn!("client {id} connected twice, keeping the first connection");
+            return;
+        }
 
-        self.clients.insert(id, Client::new(id));
+        self.clients.insert(id, Client::new(id, identity));
     }
 
     pub(crate) fn remove_client(&mut self, commands: &mut Commands, id: ClientId) {
@@ -1,14 +1,24 @@
-use super::server::{ClientMessageEvent, NetworkParamsRO, NetworkParamsRW};
+use super::{
+    context::Violation,
+    lobby::send_lobby_update,
+    server::{ClientMessageEvent, NetworkParamsRO, NetworkParamsRW},
+};
 use crate::{
-    account::AccountInstance,
+    account::{self, AccountInstance},
     assets::MetadataResources,
+    config::ServerSettings,
     game::{
+        input::{MoveInput, PlayerInputs},
+        instance::{GameInstance, GameManager},
+        interest::InterestMap,
         item::GroundItem,
-        plugin::{AabbResources, GameState},
+        plugin::AabbResources,
         skill::SkillOwner,
+        snapshot::{ServerTick, SnapshotHistory},
     },
-    state::AppState,
-    world::RpgWorld,
+    lobby::LobbyManager,
+    store::AccountStorage,
+    world::InZone,
 };
 
 use bevy::{
@@ -16,19 +26,19 @@ use bevy::{
         entity::Entity,
         event::EventReader,
         query::{With, Without},
-        schedule::NextState,
         system::{Commands, Query, Res, ResMut},
     },
     hierarchy::DespawnRecursiveExt,
-    log::{error, info},
+    log::{error, info, warn},
     math::Vec3,
     transform::components::Transform,
 };
 
-use rpg_core::{game_mode::GameMode, item::ItemDrops, storage::*};
+use rpg_core::{game_mode::GameMode, item::ItemDrops, skill::SkillId, storage::*};
+use rpg_lobby::lobby::LobbyPlayer;
 use rpg_network_protocol::protocol::*;
 use rpg_util::{
-    actions::{Action, ActionData, ActionKind, AttackData, State, UnitActions},
+    actions::{Action, ActionData, ActionKind, AttackData, UnitActions},
     item::{GroundItemDrops, UnitStorage},
     skill::{get_skill_origin, SkillSlots, SkillUse, Skills},
     unit::{Corpse, Hero, HeroBundle, Unit, UnitBundle, Waypoints},
@@ -46,7 +56,9 @@ pub(crate) fn receive_player_join(
         };
 
         let client_id = event.client_id;
-        let client = net_params.context.clients.get(&client_id).unwrap();
+        let Some(client) = net_params.context.clients.get(&client_id) else {
+            continue;
+        };
         if !client.is_authenticated_player() {
             continue;
         };
@@ -55,13 +67,18 @@ pub(crate) fn receive_player_join(
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 pub(crate) fn receive_player_leave(
     mut commands: Commands,
-    mut state: ResMut<NextState<AppState>>,
     mut leave_reader: EventReader<ClientMessageEvent>,
-    mut game_state: ResMut<GameState>,
+    mut game_manager: ResMut<GameManager>,
+    mut lobby_manager: ResMut<LobbyManager>,
+    mut store: ResMut<AccountStorage>,
     mut net_params: NetworkParamsRW,
-    player_q: Query<&Unit>,
+    mut interest: ResMut<InterestMap>,
+    mut history: ResMut<SnapshotHistory>,
+    mut account_q: Query<&mut AccountInstance>,
+    hero_q: Query<(&Unit, &Skills, &SkillSlots, &UnitStorage, &Waypoints)>,
     skill_q: Query<(Entity, &SkillOwner), With<SkillUse>>,
 ) {
     for event in leave_reader.read() {
@@ -70,39 +87,75 @@ pub(crate) fn receive_player_leave(
         };
 
         let client_id = event.client_id;
-        let client = net_params.context.clients.get(&client_id).unwrap();
+        let Some(client) = net_params.context.clients.get(&client_id) else {
+            continue;
+        };
         if !client.is_authenticated_player() {
             continue;
         };
+        let entity = client.entity;
 
-        game_state.players.retain(|p| p.client_id != client_id);
+        let Some(game_id) = game_manager.remove_player(client_id) else {
+            info!("client attempted to leave a game while not in a game");
+            continue;
+        };
 
-        let player = player_q.get(client.entity).unwrap();
+        let Ok(mut account) = account_q.get_mut(entity) else {
+            continue;
+        };
 
-        let message =
-            bincode::serialize(&ServerMessage::SCPlayerLeave(SCPlayerLeave(player.uid))).unwrap();
-        net_params
-            .server
-            .broadcast_message_except(client_id, ServerChannel::Message, message);
+        // players of a game started by a lobby are returned to it, rejoining it if they were
+        // removed while in the game
+        let lobby = game_manager
+            .get_game(game_id)
+            .and_then(|g| g.lobby_id)
+            .and_then(|id| lobby_manager.get_lobby_mut(id));
+        if let Some(lobby) = lobby {
+            let account_id = account.info.id;
+            if lobby.has_player(account_id)
+                || lobby.add_player(LobbyPlayer::new(account_id, account.info.name.clone()))
+            {
+                send_lobby_update(&mut net_params, lobby);
+            }
+        }
+
+        // the hero is only spawned once the client has finished loading
+        let Ok((unit, skills, skill_slots, storage, waypoints)) = hero_q.get(entity) else {
+            continue;
+        };
+
+        // persist the progress made during the session before the hero is removed
+        account::save_account_instance(
+            &mut store,
+            &mut account,
+            Some((unit, skills, skill_slots, storage, waypoints)),
+        );
+
+        // the hero is despawned on the clients that can see it once it is gone, see `interest`
+        let message = ServerMessage::SCPlayerLeave(SCPlayerLeave(unit.uid));
+        if let Some(game) = game_manager.get_game(game_id) {
+            game.broadcast_relevant(&mut net_params.outbound, &interest, unit.uid, &message);
+        }
+        interest.remove_client(client_id);
+        history.remove_client(client_id);
 
         // despawn any active skills that the player has cast
-        for (entity, owner) in &skill_q {
-            if owner.entity == client.entity {
-                commands.entity(entity).despawn_recursive();
+        for (skill_entity, owner) in &skill_q {
+            if owner.entity == entity {
+                commands.entity(skill_entity).despawn_recursive();
             }
         }
 
         // remove game play components from the client's entity
-        commands
-            .entity(client.entity)
-            .remove::<(HeroBundle, Transform, AabbComponent)>();
-
-        if game_state.players.is_empty() {
-            info!("no players remain, ending game");
-            state.set(AppState::CleanupSimulation);
-        } else {
-            info!("player left");
-        }
+        commands.entity(entity).remove::<(
+            HeroBundle,
+            UnitStorage,
+            Transform,
+            AabbComponent,
+            GameInstance,
+        )>();
+
+        info!("player left game {game_id:?}");
     }
 }
 
@@ -112,10 +165,9 @@ pub(crate) fn receive_player_loaded(
     mut ready_reader: EventReader<ClientMessageEvent>,
     mut net_params: NetworkParamsRW,
     aabbs: Res<AabbResources>,
-    game_state: Res<GameState>,
-    rpg_world: Res<RpgWorld>,
+    game_manager: Res<GameManager>,
     account_q: Query<&AccountInstance, Without<Unit>>,
-    hero_q: Query<&Transform, With<Unit>>,
+    hero_q: Query<(&Transform, &GameInstance), With<Unit>>,
 ) {
     for event in ready_reader.read() {
         let ClientMessage::CSClientReady(_) = &event.message else {
@@ -123,33 +175,57 @@ pub(crate) fn receive_player_loaded(
         };
 
         let client_id = event.client_id;
-        let client = net_params.context.clients.get(&client_id).unwrap();
+        let Some(client) = net_params.context.clients.get(&client_id) else {
+            continue;
+        };
         if !client.is_authenticated_player() {
             continue;
         };
 
-        let account = account_q.get(client.entity).unwrap();
+        let Ok(account) = account_q.get(client.entity) else {
+            info!("client is already spawned");
+            continue;
+        };
+
+        let Some(game) = game_manager.get_game_from_client_id(client_id) else {
+            info!("client is not in a game");
+            continue;
+        };
 
-        let id_info = game_state
-            .get_id_info_from_account_id(account.0.info.id)
-            .unwrap();
+        let Some(id_info) = game.get_id_info_from_account_id(account.0.info.id) else {
+            warn!("client {client_id} is not a player of its game");
+            continue;
+        };
 
-        let character = account
-            .get_character_from_uid(id_info.character_id)
-            .unwrap();
+        let Some(character) = account.get_character_from_uid(id_info.character_id) else {
+            warn!("client {client_id} joined with a missing character {id_info:?}");
+            continue;
+        };
 
         info!("spawning player: {:?} {id_info:?}", account.0.info);
 
         let unit = character.character.unit.clone();
         let aabb = aabbs.aabbs["hero"];
 
-        let zone_info = &rpg_world.zones[&ZoneId(0)].zone.as_ref().unwrap().info;
-        let mut spawn_position = if let ZoneInfo::OverworldTown(info) = zone_info {
-            info.spawn_position
-        } else if let ZoneInfo::UnderworldTown(info) = zone_info {
-            info.spawn_position
-        } else {
-            panic!("this should never happen {zone_info:?}");
+        let Some(zone) = game.world.zones.get(&ZoneId(0)) else {
+            info!("client is ready before the game's world is loaded");
+            continue;
+        };
+
+        let Some(zone) = &zone.zone else {
+            info!("client is ready before the game's town is loaded");
+            continue;
+        };
+        let mut spawn_position = match &zone.info {
+            ZoneInfo::OverworldTown(info) => info.spawn_position,
+            ZoneInfo::UnderworldTown(info) => info.spawn_position,
+            zone_info => {
+                warn!(
+                    "the first zone of game {:?} is not a town {zone_info:?}",
+                    game.id
+                );
+                continue;
+            }
         };
 
         let mut position_is_valid = true;
@@ -165,7 +241,11 @@ pub(crate) fn receive_player_loaded(
                 spawn_position.y += 2;
             }
 
-            for hero_transform in &hero_q {
+            for (hero_transform, hero_game) in &hero_q {
+                if hero_game.0 != game.id {
+                    continue;
+                }
+
                 if hero_transform.translation.distance(Vec3::new(
                     spawn_position.x as f32,
                     0.0,
@@ -186,33 +266,19 @@ pub(crate) fn receive_player_loaded(
 
         if !position_is_valid {
             error!("unable to find a valid spawn position");
-            return;
+            continue;
         }
 
-        let message = bincode::serialize(&ServerMessage::SCPlayerSpawn(SCPlayerSpawn {
-            position: Vec3::new(spawn_position.x as f32, 0.0, spawn_position.y as f32),
-        }))
-        .unwrap();
-        net_params
-            .server
-            .send_message(client_id, ServerChannel::Message, message);
-
-        let message = bincode::serialize(&ServerMessage::SCSpawnHero(SCSpawnHero {
-            uid: unit.uid,
+        let message = ServerMessage::SCPlayerSpawn(SCPlayerSpawn {
             position: Vec3::new(spawn_position.x as f32, 0.0, spawn_position.y as f32),
-            name: unit.name.clone(),
-            class: unit.class,
-            level: unit.level,
-            deaths: None,
-            skills: character.character.skills.clone(),
-            skill_slots: character.character.skill_slots.clone(),
-        }))
-        .unwrap();
-        net_params
-            .server
-            .broadcast_message_except(client_id, ServerChannel::Message, message);
+        });
+        net_params.outbound.send(client_id, &message);
+
+        // other players are told about the hero once it is in range, see `interest`
 
         commands.entity(client.entity).insert((
+            GameInstance(game.id),
+            InZone(ZoneId(0)),
             AabbComponent(aabb),
             Transform::from_translation(Vec3::new(
                 spawn_position.x as f32,
@@ -228,6 +294,8 @@ pub(crate) fn receive_player_loaded(
                 hero: Hero,
                 waypoints: Waypoints(character.character.waypoints.clone()),
             },
+            UnitStorage(character.character.storage.clone()),
+            PlayerInputs::default(),
         ));
         // TODO ensure the player is spawned in a town
     }
@@ -235,9 +303,10 @@ pub(crate) fn receive_player_loaded(
 
 pub(crate) fn receive_player_revive(
     mut commands: Commands,
-    server_state: Res<GameState>,
+    game_manager: Res<GameManager>,
     mut join_reader: EventReader<ClientMessageEvent>,
     mut net_params: NetworkParamsRW,
+    interest: Res<InterestMap>,
     mut player_q: Query<(&mut Unit, &mut Transform), (With<Hero>, With<Corpse>)>,
 ) {
     for event in join_reader.read() {
@@ -246,12 +315,18 @@ pub(crate) fn receive_player_revive(
         };
 
         let client_id = event.client_id;
-        let client = net_params.context.clients.get(&client_id).unwrap();
+        let Some(client) = net_params.context.clients.get(&client_id) else {
+            continue;
+        };
         if !client.is_authenticated_player() {
             continue;
         };
 
-        if server_state.options.mode == GameMode::Hardcore {
+        let Some(game) = game_manager.get_game_from_client_id(client_id) else {
+            continue;
+        };
+
+        if game.options.mode == GameMode::Hardcore {
             // TODO disconnect and logout or such
             info!("hardcore player attempted to revive!");
             continue;
@@ -260,7 +335,9 @@ pub(crate) fn receive_player_revive(
         // TODO implement xp_loss
         // The client should always know it's correct max stats
         // there shouldn't be a need to send it over here...
-        let (mut hero, mut transform) = player_q.get_mut(client.entity).unwrap();
+        let Ok((mut hero, mut transform)) = player_q.get_mut(client.entity) else {
+            continue;
+        };
         hero.stats.vitals.get_mut_stat("Hp").unwrap().value =
             hero.stats.vitals.get_stat("HpMax").unwrap().value;
         hero.stats.vitals.get_mut_stat("Ep").unwrap().value =
@@ -275,108 +352,137 @@ pub(crate) fn receive_player_revive(
         *hero_info.deaths.as_mut().unwrap() = deaths + 1;
         commands.entity(client.entity).remove::<Corpse>();
 
-        let message = bincode::serialize(&ServerMessage::SCPlayerRevive(SCPlayerRevive {
+        let message = ServerMessage::SCPlayerRevive(SCPlayerRevive {
             position: Vec3::ZERO,
             deaths: hero_info.deaths.unwrap(),
             hp: *hero.stats.vitals.stats["HpMax"].value.u32(),
             xp_total: 0,
             xp_loss: 0,
-        }))
-        .unwrap();
-
-        net_params
-            .server
-            .send_message(client_id, ServerChannel::Message, message);
-
-        let message = bincode::serialize(&ServerMessage::SCHeroRevive(SCHeroRevive(
-            transform.translation,
-        )))
-        .unwrap();
-        net_params
-            .server
-            .broadcast_message_except(client_id, ServerChannel::Message, message);
+        });
+
+        net_params.outbound.send(client_id, &message);
+
+        let message = ServerMessage::SCHeroRevive(SCHeroRevive(transform.translation));
+        game.broadcast_relevant_except(
+            &mut net_params.outbound,
+            &interest,
+            hero.uid,
+            client_id,
+            &message,
+        );
         info!("player revive");
     }
 }
 
-/// Move player
-// TODO
-// - FIXME this needs to be sent to all characters in range of each player
-//   that moves for now a naive approach will be taken
-// - FIXME add action request, move message send to action handler
+/// Queue a movement input, see `input`
 pub(crate) fn receive_movement(
     mut movement_reader: EventReader<ClientMessageEvent>,
     net_params: NetworkParamsRO,
-    mut player_q: Query<&mut UnitActions, With<Hero>>,
+    mut player_q: Query<&mut PlayerInputs, With<Hero>>,
 ) {
     for event in movement_reader.read() {
-        let ClientMessage::CSMovePlayer(_) = &event.message else {
-            continue;
+        let (sequence, input) = match &event.message {
+            ClientMessage::CSMovePlayer(msg) => (msg.sequence, MoveInput::Move(msg.direction)),
+            ClientMessage::CSMovePlayerEnd(msg) => (msg.sequence, MoveInput::End),
+            _ => continue,
         };
 
         let client_id = event.client_id;
-        let client = net_params.context.clients.get(&client_id).unwrap();
+        let Some(client) = net_params.context.clients.get(&client_id) else {
+            continue;
+        };
         if !client.is_authenticated_player() {
             continue;
         };
 
-        let mut actions = player_q.get_mut(client.entity).unwrap();
-        actions.request(Action::new(ActionData::Move(Vec3::NEG_Z), None, true));
-        //info!("player move request");
+        let Ok(mut inputs) = player_q.get_mut(client.entity) else {
+            continue;
+        };
+        inputs.push(sequence, input);
     }
 }
 
-pub(crate) fn receive_movement_end(
-    mut movement_reader: EventReader<ClientMessageEvent>,
+/// Rotate player
+pub(crate) fn receive_rotation(
+    mut rotation_reader: EventReader<ClientMessageEvent>,
     net_params: NetworkParamsRO,
     mut player_q: Query<&mut UnitActions, With<Hero>>,
 ) {
-    for event in movement_reader.read() {
-        let ClientMessage::CSMovePlayerEnd(_) = &event.message else {
+    for event in rotation_reader.read() {
+        let ClientMessage::CSRotPlayer(msg) = &event.message else {
             continue;
         };
 
         let client_id = event.client_id;
-        let client = net_params.context.clients.get(&client_id).unwrap();
+        let Some(client) = net_params.context.clients.get(&client_id) else {
+            continue;
+        };
         if !client.is_authenticated_player() {
             continue;
         };
 
-        let mut actions = player_q.get_mut(client.entity).unwrap();
-        if let Some(action) = actions.get_mut(ActionKind::Move) {
-            action.state = State::Completed;
-            //info!("end player move request");
-        }
+        let Ok(mut actions) = player_q.get_mut(client.entity) else {
+            continue;
+        };
+        actions.request(Action::new(ActionData::LookDir(msg.0), None, true));
     }
 }
 
-/// Rotate player
-pub(crate) fn receive_rotation(
-    mut rotation_reader: EventReader<ClientMessageEvent>,
+pub(crate) fn receive_snapshot_ack(
+    mut ack_reader: EventReader<ClientMessageEvent>,
     net_params: NetworkParamsRO,
-    mut player_q: Query<&mut UnitActions, With<Hero>>,
+    mut history: ResMut<SnapshotHistory>,
 ) {
-    for event in rotation_reader.read() {
-        let ClientMessage::CSRotPlayer(msg) = &event.message else {
+    for event in ack_reader.read() {
+        let ClientMessage::CSSnapshotAck(msg) = &event.message else {
             continue;
         };
 
         let client_id = event.client_id;
-        let client = net_params.context.clients.get(&client_id).unwrap();
+        let Some(client) = net_params.context.clients.get(&client_id) else {
+            continue;
+        };
         if !client.is_authenticated_player() {
             continue;
         };
 
-        let mut actions = player_q.get_mut(client.entity).unwrap();
-        actions.request(Action::new(ActionData::LookDir(msg.0), None, true));
+        history.acknowledge(client_id, msg.0);
+    }
+}
+
+pub(crate) fn receive_ping(
+    mut ping_reader: EventReader<ClientMessageEvent>,
+    mut net_params: NetworkParamsRW,
+    settings: Res<ServerSettings>,
+    tick: Res<ServerTick>,
+) {
+    for event in ping_reader.read() {
+        let ClientMessage::CSPing(msg) = &event.message else {
+            continue;
+        };
+
+        let client_id = event.client_id;
+        let Some(client) = net_params.context.clients.get(&client_id) else {
+            continue;
+        };
+        if !client.is_authenticated_player() {
+            continue;
+        };
+
+        let message = ServerMessage::SCPong(SCPong {
+            sequence: msg.0,
+            tick: tick.0,
+            tick_rate: settings.simulation.tick_rate,
+        });
+        net_params.outbound.send(client_id, &message);
     }
 }
 
 pub(crate) fn receive_skill_use_direct(
     mut skill_use_reader: EventReader<ClientMessageEvent>,
-    net_params: NetworkParamsRO,
+    mut net_params: NetworkParamsRW,
     metadata: Res<MetadataResources>,
-    mut player_q: Query<(&Transform, &mut UnitActions), With<Hero>>,
+    mut player_q: Query<(&Transform, &Skills, &SkillSlots, &mut UnitActions), With<Hero>>,
 ) {
     for event in skill_use_reader.read() {
         let ClientMessage::CSSkillUseDirect(msg) = &event.message else {
@@ -384,12 +490,24 @@ pub(crate) fn receive_skill_use_direct(
         };
 
         let client_id = event.client_id;
-        let client = net_params.context.clients.get(&client_id).unwrap();
+        let Some(client) = net_params.context.clients.get(&client_id) else {
+            continue;
+        };
         if !client.is_authenticated_player() {
             continue;
         };
 
-        let (transform, mut actions) = player_q.get_mut(client.entity).unwrap();
+        let Ok((transform, skills, skill_slots, mut actions)) = player_q.get_mut(client.entity)
+        else {
+            continue;
+        };
+
+        if !is_usable_skill(&metadata, skills, skill_slots, msg.0) {
+            net_params
+                .context
+                .record_violation(client_id, Violation::InvalidTarget);
+            continue;
+        }
         // info!("skill use direct: {msg:?}");
 
         let skill_target = get_skill_origin(
@@ -415,9 +533,9 @@ pub(crate) fn receive_skill_use_direct(
 
 pub(crate) fn receive_skill_use_targeted(
     mut skill_use_reader: EventReader<ClientMessageEvent>,
-    net_params: NetworkParamsRO,
+    mut net_params: NetworkParamsRW,
     metadata: Res<MetadataResources>,
-    mut player_q: Query<(&Transform, &mut UnitActions), With<Hero>>,
+    mut player_q: Query<(&Transform, &Skills, &SkillSlots, &mut UnitActions), With<Hero>>,
 ) {
     for event in skill_use_reader.read() {
         let ClientMessage::CSSkillUseTargeted(msg) = &event.message else {
@@ -425,12 +543,24 @@ pub(crate) fn receive_skill_use_targeted(
         };
 
         let client_id = event.client_id;
-        let client = net_params.context.clients.get(&client_id).unwrap();
+        let Some(client) = net_params.context.clients.get(&client_id) else {
+            continue;
+        };
         if !client.is_authenticated_player() {
             continue;
         };
 
-        let (transform, mut actions) = player_q.get_mut(client.entity).unwrap();
+        let Ok((transform, skills, skill_slots, mut actions)) = player_q.get_mut(client.entity)
+        else {
+            continue;
+        };
+
+        if !is_usable_skill(&metadata, skills, skill_slots, msg.skill_id) {
+            net_params
+                .context
+                .record_violation(client_id, Violation::InvalidTarget);
+            continue;
+        }
         // debug!("skill use targeted: {msg:?}");
 
         let skill_target = get_skill_origin(&metadata.rpg, &transform, msg.target, msg.skill_id);
@@ -451,7 +581,7 @@ pub(crate) fn receive_skill_use_targeted(
 
 pub(crate) fn receive_item_drop(
     mut drop_reader: EventReader<ClientMessageEvent>,
-    net_params: NetworkParamsRO,
+    mut net_params: NetworkParamsRW,
     mut ground_items: ResMut<GroundItemDrops>,
     mut hero_q: Query<(&Unit, &mut UnitStorage), With<Hero>>,
 ) {
@@ -461,12 +591,16 @@ pub(crate) fn receive_item_drop(
         };
 
         let client_id = event.client_id;
-        let client = net_params.context.clients.get(&client_id).unwrap();
+        let Some(client) = net_params.context.clients.get(&client_id) else {
+            continue;
+        };
         if !client.is_authenticated_player() {
             continue;
         };
 
-        let (hero, mut storage) = hero_q.get_mut(client.entity).unwrap();
+        let Ok((hero, mut storage)) = hero_q.get_mut(client.entity) else {
+            continue;
+        };
         if let Some(slot) = storage.slot_from_uid_mut(STORAGE_ID_CURSOR, msg.0) {
             // the slot is knownn to contain an item because the result is not `None`
             let item = slot.item.take().unwrap();
@@ -475,16 +609,39 @@ pub(crate) fn receive_item_drop(
                 source: hero.uid,
                 items: vec![item],
             });
+        } else {
+            // Not a violation, the item may have left the cursor before the drop arrived
+            info!(
+                "client {client_id} dropped an item it is not holding: {:?}",
+                msg.0
+            );
         }
     }
 }
 
+/// A skill can only be used once it is known, learned by the hero and assigned to a slot
+fn is_usable_skill(
+    metadata: &MetadataResources,
+    skills: &Skills,
+    skill_slots: &SkillSlots,
+    skill_id: SkillId,
+) -> bool {
+    metadata.rpg.skill.skills.contains_key(&skill_id)
+        && skills.0.iter().any(|skill| skill.id == skill_id)
+        && skill_slots
+            .slots
+            .iter()
+            .any(|slot| slot.skill_id == Some(skill_id))
+}
+
 pub(crate) fn receive_item_pickup(
     mut commands: Commands,
     mut pickup_reader: EventReader<ClientMessageEvent>,
     mut net_params: NetworkParamsRW,
-    mut item_q: Query<(Entity, &mut GroundItem, &Transform)>,
-    mut hero_q: Query<(&Transform, &mut UnitStorage), With<Hero>>,
+    mut interest: ResMut<InterestMap>,
+    game_manager: Res<GameManager>,
+    mut item_q: Query<(Entity, &mut GroundItem, &Transform, &GameInstance)>,
+    mut hero_q: Query<(&Transform, &GameInstance, &mut UnitStorage), With<Hero>>,
 ) {
     for event in pickup_reader.read() {
         let ClientMessage::CSItemPickup(msg) = &event.message else {
@@ -492,31 +649,39 @@ pub(crate) fn receive_item_pickup(
         };
 
         let client_id = event.client_id;
-        let client = net_params.context.clients.get(&client_id).unwrap();
+        let Some(client) = net_params.context.clients.get(&client_id) else {
+            continue;
+        };
         if !client.is_authenticated_player() {
             continue;
         };
 
-        let (u_transform, mut u_storage) = hero_q.get_mut(client.entity).unwrap();
+        let Ok((u_transform, u_game, mut u_storage)) = hero_q.get_mut(client.entity) else {
+            continue;
+        };
+
+        let Some(game) = game_manager.get_game(u_game.0) else {
+            continue;
+        };
 
-        for (i_entity, mut i_item, i_transform) in &mut item_q {
-            if i_item.0 != msg.0 {
+        for (i_entity, mut i_item, i_transform, i_game) in &mut item_q {
+            if i_item.0.uid != msg.0 || i_game != u_game {
                 continue;
             }
 
-            if i_transform.translation.distance(u_transform.translation) < 0.5 {
+            // items float `GROUND_ITEM_HEIGHT` above a hero's feet, measured in full every item
+            // would be out of reach, only the distance along the ground matters
+            let offset = i_transform.translation - u_transform.translation;
+            if Vec3::new(offset.x, 0., offset.z).length() < 0.5 {
                 let Some(slot) = u_storage.0.get_empty_slot_mut() else {
                     break;
                 };
 
                 // FIXME slot.item = i_item.0;
 
-                let message =
-                    bincode::serialize(&ServerMessage::SCDespawnItem(SCDespawnItem(msg.0)))
-                        .unwrap();
-                net_params
-                    .server
-                    .broadcast_message(ServerChannel::Message, message);
+                let message = ServerMessage::SCDespawnItem(SCDespawnItem(msg.0));
+                game.broadcast_relevant(&mut net_params.outbound, &interest, msg.0, &message);
+                interest.forget(msg.0);
 
                 info!("ground item pickup");
                 commands.entity(i_entity).despawn_recursive();
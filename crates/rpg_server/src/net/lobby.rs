@@ -1,18 +1,49 @@
-use super::server::{ClientMessageEvent, NetworkParamsRW};
-use crate::{account::AccountInstance, lobby::LobbyManager, server_state::ServerMetadataResource};
+use super::{
+    send::EncodedMessage,
+    server::{ClientMessageEvent, NetworkParamsRW},
+};
+use crate::{
+    account::AccountInstance,
+    config::ServerSettings,
+    game::instance::{GameManager, GameOptions, PlayerIdInfo},
+    lobby::LobbyManager,
+    moderation::unix_time,
+    server_state::ServerMetadataResource,
+    world::LoadZone,
+};
 
 use rpg_chat::chat::MessageId;
-use rpg_lobby::lobby::{Lobby, LobbyId, LobbyMessage, LobbyPlayer};
+use rpg_lobby::lobby::{Lobby, LobbyMessage, LobbyPlayer};
 use rpg_network_protocol::protocol::*;
+use rpg_world::zone::ZoneId;
 
 use bevy::{
     ecs::{
-        event::EventReader,
+        event::{EventReader, EventWriter},
         system::{Query, Res, ResMut},
     },
     log::info,
 };
 
+use bevy_renet::renet::ClientId;
+
+/// Send the current state of a lobby to all of it's connected players
+pub(crate) fn send_lobby_update(net_params: &mut NetworkParamsRW, lobby: &Lobby) {
+    let message = EncodedMessage::new(&ServerMessage::SCLobbyUpdate(SCLobbyUpdate(lobby.clone())));
+
+    for player in &lobby.players {
+        let Some(client) = net_params
+            .context
+            .get_client_from_account_id(player.account_id)
+        else {
+            continue;
+        };
+
+        let client_id = client.client_id;
+        net_params.outbound.push(client_id, &message);
+    }
+}
+
 pub(crate) fn receive_lobby_create(
     mut lobby_manager: ResMut<LobbyManager>,
     mut create_reader: EventReader<ClientMessageEvent>,
@@ -25,42 +56,31 @@ pub(crate) fn receive_lobby_create(
         };
 
         let client_id = event.client_id;
-        let client = net_params.context.clients.get(&client_id).unwrap();
-        if !client.is_authenticated() {
+        let Some(client) = net_params.context.clients.get(&client_id) else {
+            continue;
+        };
+        if !client.is_authenticated_player() {
             info!("unauthenticated client attempted to create a lobby: {client:?}");
             continue;
         }
         let account_id = client.account_id.unwrap();
-        let account = account_q.get(client.entity).unwrap();
+        let Ok(account) = account_q.get(client.entity) else {
+            continue;
+        };
 
-        if let Some(lobby_id) = lobby_manager.add_lobby(msg.name.clone(), msg.game_mode) {
+        if let Some(lobby_id) = lobby_manager.add_lobby(msg.name.clone(), msg.game_mode, account_id)
+        {
             info!("lobby created");
 
             lobby_manager.add_player(lobby_id, account_id, account.info.name.clone());
 
-            let message = bincode::serialize(&ServerMessage::SCLobbyCreateSuccess(
-                SCLobbyCreateSuccess(Lobby {
-                    id: lobby_id,
-                    name: msg.name.clone(),
-                    game_mode: msg.game_mode,
-                    messages: vec![],
-                    players: vec![LobbyPlayer {
-                        account_id,
-                        account_name: account.info.name.clone(),
-                    }],
-                }),
-            ))
-            .unwrap();
-
-            net_params
-                .server
-                .send_message(client_id, ServerChannel::Message, message);
+            let lobby = lobby_manager.get_lobby(lobby_id).unwrap();
+            let message = ServerMessage::SCLobbyCreateSuccess(SCLobbyCreateSuccess(lobby.clone()));
+
+            net_params.outbound.send(client_id, &message);
         } else {
-            let message =
-                bincode::serialize(&ServerMessage::SCLobbyCreateError(SCLobbyCreateError)).unwrap();
-            net_params
-                .server
-                .send_message(client_id, ServerChannel::Message, message);
+            let message = ServerMessage::SCLobbyCreateError(SCLobbyCreateError);
+            net_params.outbound.send(client_id, &message);
         }
     }
 }
@@ -77,28 +97,25 @@ pub(crate) fn receive_lobby_join(
         };
 
         let client_id = event.client_id;
-        let client = net_params.context.clients.get(&client_id).unwrap();
-        if !client.is_authenticated() {
+        let Some(client) = net_params.context.clients.get(&client_id) else {
+            continue;
+        };
+        if !client.is_authenticated_player() {
             info!("unauthenticated client attempted to join a lobby: {client:?}");
             continue;
         }
         let account_id = client.account_id.unwrap();
-        let account = account_q.get(client.entity).unwrap();
+        let Ok(account) = account_q.get(client.entity) else {
+            continue;
+        };
 
         if let Some(lobby) = lobby_manager.get_lobby_mut(msg.0) {
             info!("client joined join");
-            if lobby.add_player(LobbyPlayer {
-                account_id,
-                account_name: account.info.name.clone(),
-            }) {
-                // TODO Handle rejections for banned accounts etc.
-                let message = bincode::serialize(&ServerMessage::SCLobbyJoinSuccess(
-                    SCLobbyJoinSuccess(lobby.clone()),
-                ))
-                .unwrap();
-                net_params
-                    .server
-                    .send_message(client_id, ServerChannel::Message, message);
+            if lobby.add_player(LobbyPlayer::new(account_id, account.info.name.clone())) {
+                let message = ServerMessage::SCLobbyJoinSuccess(SCLobbyJoinSuccess(lobby.clone()));
+                net_params.outbound.send(client_id, &message);
+
+                send_lobby_update(&mut net_params, lobby);
             }
         }
     }
@@ -110,36 +127,41 @@ pub(crate) fn receive_lobby_leave(
     mut net_params: NetworkParamsRW,
 ) {
     for event in join_reader.read() {
-        let ClientMessage::CSLobbyLeave(msg) = &event.message else {
+        let ClientMessage::CSLobbyLeave(_) = &event.message else {
             continue;
         };
 
         let client_id = event.client_id;
-        let client = net_params.context.clients.get(&client_id).unwrap();
-        if !client.is_authenticated() {
+        let Some(client) = net_params.context.clients.get(&client_id) else {
+            continue;
+        };
+        if !client.is_authenticated_player() {
             info!("unauthenticated client attempted to leave a lobby: {client:?}");
             continue;
         }
+        let Some(account_id) = client.account_id else {
+            continue;
+        };
 
-        lobby_manager.remove_player(LobbyId(0), client.account_id.unwrap());
-
-        if lobby_manager
-            .get_lobby(LobbyId(0))
-            .unwrap()
-            .players
-            .is_empty()
-        {
-            lobby_manager.remove_lobby(LobbyId(0));
+        let left = lobby_manager.remove_account(account_id);
+        if left.is_empty() {
+            info!("client attempted to leave a lobby it is not in: {client:?}");
+            let message = ServerMessage::SCLobbyLeaveError(SCLobbyLeaveError);
+            net_params.outbound.send(client_id, &message);
+            continue;
         }
 
         info!("client left lobby");
 
-        // TODO Handle rejections for banned accounts etc.
-        let message =
-            bincode::serialize(&ServerMessage::SCLobbyLeaveSuccess(SCLobbyLeaveSuccess)).unwrap();
-        net_params
-            .server
-            .send_message(client_id, ServerChannel::Message, message);
+        // Empty lobbies have already been removed
+        for lobby_id in left {
+            if let Some(lobby) = lobby_manager.get_lobby(lobby_id) {
+                send_lobby_update(&mut net_params, lobby);
+            }
+        }
+
+        let message = ServerMessage::SCLobbyLeaveSuccess(SCLobbyLeaveSuccess);
+        net_params.outbound.send(client_id, &message);
     }
 }
 
@@ -156,12 +178,24 @@ pub(crate) fn receive_lobby_message(
         };
 
         let client_id = event.client_id;
-        let client = net_params.context.clients.get(&client_id).unwrap();
-        if !client.is_authenticated() {
+        let Some(client) = net_params.context.clients.get(&client_id) else {
+            continue;
+        };
+        if !client.is_authenticated_player() {
             info!("unauthenticated client attempted to leave a lobby: {client:?}");
             continue;
         }
 
+        let Ok(account) = account_q.get(client.entity) else {
+            continue;
+        };
+        if account.moderation.is_muted(unix_time()) {
+            info!("muted client attempted to message a lobby: {client:?}");
+            let message = ServerMessage::SCLobbyMessageError(SCLobbyMessageError);
+            net_params.outbound.send(client_id, &message);
+            continue;
+        }
+
         let message_id = server_metadata.0.next_message_id;
         server_metadata.0.next_message_id.0 += 1;
 
@@ -172,8 +206,6 @@ pub(crate) fn receive_lobby_message(
             continue;
         };
 
-        let account = account_q.get(client.entity).unwrap();
-
         let lobby_message = LobbyMessage {
             id: message_id,
             sender_id: account.0.info.id,
@@ -187,22 +219,233 @@ pub(crate) fn receive_lobby_message(
             .context
             .get_client_ids_for_account_ids(&account_ids);
 
-        let message = bincode::serialize(&ServerMessage::SCLobbyMessage(SCLobbyMessage(
+        let message = EncodedMessage::new(&ServerMessage::SCLobbyMessage(SCLobbyMessage(
             LobbyMessage {
                 id: MessageId(0),
                 sender_id: client.account_id.unwrap(),
                 sender: account.0.info.name.clone(),
                 message: msg.message.clone(),
             },
-        )))
-        .unwrap();
+        )));
 
         for client_id in client_ids {
-            net_params
-                .server
-                .send_message(client_id, ServerChannel::Message, message.clone());
+            net_params.outbound.push(client_id, &message);
         }
 
         lobby.messages.push(lobby_message);
     }
 }
+
+pub(crate) fn receive_lobby_ready(
+    mut lobby_manager: ResMut<LobbyManager>,
+    mut ready_reader: EventReader<ClientMessageEvent>,
+    mut net_params: NetworkParamsRW,
+    account_q: Query<&AccountInstance>,
+) {
+    for event in ready_reader.read() {
+        let ClientMessage::CSLobbyReady(msg) = &event.message else {
+            continue;
+        };
+
+        let client_id = event.client_id;
+        let Some(client) = net_params.context.clients.get(&client_id) else {
+            continue;
+        };
+        if !client.is_authenticated_player() {
+            info!("unauthenticated client attempted to ready in a lobby: {client:?}");
+            continue;
+        }
+        let account_id = client.account_id.unwrap();
+        let Ok(account) = account_q.get(client.entity) else {
+            continue;
+        };
+
+        let Some(lobby) = lobby_manager.get_lobby_mut(msg.id) else {
+            info!("client attempted to ready in a lobby that does not exist");
+            send_lobby_ready_error(&mut net_params, client_id);
+            continue;
+        };
+
+        // the picked character has to be able to play the lobby's game mode
+        if let Some(slot) = msg.slot {
+            let Some(character) = account.get_character_from_slot(slot) else {
+                info!("no character exists in slot {slot:?}");
+                send_lobby_ready_error(&mut net_params, client_id);
+                continue;
+            };
+
+            if character.info.game_mode != lobby.game_mode {
+                info!(
+                    "a {:?} character cannot ready in a {:?} lobby",
+                    character.info.game_mode, lobby.game_mode
+                );
+                send_lobby_ready_error(&mut net_params, client_id);
+                continue;
+            }
+        }
+
+        if !lobby.set_ready(account_id, msg.slot) {
+            info!("client attempted to ready in a lobby it is not in");
+            send_lobby_ready_error(&mut net_params, client_id);
+            continue;
+        }
+
+        send_lobby_update(&mut net_params, lobby);
+    }
+}
+
+fn send_lobby_ready_error(net_params: &mut NetworkParamsRW, client_id: ClientId) {
+    let message = ServerMessage::SCLobbyReadyError(SCLobbyReadyError);
+    net_params.outbound.send(client_id, &message);
+}
+
+fn send_lobby_start_error(
+    net_params: &mut NetworkParamsRW,
+    client_id: ClientId,
+    error: LobbyStartError,
+) {
+    let message = ServerMessage::SCLobbyStartError(SCLobbyStartError(error));
+    net_params.outbound.send(client_id, &message);
+}
+
+/// Create a game for a lobby and move all of it's ready players into it
+pub(crate) fn receive_lobby_start_game(
+    settings: Res<ServerSettings>,
+    mut lobby_manager: ResMut<LobbyManager>,
+    mut game_manager: ResMut<GameManager>,
+    mut load_writer: EventWriter<LoadZone>,
+    mut start_reader: EventReader<ClientMessageEvent>,
+    mut net_params: NetworkParamsRW,
+    mut account_q: Query<&mut AccountInstance>,
+) {
+    for event in start_reader.read() {
+        let ClientMessage::CSLobbyStartGame(msg) = &event.message else {
+            continue;
+        };
+
+        let client_id = event.client_id;
+        let Some(client) = net_params.context.clients.get(&client_id) else {
+            continue;
+        };
+        if !client.is_authenticated_player() {
+            info!("unauthenticated client attempted to start a lobby game: {client:?}");
+            continue;
+        }
+        let account_id = client.account_id.unwrap();
+
+        let Some(lobby) = lobby_manager.get_lobby_mut(msg.0) else {
+            send_lobby_start_error(&mut net_params, client_id, LobbyStartError::NotFound);
+            continue;
+        };
+
+        if !lobby.is_owner(account_id) {
+            send_lobby_start_error(&mut net_params, client_id, LobbyStartError::NotOwner);
+            continue;
+        }
+
+        if !lobby.get_player(account_id).is_some_and(|p| p.is_ready()) {
+            send_lobby_start_error(&mut net_params, client_id, LobbyStartError::OwnerNotReady);
+            continue;
+        }
+
+        if game_manager.get_game_from_account_id(account_id).is_some() {
+            send_lobby_start_error(&mut net_params, client_id, LobbyStartError::InGame);
+            continue;
+        }
+
+        let game_id = game_manager.create_game(GameOptions {
+            mode: lobby.game_mode,
+            max_players: settings.simulation.max_players,
+        });
+
+        info!("lobby {:?} starting game {game_id:?}", lobby.id);
+
+        // players that are already in a game, or no longer have the character they picked, are
+        // left in the lobby
+        let mut moved = vec![];
+        for player in lobby.ready_players() {
+            let slot = player.ready_slot.unwrap();
+
+            if game_manager
+                .get_game_from_account_id(player.account_id)
+                .is_some()
+            {
+                continue;
+            }
+
+            let Some(player_client) = net_params
+                .context
+                .get_client_from_account_id(player.account_id)
+            else {
+                continue;
+            };
+            let (player_client_id, player_entity) = (player_client.client_id, player_client.entity);
+
+            let Ok(mut account) = account_q.get_mut(player_entity) else {
+                continue;
+            };
+
+            let Some(character) = account.get_character_from_slot(slot) else {
+                continue;
+            };
+
+            if character.info.game_mode != lobby.game_mode {
+                continue;
+            }
+
+            let game = game_manager.get_game_mut(game_id).unwrap();
+            if game.is_full() {
+                break;
+            }
+
+            game.players.push(PlayerIdInfo {
+                slot,
+                account_id: player.account_id,
+                character_id: character.info.uid,
+                client_id: player_client_id,
+                entity: player_entity,
+            });
+
+            account.info.selected_slot = Some(slot);
+
+            let message = if player.account_id == account_id {
+                ServerMessage::SCGameCreateSuccess(SCGameCreateSuccess {
+                    game_id,
+                    game_mode: lobby.game_mode,
+                })
+            } else {
+                ServerMessage::SCGameJoinSuccess(SCGameJoinSuccess {
+                    game_id,
+                    game_mode: lobby.game_mode,
+                })
+            };
+            net_params.outbound.send(player_client_id, &message);
+
+            moved.push(player.account_id);
+        }
+
+        if moved.is_empty() {
+            game_manager.remove_game(game_id);
+            send_lobby_start_error(
+                &mut net_params,
+                client_id,
+                LobbyStartError::NoPlayersAvailable,
+            );
+            continue;
+        }
+
+        // players have to ready again once they return to the lobby
+        for moved_id in moved {
+            lobby.set_ready(moved_id, None);
+        }
+
+        game_manager.get_game_mut(game_id).unwrap().lobby_id = Some(lobby.id);
+
+        send_lobby_update(&mut net_params, lobby);
+
+        load_writer.send(LoadZone {
+            game_id,
+            zone_id: ZoneId(0),
+        });
+    }
+}
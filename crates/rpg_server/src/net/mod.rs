@@ -1,9 +1,15 @@
 pub(crate) mod server;
 
-mod client;
-mod context;
+pub(crate) mod client;
+pub(crate) mod context;
 
 mod account;
+mod admin;
 mod chat;
 mod game;
 mod lobby;
+pub(crate) mod rate_limit;
+pub(crate) mod route;
+pub(crate) mod send;
+
+pub(crate) mod token;
@@ -0,0 +1,257 @@
+//! Per client rate limits
+//!
+//! Every limited message category has a token bucket, a message that finds its bucket empty is
+//! dropped and the client is told. Each second a client keeps exceeding a chat, lobby, skill or
+//! account limit is a strike against it, enough strikes within the strike window mute the
+//! client's account and eventually disconnect it. Movement and rotation messages are only dropped, their
+//! limits follow the simulation tick rate.
+
+use crate::config::{BucketSettings, RateLimitSettings};
+
+use rpg_network_protocol::protocol::*;
+
+/// How long a bucket must stay empty before its drops count as a strike
+const SUSTAINED_OVERAGE_SECS: f64 = 1.;
+
+/// What happens to a message that exceeded its limit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Penalty {
+    /// Dropped, the client has already been told
+    Drop,
+    /// Dropped and the client is told
+    Notify,
+    /// Dropped and the client's account is muted
+    Mute,
+    /// Dropped and the client is disconnected
+    Kick,
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) struct TokenBucket {
+    tokens: f32,
+    last_refill: f64,
+    /// When the bucket last ran out, cleared once the client leaves tokens to spare
+    limited_since: Option<f64>,
+}
+
+impl Default for TokenBucket {
+    fn default() -> Self {
+        // Starts full, the first refill caps the tokens at the configured burst
+        Self {
+            tokens: f32::MAX,
+            last_refill: 0.,
+            limited_since: None,
+        }
+    }
+}
+
+impl TokenBucket {
+    /// Take a token, returns false when the bucket is empty
+    pub(crate) fn take(&mut self, settings: &BucketSettings, now: f64) -> bool {
+        let elapsed = (now - self.last_refill).max(0.) as f32;
+        self.tokens = (self.tokens + elapsed * settings.per_second).min(settings.burst);
+        self.last_refill = now;
+
+        if self.tokens < 1. {
+            return false;
+        }
+
+        self.tokens -= 1.;
+        if self.tokens >= 1. {
+            self.limited_since = None;
+        }
+
+        true
+    }
+
+    /// Whether the bucket would be full by now, a full bucket is the same as a new one
+    pub(crate) fn is_full(&self, settings: &BucketSettings, now: f64) -> bool {
+        let elapsed = (now - self.last_refill).max(0.) as f32;
+        self.tokens + elapsed * settings.per_second >= settings.burst
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct RateLimits {
+    chat: TokenBucket,
+    lobby: TokenBucket,
+    skill: TokenBucket,
+    account: TokenBucket,
+    movement: TokenBucket,
+    rotation: TokenBucket,
+    strikes: u32,
+    last_strike: f64,
+    muted: bool,
+}
+
+impl RateLimits {
+    /// Count a message against its limit, returns `None` when it may be handled
+    pub(crate) fn check(
+        &mut self,
+        settings: &RateLimitSettings,
+        tick_rate: f64,
+        category: RateLimitCategory,
+        now: f64,
+    ) -> Option<Penalty> {
+        let movement = settings.movement(tick_rate);
+        let (bucket, bucket_settings) = match category {
+            RateLimitCategory::Chat => (&mut self.chat, &settings.chat),
+            RateLimitCategory::Lobby => (&mut self.lobby, &settings.lobby),
+            RateLimitCategory::Skill => (&mut self.skill, &settings.skill),
+            RateLimitCategory::Account => (&mut self.account, &settings.account),
+            RateLimitCategory::Movement => (&mut self.movement, &movement),
+            RateLimitCategory::Rotation => (&mut self.rotation, &movement),
+        };
+
+        if bucket.take(bucket_settings, now) {
+            return None;
+        }
+        let Some(limited_since) = bucket.limited_since else {
+            bucket.limited_since = Some(now);
+            return Some(Penalty::Notify);
+        };
+        if now - limited_since < SUSTAINED_OVERAGE_SECS {
+            return Some(Penalty::Drop);
+        }
+        bucket.limited_since = Some(now);
+
+        // Dropped movement only costs the client its own inputs
+        if matches!(
+            category,
+            RateLimitCategory::Movement | RateLimitCategory::Rotation
+        ) {
+            return Some(Penalty::Notify);
+        }
+
+        if now - self.last_strike > settings.strike_window {
+            self.strikes = 0;
+            self.muted = false;
+        }
+        self.strikes += 1;
+        self.last_strike = now;
+
+        let is_chat = matches!(category, RateLimitCategory::Chat | RateLimitCategory::Lobby);
+
+        if self.strikes >= settings.kick_strikes {
+            Some(Penalty::Kick)
+        } else if is_chat
+            && !self.muted
+            && settings.mute_strikes > 0
+            && self.strikes >= settings.mute_strikes
+        {
+            self.muted = true;
+            Some(Penalty::Mute)
+        } else {
+            Some(Penalty::Notify)
+        }
+    }
+}
+
+/// The limit a message counts against, if any
+pub(crate) fn rate_limit_category(message: &ClientMessage) -> Option<RateLimitCategory> {
+    match message {
+        ClientMessage::CSChatChannelMessage(_) => Some(RateLimitCategory::Chat),
+        ClientMessage::CSLobbyMessage(_) => Some(RateLimitCategory::Lobby),
+        ClientMessage::CSSkillUseDirect(_) | ClientMessage::CSSkillUseTargeted(_) => {
+            Some(RateLimitCategory::Skill)
+        }
+        ClientMessage::CSRotPlayer(_) => Some(RateLimitCategory::Rotation),
+        ClientMessage::CSMovePlayer(_) | ClientMessage::CSMovePlayerEnd(_) => {
+            Some(RateLimitCategory::Movement)
+        }
+        // Each of these hashes or verifies a password
+        ClientMessage::CSCreateAccount(_)
+        | ClientMessage::CSLoadAccount(_)
+        | ClientMessage::CSLoadAdminAccount(_)
+        | ClientMessage::CSChangePassword(_) => Some(RateLimitCategory::Account),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TICK_RATE: f64 = 60.;
+
+    /// Send `per_second` messages of a category for `secs`, collecting the penalties
+    fn send(
+        limits: &mut RateLimits,
+        category: RateLimitCategory,
+        per_second: f64,
+        secs: f64,
+        start: f64,
+    ) -> Vec<Penalty> {
+        let settings = RateLimitSettings::default();
+        let count = (per_second * secs) as usize;
+
+        (0..count)
+            .filter_map(|i| {
+                let now = start + i as f64 / per_second;
+                limits.check(&settings, TICK_RATE, category, now)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn a_message_per_tick_is_never_limited() {
+        let mut limits = RateLimits::default();
+        let settings = RateLimitSettings::default();
+
+        for tick in 0..(TICK_RATE as usize * 60) {
+            let now = tick as f64 / TICK_RATE;
+            for category in [RateLimitCategory::Movement, RateLimitCategory::Rotation] {
+                assert_eq!(limits.check(&settings, TICK_RATE, category, now), None);
+            }
+        }
+    }
+
+    #[test]
+    fn flooding_movement_is_dropped_without_strikes() {
+        let mut limits = RateLimits::default();
+
+        let penalties = send(
+            &mut limits,
+            RateLimitCategory::Rotation,
+            TICK_RATE * 10.,
+            60.,
+            0.,
+        );
+        assert!(penalties.contains(&Penalty::Notify));
+        assert!(penalties
+            .iter()
+            .all(|penalty| matches!(penalty, Penalty::Drop | Penalty::Notify)));
+        assert_eq!(limits.strikes, 0);
+    }
+
+    #[test]
+    fn a_single_burst_is_not_a_strike() {
+        let mut limits = RateLimits::default();
+
+        let penalties = send(&mut limits, RateLimitCategory::Chat, 100., 0.1, 0.);
+        assert_eq!(penalties.first(), Some(&Penalty::Notify));
+        assert!(penalties[1..]
+            .iter()
+            .all(|penalty| *penalty == Penalty::Drop));
+        assert_eq!(limits.strikes, 0);
+    }
+
+    #[test]
+    fn sustained_chat_overage_mutes_and_kicks() {
+        let settings = RateLimitSettings::default();
+        let mut limits = RateLimits::default();
+
+        let penalties = send(&mut limits, RateLimitCategory::Chat, 10., 5., 0.);
+        assert_eq!(penalties.iter().filter(|p| **p == Penalty::Mute).count(), 1);
+        assert!(!penalties.contains(&Penalty::Kick));
+
+        let penalties = send(&mut limits, RateLimitCategory::Chat, 10., 5., 5.);
+        assert!(penalties.contains(&Penalty::Kick));
+
+        // strikes and the mute are forgiven once the window passes without exceeding a limit
+        let later = 10. + settings.strike_window * 2.;
+        let penalties = send(&mut limits, RateLimitCategory::Chat, 10., 5., later);
+        assert!(penalties.contains(&Penalty::Mute));
+        assert!(!penalties.contains(&Penalty::Kick));
+    }
+}
@@ -0,0 +1,131 @@
+//! Decoding and checking of client messages before they reach the handlers
+//!
+//! Nothing a client sends is trusted, anything a well behaved client would never send is counted
+//! against it as a protocol violation and dropped.
+
+use super::{
+    context::{NetworkContext, Violation},
+    rate_limit::{rate_limit_category, Penalty},
+};
+
+use rpg_network_protocol::protocol::*;
+
+use bevy::log::info;
+
+use bevy_renet::renet::ClientId;
+
+/// Where a client message goes once it has been decoded and checked
+#[derive(Debug)]
+pub(crate) enum Route {
+    Hello(CSHello),
+    Handler(ClientMessage),
+    /// Dropped for exceeding a rate limit
+    Limited(RateLimitCategory, Penalty),
+    Dropped,
+}
+
+/// What a client must be logged into, beyond a completed handshake, to send a message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Access {
+    Any,
+    Player,
+    Admin,
+}
+
+fn required_access(message: &ClientMessage) -> Access {
+    match message {
+        ClientMessage::CSHello(_)
+        | ClientMessage::CSConnectPlayer(_)
+        | ClientMessage::CSConnectAdmin(_)
+        | ClientMessage::CSCreateAccount(_)
+        | ClientMessage::CSLoadAccount(_)
+        | ClientMessage::CSLoadAdminAccount(_) => Access::Any,
+        ClientMessage::CSAdminListClients(_)
+        | ClientMessage::CSAdminListGames(_)
+        | ClientMessage::CSAdminKick(_)
+        | ClientMessage::CSAdminBan(_)
+        | ClientMessage::CSAdminUnban(_)
+        | ClientMessage::CSAdminMute(_)
+        | ClientMessage::CSAdminUnmute(_)
+        | ClientMessage::CSAdminBroadcast(_)
+        | ClientMessage::CSAdminGetCharacter(_)
+        | ClientMessage::CSAdminEditCharacter(_) => Access::Admin,
+        _ => Access::Player,
+    }
+}
+
+/// Positions and directions must be finite, anything else would poison the simulation
+fn has_valid_values(message: &ClientMessage) -> bool {
+    match message {
+        ClientMessage::CSRotPlayer(msg) => msg.0.is_finite(),
+        ClientMessage::CSMovePlayer(msg) => msg.direction.is_finite(),
+        ClientMessage::CSSkillUseTargeted(msg) => msg.target.is_finite(),
+        _ => true,
+    }
+}
+
+/// Decode and check a message, `now` is the time in seconds used for rate limiting
+pub(crate) fn route_message(
+    context: &mut NetworkContext,
+    client_id: ClientId,
+    bytes: &[u8],
+    now: f64,
+) -> Route {
+    if context.is_disconnecting(client_id) {
+        return Route::Dropped;
+    }
+
+    let Some(client) = context.get_client_from_id(client_id) else {
+        return Route::Dropped;
+    };
+    let has_handshake = client.handshake.is_some();
+    let is_player = client.is_authenticated_player();
+    let is_admin = client.is_authenticated_admin();
+
+    let message = match ClientMessage::decode(bytes) {
+        Ok(message) => message,
+        Err(e) => {
+            info!("client {client_id} sent an undecodable message: {e}");
+            context.record_violation(client_id, Violation::Malformed);
+            return Route::Dropped;
+        }
+    };
+
+    let violation = match (&message, has_handshake) {
+        (ClientMessage::CSHello(_), false) => None,
+        (_, false) | (ClientMessage::CSHello(_), true) => Some(Violation::Handshake),
+        (message, true) if !has_valid_values(message) => Some(Violation::InvalidValue),
+        (message, true) => match required_access(message) {
+            Access::Player if !is_player => Some(Violation::Unauthenticated),
+            // The admin handlers reply to unauthorized requests themselves
+            Access::Admin if !is_admin => {
+                context.record_violation(client_id, Violation::Unauthenticated);
+                None
+            }
+            _ => None,
+        },
+    };
+
+    if let Some(violation) = violation {
+        context.record_violation(client_id, violation);
+        return Route::Dropped;
+    }
+
+    if let Some(category) = rate_limit_category(&message) {
+        let Some(client) = context.clients.get_mut(&client_id) else {
+            return Route::Dropped;
+        };
+        if let Some(penalty) =
+            client
+                .rate_limits
+                .check(&context.rate_limits, context.tick_rate, category, now)
+        {
+            return Route::Limited(category, penalty);
+        }
+    }
+
+    match message {
+        ClientMessage::CSHello(hello) => Route::Hello(hello),
+        message => Route::Handler(message),
+    }
+}
@@ -0,0 +1,136 @@
+//! Queueing server messages and sending them in batches
+//!
+//! Systems queue messages with `Outbound`, at the end of the frame the messages queued for each
+//! client are coalesced into one batch per channel and handed to renet. A message is encoded once
+//! no matter how many clients it is queued for.
+
+use rpg_network_protocol::protocol::*;
+
+use bevy::ecs::system::{ResMut, Resource};
+
+use bevy_renet::renet::{ClientId, RenetServer};
+
+use std::{collections::HashMap, sync::Arc};
+
+/// A batch is split rather than grow past this, so that it usually fits in a single packet
+const MAX_BATCH_BYTES: usize = 1100;
+
+/// An encoded message and the channel it is delivered on, encode once to send it to many clients
+#[derive(Clone)]
+pub(crate) struct EncodedMessage {
+    pub(crate) channel: ServerChannel,
+    bytes: Arc<[u8]>,
+}
+
+impl EncodedMessage {
+    pub(crate) fn new(message: &ServerMessage) -> Self {
+        Self {
+            channel: message.channel(),
+            bytes: bincode::serialize(message).unwrap().into(),
+        }
+    }
+}
+
+/// Totals of everything sent since the server started
+#[derive(Default, Debug, Clone, Copy)]
+pub(crate) struct OutboundStats {
+    pub(crate) messages: u64,
+    pub(crate) batches: u64,
+    pub(crate) bytes: u64,
+    /// Messages for clients that disconnected before they were sent
+    pub(crate) dropped: u64,
+}
+
+/// The messages queued for each client this frame, in the order they were queued
+#[derive(Resource, Default)]
+pub(crate) struct Outbound {
+    queued: HashMap<ClientId, Vec<EncodedMessage>>,
+    /// Shared by every client, a client only needs the numbers it sees to increase
+    state_sequence: u32,
+    stats: OutboundStats,
+}
+
+impl Outbound {
+    /// Queue a message for a client
+    pub(crate) fn send(&mut self, client_id: ClientId, message: &ServerMessage) {
+        self.push(client_id, &EncodedMessage::new(message));
+    }
+
+    /// Queue an encoded message for a client
+    pub(crate) fn push(&mut self, client_id: ClientId, message: &EncodedMessage) {
+        self.queued
+            .entry(client_id)
+            .or_default()
+            .push(message.clone());
+    }
+
+    pub(crate) fn stats(&self) -> OutboundStats {
+        self.stats
+    }
+}
+
+fn send_batch(
+    server: &mut RenetServer,
+    stats: &mut OutboundStats,
+    client_id: ClientId,
+    channel: ServerChannel,
+    batch: Vec<u8>,
+) {
+    stats.batches += 1;
+    stats.bytes += batch.len() as u64;
+
+    server.send_message(client_id, channel, batch);
+}
+
+/// Send everything queued this frame, this runs once all systems had a chance to queue messages
+pub(crate) fn flush_outbound(mut server: ResMut<RenetServer>, mut outbound: ResMut<Outbound>) {
+    let Outbound {
+        queued,
+        state_sequence,
+        stats,
+    } = &mut *outbound;
+
+    queued.retain(|client_id, messages| {
+        if !server.is_connected(*client_id) {
+            stats.dropped += messages.len() as u64;
+            return false;
+        }
+
+        for channel in [
+            ServerChannel::Message,
+            ServerChannel::Event,
+            ServerChannel::State,
+        ] {
+            let mut batch = vec![];
+            let mut batched = 0;
+
+            for message in messages.iter().filter(|m| m.channel == channel) {
+                let length = BATCH_LENGTH_BYTES + message.bytes.len();
+                if batched > 0 && batch.len() + length > MAX_BATCH_BYTES {
+                    let batch = std::mem::take(&mut batch);
+                    send_batch(&mut server, stats, *client_id, channel, batch);
+                    batched = 0;
+                }
+
+                // Every state batch is sequenced, see `StateSequence`
+                if batched == 0 && channel == ServerChannel::State {
+                    batch.extend_from_slice(&state_sequence.to_le_bytes());
+                    *state_sequence = state_sequence.wrapping_add(1);
+                }
+
+                push_batch_message(&mut batch, &message.bytes);
+                batched += 1;
+                stats.messages += 1;
+            }
+
+            if batched > 0 {
+                send_batch(&mut server, stats, *client_id, channel, batch);
+            }
+        }
+
+        // Queues are kept for the next frame to avoid reallocating them
+        messages.clear();
+
+        true
+    });
+}
@@ -1,27 +1,53 @@
-use super::{account, chat, context::NetworkContext, game, lobby};
-use crate::{game::plugin::GameState, state::AppState};
+use super::{
+    account, admin, chat,
+    client::Handshake,
+    context::NetworkContext,
+    game,
+    lobby::{self, send_lobby_update},
+    rate_limit::Penalty,
+    route::{route_message, Route},
+    send::{self, Outbound},
+};
+use crate::{
+    account::{save_account, save_account_instance, AccountInstance},
+    chat::ChatManager,
+    config::NetworkSettings,
+    game::{instance::GameManager, interest::InterestMap, snapshot::SnapshotHistory},
+    lobby::LobbyManager,
+    moderation::unix_time,
+    shutdown::ShutdownCountdown,
+    state::AppState,
+    store::AccountStorage,
+};
 
 use bevy::{
-    app::{App, FixedUpdate, Plugin, PreUpdate, Update},
+    app::{App, FixedUpdate, Plugin, PostUpdate, PreUpdate, Update},
     ecs::{
         event::{Event, EventReader, EventWriter},
-        schedule::{common_conditions::*, Condition, IntoSystemConfigs, NextState},
-        system::{Commands, Res, ResMut, SystemParam},
+        schedule::{common_conditions::*, IntoSystemConfigs},
+        system::{Commands, Query, Res, ResMut, SystemParam},
     },
     log::info,
+    time::Time,
 };
 
 use bevy_renet::renet::{ClientId, RenetServer, ServerEvent};
-use bevy_renet::{transport::NetcodeServerPlugin, RenetServerPlugin};
+use bevy_renet::{transport::NetcodeServerPlugin, RenetSend, RenetServerPlugin};
 
 use bevy_renet::renet::{
     transport::{NetcodeServerTransport, ServerAuthentication, ServerConfig},
     ConnectionConfig,
 };
 
-use rpg_network_protocol::{protocol::*, *};
+use rpg_account::moderation::Sanction;
+use rpg_network_protocol::{protocol::*, token::TokenIdentity, *};
+use rpg_util::{
+    item::UnitStorage,
+    skill::{SkillSlots, Skills},
+    unit::{Unit, Waypoints},
+};
 
-use std::net::{Ipv4Addr, SocketAddr, UdpSocket};
+use std::net::UdpSocket;
 use std::time::SystemTime;
 
 #[derive(Event)]
@@ -31,8 +57,8 @@ pub(crate) struct ClientMessageEvent {
 }
 
 pub(crate) struct NetworkServerPlugin {
-    pub(crate) addr: Ipv4Addr,
-    pub(crate) port: u16,
+    pub(crate) settings: NetworkSettings,
+    pub(crate) tick_rate: f64,
 }
 
 impl Plugin for NetworkServerPlugin {
@@ -45,19 +71,28 @@ impl Plugin for NetworkServerPlugin {
 
         let server = RenetServer::new(connection_config);
 
-        let listen_addr = SocketAddr::new(self.addr.into(), self.port);
+        let listen_addr = self.settings.listen_addr();
         info!("listening on {listen_addr:?}");
 
+        let authentication = if self.settings.unsecure {
+            info!("running in unsecure mode, connect tokens are not required");
+            ServerAuthentication::Unsecure
+        } else {
+            ServerAuthentication::Secure {
+                private_key: self.settings.private_key().unwrap(),
+            }
+        };
+
         let socket = UdpSocket::bind(listen_addr).unwrap();
         let current_time: std::time::Duration = SystemTime::now()
             .duration_since(SystemTime::UNIX_EPOCH)
             .unwrap();
         let server_config = ServerConfig {
             current_time,
-            max_clients: 16,
+            max_clients: self.settings.max_clients,
             protocol_id: PROTOCOL_ID,
-            public_addresses: vec![listen_addr],
-            authentication: ServerAuthentication::Unsecure,
+            public_addresses: vec![self.settings.public_addr()],
+            authentication,
         };
 
         let transport = NetcodeServerTransport::new(server_config, socket).unwrap();
@@ -67,8 +102,15 @@ impl Plugin for NetworkServerPlugin {
             .add_plugins(RenetServerPlugin)
             .insert_resource(server)
             .insert_resource(transport)
-            .init_resource::<NetworkContext>()
+            .insert_resource(NetworkContext::new(
+                self.settings.rate_limits.clone(),
+                self.tick_rate,
+                !self.settings.unsecure,
+            ))
+            .init_resource::<Outbound>()
+            .init_resource::<account::CredentialTasks>()
             .add_systems(PreUpdate, (handle_connections, handle_messages).chain())
+            .add_systems(PostUpdate, send::flush_outbound.before(RenetSend))
             .add_systems(
                 FixedUpdate,
                 (
@@ -78,49 +120,61 @@ impl Plugin for NetworkServerPlugin {
                     game::receive_item_drop,
                     game::receive_item_pickup,
                     game::receive_movement,
-                    game::receive_movement_end,
+                    game::receive_snapshot_ack,
+                    game::receive_ping,
                 )
                     .chain()
-                    .run_if(in_state(AppState::Simulation)),
+                    .run_if(in_state(AppState::Running)),
             )
             .add_systems(
                 Update,
                 (
                     (
-                        (
-                            account::receive_account_create,
-                            account::receive_account_login,
-                            account::receive_admin_login,
-                            account::receive_character_create,
-                            account::receive_game_create,
-                        ),
-                        (
-                            lobby::receive_lobby_create,
-                            lobby::receive_lobby_join,
-                            lobby::receive_lobby_leave,
-                            lobby::receive_lobby_message,
-                        ),
-                    )
-                        .run_if(in_state(AppState::Lobby)),
+                        account::receive_account_create,
+                        account::receive_account_login,
+                        (account::receive_admin_connect, account::receive_admin_login).chain(),
+                        account::receive_character_create,
+                        account::receive_game_create
+                            .run_if(not(resource_exists::<ShutdownCountdown>)),
+                        account::receive_game_join
+                            .run_if(not(resource_exists::<ShutdownCountdown>)),
+                        account::receive_password_change,
+                        account::finish_credential_checks,
+                    ),
+                    (
+                        lobby::receive_lobby_create,
+                        lobby::receive_lobby_join,
+                        lobby::receive_lobby_leave,
+                        lobby::receive_lobby_message,
+                        lobby::receive_lobby_ready,
+                        lobby::receive_lobby_start_game
+                            .run_if(not(resource_exists::<ShutdownCountdown>)),
+                    ),
                     (
-                        account::receive_game_join,
                         chat::receive_chat_channel_message,
                         chat::receive_chat_join,
                         chat::receive_chat_leave,
-                    )
-                        .run_if(
-                            in_state(AppState::Lobby)
-                                .or_else(in_state(AppState::SpawnSimulation))
-                                .or_else(in_state(AppState::Simulation)),
-                        ),
+                    ),
                     (
                         game::receive_player_leave,
                         game::receive_player_join,
                         game::receive_player_loaded,
                         game::receive_player_revive,
-                    )
-                        .run_if(in_state(AppState::Simulation)),
-                ),
+                    ),
+                    (
+                        admin::receive_admin_list_clients,
+                        admin::receive_admin_list_games,
+                        admin::receive_admin_kick,
+                        admin::receive_admin_ban,
+                        admin::receive_admin_unban,
+                        admin::receive_admin_mute,
+                        admin::receive_admin_unmute,
+                        admin::receive_admin_broadcast,
+                        admin::receive_admin_get_character,
+                        admin::receive_admin_edit_character,
+                    ),
+                )
+                    .run_if(in_state(AppState::Running)),
             );
     }
 }
@@ -136,53 +190,224 @@ pub(crate) struct NetworkParamsRO<'w> {
 pub(crate) struct NetworkParamsRW<'w> {
     pub(crate) server: ResMut<'w, RenetServer>,
     pub(crate) context: ResMut<'w, NetworkContext>,
+    pub(crate) outbound: ResMut<'w, Outbound>,
 }
 
+#[allow(clippy::too_many_arguments)]
 fn handle_connections(
     mut commands: Commands,
-    mut state: ResMut<NextState<AppState>>,
-    mut game_state: ResMut<GameState>,
+    mut game_manager: ResMut<GameManager>,
+    mut interest: ResMut<InterestMap>,
+    mut history: ResMut<SnapshotHistory>,
+    mut lobby_manager: ResMut<LobbyManager>,
+    mut chat: ResMut<ChatManager>,
+    mut store: ResMut<AccountStorage>,
+    transport: Res<NetcodeServerTransport>,
     mut connect_reader: EventReader<ServerEvent>,
     mut net_params: NetworkParamsRW,
+    mut account_q: Query<(
+        &mut AccountInstance,
+        Option<(&Unit, &Skills, &SkillSlots, &UnitStorage, &Waypoints)>,
+    )>,
 ) {
     for event in connect_reader.read() {
         match event {
             ServerEvent::ClientConnected { client_id } => {
                 info!("client joined: {client_id}");
 
-                net_params.context.add_client(*client_id);
+                // Unsecure clients choose their own user data so it is not trusted
+                let identity = if net_params.context.require_tokens {
+                    let identity = transport
+                        .user_data(*client_id)
+                        .and_then(|user_data| TokenIdentity::from_user_data(&user_data));
+                    if identity.is_none() {
+                        info!("client {client_id} connected without a token identity");
+                        net_params.context.disconnect_later(*client_id);
+                    }
 
-                let message = bincode::serialize(&ServerMessage::SCHello(SCHello)).unwrap();
-                net_params
-                    .server
-                    .send_message(*client_id, ServerChannel::Message, message);
+                    identity
+                } else {
+                    None
+                };
+
+                // The client speaks first, see `receive_hello`
+                net_params.context.add_client(*client_id, identity);
             }
             ServerEvent::ClientDisconnected { client_id, reason } => {
                 info!("client disconnected: {reason:?}");
-                game_state.players.retain(|p| p.client_id != *client_id);
+                game_manager.remove_player(*client_id);
+                interest.remove_client(*client_id);
+                history.remove_client(*client_id);
 
-                net_params.context.remove_client(&mut commands, *client_id);
+                // persist the account before the client's entity is despawned
+                if let Some(client) = net_params.context.get_client_from_id(*client_id) {
+                    if let Ok((mut account, hero)) = account_q.get_mut(client.entity) {
+                        save_account_instance(&mut store, &mut account, hero);
+                    }
+                }
+
+                if let Some(account_id) = net_params
+                    .context
+                    .get_client_from_id(*client_id)
+                    .filter(|c| c.is_player())
+                    .and_then(|c| c.account_id)
+                {
+                    chat.remove_account(account_id);
 
-                if game_state.players.is_empty() {
-                    state.set(AppState::Lobby);
+                    for lobby_id in lobby_manager.remove_account(account_id) {
+                        if let Some(lobby) = lobby_manager.get_lobby(lobby_id) {
+                            send_lobby_update(&mut net_params, lobby);
+                        }
+                    }
                 }
+
+                net_params.context.remove_client(&mut commands, *client_id);
             }
         }
     }
 }
 
 fn handle_messages(
+    time: Res<Time>,
+    mut store: ResMut<AccountStorage>,
     mut net_params: NetworkParamsRW,
     mut message_writer: EventWriter<ClientMessageEvent>,
+    mut account_q: Query<&mut AccountInstance>,
 ) {
+    let now = time.elapsed_seconds_f64();
+
+    let pending_disconnects = std::mem::take(&mut net_params.context.pending_disconnects);
+    for client_id in pending_disconnects {
+        net_params.server.disconnect(client_id);
+    }
+
+    net_params.context.forgive_violations(now);
+
     for client_id in net_params.server.clients_id() {
         while let Some(message) = net_params
             .server
             .receive_message(client_id, ClientChannel::Message)
         {
-            let message: ClientMessage = bincode::deserialize(&message).unwrap();
+            receive_message(
+                &mut store,
+                &mut net_params,
+                &mut message_writer,
+                &mut account_q,
+                client_id,
+                &message,
+                now,
+            );
+        }
+    }
+}
 
+/// Route one message from a client to its handler, `now` is the time in seconds
+pub(crate) fn receive_message(
+    store: &mut AccountStorage,
+    net_params: &mut NetworkParamsRW,
+    message_writer: &mut EventWriter<ClientMessageEvent>,
+    account_q: &mut Query<&mut AccountInstance>,
+    client_id: ClientId,
+    bytes: &[u8],
+    now: f64,
+) {
+    match route_message(&mut net_params.context, client_id, bytes, now) {
+        Route::Hello(hello) => receive_hello(net_params, client_id, hello),
+        Route::Handler(message) => {
             message_writer.send(ClientMessageEvent { client_id, message });
         }
+        Route::Limited(category, penalty) => {
+            apply_rate_limit(store, net_params, account_q, client_id, category, penalty);
+        }
+        Route::Dropped => {}
     }
 }
+
+/// Tell a client it exceeded a rate limit and escalate repeated abuse
+fn apply_rate_limit(
+    store: &mut AccountStorage,
+    net_params: &mut NetworkParamsRW,
+    account_q: &mut Query<&mut AccountInstance>,
+    client_id: ClientId,
+    category: RateLimitCategory,
+    penalty: Penalty,
+) {
+    let muted = match penalty {
+        Penalty::Drop => return,
+        Penalty::Notify | Penalty::Kick => false,
+        Penalty::Mute => {
+            let Some(client) = net_params.context.get_client_from_id(client_id) else {
+                return;
+            };
+            let Ok(mut account) = account_q.get_mut(client.entity) else {
+                return;
+            };
+
+            let now = unix_time();
+            // A longer mute from an admin is left in place
+            if !account.moderation.is_muted(now) {
+                let duration = net_params.context.rate_limits.mute_duration;
+                account.moderation.mute = Some(Sanction::new(
+                    format!("Sending {category:?} messages too quickly"),
+                    Some(now.saturating_add(duration)),
+                ));
+                save_account(store, &account.0);
+            }
+
+            info!("muted client {client_id} for sending {category:?} messages too quickly");
+            true
+        }
+    };
+
+    let message = ServerMessage::SCRateLimited(SCRateLimited { category, muted });
+    net_params.outbound.send(client_id, &message);
+
+    if penalty == Penalty::Kick {
+        info!("disconnecting client {client_id} for exceeding {category:?} rate limits");
+        net_params.context.disconnect_later(client_id);
+    }
+}
+
+/// Accept or reject a client's hello, only clients with a matching protocol version are accepted
+fn receive_hello(net_params: &mut NetworkParamsRW, client_id: ClientId, hello: CSHello) {
+    if hello.protocol_version != PROTOCOL_VERSION {
+        info!(
+            "rejecting client {client_id} with protocol version {} build {:?}",
+            hello.protocol_version, hello.build_id
+        );
+
+        let message = ServerMessage::SCHelloError(SCHelloError {
+            protocol_version: PROTOCOL_VERSION,
+            build_id: BUILD_ID.to_string(),
+            reason: format!(
+                "The server requires protocol version {PROTOCOL_VERSION} but this client uses \
+                 version {}, please update to a matching build",
+                hello.protocol_version
+            ),
+        });
+        net_params.outbound.send(client_id, &message);
+        net_params.context.disconnect_later(client_id);
+        return;
+    }
+
+    let features = negotiate_features(FEATURES, &hello.features);
+    info!(
+        "client {client_id} build {:?} accepted with features {features:?}",
+        hello.build_id
+    );
+
+    let message = ServerMessage::SCHello(SCHello {
+        protocol_version: PROTOCOL_VERSION,
+        build_id: BUILD_ID.to_string(),
+        features: features.clone(),
+    });
+    net_params.outbound.send(client_id, &message);
+
+    let Some(client) = net_params.context.clients.get_mut(&client_id) else {
+        return;
+    };
+    client.handshake = Some(Handshake {
+        build_id: hello.build_id,
+        features,
+    });
+}
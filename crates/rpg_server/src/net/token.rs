@@ -0,0 +1,348 @@
+//! Connect token issuer
+//!
+//! Runs on its own threads so a slow or hostile token client never stalls the app, every request
+//! is handled on a thread of its own up to `MAX_PENDING_REQUESTS`. Credentials are checked against
+//! a separate handle to the account store which only ever reads records.
+
+use super::rate_limit::TokenBucket;
+use crate::{
+    config::{BucketSettings, NetworkSettings, PRIVATE_KEY_BYTES},
+    moderation::unix_time,
+    store::{AccountStore, StoreError},
+};
+
+use rpg_account::name::{has_valid_characters, validate_account_name};
+use rpg_network_protocol::{token::*, PROTOCOL_ID};
+
+use bevy::log::info;
+
+use bevy_renet::renet::transport::ConnectToken;
+
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+
+use std::{
+    collections::HashMap,
+    io,
+    net::{IpAddr, SocketAddr, TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Instant, SystemTime},
+};
+
+/// How long an issued token may be used to connect
+const TOKEN_EXPIRE_SECS: u64 = 30;
+/// How long the transport waits on a silent client before dropping it
+const TOKEN_TIMEOUT_SECS: i32 = 15;
+/// Requests beyond this many in flight are dropped without a reply
+const MAX_PENDING_REQUESTS: usize = 32;
+/// Idle account creation buckets are forgotten once there are this many addresses
+const MAX_TRACKED_ADDRS: usize = 1024;
+
+pub(crate) struct TokenIssuer<S> {
+    listener: TcpListener,
+    store: S,
+    public_addr: SocketAddr,
+    private_key: [u8; PRIVATE_KEY_BYTES],
+    account_creation: BucketSettings,
+    /// Account creation requests per address
+    creations: Mutex<HashMap<IpAddr, TokenBucket>>,
+    started: Instant,
+}
+
+impl<S: AccountStore> TokenIssuer<S> {
+    /// Listen for token requests on the same address and port as the game server
+    pub(crate) fn bind(
+        settings: &NetworkSettings,
+        private_key: [u8; PRIVATE_KEY_BYTES],
+        store: S,
+    ) -> io::Result<Self> {
+        let listener = TcpListener::bind(settings.listen_addr())?;
+        info!("issuing connect tokens on {:?}", listener.local_addr()?);
+
+        Ok(Self {
+            listener,
+            store,
+            public_addr: settings.public_addr(),
+            private_key,
+            account_creation: settings.rate_limits.account_creation,
+            creations: Mutex::new(HashMap::new()),
+            started: Instant::now(),
+        })
+    }
+
+    pub(crate) fn spawn(self) -> JoinHandle<()> {
+        let issuer = Arc::new(self);
+        let pending = Arc::new(AtomicUsize::new(0));
+
+        thread::spawn(move || {
+            for stream in issuer.listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(e) => {
+                        info!("unable to accept token request: {e}");
+                        continue;
+                    }
+                };
+
+                if pending.fetch_add(1, Ordering::AcqRel) >= MAX_PENDING_REQUESTS {
+                    pending.fetch_sub(1, Ordering::AcqRel);
+                    info!("too many pending token requests, dropping {stream:?}");
+                    continue;
+                }
+
+                let issuer = issuer.clone();
+                let pending = pending.clone();
+                thread::spawn(move || {
+                    if let Err(e) = issuer.handle(stream) {
+                        info!("token request failed: {e}");
+                    }
+                    pending.fetch_sub(1, Ordering::AcqRel);
+                });
+            }
+        })
+    }
+
+    fn handle(&self, mut stream: TcpStream) -> io::Result<()> {
+        stream.set_read_timeout(Some(TOKEN_IO_TIMEOUT))?;
+        stream.set_write_timeout(Some(TOKEN_IO_TIMEOUT))?;
+
+        let peer = stream.peer_addr()?;
+        let request: TokenRequest = read_message(&mut stream)?;
+
+        let response = match self
+            .authorize(&request, peer.ip())
+            .and_then(|identity| self.issue(&identity))
+        {
+            Ok((client_id, token)) => {
+                info!("issued connect token for client {client_id} to {peer:?}");
+                TokenResponse::Token(token)
+            }
+            Err(e) => {
+                info!("rejected token request from {peer:?}: {e:?}");
+                TokenResponse::Error(e)
+            }
+        };
+
+        write_message(&mut stream, &response)
+    }
+
+    fn authorize(&self, request: &TokenRequest, ip: IpAddr) -> Result<TokenIdentity, TokenError> {
+        match request {
+            TokenRequest::Player { name, password } => {
+                // The naming rules only apply to new accounts, existing names may predate them
+                if !has_valid_characters(name) {
+                    return Err(TokenError::InvalidCredentials);
+                }
+
+                let account = self.store.load_account(name).map_err(store_error)?;
+                let credential = self.store.load_credential(name).map_err(store_error)?;
+                if !credential.verify(password) {
+                    return Err(TokenError::InvalidCredentials);
+                }
+
+                if account.moderation.active_ban(unix_time()).is_some() {
+                    return Err(TokenError::Banned);
+                }
+
+                Ok(TokenIdentity::Player(account.info.id))
+            }
+            TokenRequest::Admin { name, password } => {
+                if !has_valid_characters(name) {
+                    return Err(TokenError::InvalidCredentials);
+                }
+
+                let credential = self
+                    .store
+                    .load_admin_credential(name)
+                    .map_err(store_error)?;
+                if !credential.verify(password) {
+                    return Err(TokenError::InvalidCredentials);
+                }
+
+                let account = self.store.load_admin_account(name).map_err(store_error)?;
+
+                Ok(TokenIdentity::Admin(account.info.id))
+            }
+            TokenRequest::CreateAccount { name } => {
+                if !self.allow_account_creation(ip) {
+                    return Err(TokenError::RateLimited);
+                }
+
+                // The name is validated again when the account is created
+                if validate_account_name(name).is_err() {
+                    return Err(TokenError::InvalidCredentials);
+                }
+
+                match self.store.account_name_exists(name) {
+                    Ok(false) => Ok(TokenIdentity::CreateAccount(name.clone())),
+                    Ok(true) => Err(TokenError::AccountExists),
+                    Err(e) => Err(store_error(e)),
+                }
+            }
+        }
+    }
+
+    /// Count an account creation request against its address
+    fn allow_account_creation(&self, ip: IpAddr) -> bool {
+        let now = self.started.elapsed().as_secs_f64();
+        let settings = &self.account_creation;
+
+        let mut creations = self.creations.lock().unwrap();
+        if creations.len() >= MAX_TRACKED_ADDRS {
+            creations.retain(|_, bucket| !bucket.is_full(settings, now));
+        }
+
+        creations.entry(ip).or_default().take(settings, now)
+    }
+
+    fn issue(&self, identity: &TokenIdentity) -> Result<(u64, Vec<u8>), TokenError> {
+        let current_time = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap();
+        // Clients never pick their own id so they can not impersonate another connection
+        let client_id = OsRng.next_u64();
+
+        let Some(user_data) = identity.to_user_data() else {
+            info!("token identity does not fit in the user data: {identity:?}");
+            return Err(TokenError::Unavailable);
+        };
+
+        let token = ConnectToken::generate(
+            current_time,
+            PROTOCOL_ID,
+            TOKEN_EXPIRE_SECS,
+            client_id,
+            TOKEN_TIMEOUT_SECS,
+            vec![self.public_addr],
+            Some(&user_data),
+            &self.private_key,
+        )
+        .map_err(|e| {
+            info!("unable to generate connect token: {e:?}");
+            TokenError::Unavailable
+        })?;
+
+        let mut bytes = vec![];
+        token
+            .write(&mut bytes)
+            .map_err(|_| TokenError::Unavailable)?;
+
+        Ok((client_id, bytes))
+    }
+}
+
+/// Missing records are reported like a bad password so names can not be probed
+fn store_error(e: StoreError) -> TokenError {
+    match e {
+        StoreError::NotFound | StoreError::InvalidName => TokenError::InvalidCredentials,
+        e => {
+            info!("token issuer store error: {e}");
+            TokenError::Unavailable
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{credential::Credential, store::MemoryAccountStore};
+
+    use rpg_account::{
+        account::{Account, AccountId, AccountInfo},
+        name::NAME_MAX_LENGTH,
+    };
+
+    use std::net::Ipv4Addr;
+
+    const PASSWORD: &str = "correct horse battery staple";
+
+    fn issuer(store: MemoryAccountStore) -> TokenIssuer<MemoryAccountStore> {
+        let settings = NetworkSettings {
+            addr: Ipv4Addr::LOCALHOST,
+            port: 0,
+            ..Default::default()
+        };
+
+        TokenIssuer::bind(&settings, [7; PRIVATE_KEY_BYTES], store).unwrap()
+    }
+
+    #[test]
+    fn account_creation_is_limited_per_address() {
+        let issuer = issuer(MemoryAccountStore::default());
+        let request = TokenRequest::CreateAccount {
+            name: "alice".into(),
+        };
+        let ip = IpAddr::from([10, 0, 0, 1]);
+
+        for _ in 0..issuer.account_creation.burst as usize {
+            assert_eq!(
+                issuer.authorize(&request, ip),
+                Ok(TokenIdentity::CreateAccount("alice".into()))
+            );
+        }
+        assert_eq!(issuer.authorize(&request, ip), Err(TokenError::RateLimited));
+
+        assert!(issuer
+            .authorize(&request, IpAddr::from([10, 0, 0, 2]))
+            .is_ok());
+    }
+
+    #[test]
+    fn players_predating_the_naming_rules_get_tokens_for_their_account() {
+        // too short and reserved, created before the naming rules existed
+        let name = "gm";
+        let account_id = AccountId(5);
+
+        let mut store = MemoryAccountStore::default();
+        store.accounts.insert(
+            name.into(),
+            Account {
+                info: AccountInfo {
+                    id: account_id,
+                    name: name.into(),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        );
+        store
+            .credentials
+            .insert(name.into(), Credential::new(account_id, PASSWORD).unwrap());
+
+        let issuer = issuer(store);
+        let ip = IpAddr::from(Ipv4Addr::LOCALHOST);
+
+        let request = TokenRequest::Player {
+            name: name.into(),
+            password: PASSWORD.into(),
+        };
+        assert_eq!(
+            issuer.authorize(&request, ip),
+            Ok(TokenIdentity::Player(account_id))
+        );
+
+        let request = TokenRequest::Player {
+            name: name.into(),
+            password: "wrong-password".into(),
+        };
+        assert_eq!(
+            issuer.authorize(&request, ip),
+            Err(TokenError::InvalidCredentials)
+        );
+    }
+
+    #[test]
+    fn identities_fit_in_the_user_data() {
+        for identity in [
+            TokenIdentity::Player(AccountId(u64::MAX)),
+            TokenIdentity::Admin(AccountId(u64::MAX)),
+            TokenIdentity::CreateAccount("a".repeat(NAME_MAX_LENGTH)),
+        ] {
+            let user_data = identity.to_user_data().unwrap();
+            assert_eq!(TokenIdentity::from_user_data(&user_data), Some(identity));
+        }
+    }
+}
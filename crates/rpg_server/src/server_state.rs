@@ -1,19 +1,23 @@
-use rpg_account::account::AccountId;
+use crate::store::{AccountStorage, StoreError};
+
+use rpg_account::{
+    account::AccountId,
+    save::{migrate_headerless, Migration, Versioned},
+};
 use rpg_chat::chat::MessageId;
 use rpg_core::uid::NextUid;
 
-use util::fs::{open_read, open_write};
-
-use bevy::ecs::{
-    system::Resource,
-    world::{FromWorld, World},
+use bevy::{
+    ecs::{
+        system::Resource,
+        world::{FromWorld, World},
+    },
+    log::info,
 };
 
 use serde_derive::{Deserialize as De, Serialize as Ser};
 
-use std::{env, path::Path};
-
-#[derive(Debug, Ser, De)]
+#[derive(Debug, Clone, Ser, De)]
 pub(crate) struct ServerMetadata {
     pub(crate) next_account_id: AccountId,
     pub(crate) next_message_id: MessageId,
@@ -21,28 +25,51 @@ pub(crate) struct ServerMetadata {
     pub(crate) rng_seed: u64,
 }
 
+impl Versioned for ServerMetadata {
+    const VERSION: u16 = 1;
+    const MIGRATIONS: &'static [Migration<Self>] = &[migrate_headerless];
+}
+
 #[derive(Resource)]
 pub(crate) struct ServerMetadataResource(pub(crate) ServerMetadata);
 
 impl FromWorld for ServerMetadataResource {
-    fn from_world(_world: &mut World) -> Self {
-        let file_path = format!("{}/server/meta.bin", env::var("RPG_SAVE_ROOT").unwrap());
-        let path = Path::new(file_path.as_str());
-
-        if let Ok(file) = open_read(path) {
-            Self(bincode::deserialize_from(file).unwrap())
-        } else {
-            let meta = ServerMetadata {
-                next_account_id: AccountId(0),
-                next_message_id: MessageId(0),
-                next_uid: NextUid::default(),
-                rng_seed: 0,
-            };
-
-            let file = open_write(path).unwrap();
-            bincode::serialize_into(file, &meta).unwrap();
-
-            Self(meta)
+    fn from_world(world: &mut World) -> Self {
+        let mut store = world.resource_mut::<AccountStorage>();
+
+        let loaded = match store.load_metadata() {
+            Ok(meta) => Ok(meta),
+            Err(primary_err) => match store.load_metadata_backup() {
+                Ok(mut meta) => {
+                    info!("unable to load server metadata: {primary_err}, using the backup");
+
+                    // Ids handed out since the backup was written must not be handed out again
+                    store
+                        .advance_counters(&mut meta)
+                        .and_then(|()| store.save_metadata(&meta))
+                        .map(|()| meta)
+                }
+                Err(StoreError::NotFound) => Err(primary_err),
+                Err(e) => panic!("unable to load server metadata: {primary_err}, backup: {e}"),
+            },
+        };
+
+        match loaded {
+            Ok(meta) => Self(meta),
+            Err(StoreError::NotFound) => {
+                let meta = ServerMetadata {
+                    next_account_id: AccountId(0),
+                    next_message_id: MessageId(0),
+                    next_uid: NextUid::default(),
+                    rng_seed: 0,
+                };
+
+                info!("creating server metadata");
+                store.save_metadata(&meta).unwrap();
+
+                Self(meta)
+            }
+            Err(e) => panic!("unable to load server metadata: {e}"),
         }
     }
 }
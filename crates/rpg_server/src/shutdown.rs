@@ -0,0 +1,176 @@
+//! Graceful shutdown
+//!
+//! The main thread owns the signal handlers, a caught signal sets the shared `requested` flag.
+//! The app then warns every client and waits for the countdown. Once it is over all accounts and
+//! the server metadata are persisted, the games are ended and, once they have been cleaned up, the
+//! transport is disconnected and the app exits.
+
+use crate::{
+    account::{save_account_instance, AccountInstance},
+    game::instance::{GameManager, GameStatus},
+    net::send::{EncodedMessage, Outbound},
+    server_state::ServerMetadataResource,
+    store::AccountStorage,
+};
+
+use rpg_network_protocol::protocol::*;
+use rpg_util::{
+    item::UnitStorage,
+    skill::{SkillSlots, Skills},
+    unit::{Unit, Waypoints},
+};
+
+use bevy::{
+    app::AppExit,
+    ecs::{
+        event::EventWriter,
+        system::{Commands, Query, Res, ResMut, Resource},
+    },
+    log::{error, info},
+    time::{Time, Timer, TimerMode},
+};
+
+use bevy_renet::renet::{transport::NetcodeServerTransport, RenetServer};
+
+use std::sync::{
+    atomic::{AtomicBool, AtomicU8, Ordering},
+    Arc,
+};
+
+/// The app shut down and persisted all state
+pub const EXIT_SUCCESS: u8 = 0;
+/// A second signal arrived before the shutdown finished
+pub const EXIT_FORCED: u8 = 1;
+/// The app shut down but some state could not be persisted
+pub const EXIT_SAVE_FAILED: u8 = 2;
+/// The app thread panicked
+pub const EXIT_PANIC: u8 = 3;
+/// The server settings could not be loaded
+pub const EXIT_INVALID_CONFIG: u8 = 4;
+
+const SHUTDOWN_COUNTDOWN_SECS: u32 = 10;
+
+/// Shared between the signal handlers on the main thread and the app
+#[derive(Resource, Clone, Default)]
+pub struct ShutdownHandle {
+    pub requested: Arc<AtomicBool>,
+    pub status: Arc<AtomicU8>,
+}
+
+impl ShutdownHandle {
+    pub fn is_requested(&self) -> bool {
+        self.requested.load(Ordering::Relaxed)
+    }
+
+    pub fn status(&self) -> u8 {
+        self.status.load(Ordering::Relaxed)
+    }
+}
+
+/// Present once a shutdown has been announced to clients
+#[derive(Resource)]
+pub(crate) struct ShutdownCountdown {
+    pub(crate) reason: String,
+    pub(crate) timer: Timer,
+    /// The exit status, set once all state has been persisted and the games are ending
+    pub(crate) saved: Option<u8>,
+}
+
+pub(crate) fn begin_shutdown(
+    mut commands: Commands,
+    handle: Res<ShutdownHandle>,
+    countdown: Option<Res<ShutdownCountdown>>,
+    server: Res<RenetServer>,
+    mut outbound: ResMut<Outbound>,
+) {
+    if countdown.is_some() || !handle.is_requested() {
+        return;
+    }
+
+    let reason = "The server is shutting down".to_string();
+    info!("shutdown requested, stopping in {SHUTDOWN_COUNTDOWN_SECS} seconds");
+
+    let message = EncodedMessage::new(&ServerMessage::SCServerShutdown(SCServerShutdown {
+        reason: reason.clone(),
+        countdown: SHUTDOWN_COUNTDOWN_SECS,
+    }));
+    for client_id in server.clients_id() {
+        outbound.push(client_id, &message);
+    }
+
+    commands.insert_resource(ShutdownCountdown {
+        reason,
+        timer: Timer::from_seconds(SHUTDOWN_COUNTDOWN_SECS as f32, TimerMode::Once),
+        saved: None,
+    });
+}
+
+/// Persist everything once the countdown is over, end all games and exit once they have been
+/// cleaned up
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn finish_shutdown(
+    time: Res<Time>,
+    handle: Res<ShutdownHandle>,
+    countdown: Option<ResMut<ShutdownCountdown>>,
+    mut game_manager: ResMut<GameManager>,
+    mut store: ResMut<AccountStorage>,
+    server_metadata: Res<ServerMetadataResource>,
+    outbound: Res<Outbound>,
+    mut server: ResMut<RenetServer>,
+    mut transport: ResMut<NetcodeServerTransport>,
+    mut exit_writer: EventWriter<AppExit>,
+    mut account_q: Query<(
+        &mut AccountInstance,
+        Option<(&Unit, &Skills, &SkillSlots, &UnitStorage, &Waypoints)>,
+    )>,
+) {
+    let Some(mut countdown) = countdown else {
+        return;
+    };
+
+    let Some(status) = countdown.saved else {
+        countdown.timer.tick(time.delta());
+        if !countdown.timer.just_finished() {
+            return;
+        }
+
+        info!("shutting down: {}", countdown.reason);
+
+        let mut status = EXIT_SUCCESS;
+
+        for (mut account, hero) in &mut account_q {
+            if !save_account_instance(&mut store, &mut account, hero) {
+                status = EXIT_SAVE_FAILED;
+            }
+        }
+
+        if let Err(e) = store.save_metadata(&server_metadata.0) {
+            error!("unable to save server metadata: {e}");
+            status = EXIT_SAVE_FAILED;
+        }
+
+        // the games are cleaned up by `cleanup_games` before the app exits
+        for game in &mut game_manager.games {
+            game.status = GameStatus::Ending;
+        }
+
+        countdown.saved = Some(status);
+        return;
+    };
+
+    if !game_manager.games.is_empty() {
+        return;
+    }
+
+    let stats = outbound.stats();
+    info!(
+        "sent {} messages in {} batches totalling {} bytes, {} messages dropped",
+        stats.messages, stats.batches, stats.bytes, stats.dropped
+    );
+
+    server.disconnect_all();
+    transport.disconnect_all(&mut server);
+
+    handle.status.store(status, Ordering::Relaxed);
+    exit_writer.send(AppExit);
+}
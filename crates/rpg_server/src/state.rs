@@ -1,11 +1,9 @@
 use bevy::ecs::schedule::States;
 
+/// Client messages are only handled once the server is `Running`, after its metadata has loaded
 #[derive(Default, Clone, PartialEq, Eq, Hash, Debug, States)]
-pub(crate) enum AppState {
+pub enum AppState {
     #[default]
     Loading,
-    Lobby,
-    SpawnSimulation,
-    Simulation,
-    CleanupSimulation,
+    Running,
 }
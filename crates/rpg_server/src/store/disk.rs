@@ -0,0 +1,180 @@
+use super::{AccountStore, StoreError};
+use crate::{credential::Credential, server_state::ServerMetadata};
+
+use rpg_account::{
+    account::{Account, AdminAccount},
+    name::has_valid_characters,
+    save::{self, Versioned},
+};
+
+use util::fs::{open_read, with_appended_extension, write_atomic};
+
+use std::{
+    fs,
+    io::{self, Read},
+    path::{Path, PathBuf},
+};
+
+/// Stores each record as a versioned bincode file below `{root}/server`
+///
+/// - `server/meta.bin`, with the previous good copy in `server/meta.bin.bak`
+/// - `server/accounts/{name}.bin`
+/// - `server/credentials/{name}.bin`
+/// - `server/admin_accounts/{name}.bin`
+/// - `server/admin_credentials/{name}.bin`
+pub(crate) struct DiskAccountStore {
+    root: PathBuf,
+}
+
+impl DiskAccountStore {
+    pub(crate) fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    /// Only names made of valid name characters are accepted, so that a client supplied name can
+    /// never resolve to a path outside of the save directory
+    fn record_path(&self, kind: &str, name: &str) -> Result<PathBuf, StoreError> {
+        if !has_valid_characters(name) {
+            return Err(StoreError::InvalidName);
+        }
+
+        Ok(self
+            .root
+            .join("server")
+            .join(kind)
+            .join(format!("{name}.bin")))
+    }
+
+    fn metadata_path(&self) -> PathBuf {
+        self.root.join("server").join("meta.bin")
+    }
+
+    fn metadata_backup_path(&self) -> PathBuf {
+        with_appended_extension(&self.metadata_path(), "bak")
+    }
+}
+
+fn read_bytes(path: &Path) -> Result<Vec<u8>, StoreError> {
+    let mut file = match open_read(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Err(StoreError::NotFound),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut bytes = vec![];
+    file.read_to_end(&mut bytes)?;
+
+    Ok(bytes)
+}
+
+fn read_record<T: Versioned>(path: &Path) -> Result<T, StoreError> {
+    Ok(save::decode(&read_bytes(path)?)?)
+}
+
+fn write_record<T: Versioned>(path: &Path, record: &T) -> Result<(), StoreError> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let bytes = save::encode(record)?;
+    write_atomic(path, &bytes)?;
+
+    Ok(())
+}
+
+fn remove_record(path: &Path) -> Result<(), StoreError> {
+    match fs::remove_file(path) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Err(StoreError::NotFound),
+        Err(e) => Err(e.into()),
+    }
+}
+
+impl AccountStore for DiskAccountStore {
+    fn load_account(&self, name: &str) -> Result<Account, StoreError> {
+        read_record(&self.record_path("accounts", name)?)
+    }
+
+    fn create_account(&mut self, account: &Account) -> Result<(), StoreError> {
+        let path = self.record_path("accounts", &account.info.name)?;
+        if path.exists() {
+            return Err(StoreError::AlreadyExists);
+        }
+
+        write_record(&path, account)
+    }
+
+    fn save_account(&mut self, account: &Account) -> Result<(), StoreError> {
+        write_record(&self.record_path("accounts", &account.info.name)?, account)
+    }
+
+    fn list_accounts(&self) -> Result<Vec<String>, StoreError> {
+        let dir = self.root.join("server").join("accounts");
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut names = vec![];
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().is_some_and(|e| e == "bin") {
+                if let Some(name) = path.file_stem().and_then(|s| s.to_str()) {
+                    names.push(name.to_string());
+                }
+            }
+        }
+
+        Ok(names)
+    }
+
+    fn delete_account(&mut self, name: &str) -> Result<(), StoreError> {
+        remove_record(&self.record_path("accounts", name)?)?;
+
+        // Accounts created before credentials existed do not have one
+        match remove_record(&self.record_path("credentials", name)?) {
+            Ok(()) | Err(StoreError::NotFound) => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn load_admin_account(&self, name: &str) -> Result<AdminAccount, StoreError> {
+        read_record(&self.record_path("admin_accounts", name)?)
+    }
+
+    fn load_credential(&self, name: &str) -> Result<Credential, StoreError> {
+        read_record(&self.record_path("credentials", name)?)
+    }
+
+    fn save_credential(&mut self, name: &str, credential: &Credential) -> Result<(), StoreError> {
+        write_record(&self.record_path("credentials", name)?, credential)
+    }
+
+    fn load_admin_credential(&self, name: &str) -> Result<Credential, StoreError> {
+        read_record(&self.record_path("admin_credentials", name)?)
+    }
+
+    fn load_metadata(&self) -> Result<ServerMetadata, StoreError> {
+        read_record(&self.metadata_path())
+    }
+
+    fn load_metadata_backup(&self) -> Result<ServerMetadata, StoreError> {
+        read_record(&self.metadata_backup_path())
+    }
+
+    /// The current metadata file is rotated into the backup first, unless it fails to load, so that
+    /// a corrupt primary never replaces the last good backup
+    fn save_metadata(&mut self, metadata: &ServerMetadata) -> Result<(), StoreError> {
+        let path = self.metadata_path();
+        match read_bytes(&path) {
+            Ok(bytes) if save::decode::<ServerMetadata>(&bytes).is_ok() => {
+                write_atomic(&self.metadata_backup_path(), &bytes)?;
+            }
+            Ok(_) | Err(StoreError::NotFound) => {}
+            Err(e) => return Err(e),
+        }
+
+        write_record(&path, metadata)
+    }
+}
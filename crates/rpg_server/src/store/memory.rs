@@ -0,0 +1,89 @@
+use super::{AccountStore, StoreError};
+use crate::{credential::Credential, server_state::ServerMetadata};
+
+use rpg_account::account::{Account, AdminAccount};
+
+use std::collections::HashMap;
+
+/// A store that keeps all records in memory, nothing outlives the store
+#[derive(Default)]
+pub(crate) struct MemoryAccountStore {
+    pub(crate) accounts: HashMap<String, Account>,
+    pub(crate) admin_accounts: HashMap<String, AdminAccount>,
+    pub(crate) credentials: HashMap<String, Credential>,
+    pub(crate) admin_credentials: HashMap<String, Credential>,
+    pub(crate) metadata: Option<ServerMetadata>,
+}
+
+impl AccountStore for MemoryAccountStore {
+    fn load_account(&self, name: &str) -> Result<Account, StoreError> {
+        self.accounts.get(name).cloned().ok_or(StoreError::NotFound)
+    }
+
+    fn create_account(&mut self, account: &Account) -> Result<(), StoreError> {
+        if self.accounts.contains_key(&account.info.name) {
+            return Err(StoreError::AlreadyExists);
+        }
+
+        self.accounts
+            .insert(account.info.name.clone(), account.clone());
+
+        Ok(())
+    }
+
+    fn save_account(&mut self, account: &Account) -> Result<(), StoreError> {
+        self.accounts
+            .insert(account.info.name.clone(), account.clone());
+
+        Ok(())
+    }
+
+    fn list_accounts(&self) -> Result<Vec<String>, StoreError> {
+        Ok(self.accounts.keys().cloned().collect())
+    }
+
+    fn delete_account(&mut self, name: &str) -> Result<(), StoreError> {
+        self.accounts.remove(name).ok_or(StoreError::NotFound)?;
+        self.credentials.remove(name);
+
+        Ok(())
+    }
+
+    fn load_admin_account(&self, name: &str) -> Result<AdminAccount, StoreError> {
+        self.admin_accounts
+            .get(name)
+            .cloned()
+            .ok_or(StoreError::NotFound)
+    }
+
+    fn load_credential(&self, name: &str) -> Result<Credential, StoreError> {
+        self.credentials
+            .get(name)
+            .cloned()
+            .ok_or(StoreError::NotFound)
+    }
+
+    fn save_credential(&mut self, name: &str, credential: &Credential) -> Result<(), StoreError> {
+        self.credentials
+            .insert(name.to_string(), credential.clone());
+
+        Ok(())
+    }
+
+    fn load_admin_credential(&self, name: &str) -> Result<Credential, StoreError> {
+        self.admin_credentials
+            .get(name)
+            .cloned()
+            .ok_or(StoreError::NotFound)
+    }
+
+    fn load_metadata(&self) -> Result<ServerMetadata, StoreError> {
+        self.metadata.clone().ok_or(StoreError::NotFound)
+    }
+
+    fn save_metadata(&mut self, metadata: &ServerMetadata) -> Result<(), StoreError> {
+        self.metadata = Some(metadata.clone());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,239 @@
+//! Persistence of accounts, credentials and server metadata
+//!
+//! All server persistence goes through the `AccountStore` trait so the network handlers do not
+//! depend on how, or where, the data is stored.
+
+mod disk;
+// The in-memory store is only used to exercise the server without touching the disk
+#[cfg(any(test, feature = "fuzz"))]
+mod memory;
+
+pub(crate) use disk::DiskAccountStore;
+#[cfg(any(test, feature = "fuzz"))]
+pub(crate) use memory::MemoryAccountStore;
+
+use crate::{credential::Credential, server_state::ServerMetadata};
+
+use rpg_account::{
+    account::{Account, AccountId, AdminAccount},
+    name::names_match,
+    save::SaveError,
+};
+
+use bevy::{
+    ecs::system::Resource,
+    prelude::{Deref, DerefMut},
+};
+
+use thiserror::Error;
+
+use std::io;
+
+#[derive(Debug, Error)]
+pub(crate) enum StoreError {
+    #[error("record not found")]
+    NotFound,
+    #[error("record already exists")]
+    AlreadyExists,
+    #[error("invalid record name")]
+    InvalidName,
+    #[error("io error: {0}")]
+    Io(#[from] io::Error),
+    #[error("save format error: {0}")]
+    Format(#[from] SaveError),
+}
+
+pub(crate) trait AccountStore: Send + Sync + 'static {
+    fn load_account(&self, name: &str) -> Result<Account, StoreError>;
+
+    /// Store a new account, this fails if an account with the same name already exists
+    fn create_account(&mut self, account: &Account) -> Result<(), StoreError>;
+
+    fn save_account(&mut self, account: &Account) -> Result<(), StoreError>;
+
+    /// The names of all stored accounts
+    fn list_accounts(&self) -> Result<Vec<String>, StoreError>;
+
+    /// Remove an account and its credential
+    fn delete_account(&mut self, name: &str) -> Result<(), StoreError>;
+
+    fn load_admin_account(&self, name: &str) -> Result<AdminAccount, StoreError>;
+
+    fn load_credential(&self, name: &str) -> Result<Credential, StoreError>;
+
+    fn save_credential(&mut self, name: &str, credential: &Credential) -> Result<(), StoreError>;
+
+    fn load_admin_credential(&self, name: &str) -> Result<Credential, StoreError>;
+
+    fn load_metadata(&self) -> Result<ServerMetadata, StoreError>;
+
+    /// Load the previous metadata, used when the current metadata can not be loaded
+    fn load_metadata_backup(&self) -> Result<ServerMetadata, StoreError> {
+        Err(StoreError::NotFound)
+    }
+
+    fn save_metadata(&mut self, metadata: &ServerMetadata) -> Result<(), StoreError>;
+
+    /// Check if an account name is in use, ignoring case
+    fn account_name_exists(&self, name: &str) -> Result<bool, StoreError> {
+        Ok(self
+            .list_accounts()?
+            .iter()
+            .any(|account_name| names_match(account_name, name)))
+    }
+
+    /// Check if any account has a character with the given name, ignoring case
+    ///
+    /// This loads every account so it should only be used for infrequent operations.
+    fn character_name_exists(&self, name: &str) -> Result<bool, StoreError> {
+        for account_name in self.list_accounts()? {
+            // Unreadable accounts cannot be logged into so their character names are free
+            let Ok(account) = self.load_account(&account_name) else {
+                continue;
+            };
+            if account
+                .characters
+                .iter()
+                .any(|c| names_match(&c.info.name, name))
+            {
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+
+    /// Allocate an account id and persist the updated metadata
+    fn next_account_id(&mut self, metadata: &mut ServerMetadata) -> Result<AccountId, StoreError> {
+        let id = metadata.next_account_id;
+        metadata.next_account_id.0 += 1;
+
+        self.save_metadata(metadata)?;
+
+        Ok(id)
+    }
+
+    /// Advance the metadata's counters past every stored account, character and item, for
+    /// metadata restored from a copy that may predate them
+    fn advance_counters(&self, metadata: &mut ServerMetadata) -> Result<(), StoreError> {
+        for account_name in self.list_accounts()? {
+            // Unreadable accounts cannot be logged into, nothing of theirs is in use
+            let Ok(account) = self.load_account(&account_name) else {
+                continue;
+            };
+
+            let next_account_id = &mut metadata.next_account_id.0;
+            *next_account_id = (*next_account_id).max(account.info.id.0 + 1);
+
+            for record in &account.characters {
+                metadata.next_uid.skip_past(record.info.uid);
+
+                let items = record
+                    .character
+                    .storage
+                    .storage
+                    .iter()
+                    .flat_map(|node| &node.node)
+                    .filter_map(|slot| slot.item.as_ref());
+                for item in items {
+                    metadata.next_uid.skip_past(item.uid);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Resource, Deref, DerefMut)]
+pub(crate) struct AccountStorage(pub(crate) Box<dyn AccountStore>);
+
+impl AccountStorage {
+    pub(crate) fn new(store: impl AccountStore) -> Self {
+        Self(Box::new(store))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rpg_account::account::AccountInfo;
+    use rpg_chat::chat::MessageId;
+    use rpg_core::uid::NextUid;
+
+    fn account(name: &str) -> Account {
+        Account {
+            info: AccountInfo {
+                name: name.into(),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn account_names_match_ignoring_case() {
+        let mut store = MemoryAccountStore::default();
+        store.create_account(&account("Alice")).unwrap();
+
+        assert!(store.account_name_exists("alice").unwrap());
+        assert!(!store.account_name_exists("bob").unwrap());
+    }
+
+    #[test]
+    fn accounts_are_only_created_once() {
+        let mut store = MemoryAccountStore::default();
+        store.create_account(&account("alice")).unwrap();
+
+        assert!(matches!(
+            store.create_account(&account("alice")),
+            Err(StoreError::AlreadyExists)
+        ));
+
+        store.delete_account("alice").unwrap();
+        assert!(matches!(
+            store.load_account("alice"),
+            Err(StoreError::NotFound)
+        ));
+    }
+
+    #[test]
+    fn account_ids_are_persisted() {
+        let mut store = MemoryAccountStore::default();
+        let mut metadata = ServerMetadata {
+            next_account_id: AccountId(7),
+            next_message_id: MessageId(0),
+            next_uid: NextUid::default(),
+            rng_seed: 0,
+        };
+
+        assert_eq!(store.next_account_id(&mut metadata).unwrap(), AccountId(7));
+        assert_eq!(store.next_account_id(&mut metadata).unwrap(), AccountId(8));
+        assert_eq!(store.load_metadata().unwrap().next_account_id, AccountId(9));
+    }
+
+    #[test]
+    fn counters_advance_past_stored_accounts() {
+        let mut store = MemoryAccountStore::default();
+        for (name, id) in [("alice", 3), ("bob", 11)] {
+            let mut account = account(name);
+            account.info.id = AccountId(id);
+            store.create_account(&account).unwrap();
+        }
+
+        let mut metadata = ServerMetadata {
+            next_account_id: AccountId(4),
+            next_message_id: MessageId(0),
+            next_uid: NextUid::default(),
+            rng_seed: 0,
+        };
+        store.advance_counters(&mut metadata).unwrap();
+        assert_eq!(metadata.next_account_id, AccountId(12));
+
+        // counters ahead of the stored records are left alone
+        metadata.next_account_id = AccountId(20);
+        store.advance_counters(&mut metadata).unwrap();
+        assert_eq!(metadata.next_account_id, AccountId(20));
+    }
+}
@@ -1,11 +1,15 @@
-use crate::{assets::MetadataResources, net::server::NetworkParamsRW, state::AppState};
+use crate::{
+    assets::MetadataResources, game::instance::GameManager, net::server::NetworkParamsRW,
+    state::AppState,
+};
 
 use bevy::{
     app::{App, Plugin, Update},
     ecs::{
+        component::Component,
         event::{Event, EventReader},
         schedule::{common_conditions::in_state, IntoSystemConfigs},
-        system::{Res, ResMut, Resource},
+        system::{Res, ResMut},
     },
     log::info,
 };
@@ -15,8 +19,15 @@ use rpg_world::zone::{Kind, Zone, ZoneId};
 
 use std::collections::HashMap;
 
+/// The zone a unit or ground item is in
+#[derive(Component, Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) struct InZone(pub(crate) ZoneId);
+
 #[derive(Event)]
-pub(crate) struct LoadZone(pub(crate) ZoneId);
+pub(crate) struct LoadZone {
+    pub(crate) game_id: GameId,
+    pub(crate) zone_id: ZoneId,
+}
 
 #[derive(Default)]
 pub(crate) enum ZoneLoadStatus {
@@ -32,7 +43,8 @@ pub(crate) struct RpgZone {
     pub(crate) status: ZoneLoadStatus,
 }
 
-#[derive(Default, Resource)]
+/// The zones of a single game instance
+#[derive(Default)]
 pub(crate) struct RpgWorld {
     pub(crate) zones: HashMap<ZoneId, RpgZone>,
 }
@@ -42,36 +54,34 @@ pub(crate) struct WorldPlugin;
 impl Plugin for WorldPlugin {
     fn build(&self, app: &mut App) {
         app.add_event::<LoadZone>()
-            .init_resource::<RpgWorld>()
-            .add_systems(
-                Update,
-                spawn_world.run_if(in_state(AppState::SpawnSimulation)),
-            );
+            .add_systems(Update, spawn_world.run_if(in_state(AppState::Running)));
     }
 }
 
 pub(crate) fn spawn_world(
-    mut rpg_world: ResMut<RpgWorld>,
+    mut game_manager: ResMut<GameManager>,
     metadata: Res<MetadataResources>,
     mut load_zone: EventReader<LoadZone>,
     mut net_params: NetworkParamsRW,
 ) {
     for load_zone_request in load_zone.read() {
-        let zone_id = load_zone_request.0;
-        let message = bincode::serialize(&ServerMessage::SCZoneLoad(SCZoneLoad(zone_id))).unwrap();
-        if rpg_world.zones.contains_key(&zone_id) {
+        let Some(game) = game_manager.get_game_mut(load_zone_request.game_id) else {
+            info!("zone requested for a game that no longer exists");
+            continue;
+        };
+
+        let zone_id = load_zone_request.zone_id;
+        let message = ServerMessage::SCZoneLoad(SCZoneLoad(zone_id));
+        if game.world.zones.contains_key(&zone_id) {
             // ..
             info!("zone is already loaded");
-            net_params
-                .server
-                .broadcast_message(ServerChannel::Message, message);
+            game.broadcast_message(&mut net_params.outbound, &message);
             continue;
         }
 
         let zone_meta = &metadata.world.zone.towns[&zone_id];
 
-        let zone_id = load_zone_request.0;
-        info!("loading zone {zone_id:?}");
+        info!("loading zone {zone_id:?} for game {:?}", game.id);
         let zone = match zone_meta.kind {
             Kind::OverworldTown | Kind::UnderworldTown => {
                 Zone::create_town(zone_id, 1234, &metadata.world)
@@ -84,14 +94,8 @@ pub(crate) fn spawn_world(
             status: ZoneLoadStatus::Loading,
         };
 
-        net_params
-            .server
-            .broadcast_message(ServerChannel::Message, message);
+        game.broadcast_message(&mut net_params.outbound, &message);
 
-        rpg_world.zones.insert(zone_id, zone);
+        game.world.zones.insert(zone_id, zone);
     }
 }
-
-pub(crate) fn cleanup(mut rpg_world: ResMut<RpgWorld>) {
-    rpg_world.zones.clear();
-}
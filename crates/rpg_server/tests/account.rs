@@ -0,0 +1,156 @@
+//! Account and character flows, see `harness`
+
+mod harness;
+
+use harness::{TestServer, PASSWORD, SLOT};
+
+use rpg_core::game_mode::GameMode;
+use rpg_network_protocol::protocol::*;
+
+#[test]
+fn account_creation_and_login() {
+    let mut server = TestServer::start();
+
+    let mut client = server.connect();
+    client.send(ClientMessage::CSConnectPlayer(CSConnectPlayer));
+    let account = client.create_account(&mut server, "alice");
+    assert_eq!(account.info.name, "alice");
+    assert!(account.characters.is_empty());
+
+    // the account is saved once its client disconnects
+    server.disconnect(client);
+
+    let mut client = server.connect();
+    client.send(ClientMessage::CSConnectPlayer(CSConnectPlayer));
+    client.send(ClientMessage::CSLoadAccount(CSLoadAccount {
+        name: "alice".into(),
+        password: PASSWORD.into(),
+    }));
+
+    let message = client.wait_for(&mut server, |m| {
+        matches!(
+            m,
+            ServerMessage::SCLoginAccountSuccess(_) | ServerMessage::SCLoginAccountError(_)
+        )
+    });
+    let ServerMessage::SCLoginAccountSuccess(msg) = message else {
+        panic!("login failed: {message:?}");
+    };
+    assert_eq!(msg.0.info.id, account.info.id);
+}
+
+#[test]
+fn account_name_must_be_unique() {
+    let mut server = TestServer::start();
+
+    let _alice = server.connect_player("alice");
+
+    let mut client = server.connect();
+    client.send(ClientMessage::CSConnectPlayer(CSConnectPlayer));
+    client.send(ClientMessage::CSCreateAccount(CSCreateAccount {
+        name: "alice".into(),
+        email: "alice@test.invalid".into(),
+        password: PASSWORD.into(),
+    }));
+
+    let message = client.wait_for(&mut server, |m| {
+        matches!(
+            m,
+            ServerMessage::SCCreateAccountSuccess(_) | ServerMessage::SCCreateAccountError(_)
+        )
+    });
+    assert_eq!(
+        message,
+        ServerMessage::SCCreateAccountError(SCCreateAccountError(CreateAccountError::NameTaken))
+    );
+}
+
+#[test]
+fn login_errors() {
+    let mut server = TestServer::start();
+
+    let alice = server.connect_player("alice");
+    server.disconnect(alice);
+
+    for (name, password, error) in [
+        ("bob", PASSWORD, LoginError::AccountNotFound),
+        ("alice", "wrong-password", LoginError::InvalidPassword),
+    ] {
+        let mut client = server.connect();
+        client.send(ClientMessage::CSConnectPlayer(CSConnectPlayer));
+        client.send(ClientMessage::CSLoadAccount(CSLoadAccount {
+            name: name.into(),
+            password: password.into(),
+        }));
+
+        let message = client.wait_for(&mut server, |m| {
+            matches!(
+                m,
+                ServerMessage::SCLoginAccountSuccess(_) | ServerMessage::SCLoginAccountError(_)
+            )
+        });
+        assert_eq!(
+            message,
+            ServerMessage::SCLoginAccountError(SCLoginAccountError(error))
+        );
+    }
+}
+
+#[test]
+fn accounts_are_only_logged_in_once() {
+    let mut server = TestServer::start();
+
+    let alice = server.connect_player("alice");
+    server.disconnect(alice);
+
+    let mut first = server.connect();
+    first.send(ClientMessage::CSConnectPlayer(CSConnectPlayer));
+    let message = first.load_account(&mut server, "alice", PASSWORD);
+    assert!(matches!(message, ServerMessage::SCLoginAccountSuccess(_)));
+
+    // a second session would save over the first
+    let mut second = server.connect();
+    second.send(ClientMessage::CSConnectPlayer(CSConnectPlayer));
+    assert_eq!(
+        second.load_account(&mut server, "alice", PASSWORD),
+        ServerMessage::SCLoginAccountError(SCLoginAccountError(LoginError::AlreadyLoggedIn))
+    );
+
+    server.disconnect(first);
+    let message = second.load_account(&mut server, "alice", PASSWORD);
+    assert!(matches!(message, ServerMessage::SCLoginAccountSuccess(_)));
+}
+
+#[test]
+fn character_creation() {
+    let mut server = TestServer::start();
+
+    let mut client = server.connect();
+    client.send(ClientMessage::CSConnectPlayer(CSConnectPlayer));
+    client.create_account(&mut server, "alice");
+
+    let record = client.create_character(&mut server, "Hero", GameMode::Normal);
+    assert_eq!(record.info.name, "Hero");
+    assert_eq!(record.info.slot, SLOT);
+    assert_eq!(record.info.game_mode, GameMode::Normal);
+
+    client.send(ClientMessage::CSCreateCharacter(CSCreateCharacter {
+        name: "Another".into(),
+        slot: SLOT,
+        class: record.character.unit.class,
+        game_mode: GameMode::Normal,
+    }));
+
+    let message = client.wait_for(&mut server, |m| {
+        matches!(
+            m,
+            ServerMessage::SCCreateCharacterSuccess(_) | ServerMessage::SCCreateCharacterError(_)
+        )
+    });
+    assert_eq!(
+        message,
+        ServerMessage::SCCreateCharacterError(SCCreateCharacterError(
+            CreateCharacterError::SlotInUse
+        ))
+    );
+}
@@ -0,0 +1,42 @@
+//! Chat flows, see `harness`
+
+mod harness;
+
+use harness::{TestClient, TestServer};
+
+use rpg_chat::chat::{ChannelId, Message, MessageId};
+use rpg_network_protocol::protocol::*;
+
+fn join_chat(server: &mut TestServer, client: &mut TestClient) {
+    client.send(ClientMessage::CSChatJoin(CSChatJoin));
+    client.wait_for(server, |m| matches!(m, ServerMessage::SCChatJoinSuccess(_)));
+}
+
+#[test]
+fn messages_skip_disconnected_subscribers() {
+    let mut server = TestServer::start();
+
+    let mut alice = server.connect_player("alice");
+    join_chat(&mut server, &mut alice);
+
+    let mut bob = server.connect_player("bob");
+    join_chat(&mut server, &mut bob);
+    server.disconnect(bob);
+
+    let sender = alice.account.as_ref().unwrap().info.id;
+    alice.send(ClientMessage::CSChatChannelMessage(CSChatChannelMessage(
+        Message {
+            message: "hello".into(),
+            id: MessageId(0),
+            channel_id: ChannelId(0),
+            sender,
+        },
+    )));
+
+    let ServerMessage::SCChatMessage(msg) = alice.wait_for(&mut server, |m| {
+        matches!(m, ServerMessage::SCChatMessage(_))
+    }) else {
+        unreachable!();
+    };
+    assert_eq!(msg.0.message, "hello");
+}
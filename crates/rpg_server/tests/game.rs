@@ -0,0 +1,230 @@
+//! Game flows from joining a game to moving, attacking and picking up items, see `harness`
+
+mod harness;
+
+use harness::{TestServer, TIMEOUT};
+
+use rpg_core::{
+    item::{CurrencyId, CurrencyInfo, Item, ItemDrops, ItemId, ItemInfo},
+    uid::{NextUid, Uid},
+};
+use rpg_network_protocol::{protocol::*, snapshot::UnitActivity};
+use rpg_util::{
+    item::GroundItemDrops,
+    unit::{Unit, Villain},
+};
+
+use bevy::{ecs::query::With, math::Vec3, transform::components::Transform};
+
+use std::time::{Duration, Instant};
+
+/// A uid far past any the server hands out during a test
+fn unused_uid() -> Uid {
+    let mut next_uid = NextUid::default();
+    for _ in 0..1 << 20 {
+        next_uid.next();
+    }
+
+    next_uid.get()
+}
+
+/// Move a villain of the only game to `position`, returning its uid
+fn place_villain(server: &mut TestServer, position: Vec3) -> Uid {
+    let world = &mut server.app.world;
+    let mut villain_q = world.query_filtered::<(&Unit, &mut Transform), With<Villain>>();
+    let (unit, mut transform) = villain_q
+        .iter_mut(world)
+        .next()
+        .expect("games are created with villains");
+    transform.translation = position;
+
+    unit.uid
+}
+
+fn unit_position(server: &mut TestServer, uid: Uid) -> Vec3 {
+    let world = &mut server.app.world;
+    let mut unit_q = world.query::<(&Unit, &Transform)>();
+    unit_q
+        .iter(world)
+        .find(|(unit, _)| unit.uid == uid)
+        .map(|(_, transform)| transform.translation)
+        .expect("the unit no longer exists")
+}
+
+#[test]
+fn create_and_join_game() {
+    let mut server = TestServer::start();
+
+    let mut alice = server.connect_player("alice");
+    let (game_id, alice_position) = alice.create_game(&mut server);
+
+    let mut bob = server.connect_player("bob");
+    let bob_position = bob.join_game(&mut server, game_id);
+
+    // heroes are not spawned on top of each other
+    assert!(alice_position.distance(bob_position) >= 2.);
+
+    // both heroes are close enough to be told about each other
+    let alice_uid = alice.hero_uid();
+    bob.wait_for(
+        &mut server,
+        |m| matches!(m, ServerMessage::SCSpawnHero(msg) if msg.uid == alice_uid),
+    );
+}
+
+#[test]
+fn movement_is_applied_and_acknowledged() {
+    let mut server = TestServer::start();
+
+    let mut client = server.connect_player("alice");
+    let (_, spawn_position) = client.create_game(&mut server);
+
+    // one input per server tick, as the client sends them
+    for _ in 0..20 {
+        client.move_player(Vec3::X);
+        client.run_for(&mut server, Duration::from_millis(16));
+    }
+    let sequence = client.stop_player();
+
+    let state = client.wait_for_input(&mut server, sequence);
+    assert!(state.position.x > spawn_position.x + 0.1);
+    assert!((state.position.z - spawn_position.z).abs() < 0.01);
+    assert_eq!(state.activity, UnitActivity::Idle);
+}
+
+#[test]
+fn skill_use_spawns_a_skill() {
+    let mut server = TestServer::start();
+
+    let mut client = server.connect_player("alice");
+    let (_, spawn_position) = client.create_game(&mut server);
+
+    let uid = client.hero_uid();
+    let skill_id = client.character().character.skill_slots[0]
+        .skill_id
+        .expect("new characters have a skill in the first slot");
+
+    client.send(ClientMessage::CSSkillUseTargeted(CSSkillUseTargeted {
+        skill_id,
+        target: spawn_position + Vec3::X,
+    }));
+
+    client.wait_for(&mut server, |m| {
+        matches!(m, ServerMessage::SCUnitAttack(msg) if msg.uid == uid && msg.skill_id == skill_id)
+    });
+    client.wait_for(&mut server, |m| {
+        matches!(m, ServerMessage::SCSpawnSkill(msg) if msg.owner_uid == uid && msg.id == skill_id)
+    });
+}
+
+#[test]
+fn skills_damage_villains() {
+    let mut server = TestServer::start();
+
+    let mut client = server.connect_player("alice");
+    let (_, spawn_position) = client.create_game(&mut server);
+
+    let skill_id = client.character().character.skill_slots[0]
+        .skill_id
+        .expect("new characters have a skill in the first slot");
+    let villain_uid = place_villain(&mut server, spawn_position + Vec3::X * 2.);
+
+    let (_, snapshot) =
+        client.wait_for_snapshot(&mut server, |_, s| s.units.contains_key(&villain_uid));
+    let full_hp = snapshot.units[&villain_uid].hp;
+
+    // blocked and dodged attacks do no damage, attack until one lands
+    let started = Instant::now();
+    loop {
+        assert!(started.elapsed() < TIMEOUT, "no attack hit the villain");
+
+        client.send(ClientMessage::CSSkillUseTargeted(CSSkillUseTargeted {
+            skill_id,
+            target: unit_position(&mut server, villain_uid),
+        }));
+        client.run_for(&mut server, Duration::from_millis(250));
+
+        let hit = client.inbox().iter().any(|m| match m {
+            ServerMessage::SCDamage(msg) => msg.uid == villain_uid,
+            ServerMessage::SCVillainDeath(msg) => msg.0 == villain_uid,
+            _ => false,
+        });
+        if hit {
+            break;
+        }
+    }
+
+    client.wait_for_snapshot(&mut server, |_, s| {
+        s.units
+            .get(&villain_uid)
+            .is_some_and(|villain| villain.hp < full_hp)
+    });
+}
+
+#[test]
+fn snapshot_deltas_apply_on_their_baseline() {
+    let mut server = TestServer::start();
+
+    let mut client = server.connect_player("alice");
+    let (_, spawn_position) = client.create_game(&mut server);
+    let uid = client.hero_uid();
+
+    for _ in 0..20 {
+        client.move_player(Vec3::X);
+        client.run_for(&mut server, Duration::from_millis(16));
+    }
+    let sequence = client.stop_player();
+
+    // acknowledged snapshots become the baseline of later ones
+    let (delta, snapshot) = client.wait_for_snapshot(&mut server, |delta, _| {
+        delta.baseline.is_some() && delta.last_input >= Some(sequence)
+    });
+
+    // the hero's maximum hp never changed, it is only in the baseline
+    assert!(delta
+        .changed
+        .iter()
+        .filter(|unit| unit.uid == uid)
+        .all(|unit| unit.hp_max.is_none()));
+
+    let hero = snapshot.units[&uid];
+    assert!(hero.hp_max > 0);
+    assert!(hero.position.x > spawn_position.x + 0.1);
+}
+
+#[test]
+fn item_pickup() {
+    let mut server = TestServer::start();
+
+    let mut client = server.connect_player("alice");
+    client.create_game(&mut server);
+
+    // items dropped by the hero land at its feet
+    let item_uid = unused_uid();
+    server
+        .app
+        .world
+        .resource_mut::<GroundItemDrops>()
+        .0
+        .push(ItemDrops {
+            source: client.hero_uid(),
+            items: vec![Item::new(
+                item_uid,
+                ItemId(0),
+                ItemInfo::Currency(CurrencyInfo { id: CurrencyId(0) }),
+            )],
+        });
+
+    client.wait_for(&mut server, |m| {
+        let ServerMessage::SCSpawnItem(msg) = m else {
+            return false;
+        };
+        msg.items.items.iter().any(|item| item.uid == item_uid)
+    });
+
+    client.send(ClientMessage::CSItemPickup(CSItemPickup(item_uid)));
+    client.wait_for(
+        &mut server,
+        |m| matches!(m, ServerMessage::SCDespawnItem(msg) if msg.0 == item_uid),
+    );
+}
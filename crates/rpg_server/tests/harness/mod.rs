@@ -0,0 +1,579 @@
+//! An in-process server and scripted clients for integration tests
+//!
+//! `TestServer` builds the real server app with `build_app`, in unsecure mode on a free loopback
+//! port and with a temporary save root, and updates it by hand instead of running it. A
+//! `TestClient` is a renet client on the same thread, waiting on a client updates the server and
+//! that client until the expected message arrives.
+//!
+//! Clients apply and acknowledge snapshots as the game client does, so once the server has an
+//! acknowledged baseline the snapshots they receive are deltas against it.
+
+// Every test binary uses a different part of the harness
+#![allow(dead_code)]
+
+use rpg_server::{build_app, config::ServerSettings, shutdown::ShutdownHandle, state::AppState};
+
+use rpg_account::{
+    account::Account,
+    character::{CharacterRecord, CharacterSlot},
+};
+use rpg_core::{class::Class, game_mode::GameMode, uid::Uid};
+use rpg_network_protocol::{
+    advertised_features,
+    protocol::*,
+    snapshot::{Snapshot, SnapshotDelta, Tick, UnitState, SNAPSHOT_HISTORY},
+    BUILD_ID, PROTOCOL_ID, PROTOCOL_VERSION,
+};
+
+use bevy::{
+    app::{App, PluginsState},
+    ecs::schedule::State,
+    math::Vec3,
+    tasks::tick_global_task_pools_on_main_thread,
+};
+
+use bevy_renet::renet::{
+    transport::{ClientAuthentication, NetcodeClientTransport},
+    ClientId, ConnectionConfig, RenetClient, RenetServer,
+};
+
+use std::{
+    collections::VecDeque,
+    env, fs,
+    net::{Ipv4Addr, SocketAddr, UdpSocket},
+    path::{Path, PathBuf},
+    process,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Once,
+    },
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+/// How long any wait may take before the test fails
+pub const TIMEOUT: Duration = Duration::from_secs(30);
+
+const FRAME: Duration = Duration::from_millis(2);
+
+pub const PASSWORD: &str = "test-password";
+
+pub const SLOT: CharacterSlot = CharacterSlot(0);
+
+/// Tells apart the save roots and clients of tests running in parallel
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+static ASSET_ROOT: Once = Once::new();
+
+/// The server loads its metadata from the repository's `assets`
+fn set_asset_root() {
+    ASSET_ROOT.call_once(|| {
+        if env::var_os("BEVY_ASSET_ROOT").is_none() {
+            let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("../..");
+            env::set_var("BEVY_ASSET_ROOT", root);
+        }
+    });
+}
+
+/// Another test may take the port before the server binds it, which is unlikely enough here
+fn free_port() -> u16 {
+    let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+    socket.local_addr().unwrap().port()
+}
+
+pub struct TestServer {
+    pub app: App,
+    addr: SocketAddr,
+    save_root: PathBuf,
+}
+
+impl TestServer {
+    /// Build the server and update it until its metadata is loaded
+    pub fn start() -> Self {
+        Self::start_with(|_| {})
+    }
+
+    /// Like `start`, `configure` may change the settings before the app is built
+    pub fn start_with(configure: impl FnOnce(&mut ServerSettings)) -> Self {
+        set_asset_root();
+
+        let save_root = env::temp_dir().join(format!(
+            "rpg_server_test_{}_{}",
+            process::id(),
+            NEXT_ID.fetch_add(1, Ordering::Relaxed)
+        ));
+
+        let mut settings = ServerSettings::default();
+        settings.network.addr = Ipv4Addr::LOCALHOST;
+        settings.network.port = free_port();
+        settings.network.unsecure = true;
+        settings.persistence.save_root = Some(save_root.clone());
+        configure(&mut settings);
+        settings.validate().unwrap();
+
+        let addr = settings.network.public_addr();
+
+        let mut app = build_app(settings, ShutdownHandle::default());
+        while app.plugins_state() == PluginsState::Adding {
+            tick_global_task_pools_on_main_thread();
+        }
+        app.finish();
+        app.cleanup();
+
+        let mut server = Self {
+            app,
+            addr,
+            save_root,
+        };
+
+        let started = Instant::now();
+        while *server.app.world.resource::<State<AppState>>().get() != AppState::Running {
+            assert!(
+                started.elapsed() < TIMEOUT,
+                "the server metadata did not load"
+            );
+            server.update();
+        }
+
+        server
+    }
+
+    pub fn update(&mut self) {
+        self.app.update();
+        thread::sleep(FRAME);
+    }
+
+    /// Connect a client and wait for the server to accept its hello
+    pub fn connect(&mut self) -> TestClient {
+        let mut client = TestClient::new(self.addr);
+        client.wait_for(self, |m| matches!(m, ServerMessage::SCHello(_)));
+
+        client
+    }
+
+    /// Disconnect a client and update until the server has dropped it
+    pub fn disconnect(&mut self, client: TestClient) {
+        let client_id = client.client_id;
+        drop(client);
+
+        let started = Instant::now();
+        while self
+            .app
+            .world
+            .resource::<RenetServer>()
+            .is_connected(client_id)
+        {
+            assert!(
+                started.elapsed() < TIMEOUT,
+                "the server did not drop the client"
+            );
+            self.update();
+        }
+
+        // The disconnect event may only be handled on the next update
+        self.update();
+    }
+
+    /// Connect a client as a player of a new account with a character in `SLOT`
+    pub fn connect_player(&mut self, name: &str) -> TestClient {
+        let mut client = self.connect();
+        client.send(ClientMessage::CSConnectPlayer(CSConnectPlayer));
+        client.create_account(self, name);
+        client.create_character(self, name, GameMode::Normal);
+
+        client
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.save_root);
+    }
+}
+
+pub struct TestClient {
+    client_id: ClientId,
+    client: RenetClient,
+    transport: NetcodeClientTransport,
+    last_update: Instant,
+    state_sequence: StateSequence,
+    /// Received messages not yet taken by a wait, snapshots are kept apart
+    inbox: Vec<ServerMessage>,
+    /// Applied snapshots and the deltas they were rebuilt from, not yet taken by a wait
+    snapshot_inbox: Vec<(SnapshotDelta, Snapshot)>,
+    /// Snapshots the server may encode against
+    snapshots: VecDeque<Snapshot>,
+    applied: Option<Tick>,
+    pub account: Option<Account>,
+    move_sequence: u32,
+}
+
+impl TestClient {
+    fn new(server_addr: SocketAddr) -> Self {
+        let connection_config = ConnectionConfig {
+            available_bytes_per_tick: 1024 * 1024,
+            client_channels_config: ClientChannel::channels_config(),
+            server_channels_config: ServerChannel::channels_config(),
+        };
+        let client = RenetClient::new(connection_config);
+
+        let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let current_time = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap();
+        let client_id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        let authentication = ClientAuthentication::Unsecure {
+            client_id,
+            protocol_id: PROTOCOL_ID,
+            server_addr,
+            user_data: None,
+        };
+        let transport = NetcodeClientTransport::new(current_time, authentication, socket).unwrap();
+
+        let mut client = Self {
+            client_id: ClientId::from_raw(client_id),
+            client,
+            transport,
+            last_update: Instant::now(),
+            state_sequence: StateSequence::default(),
+            inbox: vec![],
+            snapshot_inbox: vec![],
+            snapshots: VecDeque::new(),
+            applied: None,
+            account: None,
+            move_sequence: 0,
+        };
+
+        // Queued until the transport connects
+        client.send(ClientMessage::CSHello(CSHello {
+            protocol_version: PROTOCOL_VERSION,
+            build_id: BUILD_ID.to_string(),
+            features: advertised_features(),
+        }));
+
+        client
+    }
+
+    pub fn send(&mut self, message: ClientMessage) {
+        let message = bincode::serialize(&message).unwrap();
+        self.client.send_message(ClientChannel::Message, message);
+    }
+
+    pub fn is_disconnected(&self) -> bool {
+        self.client.is_disconnected()
+    }
+
+    /// Exchange packets with the server and decode everything received into the inbox
+    pub fn update(&mut self) {
+        let now = Instant::now();
+        let delta = now - self.last_update;
+        self.last_update = now;
+
+        self.client.update(delta);
+        self.transport.update(delta, &mut self.client).unwrap();
+
+        let mut messages = vec![];
+        for channel in [ServerChannel::Message, ServerChannel::Event] {
+            while let Some(batch) = self.client.receive_message(channel) {
+                messages.extend(decode_batch(&batch).unwrap());
+            }
+        }
+        while let Some(batch) = self.client.receive_message(ServerChannel::State) {
+            let (sequence, payload) = decode_sequenced(&batch).unwrap();
+            if self.state_sequence.accept(sequence) {
+                messages.extend(decode_batch(payload).unwrap());
+            }
+        }
+
+        for message in messages {
+            match message {
+                ServerMessage::SCSnapshot(msg) => self.receive_snapshot(msg.0),
+                message => self.inbox.push(message),
+            }
+        }
+
+        if self.client.is_connected() {
+            self.transport.send_packets(&mut self.client).unwrap();
+        }
+    }
+
+    /// Rebuild a snapshot on its baseline and acknowledge it, as the game client does
+    fn receive_snapshot(&mut self, delta: SnapshotDelta) {
+        if self
+            .applied
+            .is_some_and(|applied| !delta.tick.is_newer_than(applied))
+        {
+            return;
+        }
+
+        let baseline = delta.baseline.map(|tick| {
+            self.snapshots
+                .iter()
+                .find(|s| s.tick == tick)
+                .expect("the server encoded against a snapshot that was never acknowledged")
+        });
+        let snapshot = Snapshot::from_delta(&delta, baseline).expect("invalid snapshot delta");
+
+        self.send(ClientMessage::CSSnapshotAck(CSSnapshotAck(snapshot.tick)));
+
+        // the server never encodes against a snapshot older than the baseline again
+        if let Some(tick) = delta.baseline {
+            self.snapshots.retain(|s| !tick.is_newer_than(s.tick));
+        }
+        if self.snapshots.len() >= SNAPSHOT_HISTORY {
+            self.snapshots.pop_front();
+        }
+        self.applied = Some(snapshot.tick);
+        self.snapshots.push_back(snapshot.clone());
+        self.snapshot_inbox.push((delta, snapshot));
+    }
+
+    /// Update the server and this client until a message matching `predicate` is received, it
+    /// is taken out of the inbox and returned
+    pub fn wait_for(
+        &mut self,
+        server: &mut TestServer,
+        mut predicate: impl FnMut(&ServerMessage) -> bool,
+    ) -> ServerMessage {
+        self.wait_until(server, |client| {
+            let index = client.inbox.iter().position(&mut predicate)?;
+            Some(client.inbox.remove(index))
+        })
+    }
+
+    /// Update the server and this client until a snapshot matching `predicate` is applied, it
+    /// is returned with the delta it was rebuilt from
+    pub fn wait_for_snapshot(
+        &mut self,
+        server: &mut TestServer,
+        mut predicate: impl FnMut(&SnapshotDelta, &Snapshot) -> bool,
+    ) -> (SnapshotDelta, Snapshot) {
+        self.wait_until(server, |client| {
+            let index = client
+                .snapshot_inbox
+                .iter()
+                .position(|(delta, snapshot)| predicate(delta, snapshot))?;
+            Some(client.snapshot_inbox.remove(index))
+        })
+    }
+
+    fn wait_until<T>(
+        &mut self,
+        server: &mut TestServer,
+        mut poll: impl FnMut(&mut Self) -> Option<T>,
+    ) -> T {
+        let started = Instant::now();
+        loop {
+            if let Some(value) = poll(self) {
+                return value;
+            }
+
+            assert!(
+                started.elapsed() < TIMEOUT,
+                "timed out waiting for a message, received {:#?}",
+                self.inbox
+            );
+            assert!(!self.is_disconnected(), "disconnected while waiting");
+
+            server.update();
+            self.update();
+        }
+    }
+
+    /// Update the server and this client for `duration`
+    pub fn run_for(&mut self, server: &mut TestServer, duration: Duration) {
+        let started = Instant::now();
+        while started.elapsed() < duration {
+            server.update();
+            self.update();
+        }
+    }
+
+    /// Messages received so far that were not taken by a wait
+    pub fn inbox(&self) -> &[ServerMessage] {
+        &self.inbox
+    }
+
+    pub fn create_account(&mut self, server: &mut TestServer, name: &str) -> Account {
+        self.send(ClientMessage::CSCreateAccount(CSCreateAccount {
+            name: name.into(),
+            email: format!("{name}@test.invalid"),
+            password: PASSWORD.into(),
+        }));
+
+        let ServerMessage::SCCreateAccountSuccess(msg) = self.wait_for(server, |m| {
+            matches!(
+                m,
+                ServerMessage::SCCreateAccountSuccess(_) | ServerMessage::SCCreateAccountError(_)
+            )
+        }) else {
+            panic!("unable to create account {name}");
+        };
+
+        self.account = Some(msg.0.clone());
+
+        msg.0
+    }
+
+    /// Log into an existing account, returning the success or error reply
+    pub fn load_account(
+        &mut self,
+        server: &mut TestServer,
+        name: &str,
+        password: &str,
+    ) -> ServerMessage {
+        self.send(ClientMessage::CSLoadAccount(CSLoadAccount {
+            name: name.into(),
+            password: password.into(),
+        }));
+
+        let message = self.wait_for(server, |m| {
+            matches!(
+                m,
+                ServerMessage::SCLoginAccountSuccess(_) | ServerMessage::SCLoginAccountError(_)
+            )
+        });
+        if let ServerMessage::SCLoginAccountSuccess(msg) = &message {
+            self.account = Some(msg.0.clone());
+        }
+
+        message
+    }
+
+    pub fn create_character(
+        &mut self,
+        server: &mut TestServer,
+        name: &str,
+        game_mode: GameMode,
+    ) -> CharacterRecord {
+        self.send(ClientMessage::CSCreateCharacter(CSCreateCharacter {
+            name: name.into(),
+            slot: SLOT,
+            class: Class::default(),
+            game_mode,
+        }));
+
+        let ServerMessage::SCCreateCharacterSuccess(msg) = self.wait_for(server, |m| {
+            matches!(
+                m,
+                ServerMessage::SCCreateCharacterSuccess(_)
+                    | ServerMessage::SCCreateCharacterError(_)
+            )
+        }) else {
+            panic!("unable to create character {name}");
+        };
+
+        if let Some(account) = &mut self.account {
+            account.characters.push(msg.0.clone());
+        }
+
+        msg.0
+    }
+
+    /// The character in `SLOT`
+    pub fn character(&self) -> &CharacterRecord {
+        self.account
+            .as_ref()
+            .and_then(|account| account.get_character_from_slot(SLOT))
+            .expect("the client has no character")
+    }
+
+    pub fn hero_uid(&self) -> Uid {
+        self.character().character.unit.uid
+    }
+
+    /// Create a game with the character in `SLOT` and wait for the hero to spawn, returning the
+    /// game and the spawn position
+    pub fn create_game(&mut self, server: &mut TestServer) -> (GameId, Vec3) {
+        self.send(ClientMessage::CSCreateGame(CSCreateGame {
+            game_mode: GameMode::Normal,
+            slot: SLOT,
+        }));
+
+        let ServerMessage::SCGameCreateSuccess(msg) = self.wait_for(server, |m| {
+            matches!(
+                m,
+                ServerMessage::SCGameCreateSuccess(_) | ServerMessage::SCGameCreateError(_)
+            )
+        }) else {
+            panic!("unable to create a game");
+        };
+
+        (msg.game_id, self.spawn(server))
+    }
+
+    /// Join a game with the character in `SLOT` and wait for the hero to spawn, returning the
+    /// spawn position
+    pub fn join_game(&mut self, server: &mut TestServer, game_id: GameId) -> Vec3 {
+        self.send(ClientMessage::CSJoinGame(CSJoinGame {
+            game_mode: GameMode::Normal,
+            slot: SLOT,
+            game_id: Some(game_id),
+        }));
+
+        let ServerMessage::SCGameJoinSuccess(msg) = self.wait_for(server, |m| {
+            matches!(
+                m,
+                ServerMessage::SCGameJoinSuccess(_) | ServerMessage::SCGameJoinError(_)
+            )
+        }) else {
+            panic!("unable to join game {game_id:?}");
+        };
+        assert_eq!(msg.game_id, game_id);
+
+        self.spawn(server)
+    }
+
+    fn spawn(&mut self, server: &mut TestServer) -> Vec3 {
+        self.wait_for(server, |m| {
+            matches!(m, ServerMessage::SCPlayerJoinSuccess(_))
+        });
+        self.send(ClientMessage::CSClientReady(CSClientReady));
+
+        let ServerMessage::SCPlayerSpawn(msg) =
+            self.wait_for(server, |m| matches!(m, ServerMessage::SCPlayerSpawn(_)))
+        else {
+            unreachable!();
+        };
+
+        msg.position
+    }
+
+    /// Send a movement input, returning its sequence number
+    pub fn move_player(&mut self, direction: Vec3) -> u32 {
+        let sequence = self.move_sequence;
+        self.move_sequence += 1;
+        self.send(ClientMessage::CSMovePlayer(CSMovePlayer {
+            sequence,
+            direction,
+        }));
+
+        sequence
+    }
+
+    pub fn stop_player(&mut self) -> u32 {
+        let sequence = self.move_sequence;
+        self.move_sequence += 1;
+        self.send(ClientMessage::CSMovePlayerEnd(CSMovePlayerEnd { sequence }));
+
+        sequence
+    }
+
+    /// Wait for a snapshot that applied the movement input `sequence` and return the hero's state
+    /// in it
+    pub fn wait_for_input(&mut self, server: &mut TestServer, sequence: u32) -> UnitState {
+        let (_, snapshot) =
+            self.wait_for_snapshot(server, |delta, _| delta.last_input >= Some(sequence));
+
+        *snapshot
+            .units
+            .get(&self.hero_uid())
+            .expect("the hero is missing from the snapshot")
+    }
+}
+
+impl Drop for TestClient {
+    fn drop(&mut self) {
+        self.transport.disconnect();
+    }
+}
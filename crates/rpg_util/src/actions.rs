@@ -243,6 +243,14 @@ impl UnitActions {
     }
 }
 
+/// How far a unit moves along `direction` in `dt` seconds
+///
+/// Shared by the server simulation and the client's prediction of its own hero so that both agree
+/// on where a unit ends up.
+pub fn movement_step(unit: &Unit, direction: Vec3, dt: f32) -> Vec3 {
+    direction * (unit.get_effective_movement_speed() as f32 / 100. * dt)
+}
+
 pub fn action_tick(time: Res<Time>, mut unit_q: Query<&mut UnitActions>) {
     for mut actions in &mut unit_q {
         if actions.is_inactive() {
@@ -1,7 +1,8 @@
 use std::{
+    ffi::OsString,
     fs::{self, File},
-    io,
-    path::Path,
+    io::{self, Write},
+    path::{Path, PathBuf},
 };
 
 pub fn open_read(path: &Path) -> Result<File, io::Error> {
@@ -15,3 +16,48 @@ pub fn open_write(path: &Path) -> Result<File, io::Error> {
         .truncate(true)
         .open(path)
 }
+
+/// Append an extension to a path, `meta.bin` becomes `meta.bin.{extension}`
+pub fn with_appended_extension(path: &Path, extension: &str) -> PathBuf {
+    let mut path = OsString::from(path);
+    path.push(".");
+    path.push(extension);
+
+    path.into()
+}
+
+/// Replace the contents of `path` without ever leaving a partially written file behind
+///
+/// The data is written to a sibling `.tmp` file which is synced to disk and then renamed over the
+/// target. Either the old or the new contents will be present after a crash.
+pub fn write_atomic(path: &Path, bytes: &[u8]) -> Result<(), io::Error> {
+    let tmp_path = with_appended_extension(path, "tmp");
+
+    if let Err(e) = write_synced(&tmp_path, bytes).and_then(|_| fs::rename(&tmp_path, path)) {
+        let _ = fs::remove_file(&tmp_path);
+        return Err(e);
+    }
+
+    sync_parent(path)
+}
+
+fn write_synced(path: &Path, bytes: &[u8]) -> Result<(), io::Error> {
+    let mut file = open_write(path)?;
+    file.write_all(bytes)?;
+
+    file.sync_all()
+}
+
+/// Make a rename within the parent directory durable
+#[cfg(unix)]
+fn sync_parent(path: &Path) -> Result<(), io::Error> {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => File::open(parent)?.sync_all(),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(not(unix))]
+fn sync_parent(_path: &Path) -> Result<(), io::Error> {
+    Ok(())
+}